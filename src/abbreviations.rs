@@ -0,0 +1,56 @@
+use crate::config;
+use crate::motion::CharClass;
+use std::collections::BTreeMap;
+
+// Words Insert mode replaces as soon as a character that can't be part of a
+// word is typed after them, read from the `[abbreviations]` table of
+// `config.toml` or added with `:iabbrev`:
+//
+//     [abbreviations]
+//     teh = "the"
+//     kr = "Kind regards"
+#[derive(Default)]
+pub struct Abbreviations {
+    words: BTreeMap<String, String>,
+}
+
+impl Abbreviations {
+    pub fn load() -> Result<Self, String> {
+        let mut abbreviations = Self::default();
+        let Some(words) = config::section("abbreviations")? else {
+            return Ok(abbreviations);
+        };
+        for (word, expansion) in &words {
+            let expansion = expansion
+                .as_str()
+                .ok_or_else(|| format!("Abbreviation for {word} must be a string"))?;
+            abbreviations.add(word, expansion)?;
+        }
+        Ok(abbreviations)
+    }
+
+    // Only whole words can be abbreviations, since that is what gets looked
+    // up when a word ends.
+    pub fn add(&mut self, word: &str, expansion: &str) -> Result<(), String> {
+        if word.is_empty() || word.chars().any(|c| CharClass::of(c) != CharClass::Word) {
+            return Err(format!("Invalid abbreviation: {word}"));
+        }
+        self.words.insert(word.to_string(), expansion.to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, word: &str) -> bool {
+        self.words.remove(word).is_some()
+    }
+
+    pub fn get(&self, word: &str) -> Option<&str> {
+        self.words.get(word).map(String::as_str)
+    }
+
+    // Every abbreviation and what it expands to, in alphabetical order.
+    pub fn list(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.words
+            .iter()
+            .map(|(word, expansion)| (word.as_str(), expansion.as_str()))
+    }
+}
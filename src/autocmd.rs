@@ -0,0 +1,149 @@
+use crate::config;
+use crate::finder;
+
+// What autocommands can run on, named as in vim.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    // A file was read into a buffer, which runs when the buffer is first
+    // shown.
+    BufRead,
+    // Before and after a buffer is written to its file.
+    BufWritePre,
+    BufWritePost,
+    // A buffer's file type was worked out, right after `BufRead`. Its
+    // patterns match the file type, like `yaml`, rather than the file name.
+    FileType,
+}
+
+impl Event {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bufread" | "bufreadpost" => Some(Self::BufRead),
+            "bufwritepre" => Some(Self::BufWritePre),
+            "bufwritepost" => Some(Self::BufWritePost),
+            "filetype" => Some(Self::FileType),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BufRead => "BufRead",
+            Self::BufWritePre => "BufWritePre",
+            Self::BufWritePost => "BufWritePost",
+            Self::FileType => "FileType",
+        }
+    }
+}
+
+struct Autocommand {
+    event: Event,
+    pattern: String,
+    command: String,
+}
+
+// Commands run when something happens to a buffer, read from the
+// `[[autocmd]]` tables of `config.toml` or added with `:autocmd`:
+//
+//     [[autocmd]]
+//     event = "BufWritePre"
+//     pattern = "*.rs"
+//     command = "%s/\\s+$//"
+//
+//     [[autocmd]]
+//     event = "FileType"
+//     pattern = "javascript,typescript"
+//     command = ["set tabstop=2", "set shiftwidth=2"]
+//
+// Patterns are globs, or several separated by commas. Without a `/` they
+// match the file's name, otherwise its path as it was opened.
+#[derive(Default)]
+pub struct Autocommands {
+    autocommands: Vec<Autocommand>,
+}
+
+impl Autocommands {
+    pub fn load() -> Result<Self, String> {
+        let mut autocommands = Self::default();
+        for entry in config::tables("autocmd")? {
+            let field = |name: &str| entry.get(name).and_then(toml::Value::as_str);
+            let event =
+                field("event").ok_or_else(|| String::from("Every autocmd needs an event"))?;
+            let event =
+                Event::from_name(event).ok_or_else(|| format!("Unknown autocmd event: {event}"))?;
+            let pattern = field("pattern").unwrap_or("*");
+            let commands = match entry.get("command") {
+                Some(toml::Value::String(command)) => vec![command.as_str()],
+                Some(toml::Value::Array(commands)) => commands
+                    .iter()
+                    .map(|command| {
+                        command.as_str().ok_or_else(|| {
+                            format!(
+                                "The commands of the {} autocmd must be strings",
+                                event.name()
+                            )
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(format!("The {} autocmd needs a command", event.name())),
+            };
+            for command in commands {
+                autocommands.add(event, pattern, command);
+            }
+        }
+        Ok(autocommands)
+    }
+
+    pub fn add(&mut self, event: Event, pattern: &str, command: &str) {
+        self.autocommands.push(Autocommand {
+            event,
+            pattern: pattern.to_string(),
+            command: command.to_string(),
+        });
+    }
+
+    // The commands to run for `event` on `name`, which is the file name, or
+    // the file type for `FileType`, in the order they were added.
+    pub fn commands(&self, event: Event, name: &str) -> Vec<String> {
+        self.autocommands
+            .iter()
+            .filter(|autocommand| autocommand.event == event && matches(&autocommand.pattern, name))
+            .map(|autocommand| autocommand.command.clone())
+            .collect()
+    }
+
+    // Every autocommand as its event, pattern and command, for `:autocmd`.
+    pub fn list(&self) -> Vec<String> {
+        let width = self
+            .autocommands
+            .iter()
+            .map(|autocommand| autocommand.pattern.chars().count())
+            .max()
+            .unwrap_or(0);
+        self.autocommands
+            .iter()
+            .map(|autocommand| {
+                format!(
+                    "{:<12}  {:<width$}  {}",
+                    autocommand.event.name(),
+                    autocommand.pattern,
+                    autocommand.command
+                )
+            })
+            .collect()
+    }
+}
+
+fn matches(patterns: &str, name: &str) -> bool {
+    patterns.split(',').any(|pattern| {
+        let text = if pattern.contains('/') {
+            name
+        } else {
+            name.rsplit('/').next().unwrap_or(name)
+        };
+        finder::glob_matches(
+            &pattern.chars().collect::<Vec<char>>(),
+            &text.chars().collect::<Vec<char>>(),
+        )
+    })
+}
@@ -0,0 +1,69 @@
+use crate::event::Event;
+use crate::Position;
+use std::io;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use termion::event::Key;
+
+pub struct Size {
+    pub height: u16,
+    pub width: u16,
+}
+
+pub enum CursorStyle {
+    Bar,
+    Block,
+    Underline,
+}
+
+// Where the editor gets its input and shows its frames: `Terminal` for the
+// real thing, `Headless` for scripts and tests.
+pub trait Backend {
+    fn size(&self) -> &Size;
+
+    // Shows a frame, one string per row, with the cursor at `cursor`.
+    fn draw(
+        &mut self,
+        lines: Vec<String>,
+        cursor: &Position,
+        style: CursorStyle,
+    ) -> Result<(), io::Error>;
+
+    // Leaves a blank screen behind, when quitting.
+    fn clear(&mut self) -> Result<(), io::Error>;
+
+    // Makes the next frame redraw everything, after something else wrote to
+    // the screen.
+    fn invalidate(&mut self);
+
+    fn next_event(&self) -> Result<Event, io::Error>;
+
+    // Like `next_event`, but gives up with `None` once `timeout` has passed.
+    fn next_event_timeout(&self, timeout: Duration) -> Result<Option<Event>, io::Error>;
+
+    // Waits for a key, keeping any other events for `next_event`.
+    fn read_key(&self) -> Result<Key, io::Error>;
+
+    // Whether any more input can come. Only scripted input runs out.
+    fn has_input(&self) -> bool {
+        true
+    }
+
+    // Background work reports back through events sent here.
+    fn event_sender(&self) -> Sender<Event>;
+
+    // Gives the terminal back its normal line handling, e.g. while a shell
+    // command runs in it, until `activate_raw_mode`.
+    fn suspend_raw_mode(&self) -> Result<(), io::Error>;
+
+    fn activate_raw_mode(&self) -> Result<(), io::Error>;
+
+    // Stops the process like Ctrl-Z does in a shell.
+    fn suspend(&mut self) -> Result<(), io::Error>;
+
+    // Nobody watches a headless editor, so it skips what is only there for
+    // a person, like streaming large files or remembering where they were.
+    fn is_headless(&self) -> bool {
+        false
+    }
+}
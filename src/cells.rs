@@ -0,0 +1,156 @@
+use crate::overlay;
+use std::fmt::Write;
+use termion::clear;
+use termion::cursor;
+
+// The colors and attributes SGR codes leave the terminal with, so a cell can
+// be drawn on its own the way it looks within its line.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Pen {
+    foreground: Option<String>,
+    background: Option<String>,
+    // A bit for each of the attributes 1 (bold) to 9 (crossed out).
+    attributes: u16,
+}
+
+impl Pen {
+    // Applies the parameters of an SGR code, like `1` or `38;2;255;0;0`.
+    fn apply(&mut self, parameters: &str) {
+        let mut parameters = parameters.split(';');
+        while let Some(parameter) = parameters.next() {
+            let code: u16 = parameter.parse().unwrap_or(0);
+            match code {
+                0 => *self = Self::default(),
+                1..=9 => self.attributes |= 1 << code,
+                22 => self.attributes &= !(1 << 1 | 1 << 2),
+                23..=29 => self.attributes &= !(1 << code.saturating_sub(20)),
+                30..=37 | 90..=97 => self.foreground = Some(code.to_string()),
+                40..=47 | 100..=107 => self.background = Some(code.to_string()),
+                38 | 48 => {
+                    // `5;n` for the 256 colors, `2;r;g;b` for any other.
+                    let kind = parameters.next().unwrap_or_default();
+                    let count = if kind == "5" { 1 } else { 3 };
+                    let color: Vec<&str> = parameters.by_ref().take(count).collect();
+                    let color = Some(format!("{code};{kind};{}", color.join(";")));
+                    if code == 38 {
+                        self.foreground = color;
+                    } else {
+                        self.background = color;
+                    }
+                }
+                39 => self.foreground = None,
+                49 => self.background = None,
+                _ => (),
+            }
+        }
+    }
+
+    // The SGR code that sets the terminal to this pen from any other.
+    fn escape(&self) -> String {
+        let mut escape = String::from("\x1b[0");
+        for attribute in (1..=9).filter(|attribute| self.attributes & 1 << attribute != 0) {
+            let _ = write!(escape, ";{attribute}");
+        }
+        for color in self.foreground.iter().chain(&self.background) {
+            let _ = write!(escape, ";{color}");
+        }
+        escape.push('m');
+        escape
+    }
+}
+
+// A column of a line on screen: the character in it, with any combining
+// marks, and how it is drawn. The column a wide character covers on its
+// right has no text of its own.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cell {
+    text: String,
+    pen: Pen,
+}
+
+impl Cell {
+    fn is_continuation(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+// The cells of a line as the editor draws it, or `None` when it has escape
+// codes other than colors and attributes, which can't be split into cells.
+pub fn parse(line: &str) -> Option<Vec<Cell>> {
+    let mut cells: Vec<Cell> = Vec::new();
+    let mut pen = Pen::default();
+    for (piece, width) in overlay::pieces(line) {
+        if let Some(code) = piece.strip_prefix('\x1b') {
+            pen.apply(code.strip_prefix('[')?.strip_suffix('m')?);
+        } else if width == 0 {
+            if let Some(cell) = cells.last_mut() {
+                cell.text.push_str(piece);
+            }
+        } else {
+            cells.push(Cell {
+                text: piece.to_string(),
+                pen: pen.clone(),
+            });
+            for _ in 1..width {
+                cells.push(Cell {
+                    text: String::new(),
+                    pen: pen.clone(),
+                });
+            }
+        }
+    }
+    Some(cells)
+}
+
+// What turns `old` on screen row `row` (counted from 1, like `Goto`) into
+// `new`: only the runs of cells that differ are written, and whatever `old`
+// has past the end of `new` is cleared.
+pub fn changes(old: &[Cell], new: &[Cell], row: u16) -> String {
+    let mut output = String::new();
+    let mut pen: Option<&Pen> = None;
+    let goto = |column: usize| {
+        cursor::Goto(
+            u16::try_from(column.saturating_add(1)).unwrap_or(u16::MAX),
+            row,
+        )
+    };
+    let mut column = 0;
+    while let Some(cell) = new.get(column) {
+        if old.get(column) == Some(cell) {
+            column = column.saturating_add(1);
+            continue;
+        }
+        // Half a wide character can't be written, so the run starts at the
+        // character itself.
+        while column > 0 && new.get(column).is_some_and(Cell::is_continuation) {
+            column = column.saturating_sub(1);
+        }
+        let _ = write!(output, "{}", goto(column));
+        let start = column;
+        while let Some(cell) = new.get(column) {
+            if column > start && old.get(column) == Some(cell) && !cell.is_continuation() {
+                break;
+            }
+            if !cell.is_continuation() {
+                if pen != Some(&cell.pen) {
+                    output.push_str(&cell.pen.escape());
+                    pen = Some(&cell.pen);
+                }
+                output.push_str(&cell.text);
+            }
+            column = column.saturating_add(1);
+        }
+    }
+    if old.len() > new.len() {
+        let _ = write!(
+            output,
+            "{}{}{}",
+            goto(new.len()),
+            Pen::default().escape(),
+            clear::UntilNewline
+        );
+    } else if pen.is_some() {
+        output.push_str(&Pen::default().escape());
+    }
+    output
+}
@@ -0,0 +1,140 @@
+use crate::keymap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use termion::event::Key;
+
+pub const USAGE: &str = "\
+Usage: ggedit [options] [+line] [file[:line[:column]]]...
+
+Options:
+  -              read the text to edit from stdin
+  -R             open the files read-only
+  -d             compare two files side by side
+  -c <command>   run a : command once the files are loaded
+  --script <file>
+                 run the keys in <file>, or stdin for -, without a terminal
+  -h, --help     show this help
+  --version      show the version
+";
+
+// A file to open, and where to put the cursor in it.
+pub struct File {
+    pub name: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+// What the editor was asked to do when started.
+#[derive(Default)]
+pub struct Options {
+    pub files: Vec<File>,
+    pub read_only: bool,
+    pub read_stdin: bool,
+    // Whether to compare the two files rather than edit them one by one.
+    pub diff: bool,
+    pub commands: Vec<String>,
+    pub script: Option<String>,
+}
+
+pub enum Invocation {
+    Edit(Options),
+    Help,
+    Version,
+}
+
+// Makes sense of the arguments after the program name. `+line` applies to
+// the first file, like in vim, and `+` on its own means its last line.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    let mut options = Options::default();
+    let mut line = None;
+    let mut args = args.into_iter();
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        if only_files {
+            options.files.push(file(arg));
+            continue;
+        }
+        match arg.as_str() {
+            "--" => only_files = true,
+            "-" => options.read_stdin = true,
+            "-R" => options.read_only = true,
+            "-d" => options.diff = true,
+            "-c" => match args.next() {
+                Some(command) => options.commands.push(command),
+                None => return Err(String::from("-c needs a command")),
+            },
+            "--script" => match args.next() {
+                Some(script) => options.script = Some(script),
+                None => return Err(String::from("--script needs a file")),
+            },
+            "-h" | "--help" => return Ok(Invocation::Help),
+            "--version" => return Ok(Invocation::Version),
+            "+" => line = Some(usize::MAX),
+            _ => {
+                if let Some(number) = arg.strip_prefix('+') {
+                    match number.parse::<usize>() {
+                        Ok(number) => line = Some(number),
+                        Err(_) => return Err(format!("Invalid line number: {arg}")),
+                    }
+                } else if arg.starts_with('-') {
+                    return Err(format!("Unknown option: {arg}"));
+                } else {
+                    options.files.push(file(arg));
+                }
+            }
+        }
+    }
+    if options.diff && options.files.len() != 2 {
+        return Err(String::from("-d needs two files"));
+    }
+    if let Some(first) = options.files.first_mut() {
+        first.line = line.or(first.line);
+    }
+    Ok(Invocation::Edit(options))
+}
+
+// The keys in a `--script` file, written like key mappings, with line
+// breaks as Enter.
+pub fn read_script(path: &str) -> Result<Vec<Key>, String> {
+    let text = if path == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    };
+    text.map(|text| keymap::parse_keys(&text))
+        .map_err(|error| format!("Cannot read {path}: {error}"))
+}
+
+// Reads `name:line` and `name:line:column`, as printed by compilers and
+// grep, unless a file really has that name.
+fn file(arg: String) -> File {
+    if !Path::new(&arg).exists() {
+        if let Some((rest, last)) = arg.rsplit_once(':') {
+            if let Ok(last) = last.parse() {
+                let line = rest
+                    .rsplit_once(':')
+                    .and_then(|(name, line)| Some((name, line.parse().ok()?)));
+                if let Some((name, line)) = line.filter(|(name, _)| !name.is_empty()) {
+                    return File {
+                        name: name.to_string(),
+                        line: Some(line),
+                        column: Some(last),
+                    };
+                }
+                if !rest.is_empty() {
+                    return File {
+                        name: rest.to_string(),
+                        line: Some(last),
+                        column: None,
+                    };
+                }
+            }
+        }
+    }
+    File {
+        name: arg,
+        line: None,
+        column: None,
+    }
+}
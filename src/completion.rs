@@ -0,0 +1,145 @@
+use crate::motion::CharClass;
+use crate::Document;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+// Something that knows what the word being typed could be.
+pub trait Source {
+    fn complete(&self, word: &str) -> Vec<String>;
+}
+
+// A fixed list of names, like commands or options.
+pub struct Words(pub Vec<&'static str>);
+
+impl Source for Words {
+    fn complete(&self, word: &str) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .map(|name| (*name).to_string())
+            .collect()
+    }
+}
+
+// The words in documents, for completing words in the text, in the order
+// they first appear.
+pub struct BufferWords<'a>(pub Vec<&'a Document>);
+
+impl Source for BufferWords<'_> {
+    fn complete(&self, word: &str) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut words = Vec::new();
+        for document in &self.0 {
+            for row in (0..document.len()).filter_map(|y| document.row(y)) {
+                let row_words = row
+                    .as_str()
+                    .split(|c| CharClass::of(c) != CharClass::Word)
+                    .filter(|candidate| candidate.starts_with(word) && *candidate != word);
+                for candidate in row_words {
+                    if seen.insert(candidate) {
+                        words.push(candidate.to_string());
+                    }
+                }
+            }
+        }
+        words
+    }
+}
+
+// Files and directories, relative to the working directory. Directories get a
+// trailing `/` so completing can carry on inside them, and hidden files are
+// only offered once a `.` has been typed.
+pub struct Paths;
+
+impl Source for Paths {
+    fn complete(&self, word: &str) -> Vec<String> {
+        let (dir, prefix) = match word.rfind('/') {
+            Some(slash) => word.split_at(slash.saturating_add(1)),
+            None => ("", word),
+        };
+        let Ok(entries) = fs::read_dir(if dir.is_empty() {
+            Path::new(".")
+        } else {
+            Path::new(dir)
+        }) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.'))
+                {
+                    return None;
+                }
+                let slash = if entry.path().is_dir() { "/" } else { "" };
+                Some(format!("{dir}{name}{slash}"))
+            })
+            .collect()
+    }
+}
+
+// Tab completion of the last word on the command line. Each Tab fills in the
+// next candidate, and going past the last one brings back what was typed.
+pub struct Completion {
+    // The command line before the word being completed.
+    head: String,
+    typed: String,
+    candidates: Vec<String>,
+    selected: Option<usize>,
+}
+
+impl Completion {
+    pub fn new(line: &str, word_start: usize, source: &dyn Source) -> Option<Self> {
+        let (head, typed) = line.split_at(word_start.min(line.len()));
+        let mut candidates = source.complete(typed);
+        if candidates.is_empty() {
+            return None;
+        }
+        candidates.sort();
+        candidates.dedup();
+        Some(Self {
+            head: head.to_string(),
+            typed: typed.to_string(),
+            candidates,
+            selected: None,
+        })
+    }
+
+    // Selects the next candidate and returns the command line with it.
+    pub fn next_candidate(&mut self) -> String {
+        self.selected = match self.selected {
+            None => Some(0),
+            Some(index) if index.saturating_add(1) < self.candidates.len() => {
+                Some(index.saturating_add(1))
+            }
+            Some(_) => None,
+        };
+        self.line()
+    }
+
+    pub fn previous_candidate(&mut self) -> String {
+        self.selected = match self.selected {
+            None => self.candidates.len().checked_sub(1),
+            Some(index) => index.checked_sub(1),
+        };
+        self.line()
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    fn line(&self) -> String {
+        let word = self
+            .selected
+            .and_then(|index| self.candidates.get(index))
+            .unwrap_or(&self.typed);
+        format!("{}{}", self.head, word)
+    }
+}
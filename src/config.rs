@@ -0,0 +1,57 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Where user configuration lives: `$XDG_CONFIG_HOME/ggedit`, falling back to
+// `~/.config/ggedit`.
+pub fn dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("ggedit"))
+}
+
+// The `[name]` table of `config.toml`, or `None` when there is no such file
+// or table.
+pub fn section(name: &str) -> Result<Option<toml::Table>, String> {
+    section_in(&read(), name)
+}
+
+// The `[name]` table of `contents`, written like `config.toml`.
+pub fn section_in(contents: &str, name: &str) -> Result<Option<toml::Table>, String> {
+    match parse(contents)?.remove(name) {
+        None => Ok(None),
+        Some(toml::Value::Table(table)) => Ok(Some(table)),
+        Some(_) => Err(format!("{name} must be a table")),
+    }
+}
+
+// The `[[name]]` tables of `config.toml`, in the order they are written.
+pub fn tables(name: &str) -> Result<Vec<toml::Table>, String> {
+    let invalid = || format!("{name} must be a list of [[{name}]] tables");
+    match parse(&read())?.remove(name) {
+        None => Ok(Vec::new()),
+        Some(toml::Value::Array(values)) => values
+            .into_iter()
+            .map(|value| match value {
+                toml::Value::Table(table) => Ok(table),
+                _ => Err(invalid()),
+            })
+            .collect(),
+        Some(_) => Err(invalid()),
+    }
+}
+
+// The contents of `config.toml`, empty when it can't be read.
+fn read() -> String {
+    dir()
+        .and_then(|dir| fs::read_to_string(dir.join("config.toml")).ok())
+        .unwrap_or_default()
+}
+
+fn parse(contents: &str) -> Result<toml::Table, String> {
+    contents
+        .parse()
+        .map_err(|error: toml::de::Error| format!("Invalid config.toml: {}", error.message()))
+}
@@ -0,0 +1,156 @@
+// Past this many differing lines the diff stops looking for the shortest
+// edit and calls everything between the common start and end changed, which
+// keeps its memory small.
+const MAX_EDITS: usize = 300;
+
+// A run of changed lines: `old_len` lines from `old_start` were replaced by
+// `new_len` lines from `new_start`, counting from zero.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+// The hunks that turn `old` into `new`, in order. The lines both start and
+// end with are set aside before Myers' algorithm runs on the rest.
+pub fn hunks(old: &[&str], new: &[&str]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let old_rest = old.get(prefix..).unwrap_or_default();
+    let new_rest = new.get(prefix..).unwrap_or_default();
+    let suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = old_rest
+        .get(..old_rest.len().saturating_sub(suffix))
+        .unwrap_or_default();
+    let new_middle = new_rest
+        .get(..new_rest.len().saturating_sub(suffix))
+        .unwrap_or_default();
+    let ops = edit_script(old_middle, new_middle).unwrap_or_else(|| {
+        let removed = std::iter::repeat_n(Op::Removed, old_middle.len());
+        removed
+            .chain(std::iter::repeat_n(Op::Added, new_middle.len()))
+            .collect()
+    });
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut old_line, mut new_line) = (prefix, prefix);
+    let mut in_hunk = false;
+    for op in ops {
+        if op == Op::Same {
+            in_hunk = false;
+            old_line = old_line.saturating_add(1);
+            new_line = new_line.saturating_add(1);
+            continue;
+        }
+        if !in_hunk {
+            hunks.push(Hunk {
+                old_start: old_line,
+                old_len: 0,
+                new_start: new_line,
+                new_len: 0,
+            });
+            in_hunk = true;
+        }
+        let Some(hunk) = hunks.last_mut() else {
+            continue;
+        };
+        if op == Op::Removed {
+            hunk.old_len = hunk.old_len.saturating_add(1);
+            old_line = old_line.saturating_add(1);
+        } else {
+            hunk.new_len = hunk.new_len.saturating_add(1);
+            new_line = new_line.saturating_add(1);
+        }
+    }
+    hunks
+}
+
+// The shortest list of steps from `old` to `new`, or `None` when it would
+// take more than `MAX_EDITS`.
+fn edit_script(old: &[&str], new: &[&str]) -> Option<Vec<Op>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let limit = old.len().saturating_add(new.len()).min(MAX_EDITS) as isize;
+    // `v` holds the furthest `x` reached on each diagonal `k = x - y`, and
+    // `trace` a copy of it from before each round, to walk back through.
+    let index = |k: isize| (k.saturating_add(limit).saturating_add(1)) as usize;
+    let mut v = vec![0_isize; index(limit).saturating_add(2)];
+    let get = |v: &[isize], k: isize| v.get(index(k)).copied().unwrap_or(0);
+    // Whether diagonal `k` is reached from the one above, by an added line,
+    // rather than from the one below, by a removed line.
+    let from_above = |v: &[isize], k: isize, d: isize| {
+        k == -d || (k != d && get(v, k.saturating_sub(1)) < get(v, k.saturating_add(1)))
+    };
+    let mut trace = Vec::new();
+    let mut done = false;
+    for d in 0..=limit {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if from_above(&v, k, d) {
+                get(&v, k.saturating_add(1))
+            } else {
+                get(&v, k.saturating_sub(1)).saturating_add(1)
+            };
+            let mut y = x.saturating_sub(k);
+            while x < n && y < m && old.get(x as usize) == new.get(y as usize) {
+                x = x.saturating_add(1);
+                y = y.saturating_add(1);
+            }
+            if let Some(slot) = v.get_mut(index(k)) {
+                *slot = x;
+            }
+            if x >= n && y >= m {
+                done = true;
+                break;
+            }
+        }
+        if done {
+            break;
+        }
+    }
+    if !done {
+        return None;
+    }
+
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x.saturating_sub(y);
+        let previous_k = if from_above(v, k, d) {
+            k.saturating_add(1)
+        } else {
+            k.saturating_sub(1)
+        };
+        let previous_x = get(v, previous_k);
+        let previous_y = previous_x.saturating_sub(previous_k);
+        while x > previous_x && y > previous_y {
+            ops.push(Op::Same);
+            x = x.saturating_sub(1);
+            y = y.saturating_sub(1);
+        }
+        if d > 0 {
+            ops.push(if x == previous_x {
+                Op::Added
+            } else {
+                Op::Removed
+            });
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    ops.reverse();
+    Some(ops)
+}
@@ -0,0 +1,175 @@
+use crate::diff::{self, Hunk};
+use crate::{Document, Row};
+
+// A line of the side by side view: a row of the buffer on the left and a
+// row of the other file on the right. Where one side has lines the other
+// doesn't, the other side is missing. `changed` is set within the
+// differences.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pair {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub changed: bool,
+}
+
+// The buffer compared with another file, as `ggedit -d` and `:diffsplit`
+// show them. The hunks turn the other file into the buffer, so the buffer
+// is their new side.
+pub struct DiffView {
+    pub other: Document,
+    // The buffer being compared, which the view goes away without.
+    pub file_name: Option<String>,
+    // The revisions of the buffer and the other file the hunks are for.
+    revisions: Option<(u64, u64)>,
+    hunks: Vec<Hunk>,
+    pairs: Vec<Pair>,
+    // The pair each row of the buffer is in.
+    rows: Vec<usize>,
+}
+
+impl DiffView {
+    pub fn new(file_name: Option<String>, other: Document) -> Self {
+        Self {
+            other,
+            file_name,
+            revisions: None,
+            hunks: Vec::new(),
+            pairs: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    // Compares `document` with the other file again if either changed.
+    pub fn update(&mut self, document: &Document) {
+        let revisions = (document.revision(), self.other.revision());
+        if self.revisions == Some(revisions) {
+            return;
+        }
+        self.revisions = Some(revisions);
+        let left = lines(document);
+        let right = lines(&self.other);
+        self.hunks = diff::hunks(&right, &left);
+        self.pairs.clear();
+        let (mut y, mut other_y) = (0, 0);
+        for hunk in &self.hunks {
+            while y < hunk.new_start {
+                self.pairs.push(Pair {
+                    left: Some(y),
+                    right: Some(other_y),
+                    changed: false,
+                });
+                y = y.saturating_add(1);
+                other_y = other_y.saturating_add(1);
+            }
+            for index in 0..hunk.new_len.max(hunk.old_len) {
+                self.pairs.push(Pair {
+                    left: (index < hunk.new_len).then(|| hunk.new_start.saturating_add(index)),
+                    right: (index < hunk.old_len).then(|| hunk.old_start.saturating_add(index)),
+                    changed: true,
+                });
+            }
+            y = hunk.new_start.saturating_add(hunk.new_len);
+            other_y = hunk.old_start.saturating_add(hunk.old_len);
+        }
+        while y < left.len() || other_y < right.len() {
+            self.pairs.push(Pair {
+                left: (y < left.len()).then_some(y),
+                right: (other_y < right.len()).then_some(other_y),
+                changed: false,
+            });
+            y = y.saturating_add(1);
+            other_y = other_y.saturating_add(1);
+        }
+        self.rows = self
+            .pairs
+            .iter()
+            .enumerate()
+            .filter(|(_, pair)| pair.left.is_some())
+            .map(|(index, _)| index)
+            .collect();
+    }
+
+    pub fn pairs(&self) -> &[Pair] {
+        &self.pairs
+    }
+
+    // The pair row `y` of the buffer is in. Rows past the end, which are
+    // there before the view catches up with an edit, follow the last one.
+    pub fn pair_of(&self, y: usize) -> usize {
+        match self.rows.get(y) {
+            Some(index) => *index,
+            None => self
+                .pairs
+                .len()
+                .saturating_add(y.saturating_sub(self.rows.len())),
+        }
+    }
+
+    // The pair at the top of the view when the buffer is scrolled to row
+    // `y`, so that the lines only the other file has right above it show.
+    pub fn first_pair(&self, y: usize) -> usize {
+        match y.checked_sub(1) {
+            Some(above) => self.pair_of(above).saturating_add(1),
+            None => 0,
+        }
+    }
+
+    pub fn pair_at(&self, y: usize) -> Option<&Pair> {
+        self.pairs.get(self.pair_of(y))
+    }
+
+    // The difference row `y` of the buffer is in or, where the buffer lacks
+    // lines, right under.
+    pub fn hunk_at(&self, y: usize) -> Option<Hunk> {
+        self.hunks
+            .iter()
+            .find(|hunk| {
+                let (first, last) = self.span(hunk);
+                (first..=last).contains(&y)
+            })
+            .copied()
+    }
+
+    // The first row of the next difference after row `y`, or of the one
+    // before it when going backward.
+    pub fn next_hunk(&self, y: usize, forward: bool) -> Option<usize> {
+        let mut spans = self.hunks.iter().map(|hunk| self.span(hunk));
+        if forward {
+            spans.find(|(first, _)| *first > y).map(|(first, _)| first)
+        } else {
+            spans
+                .rev()
+                .find(|(_, last)| *last < y)
+                .map(|(first, _)| first)
+        }
+    }
+
+    // The first and last rows of the buffer `hunk` covers. Where the buffer
+    // only lacks lines, the row under the gap stands for it, or the last
+    // row at the end.
+    fn span(&self, hunk: &Hunk) -> (usize, usize) {
+        let last_row = self.rows.len().saturating_sub(1);
+        let first = hunk.new_start.min(last_row);
+        let last = hunk
+            .new_start
+            .saturating_add(hunk.new_len)
+            .saturating_sub(1)
+            .max(first);
+        (first, last)
+    }
+
+    // The lines of the other file in `hunk`.
+    pub fn other_lines(&self, hunk: &Hunk) -> Vec<String> {
+        (hunk.old_start..hunk.old_start.saturating_add(hunk.old_len))
+            .filter_map(|y| self.other.row(y))
+            .map(|row| row.as_str().to_string())
+            .collect()
+    }
+}
+
+fn lines(document: &Document) -> Vec<&str> {
+    (0..document.len())
+        .filter_map(|y| document.row(y))
+        .map(Row::as_str)
+        .collect()
+}
@@ -0,0 +1,129 @@
+// Accented letters are typed as the letter followed by `'` (acute), `!`
+// (grave), `>` (circumflex), `?` (tilde) or `:` (diaeresis); a space marks
+// the ones that don't exist.
+const ACCENTS: [char; 5] = ['\'', '!', '>', '?', ':'];
+const ACCENTED: &[(char, &str)] = &[
+    ('a', "áàâãä"),
+    ('e', "éèê ë"),
+    ('i', "íìî ï"),
+    ('o', "óòôõö"),
+    ('u', "úùû ü"),
+    ('y', "ý   ÿ"),
+    ('n', "   ñ "),
+    ('A', "ÁÀÂÃÄ"),
+    ('E', "ÉÈÊ Ë"),
+    ('I', "ÍÌÎ Ï"),
+    ('O', "ÓÒÔÕÖ"),
+    ('U', "ÚÙÛ Ü"),
+    ('Y', "Ý    "),
+    ('N', "   Ñ "),
+];
+
+// The rest, mostly as vim has them.
+const DIGRAPHS: &[(&str, char)] = &[
+    ("aa", 'å'),
+    ("AA", 'Å'),
+    ("ae", 'æ'),
+    ("AE", 'Æ'),
+    ("o/", 'ø'),
+    ("O/", 'Ø'),
+    ("c,", 'ç'),
+    ("C,", 'Ç'),
+    ("ss", 'ß'),
+    ("!I", '¡'),
+    ("?I", '¿'),
+    ("<<", '«'),
+    (">>", '»'),
+    ("\"6", '“'),
+    ("\"9", '”'),
+    ("'6", '‘'),
+    ("'9", '’'),
+    (",.", '…'),
+    ("-N", '–'),
+    ("-M", '—'),
+    (".M", '·'),
+    ("Sb", '•'),
+    ("SE", '§'),
+    ("PI", '¶'),
+    ("Co", '©'),
+    ("Rg", '®'),
+    ("TM", '™'),
+    ("Eu", '€'),
+    ("Pd", '£'),
+    ("Ye", '¥'),
+    ("Ct", '¢'),
+    ("DG", '°'),
+    ("My", 'µ'),
+    ("+-", '±'),
+    ("*X", '×'),
+    ("-:", '÷'),
+    ("!=", '≠'),
+    ("=<", '≤'),
+    (">=", '≥'),
+    ("?2", '≈'),
+    ("00", '∞'),
+    ("RT", '√'),
+    ("12", '½'),
+    ("14", '¼'),
+    ("34", '¾'),
+    ("1S", '¹'),
+    ("2S", '²'),
+    ("3S", '³'),
+    ("->", '→'),
+    ("<-", '←'),
+    ("-!", '↑'),
+    ("-v", '↓'),
+    ("=>", '⇒'),
+    ("OK", '✓'),
+    ("XX", '✗'),
+    ("a*", 'α'),
+    ("b*", 'β'),
+    ("g*", 'γ'),
+    ("d*", 'δ'),
+    ("e*", 'ε'),
+    ("l*", 'λ'),
+    ("m*", 'μ'),
+    ("p*", 'π'),
+    ("s*", 'σ'),
+    ("D*", 'Δ'),
+    ("S*", 'Σ'),
+    ("W*", 'Ω'),
+    ("NS", '\u{a0}'),
+];
+
+// The character typed with Ctrl-K and `first` and `second`, which can also
+// come the other way around.
+pub fn lookup(first: char, second: char) -> Option<char> {
+    find(first, second).or_else(|| find(second, first))
+}
+
+fn find(first: char, second: char) -> Option<char> {
+    if let Some(accent) = ACCENTS.iter().position(|&accent| accent == second) {
+        let accented = ACCENTED
+            .iter()
+            .find(|(letter, _)| *letter == first)
+            .and_then(|(_, accented)| accented.chars().nth(accent))
+            .filter(|&c| c != ' ');
+        if accented.is_some() {
+            return accented;
+        }
+    }
+    DIGRAPHS
+        .iter()
+        .find(|(keys, _)| keys.chars().eq([first, second]))
+        .map(|(_, c)| *c)
+}
+
+// Every digraph with what it types, for `:digraphs`.
+pub fn list() -> Vec<(String, char)> {
+    let mut digraphs = Vec::new();
+    for (letter, accented) in ACCENTED {
+        for (accent, c) in ACCENTS.iter().zip(accented.chars()) {
+            if c != ' ' {
+                digraphs.push((format!("{letter}{accent}"), c));
+            }
+        }
+    }
+    digraphs.extend(DIGRAPHS.iter().map(|(keys, c)| ((*keys).to_string(), *c)));
+    digraphs
+}
@@ -1,23 +1,112 @@
+use crate::filetype::Indentation;
+use crate::hex;
+use crate::highlighting::Context;
+use crate::json::Value;
+use crate::loader;
+use crate::undo::{self, Edit, UndoTree};
+use crate::FileEncoding;
+use crate::FileFormat;
 use crate::FileType;
+use crate::HighlightingOptions;
+use crate::IndentOptions;
+use crate::LineRange;
 use crate::Position;
+use crate::Rope;
 use crate::Row;
 use crate::SearchDirection;
+use crate::Settings;
+use regex::Regex;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::io::{Error, Write};
+use std::io::{BufWriter, Error, ErrorKind, Read, Write};
+use std::iter;
+use std::mem;
+use std::os::unix;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use unicode_segmentation::UnicodeSegmentation;
 
-#[derive(Default)]
 pub struct Document {
-    rows: Vec<Row>,
+    rows: Rope,
     pub file_name: Option<String>,
     dirty: bool,
     file_type: FileType,
+    // The characters that make the line after the one they end indent a
+    // level deeper, when set over the file type's own.
+    indent_after: Option<String>,
+    file_format: FileFormat,
+    file_encoding: FileEncoding,
+    end_of_line: bool,
+    // Bytes read so far and in total while the file is streamed in.
+    loading: Option<(u64, u64)>,
+    highlight_pattern: Option<Regex>,
+    highlight_generation: u64,
+    // Rows before this one are known to be highlighted in the right context.
+    stale_from: usize,
+    history: UndoTree,
+    marks: HashMap<char, Position>,
+    // When the file was last modified as far as we know, to notice other
+    // programs changing it.
+    disk_modified: Option<SystemTime>,
+    // Edits are refused while set, and `refused_edit` notes that one was
+    // tried so the editor can say why nothing happened.
+    read_only: bool,
+    refused_edit: bool,
+    // Set for the listing of a directory, which is browsed rather than
+    // edited.
+    directory: bool,
+    // Set for the `:help` buffer.
+    help: bool,
+    // Set while `:preview` shows the Markdown rendered rather than as
+    // written.
+    preview: bool,
+    // Set while the rows are the bytes of the file as `:hex` shows them,
+    // which are what gets written.
+    hex: bool,
+    // Changes whenever the text does, for what is worked out from it, like
+    // the git gutter. No two documents share one.
+    revision: u64,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            rows: Rope::default(),
+            file_name: None,
+            dirty: false,
+            file_type: FileType::default(),
+            file_format: FileFormat::default(),
+            file_encoding: FileEncoding::default(),
+            end_of_line: true,
+            loading: None,
+            highlight_pattern: None,
+            highlight_generation: 0,
+            stale_from: 0,
+            history: UndoTree::default(),
+            marks: HashMap::new(),
+            disk_modified: None,
+            read_only: false,
+            refused_edit: false,
+            directory: false,
+            help: false,
+            preview: false,
+            hex: false,
+            indent_after: None,
+            revision: next_revision(),
+        }
+    }
 }
 
 // open with overriden file_name
 impl From<&str> for Document {
     fn from(s: &str) -> Self {
         Self {
-            rows: Vec::new(),
+            rows: Rope::default(),
             file_name: if s.is_empty() {
                 Default::default()
             } else {
@@ -25,29 +114,165 @@ impl From<&str> for Document {
             },
             dirty: true,
             file_type: FileType::from(s),
+            file_format: FileFormat::default(),
+            file_encoding: FileEncoding::default(),
+            end_of_line: true,
+            loading: None,
+            highlight_pattern: None,
+            highlight_generation: 0,
+            stale_from: 0,
+            history: UndoTree::default(),
+            marks: HashMap::new(),
+            disk_modified: None,
+            read_only: false,
+            refused_edit: false,
+            directory: false,
+            help: false,
+            preview: false,
+            hex: false,
+            indent_after: None,
+            revision: next_revision(),
         }
     }
 }
 
 impl Document {
     pub fn open(filename: &str) -> Result<Self, std::io::Error> {
-        let contents = fs::read_to_string(filename)?;
-        let file_type = FileType::from(filename);
-        let mut rows = Vec::new();
-        for value in contents.lines() {
-            let mut row = Row::from(value);
-            row.highlight(file_type.highlight_options(), None);
-            rows.push(row);
+        // The file is decoded a chunk at a time straight into rows, which
+        // get highlighted once they are first drawn.
+        let read = |file_encoding: Option<FileEncoding>| {
+            let mut rows = Rope::default();
+            let decoded = loader::decode(fs::File::open(filename)?, file_encoding, |lines, _| {
+                lines
+                    .iter()
+                    .for_each(|line| rows.push(Row::from(line.as_str())));
+                Ok(())
+            })?;
+            Ok::<_, Error>((rows, decoded))
+        };
+        let (mut rows, mut decoded) = read(None)?;
+        if let Some(fallback) = decoded
+            .file_encoding
+            .fallback()
+            .filter(|_| decoded.replaced)
+        {
+            (rows, decoded) = read(Some(fallback))?;
         }
+        let file_type = FileType::from(filename);
         Ok(Self {
             rows,
             file_name: Some(filename.to_string()),
             dirty: false,
             file_type,
+            file_format: decoded.file_format,
+            file_encoding: decoded.file_encoding,
+            end_of_line: decoded.end_of_line,
+            loading: None,
+            highlight_pattern: None,
+            highlight_generation: 0,
+            stale_from: 0,
+            history: UndoTree::default(),
+            marks: HashMap::new(),
+            disk_modified: modified_time(filename),
+            read_only: !is_writable(filename),
+            refused_edit: false,
+            directory: false,
+            help: false,
+            preview: false,
+            hex: false,
+            indent_after: None,
+            revision: next_revision(),
         })
     }
 
-    pub fn save_as(&mut self, filename: Option<&&str>) -> Result<String, Error> {
+    // A document with no file name holding everything `reader` gives, like
+    // the output of a pipe. It counts as changed since it isn't saved
+    // anywhere.
+    pub fn read_from(mut reader: impl Read) -> Result<Self, std::io::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let (file_encoding, contents) = FileEncoding::decode(&bytes);
+        Ok(Self {
+            rows: contents.lines().map(Row::from).collect(),
+            dirty: true,
+            file_format: FileFormat::detect(&contents),
+            file_encoding,
+            end_of_line: contents.is_empty() || contents.ends_with('\n'),
+            ..Self::default()
+        })
+    }
+
+    // The listing of the directory `path`, one entry per row after `../`,
+    // which can't be edited.
+    pub fn directory(path: &str, entries: &[String]) -> Self {
+        Self {
+            rows: iter::once("../")
+                .chain(entries.iter().map(String::as_str))
+                .map(Row::from)
+                .collect(),
+            file_name: Some(path.to_string()),
+            disk_modified: modified_time(path),
+            read_only: true,
+            directory: true,
+            ..Self::default()
+        }
+    }
+
+    // The `:help` buffer, which has no file and can't be edited.
+    pub fn help(lines: &[String]) -> Self {
+        Self {
+            rows: lines.iter().map(|line| Row::from(line.as_str())).collect(),
+            read_only: true,
+            help: true,
+            ..Self::default()
+        }
+    }
+
+    // An empty document that rows get appended to as the file streams in.
+    pub fn loading(filename: &str, total_bytes: u64) -> Self {
+        Self {
+            file_name: Some(filename.to_string()),
+            file_type: FileType::from(filename),
+            loading: Some((0, total_bytes)),
+            disk_modified: modified_time(filename),
+            read_only: !is_writable(filename),
+            ..Self::default()
+        }
+    }
+
+    pub fn append_loaded(&mut self, lines: Vec<String>, bytes_read: u64) {
+        self.revision = next_revision();
+        for line in lines {
+            self.rows.push(Row::from(line.as_str()));
+        }
+        if let Some((read, _)) = &mut self.loading {
+            *read = bytes_read;
+        }
+    }
+
+    pub fn finish_loading(
+        &mut self,
+        file_encoding: FileEncoding,
+        file_format: FileFormat,
+        end_of_line: bool,
+    ) {
+        self.file_encoding = file_encoding;
+        self.file_format = file_format;
+        self.end_of_line = end_of_line;
+        self.loading = None;
+    }
+
+    // How far along streaming the file is, as a percentage.
+    pub fn loading_progress(&self) -> Option<u64> {
+        self.loading
+            .map(|(read, total)| read.saturating_mul(100).checked_div(total).unwrap_or(100))
+    }
+
+    pub fn save_as(
+        &mut self,
+        filename: Option<&&str>,
+        settings: &Settings,
+    ) -> Result<String, Error> {
         if self.is_empty() && !self.is_dirty() {
             return Err(Error::new(std::io::ErrorKind::Other, "Document is empty"));
         }
@@ -58,30 +283,30 @@ impl Document {
                     // if the file name is the same as the current file name
                     // then just save the file
                     if filename == file_name {
-                        self.dirty = false;
-                        self.save()
+                        let result = self.save(settings);
+                        if result.is_ok() {
+                            self.dirty = false;
+                        }
+                        result
                     } else {
                         self.file_name = Some(filename.to_string()); // change the file name TEMPORARILY
-                        match self.save() {
-                            // then save the file
-                            Ok(message) => {
-                                self.file_name = prev_name; // change the file name back
-                                Ok(message)
-                            }
-                            Err(e) => {
-                                self.file_name = prev_name; // change the file name back
-                                Err(e)
-                            }
-                        }
+                        let disk_modified = self.disk_modified;
+                        let result = self.save(settings);
+                        self.file_name = prev_name; // change the file name back
+                        self.disk_modified = disk_modified;
+                        result
                     }
                 } else {
                     self.file_name = Some(filename.to_string());
-                    self.dirty = false;
-                    self.save()
+                    let result = self.save(settings);
+                    if result.is_ok() {
+                        self.dirty = false;
+                    }
+                    result
                 }
             }
 
-            None => match self.save() {
+            None => match self.save(settings) {
                 Ok(message) => {
                     self.dirty = false;
                     Ok(message)
@@ -91,20 +316,54 @@ impl Document {
         }
     }
 
-    pub fn save(&mut self) -> Result<String, Error> {
-        if let Some(file_name) = &self.file_name {
-            let mut file = fs::File::create(file_name)?;
-            self.file_type = FileType::from(file_name.as_ref());
-            for row in &mut self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-                row.highlight(self.file_type.highlight_options(), None);
+    pub fn save(&mut self, settings: &Settings) -> Result<String, Error> {
+        if self.loading.is_some() {
+            return Err(Error::other("File is still loading"));
+        }
+        if let Some(file_name) = self.file_name.clone() {
+            // Rows only need highlighting again when saving under a new
+            // extension changes the file type.
+            let file_type = FileType::from(file_name.as_str());
+            if file_type.name() != self.file_type.name() {
+                self.file_type = file_type;
+                self.highlight_generation = self.highlight_generation.wrapping_add(1);
+                for row in self.rows.iter_mut() {
+                    row.invalidate_highlighting();
+                }
+                self.stale_from = 0;
+            }
+            if settings.trimtrailingwhitespace {
+                self.trim_trailing_whitespace();
+            }
+            if settings.backup {
+                write_backup(&file_name, settings)?;
+            }
+            // The new contents are written next to the file and renamed over
+            // it once they are safely on disk, so a crash or a full disk
+            // can't leave it half written. Symlinks are followed so the link
+            // itself isn't replaced.
+            let target = fs::canonicalize(&file_name).unwrap_or_else(|_| PathBuf::from(&file_name));
+            let temporary = temporary_path(&target);
+            let size = self
+                .write_to(&temporary)
+                .and_then(|size| {
+                    copy_permissions(&target, &temporary);
+                    fs::rename(&temporary, &target)?;
+                    Ok(size)
+                })
+                .inspect_err(|_| {
+                    let _ = fs::remove_file(&temporary);
+                })?;
+            self.disk_modified = modified_time(&file_name);
+            // The file is saved even when its history can't be.
+            if settings.undofile {
+                let _ = self.write_undo();
             }
             Ok(format!(
                 "\"{}\" {}L, {}B written",
                 file_name,
                 self.len(),
-                self.size_in_bytes()
+                size
             ))
         } else {
             Err(Error::new(
@@ -114,11 +373,134 @@ impl Document {
         }
     }
 
+    // Strips whitespace from the ends of lines, as one undoable change.
+    fn trim_trailing_whitespace(&mut self) {
+        for y in 0..self.rows.len() {
+            let before = self.snapshot(y, 1);
+            let Some(line) = before.first() else {
+                continue;
+            };
+            let trimmed = line.trim_end();
+            if trimmed.len() < line.len() {
+                let after = vec![trimmed.to_string()];
+                self.dirty = true;
+                self.replace_rows(y, 1, &after);
+                self.record(&Position { x: 0, y }, before, 1);
+            }
+        }
+    }
+
+    // Writes the rows to a new file at `path`, returning the number of bytes
+    // written once they have reached the disk. In hex they are the bytes
+    // themselves.
+    fn write_to(&self, path: &Path) -> Result<usize, Error> {
+        self.write_file(fs::File::create(path)?)
+    }
+
+    fn write_file(&self, file: fs::File) -> Result<usize, Error> {
+        let mut file = BufWriter::new(file);
+        let size = if self.hex {
+            let bytes = hex::parse(self.rows.iter().map(Row::as_str))
+                .map_err(|message| Error::new(ErrorKind::InvalidData, message))?;
+            file.write_all(&bytes)?;
+            bytes.len()
+        } else {
+            self.write_text(&mut file)?
+        };
+        file.into_inner()
+            .map_err(|error| error.into_error())?
+            .sync_all()?;
+        Ok(size)
+    }
+
+    // Writes the rows in the file's encoding and line endings, returning
+    // the number of bytes written.
+    fn write_text(&self, writer: &mut impl Write) -> Result<usize, Error> {
+        let mut size = self.file_encoding.write_bom(writer)?;
+        let line_ending = self.file_format.line_ending();
+        let last_row = self.rows.len().saturating_sub(1);
+        for (y, row) in self.rows.iter().enumerate() {
+            size = size.saturating_add(self.file_encoding.write(writer, row.as_str())?);
+            if y < last_row || self.end_of_line {
+                size = size.saturating_add(self.file_encoding.write(writer, line_ending)?);
+            }
+        }
+        Ok(size)
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.hex
+    }
+
+    // Shows the bytes of the file in hex. While the buffer has no changes
+    // they are read from the disk, so they are exactly what the file holds
+    // whatever it was decoded as.
+    pub fn show_hex(&mut self) -> Result<(), String> {
+        if self.loading.is_some() {
+            return Err(String::from("File is still loading"));
+        }
+        let bytes = match self.file_name.as_deref().filter(|_| !self.dirty) {
+            Some(file_name) => fs::read(file_name)
+                .map_err(|error| format!("Error reading {file_name}: {error}"))?,
+            None => {
+                let mut bytes = Vec::new();
+                self.write_text(&mut bytes)
+                    .map_err(|error| error.to_string())?;
+                bytes
+            }
+        };
+        self.set_lines(&hex::dump(&bytes));
+        self.hex = true;
+        Ok(())
+    }
+
+    // Turns the bytes back into text, decoding them afresh as when the file
+    // was opened.
+    pub fn show_text(&mut self) -> Result<(), String> {
+        let bytes = hex::parse(self.rows.iter().map(Row::as_str))?;
+        let (file_encoding, contents) = FileEncoding::decode(&bytes);
+        self.file_encoding = file_encoding;
+        self.file_format = FileFormat::detect(&contents);
+        self.end_of_line = contents.is_empty() || contents.ends_with('\n');
+        let lines: Vec<String> = contents.lines().map(String::from).collect();
+        self.set_lines(&lines);
+        self.hex = false;
+        Ok(())
+    }
+
+    // Swaps every row for `lines`. The history goes, since undoing across
+    // the swap would mix up bytes and text.
+    fn set_lines(&mut self, lines: &[String]) {
+        self.rows = lines.iter().map(|line| Row::from(line.as_str())).collect();
+        self.history = UndoTree::default();
+        self.highlight_generation = self.highlight_generation.wrapping_add(1);
+        self.stale_from = 0;
+        self.invalidate_from(0);
+    }
+
+    // Whether the file was modified since it was opened, saved or last
+    // checked.
+    pub fn changed_on_disk(&mut self) -> bool {
+        let Some(file_name) = &self.file_name else {
+            return false;
+        };
+        let modified = modified_time(file_name);
+        if modified.is_none() || modified == self.disk_modified {
+            return false;
+        }
+        let known = self.disk_modified.is_some();
+        self.disk_modified = modified;
+        known
+    }
+
     pub fn row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
     }
 
     pub fn insert(&mut self, at: &Position, c: char) {
+        if self.refuses_edit() {
+            return;
+        }
         if at.y > self.rows.len() {
             return;
         }
@@ -130,61 +512,648 @@ impl Document {
         if at.y == self.rows.len() {
             let mut row = Row::default();
             row.insert(0, c);
-            row.highlight(self.file_type.highlight_options(), None);
             self.rows.push(row);
+            self.record(at, Vec::new(), 1);
         } else {
+            let before = self.snapshot(at.y, 1);
             #[allow(clippy::indexing_slicing)]
             let row = &mut self.rows[at.y];
             row.insert(at.x, c);
-            row.highlight(self.file_type.highlight_options(), None);
+            self.record(at, before, 1);
+        }
+    }
+
+    pub fn replace(&mut self, at: &Position, grapheme: &str) -> Option<String> {
+        if self.refuses_edit() {
+            return None;
+        }
+        if at.y > self.rows.len() {
+            return None;
+        }
+        self.dirty = true;
+        if at.y == self.rows.len() {
+            self.insert_text(at, grapheme);
+            return None;
         }
+        let before = self.snapshot(at.y, 1);
+        #[allow(clippy::indexing_slicing)]
+        let row = &mut self.rows[at.y];
+        let replaced = row.replace(at.x, grapheme);
+        self.record(at, before, 1);
+        replaced
     }
 
     pub fn delete(&mut self, at: &Position) {
+        if self.refuses_edit() {
+            return;
+        }
         let len = self.rows.len();
         if at.y >= len {
             return;
         }
         self.dirty = true;
         if at.x == self.rows.get_mut(at.y).unwrap().len() && at.y + 1 < len {
-            let next_row = self.rows.remove(at.y + 1);
+            let before = self.snapshot(at.y, 2);
+            let next_row = self.rows.remove(at.y + 1).unwrap_or_default();
+            self.shift_marks(at.y + 1, 1, 0);
             let row = &mut self.rows[at.y];
             row.append(&next_row);
-            row.highlight(self.file_type.highlight_options(), None)
+            self.record(at, before, 1);
         } else {
+            let before = self.snapshot(at.y, 1);
             let row = &mut self.rows[at.y];
             row.delete(at.x);
-            row.highlight(self.file_type.highlight_options(), None)
+            self.record(at, before, 1);
         }
     }
 
     pub fn delete_line(&mut self, at: &Position) {
-        if at.y >= self.rows.len() {
+        self.delete_lines(at.y, 1);
+    }
+
+    // Removes up to `count` rows starting at `y`, returning their contents.
+    pub fn delete_lines(&mut self, y: usize, count: usize) -> Vec<String> {
+        if self.refuses_edit() {
+            return Vec::new();
+        }
+        if y >= self.rows.len() {
+            return Vec::new();
+        }
+        self.dirty = true;
+        let before = self.snapshot(y, count);
+        self.replace_rows(y, before.len(), &[]);
+        self.record(&Position { x: 0, y }, before.clone(), 0);
+        before
+    }
+
+    // Puts `lines` in place of up to `count` rows starting at `y`.
+    pub fn replace_lines(&mut self, y: usize, count: usize, lines: &[String]) {
+        if self.refuses_edit() {
+            return;
+        }
+        if y >= self.rows.len() {
+            return;
+        }
+        self.dirty = true;
+        let before = self.snapshot(y, count);
+        self.replace_rows(y, before.len(), lines);
+        self.record(&Position { x: 0, y }, before, lines.len());
+    }
+
+    // The text between `start` (inclusive) and `end` (exclusive), with rows
+    // joined by newlines.
+    pub fn text_range(&self, start: &Position, end: &Position) -> String {
+        let mut lines = Vec::new();
+        for y in start.y..=end.y {
+            let Some(row) = self.rows.get(y) else {
+                break;
+            };
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            lines.push(row.substring(from, to));
+        }
+        lines.join("\n")
+    }
+
+    pub fn delete_range(&mut self, start: &Position, end: &Position) -> String {
+        if self.refuses_edit() {
+            return String::new();
+        }
+        if start.y >= self.rows.len() || (start.y == end.y && start.x >= end.x) {
+            return String::new();
+        }
+        let end = if end.y >= self.rows.len() {
+            let y = self.rows.len().saturating_sub(1);
+            Position {
+                x: self.rows.get(y).map_or(0, Row::len),
+                y,
+            }
+        } else {
+            end.clone()
+        };
+        let text = self.text_range(start, &end);
+        self.dirty = true;
+        let before = self.snapshot(start.y, end.y.saturating_sub(start.y).saturating_add(1));
+        let head = self
+            .rows
+            .get(start.y)
+            .map_or_else(String::new, |row| row.substring(0, start.x));
+        let tail = self
+            .rows
+            .get(end.y)
+            .map_or_else(String::new, |row| row.substring(end.x, row.len()));
+        self.replace_rows(start.y, before.len(), &[format!("{head}{tail}")]);
+        self.record(start, before, 1);
+        text
+    }
+
+    pub fn indent_lines(&mut self, y: usize, count: usize, settings: &Settings) {
+        self.shift_lines(y, count, settings, true);
+    }
+
+    pub fn dedent_lines(&mut self, y: usize, count: usize, settings: &Settings) {
+        self.shift_lines(y, count, settings, false);
+    }
+
+    // Shifts lines by one shiftwidth, rebuilding their leading whitespace so
+    // mixed tabs and spaces come out consistent. Blank lines are not indented.
+    fn shift_lines(&mut self, y: usize, count: usize, settings: &Settings, indent: bool) {
+        if self.refuses_edit() {
+            return;
+        }
+        let before = self.snapshot(y, count);
+        let after: Vec<String> = before
+            .iter()
+            .map(|line| {
+                let body = line.trim_start_matches([' ', '\t']);
+                if body.is_empty() && indent {
+                    return line.clone();
+                }
+                let leading = &line[..line.len().saturating_sub(body.len())];
+                let width = settings.indent_width(leading);
+                let width = if indent {
+                    width.saturating_add(settings.shiftwidth)
+                } else {
+                    width.saturating_sub(settings.shiftwidth)
+                };
+                format!("{}{}", settings.indentation(width), body)
+            })
+            .collect();
+        if after == before {
+            return;
+        }
+        self.dirty = true;
+        self.replace_rows(y, before.len(), &after);
+        self.record(&Position { x: 0, y }, before, after.len());
+    }
+
+    // Comments out lines with the file type's line comment, or a block
+    // comment around each line when it has none, or uncomments them when
+    // every line that isn't blank already is. Markers go after the smallest
+    // indentation so they line up. Returns false when the file type has no
+    // comments.
+    pub fn toggle_comments(&mut self, y: usize, count: usize) -> bool {
+        let options = self.file_type.highlight_options();
+        let (open, close) = match (options.line_comment(), options.block_comment()) {
+            (Some(prefix), _) => (prefix.to_string(), String::new()),
+            (None, Some((open, close))) => (open.to_string(), close.to_string()),
+            (None, None) => return false,
+        };
+        if self.refuses_edit() {
+            return true;
+        }
+        let before = self.snapshot(y, count);
+        let is_blank = |line: &String| line.trim().is_empty();
+        let commented = before.iter().filter(|line| !is_blank(line)).all(|line| {
+            let body = line.trim();
+            body.starts_with(&open) && body.ends_with(&close)
+        });
+        let indent = before
+            .iter()
+            .filter(|line| !is_blank(line))
+            .map(|line| line.len().saturating_sub(line.trim_start().len()))
+            .min()
+            .unwrap_or(0);
+        let after: Vec<String> = before
+            .iter()
+            .map(|line| {
+                if is_blank(line) {
+                    return line.clone();
+                }
+                if commented {
+                    let start = line.len().saturating_sub(line.trim_start().len());
+                    let (leading, body) = line.split_at(start);
+                    let body = body.trim_end();
+                    let body = body.strip_prefix(open.as_str()).unwrap_or(body);
+                    let body = body.strip_suffix(close.as_str()).unwrap_or(body);
+                    let body = body.strip_prefix(' ').unwrap_or(body);
+                    let body = if close.is_empty() {
+                        body
+                    } else {
+                        body.strip_suffix(' ').unwrap_or(body)
+                    };
+                    format!("{leading}{body}")
+                } else {
+                    let (leading, body) = line.split_at(indent.min(line.len()));
+                    if close.is_empty() {
+                        format!("{leading}{open} {body}")
+                    } else {
+                        format!("{leading}{open} {body} {close}")
+                    }
+                }
+            })
+            .collect();
+        if after != before {
+            self.dirty = true;
+            self.replace_rows(y, before.len(), &after);
+            self.record(&Position { x: 0, y }, before, after.len());
+        }
+        true
+    }
+
+    pub fn insert_lines(&mut self, y: usize, lines: &[String]) {
+        if self.refuses_edit() {
+            return;
+        }
+        if y > self.rows.len() || lines.is_empty() {
+            return;
+        }
+        self.dirty = true;
+        self.replace_rows(y, 0, lines);
+        self.record(&Position { x: 0, y }, Vec::new(), lines.len());
+    }
+
+    // Inserts `text`, which may span several lines, at the given position.
+    pub fn insert_text(&mut self, at: &Position, text: &str) {
+        if self.refuses_edit() {
+            return;
+        }
+        if at.y > self.rows.len() || text.is_empty() {
             return;
         }
         self.dirty = true;
-        self.rows.remove(at.y);
+        let before = self.snapshot(at.y, 1);
+        let (head, tail) = match self.rows.get(at.y) {
+            Some(row) => {
+                let mut head = Row::from(row.as_str());
+                let tail = head.split(at.x);
+                (head.as_str().to_string(), tail.as_str().to_string())
+            }
+            None => (String::new(), String::new()),
+        };
+        let lines: Vec<String> = format!("{head}{text}{tail}")
+            .split('\n')
+            .map(String::from)
+            .collect();
+        self.replace_rows(at.y, before.len(), &lines);
+        self.record(at, before, lines.len());
     }
 
-    pub fn insert_newline(&mut self, at: &Position) {
+    // Replaces matches of `pattern` on the rows in `range` with `replacement`,
+    // only the first on each row unless `global`, as a single edit. Returns
+    // the number of matches replaced, the number of rows they were on and the
+    // last row changed.
+    pub fn substitute(
+        &mut self,
+        range: LineRange,
+        pattern: &Regex,
+        replacement: &str,
+        global: bool,
+    ) -> (usize, usize, usize) {
+        if self.refuses_edit() {
+            return (0, 0, range.start);
+        }
+        let before = self.snapshot(range.start, range.len());
+        let mut count = 0_usize;
+        let mut changed = 0_usize;
+        let mut last_changed = range.start;
+        let mut after = Vec::new();
+        for line in &before {
+            let matches = if global {
+                pattern.find_iter(line).count()
+            } else {
+                usize::from(pattern.is_match(line))
+            };
+            if matches == 0 {
+                after.push(line.clone());
+                continue;
+            }
+            count = count.saturating_add(matches);
+            changed = changed.saturating_add(1);
+            let replaced = if global {
+                pattern.replace_all(line, replacement)
+            } else {
+                pattern.replace(line, replacement)
+            };
+            after.extend(replaced.split('\n').map(String::from));
+            last_changed = range.start.saturating_add(after.len()).saturating_sub(1);
+        }
+        if count > 0 {
+            self.dirty = true;
+            self.replace_rows(range.start, before.len(), &after);
+            self.record(
+                &Position {
+                    x: 0,
+                    y: range.start,
+                },
+                before,
+                after.len(),
+            );
+        }
+        (count, changed, last_changed)
+    }
+
+    // Replaces the match of `pattern` found at `at` and returns the position
+    // just after the replacement text.
+    pub fn substitute_at(
+        &mut self,
+        at: &Position,
+        pattern: &Regex,
+        replacement: &str,
+    ) -> Option<Position> {
+        if self.refuses_edit() {
+            return None;
+        }
+        let row = self.rows.get(at.y)?;
+        let line = row.as_str().to_string();
+        let captures = pattern.captures_at(&line, row.substring(0, at.x).len())?;
+        let whole = captures.get(0)?;
+        let mut head = line.get(..whole.start())?.to_string();
+        captures.expand(replacement, &mut head);
+        let text = format!("{head}{}", line.get(whole.end()..)?);
+        let lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let before = self.snapshot(at.y, 1);
+        self.dirty = true;
+        self.replace_rows(at.y, before.len(), &lines);
+        self.record(at, before, lines.len());
+        let last_line = head.rsplit('\n').next().unwrap_or_default();
+        Some(Position {
+            x: last_line.graphemes(true).count(),
+            y: at.y.saturating_add(head.matches('\n').count()),
+        })
+    }
+
+    // Splits the row at `at`, carrying over its indentation when the file
+    // type autoindents, and returns where the cursor should continue typing.
+    pub fn insert_newline(&mut self, at: &Position) -> Position {
+        if self.refuses_edit() {
+            return at.clone();
+        }
+        let next_line = Position {
+            x: 0,
+            y: at.y.saturating_add(1),
+        };
         match Ord::cmp(&at.y, &self.rows.len()) {
             std::cmp::Ordering::Less => {
+                self.dirty = true;
+                let before = self.snapshot(at.y, 1);
+                let autoindent = self.file_type.indent_options().autoindent();
                 #[allow(clippy::indexing_slicing)]
                 let current_row = &mut self.rows[at.y];
+                let prefix = current_row.substring(0, at.x);
+                let indent: String = if autoindent {
+                    prefix
+                        .chars()
+                        .take_while(|c| *c == ' ' || *c == '\t')
+                        .collect()
+                } else {
+                    String::new()
+                };
                 let mut new_row = current_row.split(at.x);
-                current_row.highlight(self.file_type.highlight_options(), None);
-                new_row.highlight(self.file_type.highlight_options(), None);
+                if !indent.is_empty() {
+                    // Text moved off an indented line takes the line's indent
+                    // instead of its own leading whitespace.
+                    let tail = if indent.len() < prefix.len() {
+                        new_row.as_str().trim_start_matches([' ', '\t'])
+                    } else {
+                        new_row.as_str()
+                    };
+                    new_row = Row::from(format!("{indent}{tail}").as_str());
+                }
                 #[allow(clippy::integer_arithmetic)]
                 self.rows.insert(at.y + 1, new_row);
+                self.shift_marks(at.y + 1, 0, 1);
+                self.record(at, before, 2);
+                Position {
+                    x: Row::from(indent.as_str()).len(),
+                    y: next_line.y,
+                }
             }
             std::cmp::Ordering::Equal => {
+                self.dirty = true;
                 self.rows.push(Row::default());
+                self.record(at, Vec::new(), 1);
+                next_line
+            }
+            std::cmp::Ordering::Greater => at.clone(),
+        }
+    }
+
+    // The indentation a new line opened next to row `y` should start with.
+    pub fn auto_indentation(&self, y: usize) -> String {
+        if !self.file_type.indent_options().autoindent() {
+            return String::new();
+        }
+        self.rows.get(y).map_or_else(String::new, |row| {
+            row.as_str()
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect()
+        })
+    }
+
+    pub fn indent_options(&self) -> IndentOptions {
+        self.file_type.indent_options()
+    }
+
+    // How the document's file type is indented by default.
+    pub fn file_type_indentation(&self) -> &Indentation {
+        self.file_type.indentation()
+    }
+
+    pub fn set_indent_after(&mut self, indent_after: Option<String>) {
+        self.indent_after = indent_after;
+    }
+
+    // Whether a line opened after the text before `at` starts a level
+    // deeper, because the text ends with one of the characters that open a
+    // block.
+    pub fn opens_block(&self, at: &Position) -> bool {
+        if !self.file_type.indent_options().autoindent() {
+            return false;
+        }
+        let indent_after = self
+            .indent_after
+            .as_deref()
+            .or(self.file_type.indentation().indent_after.as_deref())
+            .unwrap_or_default();
+        self.rows.get(at.y).is_some_and(|row| {
+            row.substring(0, at.x)
+                .trim_end()
+                .chars()
+                .last()
+                .is_some_and(|c| indent_after.contains(c))
+        })
+    }
+
+    // Groups every edit made since the last commit into a single undo step.
+    pub fn commit(&mut self) {
+        self.history.commit();
+    }
+
+    pub fn undo(&mut self) -> Option<Position> {
+        if self.refuses_edit() {
+            return None;
+        }
+        self.commit();
+        let parent = self.history.parent()?;
+        self.go_to_state(parent)
+    }
+
+    pub fn redo(&mut self) -> Option<Position> {
+        if self.refuses_edit() {
+            return None;
+        }
+        self.commit();
+        let child = self.history.redo_child()?;
+        self.go_to_state(child)
+    }
+
+    // Goes `distance` back or forward through the states of the text in the
+    // order they were made, whichever branch of the undo tree they are on,
+    // for `:earlier` and `:later`. Returns where the last change was, or
+    // `None` when already at the oldest or newest state.
+    pub fn travel(&mut self, distance: &undo::Distance, forward: bool) -> Option<Position> {
+        if self.refuses_edit() {
+            return None;
+        }
+        self.commit();
+        let target = match (distance, forward) {
+            (undo::Distance::Changes(count), false) => self.history.earlier(*count),
+            (undo::Distance::Changes(count), true) => self.history.later(*count),
+            (undo::Distance::Time(duration), false) => self.history.earlier_by(*duration),
+            (undo::Distance::Time(duration), true) => self.history.later_by(*duration),
+        };
+        if target == self.history.current() {
+            return None;
+        }
+        self.go_to_state(target)
+    }
+
+    // The number of the current state of the text and how many there are,
+    // counting the text as it was opened as state 0.
+    pub fn undo_state(&self) -> (usize, usize) {
+        (self.history.current(), self.history.len())
+    }
+
+    fn go_to_state(&mut self, target: usize) -> Option<Position> {
+        let mut history = mem::take(&mut self.history);
+        let steps = history.go(target);
+        let mut position = None;
+        for (state, undone) in steps {
+            let edits = history.edits(state);
+            if undone {
+                for edit in edits.iter().rev() {
+                    self.replace_rows(edit.y, edit.after.len(), &edit.before);
+                }
+            } else {
+                for edit in edits {
+                    self.replace_rows(edit.y, edit.before.len(), &edit.after);
+                }
             }
-            std::cmp::Ordering::Greater => {}
+            position = edits.first().map(|edit| edit.cursor.clone()).or(position);
+        }
+        self.history = history;
+        self.dirty = true;
+        position
+    }
+
+    // Saves the undo history next to the others in the config directory, to
+    // pick up again when the file is next opened as it is now.
+    pub fn write_undo(&mut self) -> Result<(), Error> {
+        let Some(path) = self.file_name.as_deref().and_then(undo::path) else {
+            return Ok(());
+        };
+        self.commit();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.history.to_json(self.text_hash()).to_string())
+    }
+
+    // Picks up the undo history saved for the file, unless the file has
+    // changed since or there is already history of its own.
+    pub fn read_undo(&mut self) {
+        if !self.history.is_empty() || self.loading.is_some() {
+            return;
         }
+        let Some(contents) = self
+            .file_name
+            .as_deref()
+            .and_then(undo::path)
+            .and_then(|path| fs::read_to_string(path).ok())
+        else {
+            return;
+        };
+        if let Some(history) = Value::parse(&contents)
+            .ok()
+            .and_then(|value| UndoTree::from_json(&value, self.text_hash()))
+        {
+            self.history = history;
+        }
+    }
+
+    fn text_hash(&self) -> u64 {
+        undo::hash(self.rows.iter().map(Row::as_str))
+    }
+
+    fn snapshot(&self, y: usize, count: usize) -> Vec<String> {
+        (y..y.saturating_add(count))
+            .map_while(|y| self.rows.get(y))
+            .map(|row| row.as_str().to_string())
+            .collect()
+    }
+
+    // Records that the rows starting at `at.y` changed from `before` into the
+    // `after_count` rows now in their place.
+    fn record(&mut self, at: &Position, before: Vec<String>, after_count: usize) {
+        self.invalidate_from(at.y);
+        let after = self.snapshot(at.y, after_count);
+        self.history.record(Edit {
+            y: at.y,
+            before,
+            after,
+            cursor: at.clone(),
+        });
+    }
+
+    fn replace_rows(&mut self, y: usize, count: usize, lines: &[String]) {
+        let end = y.saturating_add(count).min(self.rows.len());
+        self.invalidate_from(y);
+        let rows = lines.iter().map(|line| Row::from(line.as_str()));
+        self.rows.splice(y, end.saturating_sub(y), rows);
+        self.shift_marks(y, end.saturating_sub(y), lines.len());
+    }
+
+    pub fn set_mark(&mut self, name: char, at: &Position) {
+        self.marks.insert(name, at.clone());
+    }
+
+    pub fn mark(&self, name: char) -> Option<&Position> {
+        self.marks.get(&name)
+    }
+
+    pub fn marks(&self) -> Vec<(char, &Position)> {
+        let mut marks: Vec<(char, &Position)> = self
+            .marks
+            .iter()
+            .map(|(name, position)| (*name, position))
+            .collect();
+        marks.sort_by_key(|(name, _)| *name);
+        marks
+    }
+
+    // Keeps marks on the same text after the `removed` rows at `y` were
+    // replaced by `inserted` rows; marks on rows that disappeared are dropped.
+    fn shift_marks(&mut self, y: usize, removed: usize, inserted: usize) {
+        self.marks.retain(|_, mark| {
+            if mark.y < y {
+                true
+            } else if mark.y < y.saturating_add(removed) {
+                mark.y.saturating_sub(y) < inserted
+            } else {
+                mark.y = mark.y.saturating_sub(removed).saturating_add(inserted);
+                true
+            }
+        });
     }
 
-    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
+    pub fn find(
+        &self,
+        pattern: &Regex,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<Position> {
         if at.y >= self.rows.len() {
             return None;
         }
@@ -200,12 +1169,12 @@ impl Document {
         let end = if direction == SearchDirection::Forward {
             self.rows.len()
         } else {
-            position.y
+            position.y.saturating_add(1)
         };
 
         for _ in start..end {
             if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.find(query, position.x, direction) {
+                if let Some(x) = row.find(pattern, position.x, direction) {
                     position.x = x;
                     return Some(position);
                 }
@@ -229,6 +1198,75 @@ impl Document {
         None
     }
 
+    // The number of the last match at or before `at`, and how many matches
+    // there are in total.
+    pub fn match_count(&self, pattern: &Regex, at: &Position) -> (usize, usize) {
+        let positions = self.match_positions(pattern);
+        let current = positions
+            .iter()
+            .filter(|position| (position.y, position.x) <= (at.y, at.x))
+            .count();
+        (current, positions.len())
+    }
+
+    // Where every match of `pattern` starts, in order.
+    pub fn match_positions(&self, pattern: &Regex) -> Vec<Position> {
+        let mut positions = Vec::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            if !pattern.is_match(row.as_str()) {
+                continue;
+            }
+            for (x, _) in row.matches(pattern) {
+                positions.push(Position { x, y });
+            }
+        }
+        positions
+    }
+
+    // Finds the bracket pairing with the one at `at`, honouring nesting and
+    // scanning across rows.
+    pub fn matching_bracket(&self, at: &Position) -> Option<Position> {
+        let c = *self.rows.get(at.y)?.chars().get(at.x)?;
+        let (open, close, forward) = match c {
+            '(' | ')' => ('(', ')', c == '('),
+            '[' | ']' => ('[', ']', c == '['),
+            '{' | '}' => ('{', '}', c == '{'),
+            _ => return None,
+        };
+        let (push, pop) = if forward {
+            (open, close)
+        } else {
+            (close, open)
+        };
+        let mut depth = 0_usize;
+        let rows: Box<dyn Iterator<Item = usize>> = if forward {
+            Box::new(at.y..self.rows.len())
+        } else {
+            Box::new((0..=at.y).rev())
+        };
+        for y in rows {
+            let chars = self.rows.get(y)?.chars();
+            let columns: Box<dyn Iterator<Item = usize>> = match (forward, y == at.y) {
+                (true, true) => Box::new(at.x..chars.len()),
+                (true, false) => Box::new(0..chars.len()),
+                (false, true) => Box::new((0..=at.x).rev()),
+                (false, false) => Box::new((0..chars.len()).rev()),
+            };
+            for x in columns {
+                let current = chars.get(x).copied();
+                if current == Some(push) {
+                    depth = depth.saturating_add(1);
+                } else if current == Some(pop) {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        return Some(Position { x, y });
+                    }
+                }
+            }
+        }
+        None
+    }
+
     pub fn is_empty(&self) -> bool {
         self.rows.is_empty()
     }
@@ -240,21 +1278,261 @@ impl Document {
         self.dirty
     }
 
-    pub fn highlight(&mut self, word: Option<&str>) {
-        for row in &mut self.rows {
-            row.highlight(self.file_type.highlight_options(), word);
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.directory
+    }
+
+    pub fn is_help(&self) -> bool {
+        self.help
+    }
+
+    pub fn is_previewed(&self) -> bool {
+        self.preview
+    }
+
+    pub fn set_preview(&mut self, preview: bool) {
+        self.preview = preview;
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    // Whether an edit was refused since the last call.
+    pub fn take_refused_edit(&mut self) -> bool {
+        mem::take(&mut self.refused_edit)
+    }
+
+    fn refuses_edit(&mut self) -> bool {
+        self.refused_edit = self.refused_edit || self.read_only;
+        self.read_only
+    }
+
+    // Writes the contents as they would be saved to `path`, without taking
+    // it as the document's file.
+    pub fn write_copy(&self, path: &Path) -> Result<usize, Error> {
+        self.write_to(path)
+    }
+
+    // Writes the contents as they would be saved to a new file in a new
+    // directory, both only the user can get into, so nobody else can swap
+    // the file before another program, like `sudo cp`, reads it. Returns
+    // the directory, to be removed afterwards, and the file.
+    pub fn write_private_copy(&self) -> Result<(PathBuf, PathBuf), Error> {
+        let mut attempt = 0_u32;
+        let directory = loop {
+            let directory = env::temp_dir().join(format!("ggedit.{}.{attempt}", process::id()));
+            match fs::DirBuilder::new().mode(0o700).create(&directory) {
+                Ok(()) => break directory,
+                Err(error) if error.kind() == ErrorKind::AlreadyExists && attempt < 100 => {
+                    attempt = attempt.saturating_add(1);
+                }
+                Err(error) => return Err(error),
+            }
+        };
+        let path = directory.join("copy");
+        let written = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .and_then(|file| self.write_file(file));
+        if let Err(error) = written {
+            let _ = fs::remove_dir_all(&directory);
+            return Err(error);
+        }
+        Ok((directory, path))
+    }
+
+    // The document was written by other means, like `sudo`.
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+        if let Some(file_name) = &self.file_name {
+            self.disk_modified = modified_time(file_name);
         }
     }
 
+    // Changes the search match to highlight. Rows are only rehighlighted
+    // lazily, when `highlight_rows` reaches them.
+    pub fn highlight(&mut self, pattern: Option<&Regex>) {
+        self.highlight_pattern = pattern.cloned();
+        self.highlight_generation = self.highlight_generation.wrapping_add(1);
+    }
+
+    // The pattern whose matches are highlighted, while a search is.
+    pub fn highlight_pattern(&self) -> Option<&Regex> {
+        self.highlight_pattern.as_ref()
+    }
+
+    // Brings the highlighting of `count` rows from `y` up to date, skipping
+    // rows that haven't changed since they were last highlighted.
+    //
+    // Rows above that changed are rehighlighted first, since a block comment
+    // opened there can change how everything after it looks.
+    pub fn highlight_rows(&mut self, y: usize, count: usize) {
+        // Bytes in hex aren't code in any language.
+        let plain = HighlightingOptions::default();
+        let opts = if self.hex {
+            &plain
+        } else {
+            self.file_type.highlight_options()
+        };
+        let generation = self.highlight_generation;
+        let end = y.saturating_add(count).min(self.rows.len());
+        let start = self.stale_from.min(y);
+        let mut context = start
+            .checked_sub(1)
+            .and_then(|previous| self.rows.get(previous))
+            .map_or_else(Context::default, Row::end_context);
+        for index in start..end {
+            let Some(row) = self.rows.get_mut(index) else {
+                break;
+            };
+            if row.needs_highlight(context) || (index >= y && !row.is_highlighted(generation)) {
+                row.highlight(opts, self.highlight_pattern.as_ref(), generation, context);
+            }
+            context = row.end_context();
+        }
+        self.stale_from = self.stale_from.max(end);
+    }
+
+    // Rows from `y` on may need rehighlighting for the context they start in.
+    fn invalidate_from(&mut self, y: usize) {
+        self.stale_from = self.stale_from.min(y);
+        self.revision = next_revision();
+    }
+
     pub fn size_in_bytes(&self) -> usize {
-        let mut size = 0;
-        for row in &self.rows {
-            size += row.len() + 1;
+        let line_ending = self.file_format.line_ending().len();
+        let size = self.rows.iter().fold(0_usize, |size, row| {
+            size.saturating_add(row.as_bytes().len())
+                .saturating_add(line_ending)
+        });
+        if self.end_of_line || self.rows.is_empty() {
+            size
+        } else {
+            size.saturating_sub(line_ending)
+        }
+    }
+
+    pub fn file_encoding(&self) -> FileEncoding {
+        self.file_encoding
+    }
+
+    // The new encoding is used the next time the document is saved.
+    pub fn set_file_encoding(&mut self, file_encoding: FileEncoding) {
+        if self.file_encoding != file_encoding {
+            self.file_encoding = file_encoding;
+            self.dirty = true;
+        }
+    }
+
+    pub fn end_of_line(&self) -> bool {
+        self.end_of_line
+    }
+
+    // Whether the last row is followed by a line ending when saved.
+    pub fn set_end_of_line(&mut self, end_of_line: bool) {
+        if self.end_of_line != end_of_line {
+            self.end_of_line = end_of_line;
+            self.dirty = true;
+        }
+    }
+
+    pub fn file_format(&self) -> FileFormat {
+        self.file_format
+    }
+
+    // Converting marks the document as modified so the change gets saved.
+    pub fn set_file_format(&mut self, file_format: FileFormat) {
+        if self.file_format != file_format {
+            self.file_format = file_format;
+            self.dirty = true;
         }
-        size
     }
 
     pub fn file_type(&self) -> String {
         self.file_type.name()
     }
+
+    pub fn is_prose(&self) -> bool {
+        !self.is_directory() && self.file_type.is_prose()
+    }
+
+    pub fn definition_keywords(&self) -> &'static [&'static str] {
+        self.file_type.definition_keywords()
+    }
+}
+
+// Opening for writing without truncating tells whether saving will work,
+// honouring everything from file modes to ACLs and read-only mounts. A file
+// that doesn't exist yet is left to fail when it is saved.
+fn is_writable(file_name: &str) -> bool {
+    match fs::OpenOptions::new().write(true).open(file_name) {
+        Ok(_) => true,
+        Err(error) => error.kind() == std::io::ErrorKind::NotFound,
+    }
+}
+
+fn next_revision() -> u64 {
+    static REVISION: AtomicU64 = AtomicU64::new(0);
+    REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+fn modified_time(file_name: &str) -> Option<SystemTime> {
+    fs::metadata(file_name)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Copies what is about to be overwritten to `file~`, next to it or in
+// `backupdir`, so a mistaken save can be undone from outside the editor.
+fn write_backup(file_name: &str, settings: &Settings) -> Result<(), Error> {
+    let path = Path::new(file_name);
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut backup_name = path.file_name().unwrap_or_default().to_os_string();
+    backup_name.push("~");
+    let backup = if settings.backupdir.is_empty() {
+        path.with_file_name(backup_name)
+    } else {
+        let dir = Path::new(&settings.backupdir);
+        fs::create_dir_all(dir)?;
+        dir.join(backup_name)
+    };
+    fs::copy(path, &backup).map_err(|error| {
+        Error::new(
+            error.kind(),
+            format!("Cannot write backup {}: {error}", backup.display()),
+        )
+    })?;
+    Ok(())
+}
+
+// A hidden file in the same directory as `path`, so renaming it over `path`
+// never has to cross file systems.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", process::id()));
+    path.with_file_name(name)
+}
+
+// Gives the freshly written file the mode and, where allowed, the owner of
+// the one it replaces. A new file keeps the defaults.
+fn copy_permissions(original: &Path, replacement: &Path) {
+    let Ok(metadata) = fs::metadata(original) else {
+        return;
+    };
+    let _ = fs::set_permissions(replacement, metadata.permissions());
+    let _ = unix::fs::chown(replacement, Some(metadata.uid()), Some(metadata.gid()));
 }
@@ -1,25 +1,183 @@
-use crate::terminal::CursorStyle;
+use crate::autocmd::Event as AutocommandEvent;
+use crate::backend::{Backend, CursorStyle};
+use crate::cli;
+use crate::completion::{BufferWords, Paths, Source, Words};
+use crate::diff_view::{DiffView, Pair};
+use crate::digraph;
+use crate::event::{Event, Load};
+use crate::explorer;
+use crate::filetype::Indentation;
+use crate::finder;
+use crate::git::{self, Gutter, RepoStatus};
+use crate::grep;
+use crate::headless::Headless;
+use crate::help;
+use crate::highlighting;
+use crate::jump_list::Jump;
+use crate::keymap::{self, Resolution};
+use crate::loader;
+use crate::lsp::{self, Lsp, Query, Reply, Severity};
+use crate::make;
+use crate::markdown;
+use crate::motion::{self, CharClass, CharFind};
+use crate::overlay;
+use crate::quickfix::{self, Quickfix};
+use crate::row::Backdrop;
+use crate::script::{self, Host, Value};
+use crate::search;
+use crate::session::{self, Positions};
+use crate::settings;
+use crate::shell;
+use crate::spell::Dictionary;
+use crate::status_line::Segment;
+use crate::surround;
+use crate::term_window::TermWindow;
+use crate::theme::Style;
+use crate::undo;
+use crate::user_commands;
+use crate::Abbreviations;
+use crate::Autocommands;
+use crate::Completion;
 use crate::Document;
+use crate::FileEncoding;
+use crate::FileFormat;
+use crate::FileType;
+use crate::FileTypeSettings;
+use crate::Finder;
+use crate::History;
+use crate::JumpList;
+use crate::Keymap;
+use crate::LineRange;
+use crate::Motion;
+use crate::Register;
+use crate::Registers;
 use crate::Row;
+use crate::Scripts;
+use crate::Settings;
+use crate::StatusLine;
+use crate::Substitute;
+use crate::Suggestion;
+use crate::Suggestions;
+use crate::Tags;
 use crate::Terminal;
+use crate::TextObject;
+use crate::Theme;
+use crate::UserCommands;
+use regex::Regex;
+use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::env;
-use std::process::exit;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::Duration;
 use std::time::Instant;
-use termion::color;
-use termion::event::Key;
+use termion::event::{Key, MouseButton, MouseEvent};
+use termion::{color, style};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const STATUS_BG_COLOR: color::Rgb = color::Rgb(255, 255, 255);
-const STATUS_FG_COLOR: color::Rgb = color::Rgb(23, 23, 23);
-const EMPTY_LINE_COLOR: color::Rgb = color::Rgb(204, 102, 255);
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// How far one turn of the mouse wheel scrolls.
+const SCROLL_LINES: usize = 3;
+
+// How often the git status in the status bar is brought up to date.
+const GIT_STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+// The most entries the quickfix panel shows at once.
+const QUICKFIX_ROWS: usize = 10;
+
+// The most suggestions the completion menu shows at once.
+const SUGGESTION_ROWS: usize = 10;
+
+// How many status messages `:messages` goes back.
+const MESSAGE_HISTORY: usize = 100;
+
+// How long a command waits for its next key before listing what could come.
+const HINT_DELAY: Duration = Duration::from_millis(500);
+
+// How long the cursor rests on a word before its other occurrences are
+// highlighted.
+const WORD_MATCH_DELAY: Duration = Duration::from_millis(300);
+
+// The commands offered when completing with Tab.
+const COMMANDS: &[&str] = &[
+    "args",
+    "autocmd",
+    "blame",
+    "bnext",
+    "bprevious",
+    "buffer",
+    "buffers",
+    "cclose",
+    "cnext",
+    "colorscheme",
+    "command",
+    "compile",
+    "copen",
+    "copy",
+    "cprevious",
+    "delcommand",
+    "delete",
+    "diagnostics",
+    "diffoff",
+    "diffsplit",
+    "digraphs",
+    "earlier",
+    "edit",
+    "first",
+    "GitHunkRevert",
+    "grep",
+    "help",
+    "hex",
+    "iabbrev",
+    "iunabbrev",
+    "last",
+    "later",
+    "make",
+    "ls",
+    "marks",
+    "messages",
+    "mksession",
+    "move",
+    "next",
+    "nohlsearch",
+    "preview",
+    "previous",
+    "q",
+    "read",
+    "registers",
+    "script",
+    "set",
+    "source",
+    "substitute",
+    "suspend",
+    "tag",
+    "terminal",
+    "todos",
+    "view",
+    "w",
+    "wq",
+    "yank",
+];
+
+// Options that belong to the current buffer rather than to `Settings`.
+const BUFFER_OPTIONS: &[&str] = &["endofline", "fileencoding", "fileformat", "readonly"];
 
 pub enum Mode {
     Normal,
     Insert,
     Command,
     Search,
-    // Visual,
+    Replace,
+    Visual,
+    // Keys go to the shell in the `:terminal` window.
+    Terminal,
 }
 
 #[derive(PartialEq, Copy, Clone)]
@@ -35,31 +193,272 @@ impl Mode {
             Self::Insert => String::from("Insert"),
             Self::Command => String::from("Command"),
             Self::Search => String::from("Search"),
+            Self::Replace => String::from("Replace"),
+            Self::Visual => String::from("Visual"),
+            Self::Terminal => String::from("Terminal"),
         }
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(PartialEq, Copy, Clone)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Dedent,
+    Comment,
+    // `ys`, which asks for what to surround the text with
+    Surround,
+}
+
+#[derive(Copy, Clone)]
+enum Awaiting {
+    Register,
+    Find {
+        direction: SearchDirection,
+        till: bool,
+    },
+    TextObject {
+        around: bool,
+    },
+    SetMark,
+    JumpToMark {
+        exact: bool,
+    },
+    ReplaceChar,
+    G,
+    Z,
+    // After `]` or `[`, which go forward or back to the next thing named
+    // by the following key.
+    Bracket {
+        forward: bool,
+    },
+    // After `ds`, or `cs` when `change` is set, for the surrounding to act on
+    Surrounding {
+        change: bool,
+    },
+    // After `cs` and the surrounding to change, for what it becomes
+    NewSurrounding {
+        old: char,
+    },
+}
+
+// A character being typed in Insert mode by other means than its key.
+enum Entering {
+    // After Ctrl-K, with the first of its two characters once typed.
+    Digraph(Option<char>),
+    // After Ctrl-V, for a key to insert as it is or the start of a code.
+    Literal,
+    // After Ctrl-V and `u`, `U`, `x`, `o` or a decimal digit, the digits of
+    // a character's code, up to `max_digits` of them.
+    Code {
+        radix: u32,
+        max_digits: usize,
+        digits: String,
+    },
+}
+
+// The partially typed Normal mode command, e.g. `"a2d` waiting for a motion.
+#[derive(Default)]
+struct PendingCommand {
+    register: Option<char>,
+    count: Option<usize>,
+    operator: Option<Operator>,
+    operator_count: Option<usize>,
+    awaiting: Option<Awaiting>,
+    // The keys typed so far, for the status bar, and when the last one was.
+    keys: String,
+    since: Option<Instant>,
+}
+
+impl PendingCommand {
+    fn is_empty(&self) -> bool {
+        self.register.is_none()
+            && self.count.is_none()
+            && self.operator.is_none()
+            && self.awaiting.is_none()
+    }
+}
+
+#[derive(Default, Clone, PartialEq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+// Where a Visual mode selection started; the cursor is its other end.
+#[derive(Default)]
+struct Visual {
+    anchor: Position,
+    linewise: bool,
+    // Set while a search started from the selection is typed, so it goes
+    // on once the search is done.
+    searching: bool,
+}
+
+// Where Ctrl-] jumped to a tag from, for Ctrl-T to go back to.
+struct TagJump {
+    file: Option<String>,
+    jump: Jump,
+}
+
+// The search `n` and `N` repeat.
+struct LastSearch {
+    pattern: Regex,
+    query: String,
+    direction: SearchDirection,
+}
+
+// Where the matches of the highlighted search are, found again only once
+// the text or the search changes.
+#[derive(Default)]
+struct SearchMatches {
+    revision: u64,
+    pattern: String,
+    positions: Vec<Position>,
+}
+
+// Where the cursor last stopped, in which version of the text, and when.
+struct Rest {
+    cursor: Position,
+    revision: u64,
+    since: Instant,
+}
+
+// The occurrences of a word Ctrl-N put cursors on, besides the main one.
+struct MultiCursor {
+    pattern: Regex,
+    word_len: usize,
+    // Kept in the order they are in the document.
+    positions: Vec<Position>,
+}
+
+#[derive(Clone)]
 struct StatusMessage {
     text: String,
     time: Instant,
 }
 
+// The git status of a repository, once it is known, and when it was last
+// asked for. `None` asks again at the next redraw.
+#[derive(Default)]
+struct Repository {
+    status: Option<RepoStatus>,
+    requested: Option<Instant>,
+}
+
+// A document along with where the user was in it. The active buffer lives in
+// the editor's own fields and its slot is refilled when switching away.
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+    // Set until the buffer is first shown, when its `BufRead` and `FileType`
+    // autocommands run.
+    unread: bool,
+    // How the buffer was indented when it was last shown, which comes back
+    // when it is shown again.
+    indentation: Option<Indentation>,
+}
+
 pub struct Editor {
     should_quit: bool,
-    terminal: Terminal,
+    terminal: Box<dyn Backend>,
     cursor_position: Position,
     offset: Position,
     document: Document,
     status_message: StatusMessage,
+    // The messages shown so far, oldest first, for `:messages`.
+    messages: VecDeque<StatusMessage>,
     mode: Mode,
     command_buffer: String,
     position_buffer: Position,
+    registers: Registers,
+    pending: PendingCommand,
+    popup: Option<Vec<String>>,
+    // How far a popup taller than the screen is scrolled.
+    popup_scroll: usize,
+    // The file finder, while it is open over the text.
+    finder: Option<Finder>,
+    // What the language server said about the symbol under the cursor,
+    // shown next to it until the next key.
+    hover: Option<Vec<String>>,
+    // Ctrl-K or Ctrl-V in Insert mode, and what was typed after it so far.
+    entering: Option<Entering>,
+    // The other cursors while editing with several, added with Ctrl-N.
+    multi_cursor: Option<MultiCursor>,
+    // Loaded the first time `:set spell` needs it.
+    dictionary: Option<Dictionary>,
+    // The misspelled word `z=` listed suggestions for, from where it starts
+    // to where it ends, so a digit can pick one while they are shown.
+    spelling: Option<(Position, Position, Vec<String>)>,
+    // The text `ys` and a motion picked, waiting for the character to
+    // surround it with.
+    surrounding: Option<(Position, Position)>,
+    // The completion menu under the cursor in Insert mode.
+    suggestions: Option<Suggestions>,
+    // The results of the last `:grep`, and whether their panel is shown
+    // below the text.
+    quickfix: Quickfix,
+    quickfix_open: bool,
+    // The git gutter of each file opened, or `None` for files that aren't
+    // committed. Dropped when a shell may have made a commit.
+    git_gutters: HashMap<String, Option<Gutter>>,
+    // What is known of the repository holding each directory files were
+    // opened from, for the status bar.
+    repositories: HashMap<PathBuf, Repository>,
+    // The language servers configured, and what they found in each file.
+    lsp: Lsp,
+    // Set while `read_input` waits for a line, which is typed on the
+    // message bar.
+    prompting: bool,
+    jump_list: JumpList,
+    tag_stack: Vec<TagJump>,
+    // The build command `:make` is running, if one is.
+    building: Option<String>,
+    // The shell opened by `:terminal`, shown above the quickfix panel.
+    term_window: Option<TermWindow>,
+    // The file the buffer is compared with, shown next to it.
+    diff: Option<DiffView>,
+    replaced: Vec<Option<String>>,
+    settings: Settings,
+    last_find: Option<CharFind>,
+    last_search: Option<LastSearch>,
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    theme: Theme,
+    keymap: Keymap,
+    abbreviations: Abbreviations,
+    autocommands: Autocommands,
+    user_commands: UserCommands,
+    filetype_settings: FileTypeSettings,
+    // How many user commands are running inside each other, to stop one
+    // that runs itself.
+    user_command_depth: usize,
+    // How many scripts are running inside each other through `command()`,
+    // which starts another run each time.
+    script_depth: usize,
+    // Set while autocommands run, so what they do doesn't set off more.
+    running_autocommands: bool,
+    scripts: Scripts,
+    status_line: StatusLine,
+    completion: Option<Completion>,
+    command_history: History,
+    search_history: History,
+    // Typed keys that may still turn out to be the start of a mapping, and
+    // when to stop waiting for the rest of it.
+    pending_keys: Vec<Key>,
+    pending_keys_deadline: Instant,
+    rest: Rest,
+    search_matches: SearchMatches,
+    positions: Positions,
+    visual: Visual,
+    // The files named on the command line, gone through with `:next` and
+    // `:prev`, and which of them was edited last.
+    arguments: Vec<String>,
+    argument: usize,
 }
 
 impl StatusMessage {
@@ -72,181 +471,902 @@ impl StatusMessage {
 }
 
 impl Editor {
-    pub fn run(&mut self) {
+    // Runs until the user quits or the terminal fails, in which case the
+    // error is returned with the screen already cleared.
+    pub fn run(&mut self) -> Result<(), std::io::Error> {
+        let result = self.process_events();
+        if result.is_err() {
+            self.terminal.clear()?;
+        }
+        result
+    }
+
+    fn process_events(&mut self) -> Result<(), std::io::Error> {
         loop {
-            if let Err(error) = self.refresh_screen() {
-                die(error);
-            }
+            self.refresh_screen()?;
             if self.should_quit {
-                break;
+                if !self.terminal.is_headless() {
+                    self.save_positions();
+                }
+                return Ok(());
+            }
+            // A script quits once it runs out, after finishing any mapping
+            // it ended in the middle of.
+            if !self.terminal.has_input() {
+                if self.pending_keys.is_empty() {
+                    return Ok(());
+                }
+                self.resolve_keys(true)?;
+                continue;
+            }
+            // Timer ticks keep arriving while a mapping is half typed, so
+            // the wait is for whatever is left until the mapping times out,
+            // until a pending command lists what can finish it, or until the
+            // cursor has rested on a word long enough to highlight it.
+            let deadline = [
+                Some(self.pending_keys_deadline).filter(|_| !self.pending_keys.is_empty()),
+                self.hint_deadline(),
+                self.word_match_deadline(),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let event = match deadline {
+                None => self.terminal.next_event().map(Some),
+                Some(deadline) => self
+                    .terminal
+                    .next_event_timeout(deadline.saturating_duration_since(Instant::now())),
+            };
+            let result = match event {
+                Ok(None) if self.pending_keys_deadline > Instant::now() => Ok(()),
+                Ok(None) => self.resolve_keys(true),
+                Ok(Some(Event::Tick)) => {
+                    self.check_file_changed();
+                    Ok(())
+                }
+                Ok(Some(Event::Key(Ok(key)))) => self.feed_key(key),
+                Ok(Some(Event::Key(Err(error)))) | Err(error) => Err(error),
+                Ok(Some(Event::Mouse(mouse))) => {
+                    self.process_mouse(mouse);
+                    Ok(())
+                }
+                Ok(Some(Event::Load { buffer, load })) => {
+                    self.apply_load(buffer, load);
+                    Ok(())
+                }
+                Ok(Some(Event::GitStatus { dir, status })) => {
+                    self.repositories.entry(dir).or_default().status = status;
+                    Ok(())
+                }
+                Ok(Some(Event::Lsp { file_type, message })) => {
+                    if let Some(reply) = self.lsp.handle(&file_type, message) {
+                        self.apply_reply(reply);
+                    }
+                    Ok(())
+                }
+                Ok(Some(Event::Make(build))) => {
+                    self.finish_build(build);
+                    Ok(())
+                }
+                Ok(Some(Event::TerminalOutput(bytes))) => {
+                    if let Some(window) = &mut self.term_window {
+                        window.screen.feed(&bytes);
+                    }
+                    Ok(())
+                }
+                Ok(Some(Event::TerminalClosed)) => {
+                    self.close_terminal();
+                    Ok(())
+                }
+            };
+            result?;
+            if Terminal::take_suspend_request() {
+                self.terminal.suspend()?;
+                self.refresh_git();
             }
-            if let Err(error) = self.process_keypress() {
-                die(error);
+            if self.document.take_refused_edit() {
+                self.report_read_only();
             }
         }
     }
-    pub fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
+
+    // Writes every buffer with unsaved changes to a `.recover` file next to
+    // it, or in the temporary directory when that fails or it has no name,
+    // so a crash loses nothing. Returns what happened to each one.
+    pub fn recover(&self) -> Vec<String> {
+        let documents = iter::once(&self.document)
+            .chain(self.buffers.iter().map(|buffer| &buffer.document))
+            .chain(self.diff.iter().map(|view| &view.other))
+            .filter(|document| document.is_dirty());
+        let mut report = Vec::new();
+        for (number, document) in documents.enumerate() {
+            let name = document.file_name.as_deref().unwrap_or("[No Name]");
+            let fallback =
+                env::temp_dir().join(format!("ggedit.{}.{number}.recover", process::id()));
+            let written = write_recovery(document, fallback);
+            report.push(match written {
+                Ok(path) => format!("Unsaved changes to {name} written to {}", path.display()),
+                Err(error) => format!("Unsaved changes to {name} lost: {error}"),
+            });
+        }
+        report
+    }
+
+    // With a `script`, the editor runs headless and takes its keys from it.
+    pub fn new(options: &cli::Options, script: Option<Vec<Key>>) -> Self {
+        // `-` or a pipe on stdin edits what it holds. It is read before the
+        // terminal goes raw, so typing into a terminal stdin works like it
+        // does for `cat`.
+        let read_stdin =
+            options.read_stdin || (options.files.is_empty() && !termion::is_tty(&io::stdin()));
+        let piped = read_stdin.then(|| Document::read_from(io::stdin()));
+        let terminal: Box<dyn Backend> = match script {
+            Some(keys) => Box::new(Headless::new(keys)),
+            None => Box::new(Terminal::default()),
+        };
+        Self::with_backend(options, piped, terminal)
+    }
+
+    // `piped` is what was read from stdin, which is edited instead of the
+    // first file, with the files given opened in the other buffers.
+    pub fn with_backend(
+        options: &cli::Options,
+        piped: Option<Result<Document, std::io::Error>>,
+        terminal: Box<dyn Backend>,
+    ) -> Self {
         let mut initial_status = String::from("Press Ctrl-Q to quit");
+        let read_stdin = piped.is_some();
+        let file_name = options
+            .files
+            .first()
+            .filter(|_| !read_stdin)
+            .map(|file| file.name.as_str());
+        let piped = piped.map(|document| {
+            document.unwrap_or_else(|error| {
+                initial_status = format!("Error reading stdin: {error}");
+                Document::default()
+            })
+        });
+        let headless = terminal.is_headless();
+        let theme = Theme::load_default().unwrap_or_else(|error| {
+            initial_status = error;
+            Theme::default()
+        });
+        // Scripts behave the same whatever the user has configured or done
+        // before, and leave no trace in the history.
+        let keymap = if headless {
+            Keymap::default()
+        } else {
+            Keymap::load().unwrap_or_else(|error| {
+                initial_status = error;
+                Keymap::default()
+            })
+        };
+        let abbreviations = if headless {
+            Abbreviations::default()
+        } else {
+            Abbreviations::load().unwrap_or_else(|error| {
+                initial_status = error;
+                Abbreviations::default()
+            })
+        };
+        let user_commands = if headless {
+            UserCommands::default()
+        } else {
+            UserCommands::load().unwrap_or_else(|error| {
+                initial_status = error;
+                UserCommands::default()
+            })
+        };
+        let filetype_settings = if headless {
+            FileTypeSettings::default()
+        } else {
+            FileTypeSettings::load().unwrap_or_else(|error| {
+                initial_status = error;
+                FileTypeSettings::default()
+            })
+        };
+        let autocommands = if headless {
+            Autocommands::default()
+        } else {
+            Autocommands::load().unwrap_or_else(|error| {
+                initial_status = error;
+                Autocommands::default()
+            })
+        };
+        let status_line = if headless {
+            StatusLine::default()
+        } else {
+            StatusLine::load().unwrap_or_else(|error| {
+                initial_status = error;
+                StatusLine::default()
+            })
+        };
+        let servers = if headless {
+            HashMap::new()
+        } else {
+            lsp::load_servers().unwrap_or_else(|error| {
+                initial_status = error;
+                HashMap::new()
+            })
+        };
+        let (positions, command_history, search_history) = if headless {
+            (Positions::default(), History::default(), History::default())
+        } else {
+            (
+                Positions::load(),
+                History::load("command_history"),
+                History::load("search_history"),
+            )
+        };
 
-        let doc = if args.len() > 1 {
-            if !std::path::Path::new(&args[1]).exists() {
-                Document::from(args[1].as_str())
-            } else {
-                match Document::open(&args[1]) {
-                    Ok(doc) => doc,
-                    Err(error) => {
-                        initial_status = format!("Error opening file: {}", error);
-                        Document::default()
-                    }
+        let mut doc = if let Some(doc) = piped {
+            doc
+        } else if let Some(file_name) = file_name {
+            match load_document(terminal.as_ref(), file_name, 0) {
+                Ok(doc) => doc,
+                Err(error) => {
+                    initial_status = format!("Error opening file: {}", error);
+                    Document::default()
                 }
             }
         } else {
             Document::default()
         };
+        if options.read_only {
+            doc.set_read_only(true);
+        }
+
+        let (cursor_position, offset) = match options.files.first().filter(|_| !read_stdin) {
+            Some(file) if file.line.is_some() => (start_position(file, &doc), Position::default()),
+            _ => doc
+                .file_name
+                .as_deref()
+                .and_then(|name| positions.get(name))
+                .unwrap_or_default(),
+        };
 
-        Self {
+        let mut editor = Self {
             should_quit: false,
-            terminal: Terminal::default(),
+            terminal,
             document: doc,
-            cursor_position: Position::default(),
-            offset: Position::default(),
+            cursor_position,
+            offset,
             status_message: StatusMessage::from(initial_status),
+            messages: VecDeque::new(),
             mode: Mode::Normal,
             command_buffer: String::new(),
             position_buffer: Position::default(),
+            registers: Registers::default(),
+            pending: PendingCommand::default(),
+            popup: None,
+            popup_scroll: 0,
+            finder: None,
+            hover: None,
+            entering: None,
+            multi_cursor: None,
+            dictionary: None,
+            spelling: None,
+            surrounding: None,
+            suggestions: None,
+            prompting: false,
+            quickfix: Quickfix::default(),
+            quickfix_open: false,
+            git_gutters: HashMap::new(),
+            repositories: HashMap::new(),
+            lsp: Lsp::new(servers),
+            jump_list: JumpList::default(),
+            tag_stack: Vec::new(),
+            building: None,
+            term_window: None,
+            diff: None,
+            replaced: Vec::new(),
+            settings: Settings::default(),
+            last_find: None,
+            last_search: None,
+            buffers: vec![Buffer::default()],
+            current_buffer: 0,
+            theme,
+            keymap,
+            abbreviations,
+            autocommands,
+            user_commands,
+            filetype_settings,
+            user_command_depth: 0,
+            script_depth: 0,
+            running_autocommands: false,
+            scripts: Scripts::default(),
+            status_line,
+            completion: None,
+            command_history,
+            search_history,
+            pending_keys: Vec::new(),
+            pending_keys_deadline: Instant::now(),
+            rest: Rest {
+                cursor: Position::default(),
+                revision: 0,
+                since: Instant::now(),
+            },
+            search_matches: SearchMatches::default(),
+            visual: Visual::default(),
+            positions,
+            arguments: options.files.iter().map(|file| file.name.clone()).collect(),
+            argument: 0,
+        };
+        editor.restore_cursor(editor.cursor_position.clone());
+        editor.scroll();
+        // The rest of the files are opened in the background buffers, but
+        // with -d the second one is compared with the first instead.
+        let compared = options.diff.then(|| options.files.get(1)).flatten();
+        let opened = if compared.is_some() {
+            options.files.len()
+        } else {
+            usize::from(!read_stdin)
+        };
+        for file in options.files.iter().skip(opened) {
+            match editor.open_buffer(&file.name) {
+                Ok(index) => {
+                    if let Some(buffer) = editor.buffers.get_mut(index) {
+                        if options.read_only {
+                            buffer.document.set_read_only(true);
+                        }
+                        if file.line.is_some() {
+                            buffer.cursor_position = start_position(file, &buffer.document);
+                            buffer.offset = Position::default();
+                        }
+                    }
+                }
+                Err(error) => {
+                    editor.status_message =
+                        StatusMessage::from(format!("Error opening file: {error}"));
+                }
+            }
+        }
+        if !headless {
+            editor.run_script_files();
         }
+        if !read_stdin {
+            editor.run_read_autocommands();
+        }
+        if let Some(file) = compared {
+            editor.diff_split(&file.name);
+        }
+        for command in &options.commands {
+            if let Err(error) = editor.execute_command(command) {
+                editor.status_message = StatusMessage::from(error.to_string());
+            }
+        }
+        editor
     }
 
-    fn refresh_screen(&self) -> Result<(), std::io::Error> {
-        Terminal::hide_cursor();
-        {
-            let position = &Position::default();
-            #[allow(clippy::cast_possible_truncation)]
-            let Position { mut x, mut y } = position;
-            x = x.saturating_add(1);
-            y = y.saturating_add(1);
+    // Remembers where the cursor is in every open file, for when they are
+    // opened again.
+    fn save_positions(&mut self) {
+        if let Some(name) = &self.document.file_name {
+            self.positions
+                .set(name, &self.cursor_position, &self.offset);
+        }
+        for buffer in &self.buffers {
+            if let Some(name) = &buffer.document.file_name {
+                self.positions
+                    .set(name, &buffer.cursor_position, &buffer.offset);
+            }
+        }
+        if let Err(error) = self.positions.save() {
+            self.status_message = StatusMessage::from(format!("Error saving positions: {error}"));
+        }
+    }
 
-            let x = x as u16;
-            let y = y as u16;
+    // `:mksession` writes the commands that reopen the current buffers.
+    fn make_session(&mut self, file_name: Option<&&str>) {
+        self.save_positions();
+        let mut files = Vec::new();
+        let mut current = 0;
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            let document = if index == self.current_buffer {
+                &self.document
+            } else {
+                &buffer.document
+            };
+            if let Some(name) = &document.file_name {
+                if index == self.current_buffer {
+                    current = files.len();
+                }
+                files.push(name.as_str());
+            }
+        }
+        let path = file_name.map_or(session::DEFAULT_FILE, |name| *name);
+        self.status_message =
+            StatusMessage::from(match session::write(Path::new(path), &files, current) {
+                Ok(()) => format!("Session written to {path}"),
+                Err(error) => format!("Error writing session: {error}"),
+            });
+    }
 
-            print!("{}", termion::cursor::Goto(x, y));
+    // Runs the Ex commands in a file, like a session written by
+    // `:mksession`. Lines starting with `"` are comments.
+    fn source(&mut self, file_name: &str) -> Result<(), std::io::Error> {
+        let contents = match std::fs::read_to_string(file_name) {
+            Ok(contents) => contents,
+            Err(error) => {
+                self.status_message =
+                    StatusMessage::from(format!("Cannot source {file_name}: {error}"));
+                return Ok(());
+            }
         };
-        if self.should_quit {
-            Terminal::clear_screen();
-        } else {
-            self.draw_rows();
-            self.draw_status_bar();
-            self.draw_message_bar();
-            if !matches!(self.mode, Mode::Command) {
-                {
-                    let position = &Position {
-                        x: self.cursor_position.x.saturating_sub(self.offset.x),
-                        y: self.cursor_position.y.saturating_sub(self.offset.y),
-                    };
-                    #[allow(clippy::cast_possible_truncation)]
-                    let Position { mut x, mut y } = position;
-                    x = x.saturating_add(1);
-                    y = y.saturating_add(1);
+        for line in contents.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('"') {
+                self.execute_command(line)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The pattern typed so far after `/`. An invalid one is reported in the
+    // status bar instead of being searched for.
+    fn search_pattern(&mut self) -> Option<Regex> {
+        if self.command_buffer.is_empty() {
+            return None;
+        }
+        match search::compile(&self.command_buffer, !self.settings.regex, false) {
+            Ok(pattern) => Some(pattern),
+            Err(error) => {
+                self.status_message =
+                    StatusMessage::from(format!("/{} - {error}", self.command_buffer));
+                None
+            }
+        }
+    }
 
-                    let x = x as u16;
-                    let y = y as u16;
+    // The word under or after the cursor, with the column it starts at.
+    fn word_under_cursor(&self) -> Option<(usize, String)> {
+        let y = self.cursor_position.y;
+        let row = self.document.row(y)?;
+        let x = row
+            .chars()
+            .iter()
+            .enumerate()
+            .skip(self.cursor_position.x)
+            .find(|(_, c)| CharClass::of(**c) != CharClass::Space)?
+            .0;
+        let selection = TextObject::Word.selection(&self.document, &Position { x, y }, false)?;
+        let text = row.substring(selection.start.x, selection.end.x);
+        Some((selection.start.x, text))
+    }
 
-                    print!("{}", termion::cursor::Goto(x, y));
-                };
+    // Without a language server, `gd` guesses where the word under the cursor
+    // is defined in the buffer: where it follows a keyword like `fn` or
+    // `def`, or else where it first appears.
+    fn go_to_local_definition(&mut self) {
+        let Some((_, word)) = self.word_under_cursor() else {
+            return;
+        };
+        let Ok(pattern) = Regex::new(&format!(r"\b{}\b", regex::escape(&word))) else {
+            return;
+        };
+        let keywords = self.document.definition_keywords();
+        let positions = self.document.match_positions(&pattern);
+        // The word follows one of the keywords, with only spaces in between.
+        let defines = |position: &&Position| {
+            let Some(row) = self.document.row(position.y) else {
+                return false;
+            };
+            let before = row.substring(0, position.x);
+            let Some(before) = before.strip_suffix(char::is_whitespace).map(str::trim_end) else {
+                return false;
+            };
+            let keyword = before
+                .rsplit(|c: char| !c.is_alphanumeric() && c != '_')
+                .next()
+                .unwrap_or_default();
+            keywords.contains(&keyword)
+        };
+        let Some(target) = positions
+            .iter()
+            .find(defines)
+            .or_else(|| positions.first())
+            .cloned()
+        else {
+            self.status_message = StatusMessage::from(String::from("No definition found"));
+            return;
+        };
+        self.push_jump();
+        self.restore_cursor(target);
+        self.scroll();
+    }
+
+    // Searches for the word under or after the cursor as a whole word, like
+    // vim's `*` and `#`.
+    fn search_word_under_cursor(&mut self, forward: bool) {
+        let y = self.cursor_position.y;
+        let Some((start, text)) = self.word_under_cursor() else {
+            self.status_message = StatusMessage::from(String::from("No string under cursor"));
+            return;
+        };
+        let query = whole_word_query(&text);
+        let Ok(pattern) = search::compile(&query, false, false) else {
+            return;
+        };
+        self.push_jump();
+        if let Err(error) = self.search_history.push(&query) {
+            self.status_message = StatusMessage::from(format!("Error saving history: {error}"));
+        }
+        // Start past the word itself so it isn't the match found.
+        let (from, direction) = if forward {
+            (start.saturating_add(1), SearchDirection::Forward)
+        } else {
+            (start, SearchDirection::Backward)
+        };
+        self.document.highlight(Some(&pattern));
+        self.last_search = Some(LastSearch {
+            pattern,
+            query,
+            direction,
+        });
+        self.find_next(&Position { x: from, y }, direction);
+    }
+
+    // `n` repeats the last search and `N` goes the other way.
+    fn repeat_search(&mut self, reverse: bool) {
+        let Some(search) = &self.last_search else {
+            self.status_message = StatusMessage::from(String::from("No previous search"));
+            return;
+        };
+        let direction = match (search.direction, reverse) {
+            (direction, false) => direction,
+            (SearchDirection::Forward, true) => SearchDirection::Backward,
+            (SearchDirection::Backward, true) => SearchDirection::Forward,
+        };
+        let from = match direction {
+            SearchDirection::Forward => Position {
+                x: self.cursor_position.x.saturating_add(1),
+                y: self.cursor_position.y,
+            },
+            SearchDirection::Backward => self.cursor_position.clone(),
+        };
+        self.push_jump();
+        self.find_next(&from, direction);
+    }
+
+    // Moves to the next match of the last search from `from`, wrapping
+    // around the ends of the document like vim does.
+    fn find_next(&mut self, from: &Position, direction: SearchDirection) {
+        let Some(search) = &self.last_search else {
+            return;
+        };
+        let last = self.document.len().saturating_sub(1);
+        let wrapped_from = match direction {
+            SearchDirection::Forward => Position::default(),
+            SearchDirection::Backward => Position {
+                x: self.document.row(last).map_or(0, Row::len),
+                y: last,
+            },
+        };
+        let found = self.document.find(&search.pattern, from, direction);
+        let wrapped = found.is_none();
+        let found = found.or_else(|| {
+            self.document
+                .find(&search.pattern, &wrapped_from, direction)
+        });
+        let Some(position) = found else {
+            self.status_message =
+                StatusMessage::from(format!("Pattern not found: {}", search.query));
+            return;
+        };
+        self.cursor_position = position;
+        self.scroll();
+        self.show_match_count();
+        if wrapped {
+            let message = match direction {
+                SearchDirection::Forward => "search hit BOTTOM, continuing at TOP",
+                SearchDirection::Backward => "search hit TOP, continuing at BOTTOM",
+            };
+            self.status_message =
+                StatusMessage::from(format!("{}  ({message})", self.status_message.text));
+        }
+    }
+
+    // Goes back to the mode the search was started from.
+    fn finish_search(&mut self) {
+        if mem::take(&mut self.visual.searching) {
+            self.command_buffer.clear();
+            self.switch_mode(Mode::Visual);
+        } else {
+            self.switch_mode(Mode::Normal);
+        }
+    }
+
+    // Moves to the first match of what has been typed so far after where the
+    // search started.
+    fn search_incrementally(&mut self) {
+        self.status_message = StatusMessage::from(format!("/{}", self.command_buffer));
+        let pattern = self.search_pattern();
+        if let Some(position) = pattern.as_ref().and_then(|pattern| {
+            self.document
+                .find(pattern, &self.position_buffer, SearchDirection::Forward)
+        }) {
+            self.cursor_position = position;
+            self.document.highlight(pattern.as_ref());
+            self.scroll();
+        }
+    }
+
+    // Shows which of the matches the cursor is on, like `/foo  match 3/17`.
+    fn show_match_count(&mut self) {
+        let Some(search) = &self.last_search else {
+            return;
+        };
+        let (current, total) = self
+            .document
+            .match_count(&search.pattern, &self.cursor_position);
+        self.status_message = StatusMessage::from(if total == 0 {
+            format!("/{} - No results for search", search.query)
+        } else {
+            format!("/{}  match {current}/{total}", search.query)
+        });
+    }
+
+    fn feed_key(&mut self, key: Key) -> Result<(), std::io::Error> {
+        // Keys that are the argument of a command, like the target of `f`,
+        // are taken literally, and so is what is typed into the finder.
+        if self.pending_keys.is_empty()
+            && (self.pending.awaiting.is_some() || self.finder.is_some())
+        {
+            return self.process_keypress(key);
+        }
+        self.pending_keys.push(key);
+        self.pending_keys_deadline = Instant::now() + Duration::from_millis(keymap::TIMEOUT_MS);
+        self.resolve_keys(false)
+    }
+
+    // Warns once when the file being edited is changed by another program.
+    fn check_file_changed(&mut self) {
+        if !self.document.changed_on_disk() {
+            return;
+        }
+        let name = self.document.file_name.as_deref().unwrap_or_default();
+        self.status_message = StatusMessage::from(if self.document.is_dirty() {
+            format!("\"{name}\" changed on disk and in the buffer; :e! discards your changes")
+        } else {
+            format!("\"{name}\" changed on disk; :e! to reload")
+        });
+    }
+
+    // Reads the current file again, throwing away unsaved changes.
+    fn reload(&mut self) {
+        let Some(file_name) = self.document.file_name.clone() else {
+            self.status_message = StatusMessage::from(String::from("No file name"));
+            return;
+        };
+        match load_document(self.terminal.as_ref(), &file_name, self.current_buffer) {
+            Ok(mut document) => {
+                if self.settings.undofile {
+                    document.read_undo();
+                }
+                self.document = document;
+                self.restore_cursor(self.cursor_position.clone());
+                self.run_read_autocommands();
+                self.scroll();
+            }
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("Error opening file: {error}"));
+            }
+        }
+    }
+
+    // Replaces mapped keys by what they map to and passes the rest on as
+    // typed. The expansion is not remapped again.
+    fn resolve_keys(&mut self, timed_out: bool) -> Result<(), std::io::Error> {
+        while !self.pending_keys.is_empty() {
+            let mode = self.mode.to_string().to_lowercase();
+            match self.keymap.resolve(&mode, &self.pending_keys, timed_out) {
+                Resolution::Mapped(keys) => {
+                    self.pending_keys.clear();
+                    for key in keys {
+                        self.process_keypress(key)?;
+                    }
+                }
+                Resolution::Pending => return Ok(()),
+                Resolution::Unmapped => {
+                    let key = self.pending_keys.remove(0);
+                    self.process_keypress(key)?;
+                }
             }
         }
-        Terminal::change_cursor_style(match self.mode {
+        Ok(())
+    }
+
+    fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
+        self.remember_message();
+        self.note_rest();
+        self.note_search_matches();
+        self.update_git_gutter();
+        self.request_git_status();
+        self.sync_lsp();
+        let width = self.terminal.size().width as usize;
+        let rows = self.term_window_rows();
+        if let Some(window) = &mut self.term_window {
+            window.resize(width, rows);
+        }
+        let height = self.text_height();
+        self.document.highlight_rows(self.offset.y, height);
+        self.update_diff();
+        if let Some(view) = &mut self.diff {
+            let first = view.first_pair(self.offset.y);
+            let top = view
+                .pairs()
+                .iter()
+                .skip(first)
+                .find_map(|pair| pair.right)
+                .unwrap_or(0);
+            view.other.highlight_rows(top, height);
+        }
+        if self.should_quit {
+            return self.terminal.clear();
+        }
+        let (message, message_width) = self.draw_message_bar();
+        let mut lines = self.draw_rows();
+        if let Some(hover) = &self.hover {
+            let (layer, top, left) = self.draw_hover(hover);
+            overlay::draw(&mut lines, &layer, top, left);
+        }
+        if let Some(suggestions) = &self.suggestions {
+            let (layer, top, left) = self.draw_suggestions(suggestions);
+            overlay::draw(&mut lines, &layer, top, left);
+        }
+        let finder_prompt = self.finder.as_ref().map(|finder| {
+            let (layer, top, left) = self.draw_finder(finder);
+            overlay::draw(&mut lines, &layer, top, left);
+            // Typing goes into the query, on the line below the top border.
+            Position {
+                x: left
+                    .saturating_add(4)
+                    .saturating_add(finder.query().width()),
+                y: top.saturating_add(1),
+            }
+        });
+        lines.push(self.draw_status_bar());
+        lines.push(message);
+        let cursor = if let Some(prompt) = finder_prompt {
+            prompt
+        } else if matches!(self.mode, Mode::Command) || self.prompting {
+            // Typing continues at the end of the command line.
+            Position {
+                x: message_width,
+                y: (self.terminal.size().height as usize).saturating_add(1),
+            }
+        } else if let Some(window) = self
+            .term_window
+            .as_ref()
+            .filter(|_| matches!(self.mode, Mode::Terminal))
+        {
+            // Under the title of the terminal window.
+            let (x, y) = window.screen.cursor();
+            Position {
+                x,
+                y: height.saturating_add(1).saturating_add(y),
+            }
+        } else if self.document.is_previewed() {
+            // Rendered lines don't line up with the text, so the cursor
+            // only shows which one it is on.
+            Position {
+                x: self.gutter_width(),
+                y: self.screen_row(self.cursor_position.y),
+            }
+        } else {
+            Position {
+                x: self
+                    .cursor_column()
+                    .saturating_sub(self.offset.x)
+                    .saturating_add(self.gutter_width()),
+                y: self.screen_row(self.cursor_position.y),
+            }
+        };
+        let style = match self.mode {
+            _ if self.finder.is_some() => CursorStyle::Bar,
             Mode::Normal => CursorStyle::Block,
             Mode::Insert => CursorStyle::Bar,
             Mode::Command => CursorStyle::Block,
             Mode::Search => CursorStyle::Block,
-        });
-        Terminal::show_cursor();
-        Terminal::flush()
+            Mode::Replace => CursorStyle::Underline,
+            Mode::Visual => CursorStyle::Block,
+            Mode::Terminal => CursorStyle::Block,
+        };
+        self.terminal.draw(lines, &cursor, style)
     }
 
-    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
+    fn process_keypress(&mut self, pressed_key: Key) -> Result<(), std::io::Error> {
+        self.hover = None;
+        // Any key dismisses an open popup, unless it scrolls it
+        if self.scroll_popup(pressed_key) {
+            return Ok(());
+        }
+        if self.popup.take().is_some() {
+            if let Some(spelling) = self.spelling.take() {
+                self.respell(spelling, pressed_key);
+            }
+            return Ok(());
+        }
+        if self.finder.is_some() {
+            self.process_finder_key(pressed_key);
+            return Ok(());
+        }
+
+        if !matches!(pressed_key, Key::Char('\t') | Key::BackTab) {
+            self.completion = None;
+        }
+        if matches!(self.mode, Mode::Normal)
+            && self.multi_cursor.is_some()
+            && self.pending.is_empty()
+            && self.process_multi_cursor_key(pressed_key)
+        {
+            self.scroll();
+            return Ok(());
+        }
+        if matches!(self.mode, Mode::Insert) && self.multi_cursor.is_none() {
+            if let Some(entering) = self.entering.take() {
+                if self.process_entering_key(entering, pressed_key) {
+                    return Ok(());
+                }
+            }
+            if self.process_suggestion_key(pressed_key) {
+                return Ok(());
+            }
+        }
 
         match &self.mode {
             // While in normal mode
-            Mode::Normal => match pressed_key {
-                // Command mutators
-                Key::Char('i') => self.switch_mode(Mode::Insert),
-                Key::Char('a') => {
-                    self.move_cursor(Key::Right);
-                    self.switch_mode(Mode::Insert);
-                }
-                Key::Char(':') => self.switch_mode(Mode::Command),
-                Key::Char('/') => self.switch_mode(Mode::Search),
-                Key::Char('o') => {
-                    self.move_cursor(Key::End);
-                    self.document.insert_newline(&self.cursor_position);
-                    self.switch_mode(Mode::Insert);
-                    self.move_cursor(Key::Down);
-                }
-                Key::Char('O') => {
-                    self.move_cursor(Key::Home);
-                    self.document.insert_newline(&self.cursor_position);
-                    self.switch_mode(Mode::Insert);
-                }
-
-                Key::Char('d') => loop {
-                    if let Some(key) = Terminal::read_key().ok() {
-                        match key {
-                            Key::Char('d') => {
-                                self.document.delete_line(&self.cursor_position);
-                                break;
-                            }
-                            Key::Char('w') => {
-                                // self.document.delete_word(&self.cursor_position);
-                                break;
-                            }
-                            Key::Esc => break,
-                            _ => (),
-                        }
-                    }
-                },
+            Mode::Normal => self.process_command_key(pressed_key, Self::process_normal_key),
 
-                // Movement keys
-                Key::Up
-                | Key::Down
-                | Key::Left
-                | Key::Right
-                | Key::Char('h')
-                | Key::Char('j')
-                | Key::Char('k')
-                | Key::Char('l')
-                | Key::Backspace
-                | Key::PageUp
-                | Key::PageDown
-                | Key::End
-                | Key::Home => self.move_cursor(pressed_key),
-                Key::Ctrl('q') => self.should_quit = true,
-                _ => (),
+            Mode::Visual => self.process_command_key(pressed_key, Self::process_visual_key),
+
+            // While in terminal mode
+            Mode::Terminal => match pressed_key {
+                Key::Esc => self.switch_mode(Mode::Normal),
+                _ => {
+                    if let Some(window) = &mut self.term_window {
+                        window.send(pressed_key);
+                    }
+                }
             },
 
             // While in insert mode
-            Mode::Insert => match pressed_key {
-                // Mode mutators
+            Mode::Insert if self.multi_cursor.is_some() => {
+                self.process_multi_cursor_insert_key(pressed_key);
+            }
+            Mode::Insert => self.process_insert_key(pressed_key),
+
+            // While in replace mode
+            Mode::Replace => match pressed_key {
                 Key::Esc => {
                     self.move_cursor(Key::Left);
                     self.switch_mode(Mode::Normal);
                 }
-                // Movement keys
-                Key::Up | Key::Down | Key::Left | Key::Right => self.move_cursor(pressed_key),
-                // Insertable characters
+                Key::Up | Key::Down | Key::Left | Key::Right => {
+                    self.replaced.clear();
+                    self.move_cursor(pressed_key);
+                }
+                Key::Char('\n') => {
+                    self.replaced.clear();
+                    self.cursor_position = self.document.insert_newline(&self.cursor_position);
+                }
                 Key::Char(c) => {
-                    self.document.insert(&self.cursor_position, c);
+                    let replaced = self
+                        .document
+                        .replace(&self.cursor_position, c.encode_utf8(&mut [0; 4]));
+                    self.replaced.push(replaced);
                     self.move_cursor(Key::Right);
                 }
-                // Deletion
-                Key::Delete => self.document.delete(&self.cursor_position),
-                Key::Backspace => {
-                    if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                        self.move_cursor(Key::Left);
-                        self.document.delete(&self.cursor_position);
+                // Backspace undoes the overwrites made in this session
+                Key::Backspace if self.cursor_position.x > 0 => {
+                    self.move_cursor(Key::Left);
+                    match self.replaced.pop() {
+                        Some(Some(original)) => {
+                            self.document.replace(&self.cursor_position, &original);
+                        }
+                        Some(None) => self.document.delete(&self.cursor_position),
+                        None => (),
                     }
                 }
                 _ => (),
@@ -254,60 +1374,53 @@ impl Editor {
 
             // While in command mode
             Mode::Command => match pressed_key {
+                Key::Char('\t') | Key::BackTab => {
+                    if self.completion.is_none() {
+                        self.completion = self.start_completion();
+                    }
+                    if let Some(completion) = &mut self.completion {
+                        self.command_buffer = if pressed_key == Key::BackTab {
+                            completion.previous_candidate()
+                        } else {
+                            completion.next_candidate()
+                        };
+                        self.status_message =
+                            StatusMessage::from(format!(":{}", self.command_buffer));
+                    }
+                }
                 Key::Backspace => {
+                    self.command_history.reset();
                     self.command_buffer.pop();
                     self.status_message = StatusMessage::from(format!(":{}", self.command_buffer));
                 }
                 Key::Esc => {
+                    self.command_history.reset();
                     self.command_buffer.clear();
                     self.switch_mode(Mode::Normal);
                 }
-                Key::Char('\n') => {
-                    let command_buffer_args = self
-                        .command_buffer
-                        .split_ascii_whitespace()
-                        .collect::<Vec<&str>>();
-
-                    let force = command_buffer_args.get(0).unwrap().ends_with('!');
-
-                    match command_buffer_args[0] {
-                        "q" | "q!" => {
-                            if self.document.is_dirty() && !force {
-                                self.status_message = StatusMessage::from(
-                                    "File has unsaved changes. Use :wq to save and quit, or :q! to quit without saving.".to_string(),
-                                );
-                            } else {
-                                self.should_quit = true;
-                            }
-                        }
-                        "w" => match self.document.save_as(command_buffer_args.get(1)) {
-                            Ok(message) => self.status_message = StatusMessage::from(message),
-                            Err(e) => {
-                                self.status_message = StatusMessage::from(
-                                    "Error writing file: ".to_string() + &e.to_string(),
-                                );
-                            }
-                        },
-                        "wq" => {
-                            match self.document.save_as(command_buffer_args.get(1)) {
-                                Ok(message) => self.status_message = StatusMessage::from(message),
-                                Err(e) => {
-                                    self.status_message = StatusMessage::from(e.to_string());
-                                }
-                            }
-                            self.should_quit = true;
-                        }
-                        _ => {
-                            self.status_message = StatusMessage::from(format!(
-                                "Unrecognized command: {}",
-                                command_buffer_args[0]
-                            ))
-                        }
+                Key::Up | Key::Down => {
+                    let entry = if pressed_key == Key::Up {
+                        self.command_history.previous_entry(&self.command_buffer)
+                    } else {
+                        self.command_history.next_entry()
+                    };
+                    if let Some(entry) = entry {
+                        self.command_buffer = entry;
+                        self.status_message =
+                            StatusMessage::from(format!(":{}", self.command_buffer));
                     }
-                    self.command_buffer.clear();
+                }
+                Key::Char('\n') => {
+                    let command = mem::take(&mut self.command_buffer);
                     self.switch_mode(Mode::Normal);
+                    self.execute_command(&command)?;
+                    if let Err(error) = self.command_history.push(&command) {
+                        self.status_message =
+                            StatusMessage::from(format!("Error saving history: {error}"));
+                    }
                 }
                 Key::Char(c) => {
+                    self.command_history.reset();
                     self.command_buffer.push(c);
                     self.status_message = StatusMessage::from(format!(":{}", self.command_buffer));
                 }
@@ -317,300 +1430,5299 @@ impl Editor {
             Mode::Search => {
                 match pressed_key {
                     Key::Backspace => {
+                        self.search_history.reset();
                         self.command_buffer.pop();
-                        if let Some(position) = self.document.find(
-                            &self.command_buffer,
-                            &self.cursor_position,
-                            SearchDirection::Forward,
-                        ) {
+                        self.status_message =
+                            StatusMessage::from(format!("/{}", self.command_buffer));
+                        let pattern = self.search_pattern();
+                        if let Some(position) = pattern.as_ref().and_then(|pattern| {
+                            self.document.find(
+                                pattern,
+                                &self.cursor_position,
+                                SearchDirection::Forward,
+                            )
+                        }) {
                             self.cursor_position = position;
                             self.scroll();
                         }
-                        self.status_message =
-                            StatusMessage::from(format!("/{}", self.command_buffer));
+                        self.document.highlight(pattern.as_ref());
                     }
                     Key::Esc => {
+                        self.search_history.reset();
                         self.command_buffer.clear();
                         self.status_message = StatusMessage::from(String::from(""));
                         self.cursor_position = self.position_buffer.clone();
-                        self.switch_mode(Mode::Normal);
-                        self.document.highlight(None);
+                        self.finish_search();
+                        let previous = self.last_search.as_ref().map(|search| &search.pattern);
+                        self.document.highlight(previous);
                     }
-                    Key::Char('\n') => loop {
-                        let directional_key = Terminal::read_key()?;
-
-                        match directional_key {
-                            Key::Esc => {
-                                self.cursor_position = self.position_buffer.clone();
-                                self.switch_mode(Mode::Normal);
-                                self.document.highlight(None);
-                                Terminal::change_cursor_style(CursorStyle::Block);
-                                break;
+                    Key::Char('\n') => {
+                        self.jump_list.push(Jump {
+                            cursor: self.position_buffer.clone(),
+                            offset: self.offset.clone(),
+                        });
+                        if let Err(error) = self.search_history.push(&self.command_buffer) {
+                            self.status_message =
+                                StatusMessage::from(format!("Error saving history: {error}"));
+                        }
+                        let query = mem::take(&mut self.command_buffer);
+                        self.finish_search();
+                        match search::compile(&query, !self.settings.regex, false) {
+                            Ok(pattern) if !query.is_empty() => {
+                                self.document.highlight(Some(&pattern));
+                                self.last_search = Some(LastSearch {
+                                    pattern,
+                                    query,
+                                    direction: SearchDirection::Forward,
+                                });
+                                self.show_match_count();
                             }
-
-                            Key::Char('n') | Key::Char('N') => {
-                                if directional_key == Key::Char('N') {
-                                    self.move_cursor(Key::Left);
-                                } else {
-                                    self.move_cursor(Key::Right);
-                                }
-                                if let Some(position) = self.document.find(
-                                    &self.command_buffer,
-                                    &self.cursor_position,
-                                    match directional_key {
-                                        Key::Char('N') => SearchDirection::Backward,
-                                        _ => SearchDirection::Forward,
-                                    },
-                                ) {
-                                    self.cursor_position = position;
-                                    self.scroll();
-                                } else {
-                                    if directional_key == Key::Char('N') {
-                                        self.move_cursor(Key::Right);
-                                    } else {
-                                        self.move_cursor(Key::Left);
-                                    }
-                                    self.status_message = StatusMessage::from(format!(
-                                        ":{} - No results for search",
-                                        self.command_buffer
-                                    ));
-                                }
+                            // An empty search repeats the last one
+                            Ok(_) => self.repeat_search(false),
+                            Err(error) => {
+                                self.document.highlight(None);
                                 self.status_message =
-                                    StatusMessage::from(format!("/{}", self.command_buffer));
+                                    StatusMessage::from(format!("/{query} - {error}"));
                             }
-
-                            _ => {}
                         }
-                        self.refresh_screen()?;
-                    },
+                    }
 
-                    Key::Char(c) => {
-                        self.command_buffer.push(c);
-                        if let Some(position) = self.document.find(
-                            &self.command_buffer,
-                            &self.position_buffer,
-                            SearchDirection::Forward,
-                        ) {
-                            self.cursor_position = position;
-                            self.document.highlight(Some(&self.command_buffer));
-                            self.scroll();
+                    Key::Up | Key::Down => {
+                        let entry = if pressed_key == Key::Up {
+                            self.search_history.previous_entry(&self.command_buffer)
                         } else {
-                            self.status_message = StatusMessage::from(format!(
-                                "No results for search: {}",
-                                self.command_buffer
-                            ));
+                            self.search_history.next_entry()
+                        };
+                        if let Some(entry) = entry {
+                            self.command_buffer = entry;
+                            self.search_incrementally();
                         }
-                        self.status_message =
-                            StatusMessage::from(format!("/{}", self.command_buffer));
+                    }
+                    Key::Char(c) => {
+                        self.search_history.reset();
+                        self.command_buffer.push(c);
+                        self.search_incrementally();
                     }
                     _ => (),
                 };
             }
         }
 
+        if matches!(self.mode, Mode::Insert) && self.multi_cursor.is_none() {
+            self.update_suggestions(pressed_key);
+        } else {
+            self.suggestions = None;
+        }
+        if matches!(self.mode, Mode::Normal) {
+            self.document.commit();
+        }
         self.scroll();
         Ok(())
     }
 
-    fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
-        let mut offset = &mut self.offset;
-        if y < offset.y {
-            offset.y = y;
-        } else if y >= offset.y.saturating_add(height) {
-            offset.y = y.saturating_sub(height).saturating_add(1);
-        }
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+    fn process_insert_key(&mut self, key: Key) {
+        if let Key::Char(c) = key {
+            if CharClass::of(c) != CharClass::Word {
+                self.expand_abbreviation();
+            }
         }
-    }
-
-    fn move_cursor(&mut self, key: Key) {
-        let Position { mut y, mut x } = self.cursor_position;
-        let height = self.document.len();
-        let terminal_height = self.terminal.size().height as usize;
-        let mut width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
-        };
         match key {
-            Key::Up | Key::Char('k') => y = y.saturating_sub(1),
-            Key::Down | Key::Char('j') => {
-                if y < height {
-                    y = y.saturating_add(1);
-                }
+            // Mode mutators
+            Key::Esc => {
+                self.move_cursor(Key::Left);
+                self.switch_mode(Mode::Normal);
             }
-            Key::Left | Key::Char('h') | Key::Backspace => {
-                if x > 0 {
-                    x -= 1;
-                } else if y > 0 {
-                    y -= 1;
-                    if let Some(row) = self.document.row(y) {
-                        x = row.len();
-                    } else {
-                        x = 0;
-                    }
+            // Movement keys
+            Key::Up | Key::Down | Key::Left | Key::Right => self.move_cursor(key),
+            // Insertable characters
+            Key::Char('\n') => {
+                let opens_block = self.document.opens_block(&self.cursor_position);
+                self.cursor_position = self.document.insert_newline(&self.cursor_position);
+                if opens_block {
+                    self.indent_opened_line();
                 }
             }
-            Key::Right | Key::Char('l') => {
-                if x < width {
-                    x += 1;
-                } else if y < height {
-                    y += 1;
-                    x = 0;
-                }
+            Key::Char('\t') if self.settings.expandtab => {
+                // Pad with spaces up to the next tab stop
+                let tabstop = self.settings.tabstop.max(1);
+                let column = self.cursor_column();
+                let width = tabstop.saturating_sub(column.checked_rem(tabstop).unwrap_or(0));
+                self.document
+                    .insert_text(&self.cursor_position, &" ".repeat(width));
+                self.cursor_position.x = self.cursor_position.x.saturating_add(width);
             }
-            Key::PageUp => {
-                y = if y > terminal_height {
-                    y.saturating_sub(terminal_height)
-                } else {
-                    0
+            Key::Char(c) => {
+                self.insert_char(c);
+                if c == '}' {
+                    self.dedent_closing_brace();
                 }
             }
-            Key::PageDown => {
-                y = if y.saturating_add(terminal_height) < height {
-                    y.saturating_add(terminal_height)
-                } else {
-                    height
+            Key::Ctrl('k') => self.entering = Some(Entering::Digraph(None)),
+            Key::Ctrl('v') => self.entering = Some(Entering::Literal),
+            // Deletion
+            Key::Delete => self.document.delete(&self.cursor_position),
+            Key::Backspace => {
+                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
+                    self.move_cursor(Key::Left);
+                    self.document.delete(&self.cursor_position);
                 }
             }
-            Key::Home | Key::Char('0') => x = 0,
-            Key::End | Key::Char('$') => x = width,
             _ => (),
         }
+    }
 
-        // If the cursor is at the end of a line, it should stay there when the
-        // user presses the down arrow key.
-        width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
+    // Ctrl-N in Normal mode: picks the word under the cursor the first time,
+    // and after that adds a cursor on its next occurrence, which becomes the
+    // main cursor.
+    fn add_cursor(&mut self) {
+        let Some(multi_cursor) = &self.multi_cursor else {
+            let Some((start, word)) = self.word_under_cursor() else {
+                self.status_message = StatusMessage::from(String::from("No string under cursor"));
+                return;
+            };
+            let Ok(pattern) = search::compile(&whole_word_query(&word), false, false) else {
+                return;
+            };
+            self.cursor_position.x = start;
+            self.multi_cursor = Some(MultiCursor {
+                pattern,
+                word_len: word.graphemes(true).count(),
+                positions: Vec::new(),
+            });
+            self.status_message = StatusMessage::from(String::from("1 cursor"));
+            return;
+        };
+        let after = Position {
+            x: self.cursor_position.x.saturating_add(1),
+            y: self.cursor_position.y,
         };
+        let next = self
+            .document
+            .find(&multi_cursor.pattern, &after, SearchDirection::Forward)
+            .or_else(|| {
+                self.document.find(
+                    &multi_cursor.pattern,
+                    &Position::default(),
+                    SearchDirection::Forward,
+                )
+            })
+            .filter(|next| *next != self.cursor_position && !multi_cursor.positions.contains(next));
+        let Some(next) = next else {
+            self.status_message = StatusMessage::from(String::from("No more occurrences"));
+            return;
+        };
+        let main = mem::replace(&mut self.cursor_position, next);
+        if let Some(multi_cursor) = &mut self.multi_cursor {
+            multi_cursor.positions.push(main);
+            multi_cursor
+                .positions
+                .sort_by_key(|position| (position.y, position.x));
+            self.status_message = StatusMessage::from(format!(
+                "{} cursors",
+                multi_cursor.positions.len().saturating_add(1)
+            ));
+        }
+    }
 
-        if x > width {
-            x = width;
+    // The keys Normal mode takes differently with several cursors: Ctrl-N
+    // adds another, `i`, `a` and `c` insert before, after or in place of the
+    // word at every cursor, and Esc goes back to one. Any other key also
+    // goes back to one, and is then handled as usual. Returns whether `key`
+    // was one of them.
+    fn process_multi_cursor_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Ctrl('n') => self.add_cursor(),
+            Key::Char('i') => self.switch_mode(Mode::Insert),
+            Key::Char('a') => {
+                let width = self
+                    .multi_cursor
+                    .as_ref()
+                    .map_or(0, |cursors| cursors.word_len);
+                self.at_every_cursor(|editor| {
+                    editor.cursor_position.x = editor.cursor_position.x.saturating_add(width);
+                });
+                self.switch_mode(Mode::Insert);
+            }
+            Key::Char('c') => {
+                if self.document.is_read_only() {
+                    self.report_read_only();
+                    return true;
+                }
+                let width = self
+                    .multi_cursor
+                    .as_ref()
+                    .map_or(0, |cursors| cursors.word_len);
+                self.at_every_cursor(|editor| {
+                    let end = Position {
+                        x: editor.cursor_position.x.saturating_add(width),
+                        y: editor.cursor_position.y,
+                    };
+                    editor.document.delete_range(&editor.cursor_position, &end);
+                });
+                self.switch_mode(Mode::Insert);
+            }
+            Key::Esc => {
+                self.multi_cursor = None;
+                self.status_message = StatusMessage::from(String::new());
+            }
+            _ => {
+                self.multi_cursor = None;
+                return false;
+            }
         }
+        true
+    }
 
-        self.cursor_position = Position { x, y }
+    // Insert mode types at every cursor, until Esc goes back to one.
+    fn process_multi_cursor_insert_key(&mut self, key: Key) {
+        if key == Key::Esc {
+            self.multi_cursor = None;
+            self.process_insert_key(key);
+            return;
+        }
+        if matches!(key, Key::Ctrl('k' | 'v')) {
+            return;
+        }
+        self.at_every_cursor(|editor| editor.process_insert_key(key));
     }
 
-    fn draw_welcome_message(&self) {
-        let mut welcome_message = format!("ggedit v{}", VERSION);
-        let width = self.terminal.size().width as usize;
-        let len = welcome_message.len();
-        let padding = width.saturating_sub(len) / 2;
-        let spaces = " ".repeat(padding.saturating_sub(1));
-        welcome_message = format!("~{}{}", spaces, welcome_message);
-        welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
+    // Does `edit` at each cursor in turn, from the first in the document to
+    // the last, as one change to undo. The text after an edit is left as it
+    // was, so the cursors still to come keep their distance from the end of
+    // their line, and move down or up by the lines the edit added or
+    // removed.
+    fn at_every_cursor(&mut self, edit: impl Fn(&mut Self)) {
+        let Some(multi_cursor) = self.multi_cursor.as_mut() else {
+            edit(self);
+            return;
+        };
+        let mut cursors = mem::take(&mut multi_cursor.positions);
+        let main = self.cursor_position.clone();
+        cursors.push(main.clone());
+        cursors.sort_by_key(|position| (position.y, position.x));
+        cursors.dedup();
+        let mut main_index = cursors
+            .iter()
+            .position(|cursor| *cursor == main)
+            .unwrap_or(0);
+        for index in 0..cursors.len() {
+            let Some((cursor, later)) = cursors
+                .get_mut(index..)
+                .and_then(|rest| rest.split_first_mut())
+            else {
+                break;
+            };
+            let lines = self.document.len();
+            let from_end: Vec<usize> = later
+                .iter()
+                .map(|later| motion::row_len(&self.document, later.y).saturating_sub(later.x))
+                .collect();
+            self.cursor_position = cursor.clone();
+            edit(self);
+            *cursor = self.cursor_position.clone();
+            let (added, removed) = (
+                self.document.len().saturating_sub(lines),
+                lines.saturating_sub(self.document.len()),
+            );
+            for (later, distance) in later.iter_mut().zip(from_end) {
+                later.y = later.y.saturating_add(added).saturating_sub(removed);
+                later.x = motion::row_len(&self.document, later.y).saturating_sub(distance);
+            }
+        }
+        // Cursors that edits brought together become one.
+        let main = cursors.get(main_index).cloned().unwrap_or_default();
+        cursors.dedup();
+        main_index = cursors
+            .iter()
+            .position(|cursor| *cursor == main)
+            .unwrap_or(0);
+        self.cursor_position = cursors.remove(main_index);
+        if let Some(multi_cursor) = self.multi_cursor.as_mut() {
+            multi_cursor.positions = cursors;
+        }
     }
 
-    pub fn draw_row(&self, row: &Row) {
-        let terminal_width = self.terminal.size().width as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(terminal_width);
-        let row = row.render(start, end);
-        println!("{}\r", row);
+    // Replaces the abbreviation just typed, if the word before the cursor is
+    // one, by what it stands for.
+    fn expand_abbreviation(&mut self) {
+        let (start, word) = self.word_before_cursor();
+        if let Some(expansion) = self.abbreviations.get(&word).map(str::to_string) {
+            self.replace_before_cursor(&start, &expansion);
+        }
     }
 
-    fn draw_rows(&self) {
-        let height = self.terminal.size().height;
-        for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
-            {
-                self.draw_row(row);
-            } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
+    // Puts `text` in place of what is between `start` and the cursor, and the
+    // cursor after it.
+    fn replace_before_cursor(&mut self, start: &Position, text: &str) {
+        self.document.delete_range(start, &self.cursor_position);
+        self.document.insert_text(start, text);
+        let lines: Vec<&str> = text.split('\n').collect();
+        let last = lines.last().copied().unwrap_or_default();
+        let graphemes = last.graphemes(true).count();
+        self.cursor_position = Position {
+            x: if lines.len() > 1 {
+                graphemes
             } else {
-                Terminal::set_fg_color(EMPTY_LINE_COLOR);
-                if terminal_row != 0 {
-                    println!("~\r");
-                } else {
-                    println!("\r");
+                start.x.saturating_add(graphemes)
+            },
+            y: start.y.saturating_add(lines.len().saturating_sub(1)),
+        };
+    }
+
+    // The keys that go through the completion menu while it is open: Ctrl-N
+    // and Ctrl-P or the arrows pick a suggestion, and Enter or Tab puts it
+    // in place of the word. When it is closed, Ctrl-N and Ctrl-P open it
+    // with the words of the open buffers. Returns whether `key` was one of
+    // them.
+    fn process_suggestion_key(&mut self, key: Key) -> bool {
+        let Some(suggestions) = self.suggestions.as_mut() else {
+            if !matches!(key, Key::Ctrl('n' | 'p')) {
+                return false;
+            }
+            let (start, word) = self.word_before_cursor();
+            self.suggestions = Suggestions::new(start, self.buffer_words(&word), &word);
+            match &mut self.suggestions {
+                Some(suggestions) if key == Key::Ctrl('p') => suggestions.select(-1),
+                Some(_) => (),
+                None => {
+                    self.status_message = StatusMessage::from(String::from("No completions"));
+                }
+            }
+            return true;
+        };
+        match key {
+            Key::Ctrl('n') | Key::Down => suggestions.select(1),
+            Key::Ctrl('p') | Key::Up => suggestions.select(-1),
+            Key::Char('\n' | '\t') => {
+                let text = suggestions
+                    .selected()
+                    .map(|suggestion| suggestion.text.clone());
+                let start = suggestions.start.clone();
+                self.suggestions = None;
+                if let Some(text) = text {
+                    self.replace_before_cursor(&start, &text);
                 }
-                Terminal::reset_fg_color();
             }
+            _ => return false,
         }
+        true
     }
 
-    fn draw_status_bar(&self) {
-        let mut status;
-        let width = self.terminal.size().width as usize;
-        let mut file_name = "[No Name]".to_string();
-        let dirty_indicator = if self.document.is_dirty() { " [+]" } else { "" };
-        if let Some(name) = &self.document.file_name {
-            file_name = name.clone();
-            file_name.truncate(20);
+    // Narrows the completion menu down to the word before the cursor once a
+    // key was typed, closing it when there is no word any more, and asks the
+    // language server for suggestions after a word character or a `.`.
+    fn update_suggestions(&mut self, key: Key) {
+        let (start, word) = self.word_before_cursor();
+        if let Some(suggestions) = &mut self.suggestions {
+            if suggestions.start != start || !suggestions.filter(&word) {
+                self.suggestions = None;
+            }
+        }
+        let typed = match key {
+            Key::Char(c) => c == '.' || CharClass::of(c) == CharClass::Word,
+            _ => false,
+        };
+        if typed && self.lsp.has_server(&self.document.file_type()) {
+            let sender = self.terminal.event_sender();
+            let _ = self.lsp.ask(
+                &self.document,
+                &self.cursor_position,
+                Query::Completion,
+                &sender,
+            );
+        }
+    }
+
+    // Opens the completion menu with what the language server suggested,
+    // or with the words of the document when that was nothing.
+    fn show_suggestions(&mut self, suggestions: Vec<Suggestion>) {
+        if !matches!(self.mode, Mode::Insert) {
+            return;
         }
-        status = format!("{}{}", file_name, dirty_indicator,);
+        let (start, word) = self.word_before_cursor();
+        let suggestions = if suggestions.is_empty() && !word.is_empty() {
+            self.buffer_words(&word)
+        } else {
+            suggestions
+        };
+        self.suggestions = Suggestions::new(start, suggestions, &word);
+    }
+
+    // The words starting with `word` in the current buffer, then in the
+    // others.
+    fn buffer_words(&self, word: &str) -> Vec<Suggestion> {
+        let documents = iter::once(&self.document)
+            .chain(self.buffers.iter().map(|buffer| &buffer.document))
+            .collect();
+        BufferWords(documents)
+            .complete(word)
+            .into_iter()
+            .map(Suggestion::from)
+            .collect()
+    }
 
-        let mode_indicator: String = format!(" [ {} ] ", self.mode.to_string());
+    // Where the word the cursor is at the end of starts, and the word.
+    fn word_before_cursor(&self) -> (Position, String) {
+        let Position { x, y } = self.cursor_position.clone();
+        let row = self.document.row(y).map_or("", Row::as_str);
+        let before: Vec<&str> = row.graphemes(true).take(x).collect();
+        let length = before
+            .iter()
+            .rev()
+            .take_while(|grapheme| {
+                grapheme
+                    .chars()
+                    .next()
+                    .is_some_and(|c| CharClass::of(c) == CharClass::Word)
+            })
+            .count();
+        let start = x.saturating_sub(length);
+        let word = before.get(start..).unwrap_or_default().concat();
+        (Position { x: start, y }, word)
+    }
 
-        let line_indicator = format!(
-            "{}/{}",
-            self.cursor_position.y.saturating_add(1),
-            self.document.len()
-        );
-        let len = status.len() + line_indicator.len();
-        if width > len {
-            status.push_str(
-                &" ".repeat(
-                    width
-                        .saturating_sub(len)
-                        .saturating_sub(mode_indicator.len()),
-                ),
-            );
+    // Hands `key` to `process`, remembering it while the command it is part
+    // of isn't finished.
+    fn process_command_key(&mut self, key: Key, process: fn(&mut Self, Key)) {
+        let mut keys = mem::take(&mut self.pending.keys);
+        process(self, key);
+        if !self.pending.is_empty() || self.surrounding.is_some() {
+            keys.push_str(&keymap::notation(key));
+            self.pending.keys = keys;
+            self.pending.since = Some(Instant::now());
         }
-        status = format!("{status}{mode_indicator}{line_indicator}");
-        status.truncate(width);
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{}\r", status);
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
     }
 
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
-        let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
-            let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+    // When the list of what can finish the pending command is due, if it
+    // isn't shown yet.
+    fn hint_deadline(&self) -> Option<Instant> {
+        let deadline = self.pending.since? + HINT_DELAY;
+        (deadline > Instant::now()).then_some(deadline)
+    }
+
+    // Starts the wait for the word highlight over whenever the cursor moves
+    // or the text changes.
+    fn note_rest(&mut self) {
+        let revision = self.document.revision();
+        if self.rest.cursor != self.cursor_position || self.rest.revision != revision {
+            self.rest = Rest {
+                cursor: self.cursor_position.clone(),
+                revision,
+                since: Instant::now(),
+            };
         }
     }
 
-    fn switch_mode(&mut self, mode: Mode) {
-        match mode {
-            Mode::Normal => {
-                Terminal::change_cursor_style(CursorStyle::Block);
-                self.command_buffer.clear();
-                self.status_message = StatusMessage::from(String::from(""));
-            }
-            Mode::Insert => {
-                Terminal::change_cursor_style(CursorStyle::Bar);
+    fn note_search_matches(&mut self) {
+        let Some(pattern) = self.document.highlight_pattern() else {
+            return;
+        };
+        let revision = self.document.revision();
+        if self.search_matches.revision != revision
+            || self.search_matches.pattern != pattern.as_str()
+        {
+            self.search_matches = SearchMatches {
+                revision,
+                pattern: pattern.as_str().to_string(),
+                positions: self.document.match_positions(pattern),
+            };
+        }
+    }
+
+    fn word_match_deadline(&self) -> Option<Instant> {
+        if !matches!(self.mode, Mode::Normal) {
+            return None;
+        }
+        let deadline = self.rest.since + WORD_MATCH_DELAY;
+        (deadline > Instant::now()).then_some(deadline)
+    }
+
+    // The whole-word pattern for the word the cursor has rested on in Normal
+    // mode, once it has for `WORD_MATCH_DELAY`.
+    fn word_match_pattern(&self) -> Option<Regex> {
+        if !matches!(self.mode, Mode::Normal)
+            || self.multi_cursor.is_some()
+            || self.rest.since.elapsed() < WORD_MATCH_DELAY
+        {
+            return None;
+        }
+        let Position { x, y } = self.cursor_position;
+        let row = self.document.row(y)?;
+        let c = row.chars().get(x).copied()?;
+        if CharClass::of(c) != CharClass::Word {
+            return None;
+        }
+        let (_, word) = self.word_under_cursor()?;
+        search::compile(&whole_word_query(&word), false, false).ok()
+    }
+
+    // The other occurrences on row `y` of the word the cursor rests on.
+    fn word_match_columns(
+        &self,
+        y: usize,
+        row: &Row,
+        pattern: Option<&Regex>,
+    ) -> Vec<(usize, highlighting::Type)> {
+        let Some(pattern) = pattern else {
+            return Vec::new();
+        };
+        let cursor = &self.cursor_position;
+        row.matches(pattern)
+            .into_iter()
+            .filter(|(start, end)| y != cursor.y || !(*start..*end).contains(&cursor.x))
+            .flat_map(|(start, end)| (start..end).map(|x| (x, highlighting::Type::WordMatch)))
+            .collect()
+    }
+
+    // What can finish the pending command, once it has waited `HINT_DELAY`.
+    fn pending_hints(&self) -> Vec<String> {
+        if self
+            .pending
+            .since
+            .is_none_or(|since| since.elapsed() < HINT_DELAY)
+        {
+            return Vec::new();
+        }
+        let first = match (self.pending.awaiting, self.pending.operator) {
+            _ if self.surrounding.is_some() => "ys{motion}",
+            (Some(Awaiting::Register), _) => "\"",
+            (Some(Awaiting::Find { .. }), _) => "f t F T",
+            (Some(Awaiting::TextObject { .. }), _) => "an operator and i or a",
+            (Some(Awaiting::SetMark), _) => "m",
+            (Some(Awaiting::JumpToMark { .. }), _) => "' `",
+            (Some(Awaiting::ReplaceChar), _) => "r",
+            (Some(Awaiting::G), None) => "g",
+            (Some(Awaiting::G), Some(_)) => "an operator and g",
+            (Some(Awaiting::Z), _) => "z",
+            (Some(Awaiting::Bracket { .. }), _) => "] [",
+            (Some(Awaiting::Surrounding { .. }), _) => "ds cs",
+            (Some(Awaiting::NewSurrounding { .. }), _) => "cs{old}",
+            (None, Some(operator)) => {
+                let line = match operator {
+                    Operator::Delete => ("d", "line"),
+                    Operator::Change => ("c", "line"),
+                    Operator::Yank => ("y", "line"),
+                    Operator::Indent => (">", "line"),
+                    Operator::Dedent => ("<", "line"),
+                    Operator::Comment => ("c", "line"),
+                    Operator::Surround => ("s", "line"),
+                };
+                let mut hints = vec![line];
+                hints.extend(help::pending("an operator"));
+                if matches!(
+                    operator,
+                    Operator::Delete | Operator::Change | Operator::Yank
+                ) {
+                    hints.extend(help::pending("d c y"));
+                }
+                return self.hint_lines(&hints);
             }
-            Mode::Command => {
-                self.status_message = StatusMessage::from(String::from(":"));
+            (None, None) if self.pending.register.is_some() => "\"{register}",
+            (None, None) => return Vec::new(),
+        };
+        self.hint_lines(help::pending(first))
+    }
+
+    // The keys typed so far, then each continuation under them.
+    fn hint_lines(&self, hints: &[(&str, &str)]) -> Vec<String> {
+        let width = hints
+            .iter()
+            .map(|(keys, _)| keys.width())
+            .max()
+            .unwrap_or(0);
+        iter::once(self.pending.keys.clone())
+            .chain(
+                hints
+                    .iter()
+                    .map(|(keys, description)| format!("  {keys:<width$}  {description}")),
+            )
+            .collect()
+    }
+
+    fn process_normal_key(&mut self, key: Key) {
+        if let Some((start, end)) = self.surrounding.take() {
+            if let Key::Char(c) = key {
+                self.add_surrounding(c, &start, &end);
             }
-            Mode::Search => {
-                self.position_buffer = self.cursor_position.clone();
-                self.status_message = StatusMessage::from(String::from("/"));
+            return;
+        }
+        if let Some(awaiting) = self.pending.awaiting.take() {
+            match (awaiting, key) {
+                (Awaiting::Register, Key::Char(name)) if Registers::is_valid(name) => {
+                    self.pending.register = Some(name);
+                    return;
+                }
+                (Awaiting::Find { direction, till }, Key::Char(c)) => {
+                    let find = CharFind {
+                        c,
+                        direction,
+                        till,
+                        repeat: false,
+                    };
+                    self.last_find = Some(find);
+                    self.execute_motion(Motion::Find(find));
+                }
+                (Awaiting::ReplaceChar, Key::Char(c)) => self.replace_chars(c),
+                (Awaiting::G, Key::Char('g')) => {
+                    let line = self.pending_count().saturating_sub(1);
+                    self.execute_motion(Motion::Line(line));
+                }
+                (Awaiting::G, Key::Char('b')) => self.blame(),
+                // `gc` comments the lines a motion covers
+                (Awaiting::G, Key::Char('c')) => match self.pending.operator {
+                    _ if matches!(self.mode, Mode::Visual) => {
+                        self.apply_visual(Operator::Comment);
+                    }
+                    None => {
+                        self.pending.operator = Some(Operator::Comment);
+                        self.pending.operator_count = self.pending.count.take();
+                        return;
+                    }
+                    Some(Operator::Comment) => {
+                        let count = self.pending_count();
+                        self.apply_linewise(Operator::Comment, self.cursor_position.y, count);
+                    }
+                    Some(_) => (),
+                },
+                (Awaiting::G, Key::Char('d')) => {
+                    if self.lsp.has_server(&self.document.file_type()) {
+                        self.ask_lsp(Query::Definition);
+                    } else {
+                        self.go_to_local_definition();
+                    }
+                }
+                (Awaiting::Surrounding { change: false }, Key::Char(c)) => {
+                    self.change_surrounding(c, None);
+                }
+                (Awaiting::Surrounding { change: true }, Key::Char(old)) => {
+                    self.pending.awaiting = Some(Awaiting::NewSurrounding { old });
+                    return;
+                }
+                (Awaiting::NewSurrounding { old }, Key::Char(c)) => {
+                    self.change_surrounding(old, Some(c));
+                }
+                (Awaiting::Bracket { forward }, Key::Char('c')) => {
+                    for _ in 0..self.pending_count() {
+                        self.jump_to_hunk(forward);
+                    }
+                }
+                (Awaiting::Bracket { forward }, Key::Char('s')) => {
+                    for _ in 0..self.pending_count() {
+                        self.jump_to_misspelling(forward);
+                    }
+                }
+                (Awaiting::Z, Key::Char('=')) => self.suggest_spellings(),
+                (Awaiting::Z, Key::Char(c @ ('z' | 't' | 'b'))) => self.align_view(c),
+                (Awaiting::SetMark, Key::Char(name)) if name.is_ascii_lowercase() => {
+                    self.document.set_mark(name, &self.cursor_position);
+                }
+                (Awaiting::JumpToMark { exact }, Key::Char(name)) => {
+                    if self.document.mark(name).is_none() {
+                        self.status_message = StatusMessage::from(String::from("Mark not set"));
+                    } else if exact {
+                        self.execute_motion(Motion::Mark(name));
+                    } else {
+                        self.execute_motion(Motion::MarkLine(name));
+                    }
+                }
+                (Awaiting::TextObject { around }, Key::Char(c)) => {
+                    if let Some(object) = TextObject::from_char(c) {
+                        if matches!(self.mode, Mode::Visual) {
+                            self.select_text_object(object, around);
+                        } else {
+                            self.execute_text_object(object, around);
+                        }
+                    }
+                }
+                _ => (),
             }
+            self.pending = PendingCommand::default();
+            return;
+        }
+        if self.document.is_directory()
+            && self.pending.operator.is_none()
+            && self.process_directory_key(key)
+        {
+            self.pending = PendingCommand::default();
+            return;
         }
-        self.mode = mode;
-    }
-}
 
-fn die(_e: std::io::Error) {
-    Terminal::clear_screen();
-    exit(0);
-}
+        match key {
+            // Counts, where a leading `0` is still the line start motion
+            Key::Char(c @ '0'..='9') if c != '0' || self.pending.count.is_some() => {
+                let digit = c.to_digit(10).map_or(0, |digit| digit as usize);
+                let count = self.pending.count.unwrap_or(0);
+                self.pending.count = Some(count.saturating_mul(10).saturating_add(digit));
+                return;
+            }
+            Key::Char('"') if self.pending.operator.is_none() => {
+                self.pending.awaiting = Some(Awaiting::Register);
+                return;
+            }
+            Key::Char(c @ ('\'' | '`')) => {
+                self.pending.awaiting = Some(Awaiting::JumpToMark { exact: c == '`' });
+                return;
+            }
+            Key::Char('r') if self.pending.operator.is_none() => {
+                self.pending.awaiting = Some(Awaiting::ReplaceChar);
+                return;
+            }
+            Key::Char('R') if self.pending.operator.is_none() => self.switch_mode(Mode::Replace),
+            Key::Char('K') if self.pending.operator.is_none() => self.ask_lsp(Query::Hover),
+            Key::Char('m') if self.pending.operator.is_none() => {
+                self.pending.awaiting = Some(Awaiting::SetMark);
+                return;
+            }
+            Key::Char('g') => {
+                self.pending.awaiting = Some(Awaiting::G);
+                return;
+            }
+            Key::Char('z') if self.pending.operator.is_none() => {
+                self.pending.awaiting = Some(Awaiting::Z);
+                return;
+            }
+            Key::Char(c @ (']' | '[')) if self.pending.operator.is_none() => {
+                self.pending.awaiting = Some(Awaiting::Bracket { forward: c == ']' });
+                return;
+            }
+            // `G` goes to the last line, or to line `{count}` when given one
+            Key::Char('G') if self.has_count() => {
+                let line = self.pending_count().saturating_sub(1);
+                self.execute_motion(Motion::Line(line));
+            }
+            Key::Char(c @ ('f' | 'F' | 't' | 'T')) => {
+                self.pending.awaiting = Some(Awaiting::Find {
+                    direction: if c.is_ascii_lowercase() {
+                        SearchDirection::Forward
+                    } else {
+                        SearchDirection::Backward
+                    },
+                    till: c.eq_ignore_ascii_case(&'t'),
+                });
+                return;
+            }
+            Key::Char(c @ ('n' | 'N')) if self.pending.operator.is_none() => {
+                for _ in 0..self.pending_count() {
+                    self.repeat_search(c == 'N');
+                }
+            }
+            Key::Char(c @ ('*' | '#')) if self.pending.operator.is_none() => {
+                self.search_word_under_cursor(c == '*');
+            }
+            // `;` repeats the last character find and `,` reverses it
+            Key::Char(c @ (';' | ',')) => {
+                if let Some(find) = self.last_find {
+                    let find = if c == ',' { find.reversed() } else { find };
+                    self.execute_motion(Motion::Find(CharFind {
+                        repeat: true,
+                        ..find
+                    }));
+                }
+            }
+
+            // Operators, where doubling one (`dd`, `cc`, `>>`) acts on lines
+            Key::Char(c @ ('d' | 'c' | 'y' | '>' | '<')) => {
+                let operator = match c {
+                    'd' => Operator::Delete,
+                    'c' => Operator::Change,
+                    '>' => Operator::Indent,
+                    '<' => Operator::Dedent,
+                    _ => Operator::Yank,
+                };
+                match self.pending.operator {
+                    None => {
+                        self.pending.operator = Some(operator);
+                        self.pending.operator_count = self.pending.count.take();
+                        return;
+                    }
+                    // `gcc` works like doubling `gc`
+                    Some(pending)
+                        if pending == operator || (pending == Operator::Comment && c == 'c') =>
+                    {
+                        let count = self.pending_count();
+                        self.apply_linewise(pending, self.cursor_position.y, count);
+                    }
+                    Some(_) => (),
+                }
+            }
+            // `ds`, `cs` and `ys` work on surroundings, and `yss` on a line
+            Key::Char('s') if self.pending.operator.is_some() => match self.pending.operator {
+                Some(operator @ (Operator::Delete | Operator::Change)) => {
+                    self.pending.awaiting = Some(Awaiting::Surrounding {
+                        change: operator == Operator::Change,
+                    });
+                    return;
+                }
+                Some(Operator::Yank) => {
+                    self.pending.operator = Some(Operator::Surround);
+                    return;
+                }
+                Some(Operator::Surround) => {
+                    let count = self.pending_count();
+                    self.apply_linewise(Operator::Surround, self.cursor_position.y, count);
+                }
+                _ => (),
+            },
+            // `do` and `dp` take the difference under the cursor from the
+            // other file or put it there
+            Key::Char(c @ ('o' | 'p'))
+                if self.pending.operator == Some(Operator::Delete) && self.diff.is_some() =>
+            {
+                if c == 'o' {
+                    self.diff_obtain();
+                } else {
+                    self.diff_put();
+                }
+            }
+            Key::Char(c @ ('i' | 'a')) if self.pending.operator.is_some() => {
+                self.pending.awaiting = Some(Awaiting::TextObject { around: c == 'a' });
+                return;
+            }
+            _ if self.pending.operator.is_some() => {
+                if let Some(motion) = Self::motion_for(key) {
+                    self.execute_motion(motion);
+                }
+            }
+
+            // Command mutators
+            Key::Char('i') => self.switch_mode(Mode::Insert),
+            Key::Char('a') => {
+                self.move_cursor(Key::Right);
+                self.switch_mode(Mode::Insert);
+            }
+            Key::Char(':') => self.switch_mode(Mode::Command),
+            Key::Char('/') => self.switch_mode(Mode::Search),
+            Key::Char(c @ ('v' | 'V')) => self.start_visual(c == 'V'),
+            Key::Char(c @ ('o' | 'O')) => {
+                let y = self.cursor_position.y;
+                let mut indent = self.document.auto_indentation(y);
+                let end = Position {
+                    x: self.document.row(y).map_or(0, Row::len),
+                    y,
+                };
+                if c == 'o' && self.document.opens_block(&end) {
+                    let width = self.settings.indent_width(&indent);
+                    indent = self
+                        .settings
+                        .indentation(width.saturating_add(self.settings.shiftwidth));
+                }
+                let y = if c == 'o' { y.saturating_add(1) } else { y };
+                let y = y.min(self.document.len());
+                self.document.insert_lines(y, &[indent.clone()]);
+                self.cursor_position = Position {
+                    x: Row::from(indent.as_str()).len(),
+                    y,
+                };
+                self.switch_mode(Mode::Insert);
+            }
+
+            // Registers
+            Key::Char('p') => self.paste(self.pending.register, false),
+            Key::Char('P') => self.paste(self.pending.register, true),
+
+            // Jump list, where Ctrl-I arrives as a tab
+            Key::Ctrl('o') => {
+                let current = self.current_jump();
+                if let Some(jump) = self.jump_list.back(current) {
+                    self.restore_jump(jump);
+                }
+            }
+            Key::Char('\t') => {
+                if let Some(jump) = self.jump_list.forward() {
+                    self.restore_jump(jump);
+                }
+            }
+            // Terminals send Ctrl-] as the same byte as Ctrl-5.
+            Key::Ctrl(']' | '5') => {
+                if let Some((_, word)) = self.word_under_cursor() {
+                    self.jump_to_tag(&word);
+                }
+            }
+            Key::Ctrl('t') => self.pop_tag(),
+
+            // Multiple cursors
+            Key::Ctrl('n') => self.add_cursor(),
+
+            // Moving and duplicating lines
+            Key::Alt(c @ ('j' | 'k')) => {
+                let y = self.cursor_position.y;
+                self.move_lines(y, y, c == 'j');
+            }
+            Key::Alt(c @ ('J' | 'K')) => {
+                let y = self.cursor_position.y;
+                self.duplicate_lines(y, y, c == 'J');
+            }
+
+            // History
+            Key::Char('u') => match self.document.undo() {
+                Some(position) => self.restore_cursor(position),
+                None => {
+                    self.status_message =
+                        StatusMessage::from(String::from("Already at oldest change"));
+                }
+            },
+            Key::Ctrl('r') => match self.document.redo() {
+                Some(position) => self.restore_cursor(position),
+                None => {
+                    self.status_message =
+                        StatusMessage::from(String::from("Already at newest change"));
+                }
+            },
+
+            // Movement keys
+            Key::Up
+            | Key::Down
+            | Key::Left
+            | Key::Right
+            | Key::Char('h')
+            | Key::Char('j')
+            | Key::Char('k')
+            | Key::Char('l')
+            | Key::Backspace
+            | Key::PageUp
+            | Key::PageDown
+            | Key::End
+            | Key::Home => {
+                for _ in 0..self.pending_count() {
+                    self.move_cursor(key);
+                }
+            }
+            // Ctrl-D/Ctrl-U scroll half a screen, or `{count}` lines, with
+            // the cursor, while Ctrl-E/Ctrl-Y scroll only the view
+            Key::Ctrl(c @ ('d' | 'u')) => {
+                let lines = if self.has_count() {
+                    self.pending_count()
+                } else {
+                    (self.text_height() / 2).max(1)
+                };
+                self.scroll_cursor(c == 'd', lines);
+            }
+            Key::Ctrl(c @ ('e' | 'y')) => self.scroll_view(c == 'e', self.pending_count()),
+            Key::Ctrl('q') => self.should_quit = true,
+            Key::Ctrl('p') => self.open_finder(),
+            Key::Ctrl('z') => Terminal::request_suspend(),
+            _ => {
+                if let Some(motion) = Self::motion_for(key) {
+                    self.execute_motion(motion);
+                }
+            }
+        }
+        self.pending = PendingCommand::default();
+    }
+
+    // Motions and their prefixes are handled as in Normal mode, moving the
+    // end of the selection; operators act on the selection and end it.
+    fn process_visual_key(&mut self, key: Key) {
+        if self.pending.awaiting.is_some() {
+            self.process_normal_key(key);
+            return;
+        }
+        let operator = match key {
+            Key::Char('d' | 'x') | Key::Delete => Some(Operator::Delete),
+            Key::Char('c' | 's') => Some(Operator::Change),
+            Key::Char('y') => Some(Operator::Yank),
+            Key::Char('>') => Some(Operator::Indent),
+            Key::Char('<') => Some(Operator::Dedent),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            self.apply_visual(operator);
+            self.pending = PendingCommand::default();
+            return;
+        }
+        match key {
+            Key::Esc | Key::Ctrl('c') => self.switch_mode(Mode::Normal),
+            // The other of `v` and `V` changes the kind of selection.
+            Key::Char(c @ ('v' | 'V')) => {
+                if self.visual.linewise == (c == 'V') {
+                    self.switch_mode(Mode::Normal);
+                } else {
+                    self.visual.linewise = c == 'V';
+                }
+            }
+            Key::Char('o' | 'O') => {
+                mem::swap(&mut self.visual.anchor, &mut self.cursor_position);
+            }
+            Key::Alt(c @ ('j' | 'k' | 'J' | 'K')) => {
+                let (start, end) = self.visual_bounds();
+                if c.is_lowercase() {
+                    self.move_lines(start.y, end.y, c == 'j');
+                } else {
+                    self.duplicate_lines(start.y, end.y, c == 'J');
+                }
+            }
+            Key::Char(c @ ('i' | 'a')) => {
+                self.pending.awaiting = Some(Awaiting::TextObject { around: c == 'a' });
+            }
+            // A search moves the end of the selection to the match.
+            Key::Char('/') => {
+                self.visual.searching = true;
+                self.switch_mode(Mode::Search);
+            }
+            // `:` starts a command on the selected lines.
+            Key::Char(':') => {
+                self.switch_mode(Mode::Command);
+                self.command_buffer = String::from("'<,'>");
+                self.status_message = StatusMessage::from(format!(":{}", self.command_buffer));
+            }
+            Key::Char(
+                '0'..='9'
+                | '"'
+                | 'g'
+                | 'f'
+                | 'F'
+                | 't'
+                | 'T'
+                | ';'
+                | ','
+                | 'n'
+                | 'N'
+                | '*'
+                | '#'
+                | '\''
+                | '`',
+            )
+            | Key::PageUp
+            | Key::PageDown
+            | Key::Ctrl('d' | 'u' | 'e' | 'y') => self.process_normal_key(key),
+            _ if Self::motion_for(key).is_some() => self.process_normal_key(key),
+            _ => self.pending = PendingCommand::default(),
+        }
+    }
+
+    // A left click moves the cursor and dragging selects, while the wheel
+    // scrolls the view, taking the cursor along only when it would go out of
+    // sight.
+    fn process_mouse(&mut self, mouse: MouseEvent) {
+        self.hover = None;
+        self.suggestions = None;
+        if self.popup.take().is_some()
+            || self.finder.is_some()
+            || !matches!(self.mode, Mode::Normal | Mode::Insert | Mode::Visual)
+        {
+            return;
+        }
+        match mouse {
+            MouseEvent::Press(MouseButton::Left, x, y) => {
+                let Some(position) = self.position_at(x, y) else {
+                    return;
+                };
+                if matches!(self.mode, Mode::Visual) {
+                    self.switch_mode(Mode::Normal);
+                }
+                self.cursor_position = position;
+            }
+            MouseEvent::Hold(x, y) => {
+                let Some(position) = self.position_at(x, y) else {
+                    return;
+                };
+                if matches!(self.mode, Mode::Normal) {
+                    self.start_visual(false);
+                }
+                if matches!(self.mode, Mode::Visual) {
+                    self.cursor_position = position;
+                }
+            }
+            MouseEvent::Press(MouseButton::WheelUp, _, _) => {
+                self.scroll_view(false, SCROLL_LINES);
+            }
+            MouseEvent::Press(MouseButton::WheelDown, _, _) => {
+                self.scroll_view(true, SCROLL_LINES);
+            }
+            _ => return,
+        }
+        self.scroll();
+    }
+
+    // Scrolls the view by `lines`, up to showing just the last line, and
+    // takes the cursor along only when it would go out of sight.
+    fn scroll_view(&mut self, down: bool, lines: usize) {
+        if down {
+            let last = self.document.len().saturating_sub(1);
+            self.offset.y = self.offset.y.saturating_add(lines).min(last);
+        } else {
+            self.offset.y = self.offset.y.saturating_sub(lines);
+        }
+        // The cursor stays `scrolloff` lines away from the edges, except at
+        // the start and end of the file.
+        let height = self.text_height();
+        let scrolloff = self.scrolloff();
+        let top = self.offset.y;
+        let bottom = top.saturating_add(height).saturating_sub(1);
+        let last = self.document.len().saturating_sub(1);
+        let low = if top == 0 {
+            0
+        } else {
+            top.saturating_add(scrolloff)
+        };
+        let high = if bottom >= last {
+            bottom
+        } else {
+            bottom.saturating_sub(scrolloff)
+        };
+        let y = self.cursor_position.y.clamp(low, high.max(low));
+        if y != self.cursor_position.y {
+            self.restore_cursor(Position {
+                x: self.cursor_position.x,
+                y,
+            });
+        }
+    }
+
+    // Scrolls so the cursor's line is at the top of the screen (`t`), the
+    // middle (`z`) or the bottom (`b`).
+    fn align_view(&mut self, at: char) {
+        let y = self.cursor_position.y;
+        let height = self.text_height();
+        self.offset.y = match at {
+            't' => y,
+            'b' => y.saturating_sub(height.saturating_sub(1)),
+            _ => y.saturating_sub(height / 2),
+        };
+    }
+
+    // Moves the cursor and the view by `lines`, stopping the view once the
+    // last line is at the bottom.
+    fn scroll_cursor(&mut self, down: bool, lines: usize) {
+        let Position { x, y } = self.cursor_position;
+        let y = if down {
+            let bottom = self.document.len().saturating_sub(self.text_height());
+            self.offset.y = self
+                .offset
+                .y
+                .saturating_add(lines)
+                .min(bottom.max(self.offset.y));
+            y.saturating_add(lines)
+                .min(self.document.len().saturating_sub(1))
+        } else {
+            self.offset.y = self.offset.y.saturating_sub(lines);
+            y.saturating_sub(lines)
+        };
+        self.restore_cursor(Position { x, y });
+    }
+
+    // The document position drawn at the one-based terminal cell `x`, `y`.
+    fn position_at(&self, x: u16, y: u16) -> Option<Position> {
+        let row = usize::from(y).checked_sub(1)?;
+        if row >= self.text_height() {
+            return None;
+        }
+        let column = usize::from(x)
+            .saturating_sub(1)
+            .saturating_sub(self.gutter_width())
+            .saturating_add(self.offset.x);
+        let y = self
+            .offset
+            .y
+            .saturating_add(row)
+            .min(self.document.len().saturating_sub(1));
+        let x = self
+            .document
+            .row(y)
+            .map_or(0, |row| row.index_at(column, self.settings.tabstop));
+        Some(Position { x, y })
+    }
+
+    fn start_visual(&mut self, linewise: bool) {
+        self.visual = Visual {
+            anchor: self.cursor_position.clone(),
+            linewise,
+            searching: false,
+        };
+        self.switch_mode(Mode::Visual);
+    }
+
+    // The ends of the selection in document order.
+    fn visual_bounds(&self) -> (Position, Position) {
+        let anchor = &self.visual.anchor;
+        let cursor = &self.cursor_position;
+        if (anchor.y, anchor.x) <= (cursor.y, cursor.x) {
+            (anchor.clone(), cursor.clone())
+        } else {
+            (cursor.clone(), anchor.clone())
+        }
+    }
+
+    fn apply_visual(&mut self, operator: Operator) {
+        let (start, end) = self.visual_bounds();
+        let linewise = self.visual.linewise;
+        self.switch_mode(Mode::Normal);
+        if linewise {
+            let count = end.y.saturating_sub(start.y).saturating_add(1);
+            self.apply_linewise(operator, start.y, count);
+            return;
+        }
+        // The selection includes the character under its end, and the line
+        // break when that is past the end of the line.
+        let width = self.document.row(end.y).map_or(0, Row::len);
+        let to = if end.x >= width && end.y.saturating_add(1) < self.document.len() {
+            Position {
+                x: 0,
+                y: end.y.saturating_add(1),
+            }
+        } else {
+            Position {
+                x: end.x.saturating_add(1).min(width),
+                y: end.y,
+            }
+        };
+        self.apply_charwise(operator, &start, &to);
+    }
+
+    // `iw`, `a(` and so on select the text object around the cursor.
+    fn select_text_object(&mut self, object: TextObject, around: bool) {
+        let Some(selection) = object.selection(&self.document, &self.cursor_position, around)
+        else {
+            return;
+        };
+        self.visual.linewise = selection.linewise;
+        self.visual.anchor = selection.start;
+        self.cursor_position = if selection.linewise {
+            selection.end
+        } else if selection.end.x == 0 && selection.end.y > self.visual.anchor.y {
+            let y = selection.end.y.saturating_sub(1);
+            Position {
+                x: self.document.row(y).map_or(0, Row::len),
+                y,
+            }
+        } else {
+            Position {
+                x: selection.end.x.saturating_sub(1),
+                y: selection.end.y,
+            }
+        };
+    }
+
+    fn motion_for(key: Key) -> Option<Motion> {
+        match key {
+            Key::Char('h') | Key::Left | Key::Backspace => Some(Motion::Left),
+            Key::Char('l' | ' ') | Key::Right => Some(Motion::Right),
+            Key::Char('k') | Key::Up => Some(Motion::Up),
+            Key::Char('j') | Key::Down => Some(Motion::Down),
+            Key::Char('0') | Key::Home => Some(Motion::LineStart),
+            Key::Char('^') => Some(Motion::FirstNonBlank),
+            Key::Char('$') | Key::End => Some(Motion::LineEnd),
+            Key::Char('w') => Some(Motion::WordForward),
+            Key::Char('%') => Some(Motion::MatchingBracket),
+            Key::Char('e') => Some(Motion::WordEnd),
+            Key::Char('b') => Some(Motion::WordBackward),
+            Key::Char('}') => Some(Motion::ParagraphForward),
+            Key::Char('{') => Some(Motion::ParagraphBackward),
+            Key::Char(')') => Some(Motion::SentenceForward),
+            Key::Char('(') => Some(Motion::SentenceBackward),
+            Key::Char('G') => Some(Motion::LastLine),
+            _ => None,
+        }
+    }
+
+    fn has_count(&self) -> bool {
+        self.pending.count.is_some() || self.pending.operator_count.is_some()
+    }
+
+    // Operator and motion counts multiply, so `2d3w` deletes six words.
+    fn pending_count(&self) -> usize {
+        let operator_count = self.pending.operator_count.unwrap_or(1);
+        operator_count.saturating_mul(self.pending.count.unwrap_or(1))
+    }
+
+    fn execute_motion(&mut self, motion: Motion) {
+        let motion = match motion {
+            Motion::LineStart if self.settings.smarthome => Motion::SmartLineStart,
+            motion => motion,
+        };
+        let count = self.pending_count();
+        let start = self.cursor_position.clone();
+        let Some(operator) = self.pending.operator else {
+            let target = motion.target(&self.document, &start, count);
+            if motion.is_jump() {
+                self.push_jump();
+            }
+            self.restore_cursor(target);
+            return;
+        };
+
+        let change_word = operator == Operator::Change && motion == Motion::WordForward;
+        let (target, inclusive) = match change_word
+            .then(|| motion::change_word_end(&self.document, &start, count))
+            .flatten()
+        {
+            Some(target) => (target, true),
+            None => (
+                motion.target(&self.document, &start, count),
+                motion.is_inclusive(),
+            ),
+        };
+
+        if motion.is_linewise() {
+            let first = start.y.min(target.y);
+            let last = start.y.max(target.y);
+            self.apply_linewise(
+                operator,
+                first,
+                last.saturating_sub(first).saturating_add(1),
+            );
+            return;
+        }
+
+        let (from, mut to) = if (target.y, target.x) < (start.y, start.x) {
+            (target, start)
+        } else {
+            (start, target)
+        };
+        if inclusive {
+            to.x = to.x.saturating_add(1);
+        } else if to.y > from.y && to.x == 0 {
+            // An exclusive motion onto the start of a row stops at the end of
+            // the previous one, so `dw` on the last word keeps the newline.
+            to.y = to.y.saturating_sub(1);
+            to.x = self.document.row(to.y).map_or(0, Row::len);
+        }
+        self.apply_charwise(operator, &from, &to);
+    }
+
+    // Indents the line just opened one shiftwidth deeper than it came, for
+    // the block the line before opened.
+    fn indent_opened_line(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let Some(row) = self.document.row(y) else {
+            return;
+        };
+        let leading = row.substring(0, x);
+        let width = self.settings.indent_width(&leading);
+        let indent = self
+            .settings
+            .indentation(width.saturating_add(self.settings.shiftwidth));
+        let start = Position { x: 0, y };
+        self.document.delete_range(&start, &self.cursor_position);
+        self.document.insert_text(&start, &indent);
+        self.cursor_position.x = Row::from(indent.as_str()).len();
+    }
+
+    // A `}` typed as the first thing on a line closes a block, so it moves
+    // back one shiftwidth.
+    fn dedent_closing_brace(&mut self) {
+        if !self.document.indent_options().dedent_closing_brace() {
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        let Some(row) = self.document.row(y) else {
+            return;
+        };
+        if !row.substring(0, x.saturating_sub(1)).trim().is_empty() {
+            return;
+        }
+        let width = row.len();
+        self.document.dedent_lines(y, 1, &self.settings);
+        let removed = width.saturating_sub(self.document.row(y).map_or(0, Row::len));
+        self.cursor_position.x = x.saturating_sub(removed);
+    }
+
+    // `r` overwrites `count` characters, failing if the line is too short.
+    fn replace_chars(&mut self, c: char) {
+        let count = self.pending_count();
+        let Position { x, y } = self.cursor_position;
+        let width = self.document.row(y).map_or(0, Row::len);
+        if x.saturating_add(count) > width {
+            return;
+        }
+        if c == '\n' {
+            let end = Position {
+                x: x.saturating_add(count),
+                y,
+            };
+            self.document.delete_range(&self.cursor_position, &end);
+            self.document.insert_newline(&self.cursor_position);
+            self.cursor_position = Position {
+                x: 0,
+                y: y.saturating_add(1),
+            };
+            return;
+        }
+        for offset in 0..count {
+            let at = Position {
+                x: x.saturating_add(offset),
+                y,
+            };
+            self.document.replace(&at, c.encode_utf8(&mut [0; 4]));
+        }
+        self.cursor_position.x = x.saturating_add(count.saturating_sub(1));
+    }
+
+    fn execute_text_object(&mut self, object: TextObject, around: bool) {
+        let Some(operator) = self.pending.operator else {
+            return;
+        };
+        let Some(selection) = object.selection(&self.document, &self.cursor_position, around)
+        else {
+            return;
+        };
+        if selection.linewise {
+            let count = selection
+                .end
+                .y
+                .saturating_sub(selection.start.y)
+                .saturating_add(1);
+            self.apply_linewise(operator, selection.start.y, count);
+        } else {
+            self.apply_charwise(operator, &selection.start, &selection.end);
+        }
+    }
+
+    fn apply_charwise(&mut self, operator: Operator, from: &Position, to: &Position) {
+        let register = self.pending.register;
+        let text = match operator {
+            Operator::Yank => self.document.text_range(from, to),
+            Operator::Delete | Operator::Change => self.document.delete_range(from, to),
+            // Shifting and commenting always work on whole lines
+            Operator::Indent | Operator::Dedent | Operator::Comment => {
+                let count = to.y.saturating_sub(from.y).saturating_add(1);
+                self.apply_linewise(operator, from.y, count);
+                return;
+            }
+            Operator::Surround => {
+                self.surrounding = Some((from.clone(), to.clone()));
+                return;
+            }
+        };
+        if !text.is_empty() {
+            self.registers.set(register, Register::charwise(&text));
+        }
+        self.restore_cursor(from.clone());
+        if operator == Operator::Change {
+            self.switch_mode(Mode::Insert);
+        }
+    }
+
+    fn apply_linewise(&mut self, operator: Operator, first: usize, count: usize) {
+        // Lines are surrounded from their first non-blank character on
+        if operator == Operator::Surround {
+            let last = first
+                .saturating_add(count)
+                .saturating_sub(1)
+                .min(self.document.len().saturating_sub(1));
+            let start = Position {
+                x: motion::first_non_blank(&self.document, first),
+                y: first,
+            };
+            let end = Position {
+                x: motion::row_len(&self.document, last),
+                y: last,
+            };
+            self.surrounding = Some((start, end));
+            return;
+        }
+        if matches!(
+            operator,
+            Operator::Indent | Operator::Dedent | Operator::Comment
+        ) {
+            if operator == Operator::Indent {
+                self.document.indent_lines(first, count, &self.settings);
+            } else if operator == Operator::Dedent {
+                self.document.dedent_lines(first, count, &self.settings);
+            } else if !self.document.toggle_comments(first, count) {
+                self.status_message = StatusMessage::from(format!(
+                    "No comments in {} files",
+                    self.document.file_type()
+                ));
+            }
+            self.restore_cursor(Position {
+                x: motion::first_non_blank(&self.document, first),
+                y: first,
+            });
+            return;
+        }
+        let register = self.pending.register;
+        let lines = match operator {
+            Operator::Yank => (first..first.saturating_add(count))
+                .filter_map(|y| self.document.row(y))
+                .map(|row| row.as_str().to_string())
+                .collect(),
+            _ => self.document.delete_lines(first, count),
+        };
+        if !lines.is_empty() {
+            self.registers.set(register, Register::linewise(lines));
+        }
+        match operator {
+            Operator::Yank => self.restore_cursor(Position {
+                x: self.cursor_position.x,
+                y: first,
+            }),
+            Operator::Delete
+            | Operator::Indent
+            | Operator::Dedent
+            | Operator::Comment
+            | Operator::Surround => {
+                self.restore_cursor(Position { x: 0, y: first });
+            }
+            Operator::Change => {
+                let y = first.min(self.document.len());
+                self.document.insert_lines(y, &[String::new()]);
+                self.cursor_position = Position { x: 0, y };
+                self.switch_mode(Mode::Insert);
+            }
+        }
+    }
+
+    fn insert_char(&mut self, c: char) {
+        let len = motion::row_len(&self.document, self.cursor_position.y);
+        self.document.insert(&self.cursor_position, c);
+        // Combining characters extend the previous grapheme
+        if motion::row_len(&self.document, self.cursor_position.y) > len {
+            self.move_cursor(Key::Right);
+        }
+    }
+
+    // Handles a key typed after Ctrl-K or Ctrl-V, returning false when it
+    // ends a character code without being part of it, so it is still typed.
+    fn process_entering_key(&mut self, entering: Entering, key: Key) -> bool {
+        let Key::Char(c) = key else {
+            return matches!(key, Key::Esc);
+        };
+        match entering {
+            Entering::Digraph(None) => self.entering = Some(Entering::Digraph(Some(c))),
+            // Unknown digraphs type their second character, like in vim.
+            Entering::Digraph(Some(first)) => {
+                self.insert_char(digraph::lookup(first, c).unwrap_or(c));
+            }
+            Entering::Literal => {
+                let (radix, max_digits) = match c {
+                    'u' => (16, 4),
+                    'U' => (16, 8),
+                    'x' | 'X' => (16, 2),
+                    'o' | 'O' => (8, 3),
+                    '0'..='9' => (10, 3),
+                    // Anything else is typed as it is, so Ctrl-V Tab is a
+                    // tab even with `expandtab`.
+                    '\n' => return false,
+                    c => {
+                        self.insert_char(c);
+                        return true;
+                    }
+                };
+                let digits = if c.is_ascii_digit() {
+                    c.to_string()
+                } else {
+                    String::new()
+                };
+                self.entering = Some(Entering::Code {
+                    radix,
+                    max_digits,
+                    digits,
+                });
+            }
+            Entering::Code {
+                radix,
+                max_digits,
+                mut digits,
+            } => {
+                if !c.is_digit(radix) {
+                    self.insert_code(&digits, radix);
+                    return false;
+                }
+                digits.push(c);
+                if digits.len() < max_digits {
+                    self.entering = Some(Entering::Code {
+                        radix,
+                        max_digits,
+                        digits,
+                    });
+                } else {
+                    self.insert_code(&digits, radix);
+                }
+            }
+        }
+        true
+    }
+
+    fn insert_code(&mut self, digits: &str, radix: u32) {
+        match u32::from_str_radix(digits, radix)
+            .ok()
+            .and_then(char::from_u32)
+        {
+            Some(c) => self.insert_char(c),
+            None if digits.is_empty() => (),
+            None => {
+                self.status_message =
+                    StatusMessage::from(format!("Invalid character code: {digits}"));
+            }
+        }
+    }
+
+    // Puts the delimiters for `c` around the text from `start` to `end`,
+    // leaving out the whitespace it ends with.
+    fn add_surrounding(&mut self, c: char, start: &Position, end: &Position) {
+        let Some((open, close)) = surround::pair(c) else {
+            return;
+        };
+        let mut end = end.clone();
+        while end.y == start.y
+            && end.x > start.x
+            && motion::char_at(
+                &self.document,
+                &Position {
+                    x: end.x.saturating_sub(1),
+                    y: end.y,
+                },
+            )
+            .is_whitespace()
+        {
+            end.x = end.x.saturating_sub(1);
+        }
+        self.document.insert_text(&end, &close);
+        self.document.insert_text(start, &open);
+        self.restore_cursor(start.clone());
+    }
+
+    // Deletes the delimiters for `old` around the cursor, or replaces them
+    // with those for `new`.
+    fn change_surrounding(&mut self, old: char, new: Option<char>) {
+        let Some(delimiters) = surround::find(&self.document, &self.cursor_position, old) else {
+            return;
+        };
+        let (open, close) = match new {
+            Some(new) => match surround::pair(new) {
+                Some(pair) => pair,
+                None => return,
+            },
+            None => (String::new(), String::new()),
+        };
+        // The closing one goes first so the opening one stays where it is.
+        let (start, end) = delimiters.close;
+        self.document.delete_range(&start, &end);
+        self.document.insert_text(&start, &close);
+        let (start, end) = delimiters.open;
+        self.document.delete_range(&start, &end);
+        self.document.insert_text(&start, &open);
+        self.restore_cursor(start);
+    }
+
+    fn paste(&mut self, register: Option<char>, before: bool) {
+        let Some(register) = self.registers.get(register).cloned() else {
+            self.status_message = StatusMessage::from(String::from("Nothing in register"));
+            return;
+        };
+        if register.lines.is_empty() {
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        if register.linewise {
+            let y = if before {
+                y
+            } else {
+                y.saturating_add(1).min(self.document.len())
+            };
+            self.document.insert_lines(y, &register.lines);
+            self.cursor_position = Position { x: 0, y };
+        } else {
+            let width = self.document.row(y).map_or(0, Row::len);
+            let x = if before || width == 0 {
+                x
+            } else {
+                x.saturating_add(1).min(width)
+            };
+            self.document
+                .insert_text(&Position { x, y }, &register.text());
+            self.cursor_position = Position { x, y };
+            if let [line] = register.lines.as_slice() {
+                self.cursor_position.x =
+                    x.saturating_add(Row::from(line.as_str()).len().saturating_sub(1));
+            }
+        }
+    }
+
+    fn show_registers(&mut self) {
+        let mut lines = vec![String::from("Name  Type  Content")];
+        for (name, register) in self.registers.list() {
+            if register.lines.is_empty() {
+                continue;
+            }
+            let kind = if register.linewise { 'l' } else { 'c' };
+            let mut content = register.lines.join("^J");
+            if register.linewise {
+                content.push_str("^J");
+            }
+            lines.push(format!("\"{name}    {kind}     {content}"));
+        }
+        self.show_popup(lines);
+    }
+
+    fn execute_command(&mut self, command: &str) -> Result<(), std::io::Error> {
+        let last = self.document.len().saturating_sub(1);
+        let mark = |name| self.document.mark(name).map(|position| position.y);
+        let (range, command) =
+            match LineRange::parse(command.trim_start(), self.cursor_position.y, last, &mark) {
+                Ok(parsed) => parsed,
+                Err(message) => {
+                    self.status_message = StatusMessage::from(message);
+                    return Ok(());
+                }
+            };
+        if let Some(substitute) = Substitute::parse(command) {
+            let range = range.unwrap_or_else(|| LineRange::line(self.cursor_position.y));
+            return match substitute {
+                Ok(substitute) => self.substitute(range, &substitute),
+                Err(message) => {
+                    self.status_message = StatusMessage::from(message);
+                    Ok(())
+                }
+            };
+        }
+
+        if let Some(result) = self.execute_line_command(range, command) {
+            if let Err(message) = result {
+                self.status_message = StatusMessage::from(message);
+            }
+            return Ok(());
+        }
+        if let Some(result) = self.execute_shell_command(range, command) {
+            return result;
+        }
+
+        let command_buffer_args = command.split_ascii_whitespace().collect::<Vec<&str>>();
+        let Some(name) = command_buffer_args.first() else {
+            // A range on its own, like `:12` or `:$`, goes to its last line.
+            if let Some(range) = range {
+                self.goto_line(range.end);
+            }
+            return Ok(());
+        };
+        if range.is_some() {
+            self.status_message = StatusMessage::from(String::from("No range allowed"));
+            return Ok(());
+        }
+
+        if let Some(replacement) = self.user_commands.get(name) {
+            let arguments = command.trim_start().get(name.len()..).unwrap_or_default();
+            let commands = user_commands::expand(replacement, arguments.trim());
+            return self.run_user_commands(&commands);
+        }
+
+        let force = name.ends_with('!');
+
+        match *name {
+            "q" | "q!" => {
+                let dirty = self.document.is_dirty()
+                    || self.buffers.iter().any(|buffer| buffer.document.is_dirty())
+                    || self.diff.as_ref().is_some_and(|view| view.other.is_dirty());
+                if dirty && !force {
+                    self.status_message = StatusMessage::from(
+                        "File has unsaved changes. Use :wq to save and quit, or :q! to quit without saving.".to_string(),
+                    );
+                } else {
+                    self.should_quit = true;
+                }
+            }
+            "w" | "w!" => {
+                self.write(command_buffer_args.get(1), force)?;
+            }
+            "blame" => self.blame(),
+            "diagnostics" => self.show_diagnostics(),
+            "dig" | "digraphs" => self.show_digraphs(),
+            "GitHunkRevert" => self.revert_hunk(),
+            "gr" | "grep" => {
+                let query = command
+                    .trim_start()
+                    .split_once(char::is_whitespace)
+                    .map_or("", |(_, query)| query.trim());
+                self.grep(query);
+            }
+            "cn" | "cnext" => self.step_quickfix(true),
+            "cp" | "cprev" | "cprevious" | "cN" | "cNext" => self.step_quickfix(false),
+            "cope" | "copen" => self.quickfix_open = true,
+            "todos" => self.list_todos(),
+            "mak" | "make" => {
+                let arguments = command_buffer_args.get(1..).unwrap_or_default().join(" ");
+                let command = format!("{} {arguments}", self.settings.makeprg);
+                self.build(command.trim_end().to_string());
+            }
+            "comp" | "compile" => match command_buffer_args.get(1..) {
+                Some(arguments) if !arguments.is_empty() => self.build(arguments.join(" ")),
+                _ => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "ter" | "terminal" => self.open_terminal(),
+            "preview" => self.toggle_preview(),
+            "hex" => self.toggle_hex(),
+            "ta" | "tag" => match command_buffer_args.get(1) {
+                Some(name) => self.jump_to_tag(name),
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "ccl" | "cclose" => self.quickfix_open = false,
+            "e!" | "edit!" => self.reload(),
+            "diffs" | "diffsplit" => match command_buffer_args.get(1) {
+                Some(file_name) => self.diff_split(file_name),
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "diffo" | "diffoff" => self.end_diff(),
+            "e" | "edit" => match command_buffer_args.get(1) {
+                Some(file_name) => self.edit(file_name),
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "vie" | "view" => match command_buffer_args.get(1) {
+                Some(file_name) => {
+                    self.edit(file_name);
+                    if self.document.file_name.as_deref() == Some(file_name) {
+                        self.document.set_read_only(true);
+                    }
+                }
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "bn" | "bnext" => {
+                let next = self.current_buffer.saturating_add(1);
+                self.switch_buffer(next.checked_rem(self.buffers.len()).unwrap_or(0));
+            }
+            "bp" | "bprev" | "bprevious" => {
+                let previous = self
+                    .current_buffer
+                    .checked_sub(1)
+                    .unwrap_or_else(|| self.buffers.len().saturating_sub(1));
+                self.switch_buffer(previous);
+            }
+            "b" | "buffer" => match command_buffer_args.get(1).map(|n| n.parse::<usize>()) {
+                Some(Ok(number)) if (1..=self.buffers.len()).contains(&number) => {
+                    self.switch_buffer(number.saturating_sub(1));
+                }
+                Some(_) => {
+                    self.status_message = StatusMessage::from(String::from("No such buffer"));
+                }
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "ls" | "buffers" => self.show_buffers(),
+            "n" | "next" => self.edit_argument(
+                Some(self.argument.saturating_add(1)),
+                "Cannot go beyond last file",
+            ),
+            "N" | "Next" | "prev" | "previous" => {
+                self.edit_argument(self.argument.checked_sub(1), "Cannot go before first file");
+            }
+            "fir" | "first" | "rew" | "rewind" => self.edit_argument(Some(0), ""),
+            "la" | "last" => {
+                self.edit_argument(self.arguments.len().checked_sub(1), "");
+            }
+            "ar" | "args" => self.show_arguments(),
+            "set" => {
+                let arguments = settings::join_escaped(&command_buffer_args[1..]);
+                let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+                self.set_options(&arguments);
+            }
+            "reg" | "registers" => self.show_registers(),
+            "mks" | "mksession" => self.make_session(command_buffer_args.get(1)),
+            "so" | "source" => match command_buffer_args.get(1) {
+                Some(file_name) => self.source(file_name)?,
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "marks" => self.show_marks(),
+            "au" | "autocmd" => self.autocmd(&command_buffer_args[1..]),
+            "com" | "command" | "com!" | "command!" => {
+                let arguments = command.trim_start().get(name.len()..).unwrap_or_default();
+                self.define_command(arguments.trim(), force);
+            }
+            "delc" | "delcommand" => match command_buffer_args.get(1) {
+                Some(name) if self.user_commands.remove(name) => (),
+                Some(name) => {
+                    self.status_message = StatusMessage::from(format!("No such command: {name}"));
+                }
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "iab" | "iabbrev" => self.abbreviate(&command_buffer_args[1..]),
+            "scr" | "script" => {
+                let source = command.trim_start().get(name.len()..).unwrap_or_default();
+                if let Err(error) = self.run_script(source) {
+                    self.status_message = StatusMessage::from(format!("Script error: {error}"));
+                }
+            }
+            "iuna" | "iunabbrev" => match command_buffer_args.get(1) {
+                Some(word) if self.abbreviations.remove(word) => (),
+                Some(word) => {
+                    self.status_message =
+                        StatusMessage::from(format!("No such abbreviation: {word}"));
+                }
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Argument required"));
+                }
+            },
+            "h" | "help" => self.help(command_buffer_args.get(1).copied()),
+            "mes" | "messages" => self.show_messages(),
+            "sus" | "suspend" | "st" | "stop" => {
+                self.terminal.suspend()?;
+                self.refresh_git();
+            }
+            "ea" | "earlier" => self.travel_history(command_buffer_args.get(1).copied(), false),
+            "lat" | "later" => self.travel_history(command_buffer_args.get(1).copied(), true),
+            "noh" | "nohlsearch" => self.document.highlight(None),
+            "colo" | "colorscheme" => match command_buffer_args.get(1) {
+                Some(name) => match Theme::load(name) {
+                    Ok(theme) => self.theme = theme,
+                    Err(message) => self.status_message = StatusMessage::from(message),
+                },
+                None => {
+                    self.status_message = StatusMessage::from(self.theme.name().to_string());
+                }
+            },
+            "wq" | "wq!" => {
+                if self.write(command_buffer_args.get(1), force)? {
+                    self.should_quit = true;
+                }
+            }
+            _ if self.scripts.has_command(name) => {
+                self.run_user_command(name, &command_buffer_args[1..]);
+            }
+            _ => {
+                self.status_message = StatusMessage::from(format!("Unrecognized command: {}", name))
+            }
+        }
+        Ok(())
+    }
+
+    // Saves the document, or a copy of it when given another file name.
+    // Writing over a read-only file takes `!`, which also falls back to
+    // `sudo` when the file itself can't be written. Returns whether the
+    // file was written.
+    fn write(&mut self, file_name: Option<&&str>, force: bool) -> Result<bool, std::io::Error> {
+        let own_file =
+            file_name.is_none_or(|name| self.document.file_name.as_deref() == Some(*name));
+        if own_file && self.document.is_read_only() && !force {
+            self.status_message =
+                StatusMessage::from(String::from("'readonly' option is set (add ! to override)"));
+            return Ok(false);
+        }
+        if own_file {
+            self.run_autocommands(AutocommandEvent::BufWritePre);
+        }
+        let written = match self.document.save_as(file_name, &self.settings) {
+            Ok(message) => {
+                // Trimming trailing whitespace can leave the cursor past
+                // the end of its line.
+                self.restore_cursor(self.cursor_position.clone());
+                if own_file {
+                    self.document.set_read_only(false);
+                }
+                self.refresh_git();
+                self.status_message = StatusMessage::from(message);
+                Ok(true)
+            }
+            Err(error) if force && own_file && error.kind() == ErrorKind::PermissionDenied => {
+                self.write_with_sudo()
+            }
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("Error writing file: {error}"));
+                Ok(false)
+            }
+        };
+        if own_file && matches!(written, Ok(true)) {
+            self.run_autocommands(AutocommandEvent::BufWritePost);
+        }
+        written
+    }
+
+    // Writes the document to a private temporary file and has `sudo` copy
+    // it over the original. The password, when sudo wants one, is asked for here
+    // since the terminal's input belongs to the editor.
+    fn write_with_sudo(&mut self) -> Result<bool, std::io::Error> {
+        let Some(file_name) = self.document.file_name.clone() else {
+            return Ok(false);
+        };
+        let password = if shell::output("sudo -n true", None).is_ok() {
+            String::new()
+        } else {
+            match self.read_input("[sudo] password: ", String::new(), true)? {
+                Some(password) => password,
+                None => {
+                    self.status_message = StatusMessage::from(String::from("Write cancelled"));
+                    return Ok(false);
+                }
+            }
+        };
+        let (directory, temporary) = match self.document.write_private_copy() {
+            Ok(written) => written,
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("Error writing file: {error}"));
+                return Ok(false);
+            }
+        };
+        let cmd = format!(
+            "sudo -S -p '' cp -- {} {}",
+            shell::quote(&temporary.to_string_lossy()),
+            shell::quote(&file_name)
+        );
+        let result = shell::output(&cmd, Some(&format!("{password}\n")));
+        let _ = fs::remove_dir_all(&directory);
+        match result {
+            Ok(_) => {
+                self.document.mark_saved();
+                self.document.set_read_only(false);
+                self.status_message =
+                    StatusMessage::from(format!("\"{file_name}\" written with sudo"));
+                Ok(true)
+            }
+            Err(message) => {
+                self.status_message = StatusMessage::from(message);
+                Ok(false)
+            }
+        }
+    }
+
+    // Reads a line typed on the message bar after `prompt`, starting out as
+    // `text`, or `None` when it is cancelled. Hidden input, like a password,
+    // doesn't show.
+    fn read_input(
+        &mut self,
+        prompt: &str,
+        mut text: String,
+        hidden: bool,
+    ) -> Result<Option<String>, std::io::Error> {
+        self.prompting = true;
+        let result = loop {
+            let shown = if hidden { "" } else { text.as_str() };
+            self.status_message = StatusMessage::from(format!("{prompt}{shown}"));
+            if let Err(error) = self.refresh_screen() {
+                break Err(error);
+            }
+            match self.terminal.read_key() {
+                Ok(Key::Char('\n')) => break Ok(Some(text)),
+                Ok(Key::Char(c)) => text.push(c),
+                Ok(Key::Backspace) => {
+                    text.pop();
+                }
+                Ok(Key::Esc | Key::Ctrl('c')) => break Ok(None),
+                Ok(_) => (),
+                Err(error) => break Err(error),
+            }
+        };
+        self.prompting = false;
+        result
+    }
+
+    fn report_read_only(&mut self) {
+        self.status_message = StatusMessage::from(String::from(
+            "Cannot make changes, 'readonly' is set (:set noreadonly to allow them)",
+        ));
+    }
+
+    // Runs `:d`, `:y`, `:m` and `:t`, which work on whole lines and default
+    // to the current one. Their name may be followed directly by an argument,
+    // like `:m0`. Returns `None` when `command` is something else.
+    fn execute_line_command(
+        &mut self,
+        range: Option<LineRange>,
+        command: &str,
+    ) -> Option<Result<(), String>> {
+        let name_end = command
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(command.len());
+        let (name, argument) = command.split_at(name_end);
+        let argument = argument.trim();
+        let range = range.unwrap_or_else(|| LineRange::line(self.cursor_position.y));
+        let result = match name {
+            "d" | "delete" | "y" | "yank" => {
+                self.line_command_arguments(range, argument)
+                    .map(|(register, range)| {
+                        if name.starts_with('d') {
+                            self.delete_line_range(register, range);
+                        } else {
+                            self.yank_line_range(register, range);
+                        }
+                    })
+            }
+            "m" | "move" | "t" | "co" | "copy" => {
+                let last = self.document.len().saturating_sub(1);
+                let mark = |name| self.document.mark(name).map(|position| position.y);
+                LineRange::destination(argument, self.cursor_position.y, last, &mark).and_then(
+                    |destination| {
+                        if name.starts_with('m') {
+                            self.move_line_range(range, destination)
+                        } else {
+                            self.copy_line_range(range, destination);
+                            Ok(())
+                        }
+                    },
+                )
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    // The optional register and count after `:d` and `:y`. A count makes the
+    // command act on that many lines from the last line of the range.
+    fn line_command_arguments(
+        &self,
+        range: LineRange,
+        argument: &str,
+    ) -> Result<(Option<char>, LineRange), String> {
+        let mut rest = argument;
+        let mut register = None;
+        if let Some(c) = rest.chars().next().filter(|c| !c.is_ascii_digit()) {
+            if !Registers::is_valid(c) {
+                return Err(format!("Invalid register: {c}"));
+            }
+            register = Some(c);
+            rest = rest.get(c.len_utf8()..).unwrap_or_default().trim_start();
+        }
+        if rest.is_empty() {
+            return Ok((register, range));
+        }
+        match rest.parse::<usize>() {
+            Ok(count) if count > 0 => {
+                let last = self.document.len().saturating_sub(1);
+                Ok((
+                    register,
+                    LineRange {
+                        start: range.end,
+                        end: range.end.saturating_add(count).saturating_sub(1).min(last),
+                    },
+                ))
+            }
+            _ => Err(format!("Trailing characters: {rest}")),
+        }
+    }
+
+    fn delete_line_range(&mut self, register: Option<char>, range: LineRange) {
+        let lines = self.document.delete_lines(range.start, range.len());
+        if lines.is_empty() {
+            return;
+        }
+        let count = lines.len();
+        self.registers.set(register, Register::linewise(lines));
+        self.restore_cursor(Position {
+            x: 0,
+            y: range.start.min(self.document.len().saturating_sub(1)),
+        });
+        self.cursor_position.x = motion::first_non_blank(&self.document, self.cursor_position.y);
+        self.report_lines(count, "fewer lines");
+    }
+
+    fn yank_line_range(&mut self, register: Option<char>, range: LineRange) {
+        let lines: Vec<String> = (range.start..=range.end)
+            .filter_map(|y| self.document.row(y))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+        let count = lines.len();
+        self.registers.set(register, Register::linewise(lines));
+        self.report_lines(count, "lines yanked");
+    }
+
+    // Moves the lines in `range` so they start at row `destination`, counted
+    // before they are taken out.
+    fn move_line_range(&mut self, range: LineRange, destination: usize) -> Result<(), String> {
+        if destination > range.start && destination <= range.end {
+            return Err(String::from("Cannot move a range of lines into itself"));
+        }
+        let lines = self.document.delete_lines(range.start, range.len());
+        let count = lines.len();
+        let y = if destination > range.end {
+            destination.saturating_sub(count)
+        } else {
+            destination
+        };
+        let y = y.min(self.document.len());
+        self.document.insert_lines(y, &lines);
+        self.goto_moved_lines(y, count);
+        self.report_lines(count, "lines moved");
+        Ok(())
+    }
+
+    fn copy_line_range(&mut self, range: LineRange, destination: usize) {
+        let lines: Vec<String> = (range.start..=range.end)
+            .filter_map(|y| self.document.row(y))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        let count = lines.len();
+        let y = destination.min(self.document.len());
+        self.document.insert_lines(y, &lines);
+        self.goto_moved_lines(y, count);
+        self.report_lines(count, "more lines");
+    }
+
+    // `Alt-j` and `Alt-k` move the lines from `start` to `end` down or up a
+    // line, taking the cursor and any selection with them.
+    fn move_lines(&mut self, start: usize, end: usize, down: bool) {
+        let last = self.document.len().saturating_sub(1);
+        if (down && end >= last) || (!down && start == 0) {
+            return;
+        }
+        let lines = self
+            .document
+            .delete_lines(start, end.saturating_sub(start).saturating_add(1));
+        if lines.is_empty() {
+            return;
+        }
+        let y = if down {
+            start.saturating_add(1)
+        } else {
+            start.saturating_sub(1)
+        };
+        self.document.insert_lines(y, &lines);
+        self.shift_cursor_lines(if down { 1 } else { -1 });
+    }
+
+    // `Alt-J` and `Alt-K` copy the lines from `start` to `end` below or above
+    // themselves, leaving the cursor and any selection on the copy.
+    fn duplicate_lines(&mut self, start: usize, end: usize, down: bool) {
+        let lines: Vec<String> = (start..=end)
+            .filter_map(|y| self.document.row(y))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        let count = lines.len();
+        let y = if down { end.saturating_add(1) } else { start };
+        self.document
+            .insert_lines(y.min(self.document.len()), &lines);
+        if down && !self.document.is_read_only() {
+            self.shift_cursor_lines(isize::try_from(count).unwrap_or(0));
+        }
+    }
+
+    fn shift_cursor_lines(&mut self, lines: isize) {
+        let shift = |y: usize| y.saturating_add_signed(lines);
+        self.cursor_position.y = shift(self.cursor_position.y);
+        if matches!(self.mode, Mode::Visual) {
+            self.visual.anchor.y = shift(self.visual.anchor.y);
+        }
+    }
+
+    // Like vim, the cursor ends up on the last of the lines put at `y`.
+    fn goto_moved_lines(&mut self, y: usize, count: usize) {
+        let last = y.saturating_add(count).saturating_sub(1);
+        self.restore_cursor(Position {
+            x: motion::first_non_blank(&self.document, last),
+            y: last,
+        });
+    }
+
+    // Changes to more than two lines are reported, like vim's `report`.
+    fn report_lines(&mut self, count: usize, what: &str) {
+        if count > 2 {
+            self.status_message = StatusMessage::from(format!("{count} {what}"));
+        }
+    }
+
+    // Runs `:!cmd`, or filters the lines through it when given a range, and
+    // handles `:r !cmd`, `:r file` and `:w !cmd`. Returns `None` when
+    // `command` is something else.
+    fn execute_shell_command(
+        &mut self,
+        range: Option<LineRange>,
+        command: &str,
+    ) -> Option<Result<(), std::io::Error>> {
+        if let Some(cmd) = command.strip_prefix('!') {
+            let cmd = cmd.trim();
+            if cmd.is_empty() {
+                self.status_message = StatusMessage::from(String::from("Argument required"));
+                return Some(Ok(()));
+            }
+            return Some(match range {
+                Some(range) => {
+                    self.filter_lines(range, cmd);
+                    Ok(())
+                }
+                None => self.run_shell(cmd, None),
+            });
+        }
+        let name_end = command
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(command.len());
+        let (name, argument) = command.split_at(name_end);
+        match name {
+            "r" | "read" => {
+                let y = range.map_or(self.cursor_position.y, |range| range.end);
+                self.read_lines(y, argument.trim());
+                Some(Ok(()))
+            }
+            // `:w!cmd` is a forced write to a file named `cmd`, so the space
+            // is what makes this a pipe.
+            "w" | "write" if argument.starts_with(char::is_whitespace) => {
+                let cmd = argument.trim_start().strip_prefix('!')?.trim();
+                let last = self.document.len().saturating_sub(1);
+                let range = range.unwrap_or(LineRange {
+                    start: 0,
+                    end: last,
+                });
+                let input = self.lines_text(range);
+                Some(self.run_shell(cmd, Some(&input)))
+            }
+            _ => None,
+        }
+    }
+
+    // Hands the screen over to `cmd` and waits for a key once it is done, so
+    // its output can be read.
+    fn run_shell(&mut self, cmd: &str, input: Option<&str>) -> Result<(), std::io::Error> {
+        self.terminal.suspend_raw_mode()?;
+        print!("\r\n:!{cmd}\r\n");
+        Terminal::flush()?;
+        match shell::run(cmd, input) {
+            Ok(status) if status.success() => (),
+            Ok(status) => match status.code() {
+                Some(code) => println!("\nshell returned {code}"),
+                None => println!("\nshell command was interrupted"),
+            },
+            Err(error) => println!("\nCannot run {cmd}: {error}"),
+        }
+        self.refresh_git();
+        print!("\nPress any key to continue");
+        Terminal::flush()?;
+        self.terminal.activate_raw_mode()?;
+        self.terminal.read_key()?;
+        self.terminal.invalidate();
+        Ok(())
+    }
+
+    // Replaces the lines in `range` by what `cmd` prints when given them.
+    fn filter_lines(&mut self, range: LineRange, cmd: &str) {
+        let input = self.lines_text(range);
+        match shell::output(cmd, Some(&input)) {
+            Ok(output) => {
+                let lines: Vec<String> = output.lines().map(String::from).collect();
+                self.document.delete_lines(range.start, range.len());
+                let y = range.start.min(self.document.len());
+                self.document.insert_lines(y, &lines);
+                self.restore_cursor(Position {
+                    x: motion::first_non_blank(&self.document, y),
+                    y,
+                });
+                self.report_lines(range.len(), "lines filtered");
+            }
+            Err(message) => self.status_message = StatusMessage::from(message),
+        }
+    }
+
+    // Puts the output of `!cmd`, or the contents of a file, below row `y`.
+    fn read_lines(&mut self, y: usize, argument: &str) {
+        let contents = match argument.strip_prefix('!') {
+            Some(cmd) => shell::output(cmd.trim(), None),
+            None if argument.is_empty() => Err(String::from("Argument required")),
+            None => std::fs::read_to_string(argument)
+                .map_err(|error| format!("Error opening file: {error}")),
+        };
+        match contents {
+            Ok(contents) => {
+                let lines: Vec<String> = contents.lines().map(String::from).collect();
+                let y = if self.document.is_empty() {
+                    0
+                } else {
+                    y.saturating_add(1).min(self.document.len())
+                };
+                self.document.insert_lines(y, &lines);
+                if !lines.is_empty() {
+                    self.restore_cursor(Position {
+                        x: motion::first_non_blank(&self.document, y),
+                        y,
+                    });
+                }
+            }
+            Err(message) => self.status_message = StatusMessage::from(message),
+        }
+    }
+
+    // The lines in `range`, each ending in a newline, as a command reads them.
+    fn lines_text(&self, range: LineRange) -> String {
+        (range.start..=range.end)
+            .filter_map(|y| self.document.row(y))
+            .map(|row| format!("{}\n", row.as_str()))
+            .collect()
+    }
+
+    // Completes the command name, then option names after `:set` or paths
+    // after commands that take a file.
+    fn start_completion(&self) -> Option<Completion> {
+        let line = &self.command_buffer;
+        let word_start = line.rfind(' ').map_or(0, |space| space.saturating_add(1));
+        if word_start == 0 {
+            return Completion::new(line, 0, &Words(COMMANDS.to_vec()));
+        }
+        match line.split_whitespace().next()? {
+            "set" | "se" => {
+                let options = settings::NAMES.iter().chain(BUFFER_OPTIONS);
+                Completion::new(line, word_start, &Words(options.copied().collect()))
+            }
+            "diffs" | "diffsplit" | "e" | "edit" | "mksession" | "r" | "read" | "so" | "source"
+            | "view" | "w" | "wq" => Completion::new(line, word_start, &Paths),
+            "ta" | "tag" => Completion::new(line, word_start, &Tags::load().ok()?),
+            _ => None,
+        }
+    }
+
+    fn substitute(
+        &mut self,
+        range: LineRange,
+        substitute: &Substitute,
+    ) -> Result<(), std::io::Error> {
+        let pattern = match search::compile(
+            &substitute.pattern,
+            !self.settings.regex,
+            substitute.ignore_case,
+        ) {
+            Ok(pattern) => pattern,
+            Err(message) => {
+                self.status_message = StatusMessage::from(message);
+                return Ok(());
+            }
+        };
+        let (count, lines) = if substitute.confirm {
+            self.substitute_confirm(range, &pattern, substitute)?
+        } else {
+            let (count, lines, last_changed) = self.document.substitute(
+                range,
+                &pattern,
+                &substitute.replacement,
+                substitute.global,
+            );
+            if count > 0 {
+                self.cursor_position = Position {
+                    x: 0,
+                    y: last_changed,
+                };
+            }
+            (count, lines)
+        };
+        self.status_message = StatusMessage::from(if count == 0 {
+            format!("Pattern not found: {}", substitute.pattern)
+        } else {
+            format!(
+                "{count} substitution{} on {lines} line{}",
+                if count == 1 { "" } else { "s" },
+                if lines == 1 { "" } else { "s" }
+            )
+        });
+        Ok(())
+    }
+
+    // Asks before each replacement, like vim's `c` flag: `y` replaces, `n`
+    // skips, `a` replaces the rest, `l` replaces this one and stops, and `q`
+    // or Esc stops. Returns the number of matches and rows replaced.
+    fn substitute_confirm(
+        &mut self,
+        range: LineRange,
+        pattern: &Regex,
+        substitute: &Substitute,
+    ) -> Result<(usize, usize), std::io::Error> {
+        let mut end = range.end;
+        let mut at = Position {
+            x: 0,
+            y: range.start,
+        };
+        let mut count = 0_usize;
+        let mut lines = 0_usize;
+        let mut last_changed = None;
+        let mut all = false;
+        self.document.highlight(Some(pattern));
+        while at.y <= end {
+            let Some((start, match_end)) = self
+                .document
+                .row(at.y)
+                .and_then(|row| row.find_match(pattern, at.x, SearchDirection::Forward))
+            else {
+                at = Position {
+                    x: 0,
+                    y: at.y.saturating_add(1),
+                };
+                continue;
+            };
+            let answer = if all {
+                'a'
+            } else {
+                self.cursor_position = Position { x: start, y: at.y };
+                self.scroll();
+                self.status_message =
+                    StatusMessage::from(String::from("Replace this match? (y/n/a/q/l)"));
+                self.refresh_screen()?;
+                match self.terminal.read_key()? {
+                    Key::Char(c @ ('y' | 'n' | 'a' | 'l')) => c,
+                    _ => break,
+                }
+            };
+            let next = if answer == 'n' {
+                Position {
+                    x: match_end.max(start.saturating_add(1)),
+                    y: at.y,
+                }
+            } else {
+                let rows = self.document.len();
+                let match_start = Position { x: start, y: at.y };
+                let Some(after) =
+                    self.document
+                        .substitute_at(&match_start, pattern, &substitute.replacement)
+                else {
+                    break;
+                };
+                end = end.saturating_add(self.document.len().saturating_sub(rows));
+                count = count.saturating_add(1);
+                if last_changed != Some(at.y) {
+                    lines = lines.saturating_add(1);
+                }
+                last_changed = Some(after.y);
+                self.cursor_position = match_start.clone();
+                if answer == 'l' {
+                    break;
+                }
+                all = all || answer == 'a';
+                // An empty match replaced by nothing would be found again.
+                if after == match_start {
+                    Position {
+                        x: start.saturating_add(1),
+                        y: at.y,
+                    }
+                } else {
+                    after
+                }
+            };
+            at = if substitute.global {
+                next
+            } else {
+                Position {
+                    x: 0,
+                    y: next.y.saturating_add(1),
+                }
+            };
+        }
+        self.document.highlight(None);
+        Ok((count, lines))
+    }
+
+    // Switches to the buffer for `file_name`, opening it if needed.
+    fn edit(&mut self, file_name: &str) {
+        if self.document.file_name.as_deref() == Some(file_name) {
+            return;
+        }
+        if let Some(index) = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.document.file_name.as_deref() == Some(file_name))
+        {
+            self.switch_buffer(index);
+            return;
+        }
+        match self.open_buffer(file_name) {
+            Ok(index) => {
+                self.switch_buffer(index);
+                self.restore_cursor(self.cursor_position.clone());
+            }
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("Error opening file: {error}"));
+            }
+        }
+    }
+
+    // The keys that browse a directory listing: Enter opens the entry under
+    // the cursor and `-` the directory above, while `%` and `d` make a new
+    // file or directory, `D` deletes an entry and `R` renames it. Returns
+    // whether `key` was one of them.
+    fn process_directory_key(&mut self, key: Key) -> bool {
+        let dir = self.document.file_name.clone().unwrap_or_default();
+        let entry = self
+            .document
+            .row(self.cursor_position.y)
+            .map(|row| row.as_str().to_string())
+            .unwrap_or_default();
+        let result = match key {
+            Key::Char('\n') => {
+                self.browse(&explorer::join(&dir, &entry));
+                Ok(())
+            }
+            Key::Char('-') => {
+                self.browse(&explorer::join(&dir, "../"));
+                Ok(())
+            }
+            Key::Char(c @ ('%' | 'd')) => self.create_entry(&dir, c == 'd'),
+            Key::Char('D') => self.delete_entry(&dir, &entry),
+            Key::Char('R') => self.rename_entry(&dir, &entry),
+            _ => return false,
+        };
+        if let Err(error) = result {
+            self.status_message = StatusMessage::from(format!("Error: {error}"));
+        }
+        true
+    }
+
+    // Edits `path`, and when it is the directory that held the current one
+    // puts the cursor on the entry that was left.
+    fn browse(&mut self, path: &str) {
+        let left = self.document.file_name.clone().unwrap_or_default();
+        self.edit(path);
+        if !self.document.is_directory() {
+            return;
+        }
+        let Some(name) = Path::new(&left).file_name() else {
+            return;
+        };
+        let entry = format!("{}/", name.to_string_lossy());
+        self.select_entry(&entry);
+    }
+
+    // Moves the cursor to the row of the listing naming `entry`.
+    fn select_entry(&mut self, entry: &str) {
+        if let Some(y) =
+            (0..self.document.len()).find(|&y| self.document.row(y).map(Row::as_str) == Some(entry))
+        {
+            self.restore_cursor(Position { x: 0, y });
+        }
+    }
+
+    fn create_entry(&mut self, dir: &str, directory: bool) -> Result<(), std::io::Error> {
+        let prompt = if directory {
+            "New directory: "
+        } else {
+            "New file: "
+        };
+        let Some(name) = self.read_input(prompt, String::new(), false)? else {
+            self.status_message = StatusMessage::from(String::new());
+            return Ok(());
+        };
+        if name.is_empty() {
+            self.status_message = StatusMessage::from(String::new());
+            return Ok(());
+        }
+        let name = if directory && !name.ends_with('/') {
+            format!("{name}/")
+        } else {
+            name
+        };
+        explorer::create(&format!("{}/{name}", dir.trim_end_matches('/')))?;
+        self.reload();
+        self.select_entry(&name);
+        self.status_message = StatusMessage::from(format!("Created {name}"));
+        Ok(())
+    }
+
+    fn delete_entry(&mut self, dir: &str, entry: &str) -> Result<(), std::io::Error> {
+        if entry == "../" {
+            return Ok(());
+        }
+        self.status_message = StatusMessage::from(format!("Delete {entry}? (y/n)"));
+        self.refresh_screen()?;
+        if self.terminal.read_key()? != Key::Char('y') {
+            self.status_message = StatusMessage::from(String::new());
+            return Ok(());
+        }
+        explorer::delete(&explorer::join(dir, entry))?;
+        self.reload();
+        self.status_message = StatusMessage::from(format!("Deleted {entry}"));
+        Ok(())
+    }
+
+    fn rename_entry(&mut self, dir: &str, entry: &str) -> Result<(), std::io::Error> {
+        if entry == "../" {
+            return Ok(());
+        }
+        let old_name = entry.trim_end_matches('/');
+        let Some(name) = self.read_input("Rename to: ", old_name.to_string(), false)? else {
+            self.status_message = StatusMessage::from(String::new());
+            return Ok(());
+        };
+        if name.is_empty() || name == old_name {
+            self.status_message = StatusMessage::from(String::new());
+            return Ok(());
+        }
+        fs::rename(explorer::join(dir, entry), explorer::join(dir, &name))?;
+        self.reload();
+        let suffix = if entry.ends_with('/') { "/" } else { "" };
+        self.select_entry(&format!("{}{suffix}", name.trim_end_matches('/')));
+        self.status_message = StatusMessage::from(format!("Renamed {entry} to {name}"));
+        Ok(())
+    }
+
+    // Searches the files under the current directory for `query`, listing
+    // the matching lines in the quickfix panel and going to the first.
+    fn grep(&mut self, query: &str) {
+        self.grep_in(&env::current_dir().unwrap_or_default(), query);
+    }
+
+    // Searches the files under `root`, which are listed by their path from
+    // the current directory, or in full when they aren't under it.
+    fn grep_in(&mut self, root: &Path, query: &str) {
+        if query.is_empty() {
+            self.status_message = StatusMessage::from(String::from("Argument required"));
+            return;
+        }
+        let pattern = match search::compile(query, !self.settings.regex, false) {
+            Ok(pattern) => pattern,
+            Err(message) => {
+                self.status_message = StatusMessage::from(message);
+                return;
+            }
+        };
+        let current = env::current_dir().unwrap_or_default();
+        let mut entries = grep::search(root, &pattern);
+        for entry in &mut entries {
+            let path = root.join(&entry.file);
+            entry.file = path
+                .strip_prefix(&current)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned();
+        }
+        if entries.is_empty() {
+            self.status_message = StatusMessage::from(format!("No matches for {query}"));
+            return;
+        }
+        self.quickfix = Quickfix::new(format!(":grep {query}"), entries);
+        self.quickfix_open = true;
+        self.go_to_quickfix_entry();
+    }
+
+    // Puts the TODO, FIXME, XXX and NOTE markers in the comments of the
+    // buffer in the quickfix list.
+    fn list_todos(&mut self) {
+        let Some(file) = self.document.file_name.clone() else {
+            self.status_message = StatusMessage::from(String::from("No file name"));
+            return;
+        };
+        self.document.highlight_rows(0, self.document.len());
+        let mut entries = Vec::new();
+        for y in 0..self.document.len() {
+            let Some(row) = self.document.row(y) else {
+                break;
+            };
+            let mut x = 0;
+            while x < row.len() {
+                if *row.highlighting_at(x) != highlighting::Type::Todo {
+                    x = x.saturating_add(1);
+                    continue;
+                }
+                entries.push(quickfix::Entry {
+                    file: file.clone(),
+                    line: y.saturating_add(1),
+                    column: x.saturating_add(1),
+                    text: row.as_str().trim().to_string(),
+                });
+                while *row.highlighting_at(x) == highlighting::Type::Todo {
+                    x = x.saturating_add(1);
+                }
+            }
+        }
+        if entries.is_empty() {
+            self.status_message = StatusMessage::from(String::from("No TODOs found"));
+            return;
+        }
+        self.quickfix = Quickfix::new(String::from(":todos"), entries);
+        self.quickfix_open = true;
+        self.go_to_quickfix_entry();
+    }
+
+    // Runs a build command in the background, or right away without a
+    // terminal, and lists the errors it reports in the quickfix panel.
+    fn build(&mut self, command: String) {
+        if self.building.is_some() {
+            self.status_message = StatusMessage::from(String::from("A build is already running"));
+            return;
+        }
+        if self.terminal.is_headless() {
+            self.finish_build(make::run(command));
+            return;
+        }
+        self.status_message = StatusMessage::from(format!("Running {command}"));
+        self.building = Some(command.clone());
+        make::start(command, self.terminal.event_sender());
+    }
+
+    fn finish_build(&mut self, build: make::Build) {
+        self.building = None;
+        let (success, output) = match build.result {
+            Ok(result) => result,
+            Err(message) => {
+                self.status_message = StatusMessage::from(message);
+                return;
+            }
+        };
+        let entries = make::parse(&output);
+        if entries.is_empty() {
+            self.status_message = StatusMessage::from(if success {
+                format!("{} succeeded", build.command)
+            } else {
+                format!("{} failed without reporting where", build.command)
+            });
+            return;
+        }
+        self.quickfix = Quickfix::new(format!(":make {}", build.command), entries);
+        self.quickfix_open = true;
+        self.go_to_quickfix_entry();
+    }
+
+    fn step_quickfix(&mut self, forward: bool) {
+        if self.quickfix.is_empty() {
+            self.status_message = StatusMessage::from(String::from("No quickfix list"));
+        } else if self.quickfix.step(forward, 1).is_none() {
+            self.status_message = StatusMessage::from(String::from("No more items"));
+        } else {
+            self.go_to_quickfix_entry();
+        }
+    }
+
+    // Edits the file of the current quickfix entry with the cursor on it.
+    fn go_to_quickfix_entry(&mut self) {
+        let Some(entry) = self.quickfix.current() else {
+            return;
+        };
+        let file = entry.file.clone();
+        let target = Position {
+            x: entry.column.saturating_sub(1),
+            y: entry.line.saturating_sub(1),
+        };
+        let message = format!(
+            "({} of {}): {}",
+            self.quickfix.current_index().saturating_add(1),
+            self.quickfix.len(),
+            entry.text
+        );
+        self.push_jump();
+        self.edit(&file);
+        if self.document.file_name.as_deref() != Some(file.as_str()) {
+            return;
+        }
+        self.restore_cursor(target);
+        self.scroll();
+        self.status_message = StatusMessage::from(message);
+    }
+
+    // Lists the files under the current directory for picking one by typing
+    // part of its name.
+    fn open_finder(&mut self) {
+        let directory = env::current_dir().unwrap_or_default();
+        let files = finder::list_files(&directory);
+        if files.is_empty() {
+            self.status_message = StatusMessage::from(String::from("No files to find"));
+            return;
+        }
+        self.finder = Some(Finder::new(files));
+    }
+
+    // Typing narrows down the files, the arrows or Tab pick among them and
+    // Enter edits the one picked.
+    fn process_finder_key(&mut self, key: Key) {
+        let Some(finder) = self.finder.as_mut() else {
+            return;
+        };
+        match key {
+            Key::Esc | Key::Ctrl('c') => self.finder = None,
+            Key::Char('\n') => {
+                let selected = finder.selected().map(String::from);
+                self.finder = None;
+                if let Some(file_name) = selected {
+                    self.edit(&file_name);
+                }
+            }
+            Key::Up | Key::Ctrl('p') | Key::BackTab => finder.select(-1),
+            Key::Down | Key::Ctrl('n') | Key::Char('\t') => finder.select(1),
+            Key::Backspace => finder.pop(),
+            Key::Char(c) => finder.push(c),
+            _ => (),
+        }
+    }
+
+    // Loads `file_name` into a new buffer, with the cursor where it was left
+    // the last time the file was open, and returns its index.
+    fn open_buffer(&mut self, file_name: &str) -> Result<usize, std::io::Error> {
+        let mut document = load_document(self.terminal.as_ref(), file_name, self.buffers.len())?;
+        if self.settings.undofile {
+            document.read_undo();
+        }
+        let (cursor_position, offset) = self.positions.get(file_name).unwrap_or_default();
+        self.buffers.push(Buffer {
+            document,
+            cursor_position,
+            offset,
+            unread: true,
+            indentation: None,
+        });
+        Ok(self.buffers.len().saturating_sub(1))
+    }
+
+    // Edits the file at `index` in the argument list, or says why there is
+    // none when it is `None`.
+    fn edit_argument(&mut self, index: Option<usize>, error: &str) {
+        match index.and_then(|index| Some((index, self.arguments.get(index)?.clone()))) {
+            Some((index, file_name)) => {
+                self.argument = index;
+                self.edit(&file_name);
+            }
+            None if self.arguments.is_empty() => {
+                self.status_message = StatusMessage::from(String::from("Argument list is empty"));
+            }
+            None => self.status_message = StatusMessage::from(error.to_string()),
+        }
+    }
+
+    // Lists the argument list with the current file in brackets.
+    fn show_arguments(&mut self) {
+        let list: Vec<String> = self
+            .arguments
+            .iter()
+            .enumerate()
+            .map(|(index, file_name)| {
+                if index == self.argument {
+                    format!("[{file_name}]")
+                } else {
+                    file_name.clone()
+                }
+            })
+            .collect();
+        self.status_message = StatusMessage::from(list.join(" "));
+    }
+
+    // Hands rows streamed in the background to the buffer they belong to.
+    fn apply_load(&mut self, buffer: usize, load: Load) {
+        let document = if buffer == self.current_buffer {
+            &mut self.document
+        } else if let Some(slot) = self.buffers.get_mut(buffer) {
+            &mut slot.document
+        } else {
+            return;
+        };
+        match load {
+            Load::Rows { rows, bytes_read } => document.append_loaded(rows, bytes_read),
+            Load::Finished {
+                file_encoding,
+                file_format,
+                end_of_line,
+            } => document.finish_loading(file_encoding, file_format, end_of_line),
+            Load::Failed(error) => {
+                document.finish_loading(Default::default(), Default::default(), true);
+                self.status_message = StatusMessage::from(format!("Error loading file: {error}"));
+            }
+        }
+    }
+
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current_buffer || index >= self.buffers.len() {
+            return;
+        }
+        self.document.commit();
+        let current = Buffer {
+            document: mem::take(&mut self.document),
+            cursor_position: mem::take(&mut self.cursor_position),
+            offset: mem::take(&mut self.offset),
+            unread: false,
+            indentation: Some(Indentation {
+                tabstop: Some(self.settings.tabstop),
+                shiftwidth: Some(self.settings.shiftwidth),
+                expandtab: Some(self.settings.expandtab),
+                indent_after: None,
+            }),
+        };
+        let Some(slot) = self.buffers.get_mut(index) else {
+            return;
+        };
+        let next = mem::take(slot);
+        if let Some(slot) = self.buffers.get_mut(self.current_buffer) {
+            *slot = current;
+        }
+        self.document = next.document;
+        self.cursor_position = next.cursor_position;
+        self.offset = next.offset;
+        self.current_buffer = index;
+        if let Some(indentation) = &next.indentation {
+            self.apply_indentation(indentation);
+        }
+        if next.unread {
+            self.run_read_autocommands();
+        }
+    }
+
+    // Runs the autocommands for `event` on the current buffer, reporting what
+    // goes wrong on the message line.
+    fn run_autocommands(&mut self, event: AutocommandEvent) {
+        if self.running_autocommands || self.document.is_directory() || self.document.is_help() {
+            return;
+        }
+        let name = match event {
+            AutocommandEvent::FileType => {
+                let file_type = self.document.file_type();
+                if file_type == FileType::default().name() {
+                    return;
+                }
+                file_type.to_lowercase()
+            }
+            _ => match &self.document.file_name {
+                Some(file_name) => file_name.clone(),
+                None => return,
+            },
+        };
+        self.running_autocommands = true;
+        for command in self.autocommands.commands(event, &name) {
+            if let Err(error) = self.execute_command(&command) {
+                self.status_message = StatusMessage::from(error.to_string());
+            }
+        }
+        self.running_autocommands = false;
+    }
+
+    // A buffer is read once it is first shown, when it takes on how its file
+    // type is indented, and then its autocommands run.
+    fn run_read_autocommands(&mut self) {
+        let file_type = self.document.file_type();
+        let indentation = self
+            .filetype_settings
+            .indentation(&file_type, self.document.file_type_indentation());
+        self.apply_indentation(&indentation);
+        self.document.set_indent_after(indentation.indent_after);
+        self.run_autocommands(AutocommandEvent::BufRead);
+        self.run_autocommands(AutocommandEvent::FileType);
+    }
+
+    fn apply_indentation(&mut self, indentation: &Indentation) {
+        if let Some(tabstop) = indentation.tabstop {
+            self.settings.tabstop = tabstop;
+        }
+        if let Some(shiftwidth) = indentation.shiftwidth {
+            self.settings.shiftwidth = shiftwidth;
+        }
+        if let Some(expandtab) = indentation.expandtab {
+            self.settings.expandtab = expandtab;
+        }
+    }
+
+    // Runs the scripts in the `scripts` directory of the config directory,
+    // which is where functions and commands are usually defined.
+    fn run_script_files(&mut self) {
+        for (name, source) in script::sources() {
+            if let Err(error) = source.and_then(|source| self.run_script(&source)) {
+                self.status_message = StatusMessage::from(format!("Error in {name}: {error}"));
+            }
+        }
+    }
+
+    fn run_script(&mut self, source: &str) -> Result<(), String> {
+        self.with_scripts(|scripts, editor| scripts.run(source, editor))
+    }
+
+    // Runs the script function `name` as a command, passing it the
+    // command's arguments.
+    fn run_user_command(&mut self, name: &str, arguments: &[&str]) {
+        let arguments = arguments
+            .iter()
+            .map(|&argument| Value::from(argument))
+            .collect();
+        let result = self.with_scripts(|scripts, editor| scripts.call(name, arguments, editor));
+        if let Err(error) = result {
+            self.status_message = StatusMessage::from(format!("Script error: {error}"));
+        }
+    }
+
+    // Scripts can run commands that run scripts, so each works on a copy of
+    // what has been defined, which it puts back once it's done. The limits
+    // of one run don't stop a script that runs itself that way, so the runs
+    // inside each other are counted too.
+    fn with_scripts<T>(
+        &mut self,
+        run: impl FnOnce(&mut Scripts, &mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if self.script_depth >= 20 {
+            return Err(String::from("too many nested calls"));
+        }
+        self.script_depth = self.script_depth.saturating_add(1);
+        let mut scripts = self.scripts.clone();
+        let result = run(&mut scripts, self);
+        self.scripts = scripts;
+        self.script_depth = self.script_depth.saturating_sub(1);
+        result
+    }
+
+    fn show_buffers(&mut self) {
+        let mut lines = Vec::new();
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            let (document, line) = if index == self.current_buffer {
+                (&self.document, self.cursor_position.y)
+            } else {
+                (&buffer.document, buffer.cursor_position.y)
+            };
+            let active = if index == self.current_buffer {
+                '%'
+            } else {
+                ' '
+            };
+            let dirty = if document.is_dirty() { '+' } else { ' ' };
+            let name = document.file_name.as_deref().unwrap_or("[No Name]");
+            lines.push(format!(
+                "{:>3} {active} {dirty} \"{name}\"  line {}",
+                index.saturating_add(1),
+                line.saturating_add(1)
+            ));
+        }
+        self.show_popup(lines);
+    }
+
+    fn set_options(&mut self, arguments: &[&str]) {
+        let mut messages = Vec::new();
+        for argument in arguments {
+            let result = self
+                .set_buffer_option(argument)
+                .unwrap_or_else(|| self.settings.set(argument));
+            match result {
+                Ok(Some(message)) | Err(message) => messages.push(message),
+                Ok(None) => (),
+            }
+        }
+        if let Err(message) = self.load_dictionary() {
+            self.settings.spell = false;
+            messages.push(message);
+        }
+        if self.settings.undofile {
+            self.document.read_undo();
+        }
+        if !messages.is_empty() {
+            self.status_message = StatusMessage::from(messages.join(" "));
+        }
+    }
+
+    // Goes back or forward through the undo history by a number of changes
+    // or a time, for `:earlier` and `:later`.
+    fn travel_history(&mut self, argument: Option<&str>, forward: bool) {
+        let Some(distance) = undo::Distance::parse(argument) else {
+            self.status_message = StatusMessage::from(format!(
+                "Invalid argument: {}",
+                argument.unwrap_or_default()
+            ));
+            return;
+        };
+        match self.document.travel(&distance, forward) {
+            Some(position) => {
+                self.restore_cursor(position);
+                let (state, count) = self.document.undo_state();
+                self.status_message =
+                    StatusMessage::from(format!("Change {state} of {}", count.saturating_sub(1)));
+            }
+            None => {
+                self.status_message = StatusMessage::from(String::from(if forward {
+                    "Already at newest change"
+                } else {
+                    "Already at oldest change"
+                }));
+            }
+        }
+    }
+
+    // `:autocmd {event} {pattern} {command}` adds an autocommand, and lists
+    // them without arguments.
+    fn autocmd(&mut self, arguments: &[&str]) {
+        match arguments {
+            [] => {
+                let lines = self.autocommands.list();
+                if lines.is_empty() {
+                    self.status_message = StatusMessage::from(String::from("No autocommands"));
+                } else {
+                    self.show_popup(lines);
+                }
+            }
+            [event, pattern, command @ ..] if !command.is_empty() => {
+                match AutocommandEvent::from_name(event) {
+                    Some(event) => self.autocommands.add(event, pattern, &command.join(" ")),
+                    None => {
+                        self.status_message =
+                            StatusMessage::from(format!("Unknown autocmd event: {event}"));
+                    }
+                }
+            }
+            _ => {
+                self.status_message = StatusMessage::from(String::from(
+                    "Usage: :autocmd {event} {pattern} {command}",
+                ));
+            }
+        }
+    }
+
+    // `:command {Name} {replacement}` adds a command, and lists them without
+    // arguments or what one runs with just its name.
+    fn define_command(&mut self, arguments: &str, replace: bool) {
+        let (name, replacement) = arguments
+            .split_once(char::is_whitespace)
+            .unwrap_or((arguments, ""));
+        let replacement = replacement.trim();
+        if name.is_empty() {
+            let lines = self
+                .user_commands
+                .list()
+                .map(|(name, replacement)| format!("{name:<12} {replacement}"))
+                .collect::<Vec<_>>();
+            if lines.is_empty() {
+                self.status_message = StatusMessage::from(String::from("No user-defined commands"));
+            } else {
+                self.show_popup(lines);
+            }
+        } else if replacement.is_empty() {
+            self.status_message = StatusMessage::from(match self.user_commands.get(name) {
+                Some(replacement) => format!("{name:<12} {replacement}"),
+                None => format!("No such command: {name}"),
+            });
+        } else if let Err(message) = self.user_commands.add(name, replacement, replace) {
+            self.status_message = StatusMessage::from(message);
+        }
+    }
+
+    fn run_user_commands(&mut self, commands: &[String]) -> Result<(), std::io::Error> {
+        if self.user_command_depth >= 20 {
+            self.status_message =
+                StatusMessage::from(String::from("User commands nested too deeply"));
+            return Ok(());
+        }
+        self.user_command_depth = self.user_command_depth.saturating_add(1);
+        let mut result = Ok(());
+        for command in commands {
+            result = self.execute_command(command);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.user_command_depth = self.user_command_depth.saturating_sub(1);
+        result
+    }
+
+    // `:iabbrev {word} {expansion}` adds an abbreviation, and lists them
+    // without arguments or what one word expands to with just that word.
+    fn abbreviate(&mut self, arguments: &[&str]) {
+        match arguments {
+            [] => {
+                let lines = self
+                    .abbreviations
+                    .list()
+                    .map(|(word, expansion)| format!("i  {word:<12} {expansion}"))
+                    .collect::<Vec<_>>();
+                if lines.is_empty() {
+                    self.status_message =
+                        StatusMessage::from(String::from("No abbreviation found"));
+                } else {
+                    self.show_popup(lines);
+                }
+            }
+            [word] => {
+                self.status_message = StatusMessage::from(match self.abbreviations.get(word) {
+                    Some(expansion) => format!("i  {word:<12} {expansion}"),
+                    None => String::from("No abbreviation found"),
+                });
+            }
+            [word, expansion @ ..] => {
+                if let Err(message) = self.abbreviations.add(word, &expansion.join(" ")) {
+                    self.status_message = StatusMessage::from(message);
+                }
+            }
+        }
+    }
+
+    fn show_marks(&mut self) {
+        let mut lines = vec![String::from("mark  line  col  text")];
+        for (name, position) in self.document.marks() {
+            let text = self
+                .document
+                .row(position.y)
+                .map_or_else(String::new, |row| row.as_str().trim().to_string());
+            lines.push(format!(
+                " {name}    {:>4}  {:>3}  {text}",
+                position.y.saturating_add(1),
+                position.x
+            ));
+        }
+        self.show_popup(lines);
+    }
+
+    fn show_digraphs(&mut self) {
+        let entries: Vec<String> = digraph::list()
+            .iter()
+            .map(|(keys, c)| format!("{keys} {c}"))
+            .collect();
+        // As many entries as fit across the screen, three columns apart.
+        let entry_width = entries.iter().map(|entry| entry.width()).max().unwrap_or(1);
+        let per_row = (self.terminal.size().width as usize)
+            .saturating_add(3)
+            .checked_div(entry_width.saturating_add(3))
+            .unwrap_or(1)
+            .max(1);
+        let lines = entries
+            .chunks(per_row)
+            .map(|chunk| chunk.join("   "))
+            .collect();
+        self.show_popup(lines);
+    }
+
+    // Opens the help buffer, or goes back to it, at `topic`.
+    fn help(&mut self, topic: Option<&str>) {
+        let lines = help::text(&self.keymap.mappings());
+        let line = match topic {
+            Some(topic) => match help::find(&lines, topic) {
+                Some(line) => line,
+                None => {
+                    self.status_message = StatusMessage::from(format!("No help for {topic}"));
+                    return;
+                }
+            },
+            None => 0,
+        };
+        if self.document.is_help() {
+            self.push_jump();
+        } else {
+            let index = self
+                .buffers
+                .iter()
+                .position(|buffer| buffer.document.is_help())
+                .unwrap_or_else(|| {
+                    self.buffers.push(Buffer {
+                        document: Document::help(&lines),
+                        ..Buffer::default()
+                    });
+                    self.buffers.len().saturating_sub(1)
+                });
+            self.switch_buffer(index);
+        }
+        self.restore_cursor(Position { x: 0, y: line });
+        self.align_view('t');
+    }
+
+    fn show_messages(&mut self) {
+        let lines = self
+            .messages
+            .iter()
+            .map(|message| message.text.clone())
+            .collect();
+        self.show_popup(lines);
+    }
+
+    // Keeps the message on the message line, once, for `:messages`.
+    fn remember_message(&mut self) {
+        // The command line being typed isn't a message.
+        let message = &self.status_message;
+        if matches!(self.mode, Mode::Command | Mode::Search)
+            || self.prompting
+            || message.text.is_empty()
+            || self
+                .messages
+                .back()
+                .is_some_and(|last| last.time == message.time)
+        {
+            return;
+        }
+        if self.messages.len() >= MESSAGE_HISTORY {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message.clone());
+    }
+
+    fn show_popup(&mut self, mut lines: Vec<String>) {
+        lines.push(String::from("Press any key to continue"));
+        self.popup = Some(lines);
+        self.popup_scroll = 0;
+    }
+
+    // Scrolls a popup taller than the screen, which any other key dismisses.
+    fn scroll_popup(&mut self, key: Key) -> bool {
+        let height = self.terminal.size().height as usize;
+        let Some(popup) = &self.popup else {
+            return false;
+        };
+        // The last line stays at the bottom of the screen.
+        let last_scroll = popup.len().saturating_sub(height);
+        if last_scroll == 0 {
+            return false;
+        }
+        let page = height.saturating_sub(1).max(1);
+        self.popup_scroll = match key {
+            Key::Char('j') | Key::Down | Key::Ctrl('e') => self.popup_scroll.saturating_add(1),
+            Key::Char('k') | Key::Up | Key::Ctrl('y') => self.popup_scroll.saturating_sub(1),
+            Key::Char(' ') | Key::Ctrl('f') | Key::PageDown => {
+                self.popup_scroll.saturating_add(page)
+            }
+            Key::Ctrl('b') | Key::PageUp => self.popup_scroll.saturating_sub(page),
+            Key::Char('g') => 0,
+            Key::Char('G') => last_scroll,
+            _ => return false,
+        }
+        .min(last_scroll);
+        true
+    }
+
+    // The lines of the popup that fit on the screen, with the last one at the
+    // bottom, saying how to scroll when they don't all fit.
+    fn visible_popup(&self) -> Vec<String> {
+        let height = self.terminal.size().height as usize;
+        let Some((last, lines)) = self.popup.as_deref().and_then(<[String]>::split_last) else {
+            return self.pending_hints();
+        };
+        if lines.len() < height {
+            return self.popup.clone().unwrap_or_default();
+        }
+        let mut visible: Vec<String> = lines
+            .iter()
+            .skip(self.popup_scroll)
+            .take(height.saturating_sub(1))
+            .cloned()
+            .collect();
+        visible.push(format!(
+            "{last} (j/k to scroll, {}-{} of {})",
+            self.popup_scroll.saturating_add(1),
+            self.popup_scroll.saturating_add(visible.len()),
+            lines.len()
+        ));
+        visible
+    }
+
+    fn goto_line(&mut self, y: usize) {
+        self.push_jump();
+        let target = Motion::Line(y).target(&self.document, &self.cursor_position, 1);
+        self.restore_cursor(target);
+    }
+
+    fn current_jump(&self) -> Jump {
+        Jump {
+            cursor: self.cursor_position.clone(),
+            offset: self.offset.clone(),
+        }
+    }
+
+    // Goes to where the `tags` file says `name` is defined, remembering
+    // where from for Ctrl-T.
+    fn jump_to_tag(&mut self, name: &str) {
+        let tags = match Tags::load() {
+            Ok(tags) => tags,
+            Err(message) => {
+                self.status_message = StatusMessage::from(message);
+                return;
+            }
+        };
+        let found = tags.find(name);
+        let Some(tag) = found.first() else {
+            self.status_message = StatusMessage::from(format!("Tag not found: {name}"));
+            return;
+        };
+        self.tag_stack.push(TagJump {
+            file: self.document.file_name.clone(),
+            jump: self.current_jump(),
+        });
+        self.push_jump();
+        self.edit(&tag.file);
+        if self.document.file_name.as_deref() != Some(tag.file.as_str()) {
+            return;
+        }
+        let Some(position) = tag.position(&self.document) else {
+            self.status_message =
+                StatusMessage::from(format!("Tag {name} not found in {}", tag.file));
+            return;
+        };
+        self.restore_cursor(position);
+        self.scroll();
+        if found.len() > 1 {
+            self.status_message = StatusMessage::from(format!("tag 1 of {}", found.len()));
+        }
+    }
+
+    // Switches the buffer between its text and its bytes in hex, starting
+    // again at the top since the two don't line up.
+    fn toggle_hex(&mut self) {
+        let result = if self.document.is_hex() {
+            self.document.show_text()
+        } else {
+            self.document.show_hex()
+        };
+        match result {
+            Ok(()) => {
+                self.restore_cursor(Position::default());
+                self.scroll();
+            }
+            Err(message) => self.status_message = StatusMessage::from(message),
+        }
+    }
+
+    // Compares the buffer with `file_name`, shown next to it.
+    fn diff_split(&mut self, file_name: &str) {
+        match Document::open(file_name) {
+            Ok(other) => {
+                self.end_diff();
+                self.diff = Some(DiffView::new(self.document.file_name.clone(), other));
+                self.scroll();
+            }
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("Error opening file: {error}"));
+            }
+        }
+    }
+
+    // Keeps the diff view up to date with the buffer, and closes it once
+    // another buffer is shown.
+    fn update_diff(&mut self) {
+        let Some(view) = &mut self.diff else {
+            return;
+        };
+        if view.file_name != self.document.file_name {
+            self.end_diff();
+            return;
+        }
+        view.update(&self.document);
+    }
+
+    // Closes the diff view. When `dp` left the other file with unsaved
+    // changes, it stays open as a buffer of its own to be written from.
+    fn end_diff(&mut self) {
+        let Some(view) = self.diff.take() else {
+            return;
+        };
+        if !view.other.is_dirty() {
+            return;
+        }
+        let name = view
+            .other
+            .file_name
+            .clone()
+            .unwrap_or_else(|| String::from("[No Name]"));
+        self.buffers.push(Buffer {
+            document: view.other,
+            cursor_position: Position::default(),
+            offset: Position::default(),
+            unread: false,
+            indentation: None,
+        });
+        self.status_message = StatusMessage::from(format!(
+            "{name} has unsaved changes, in buffer {}",
+            self.buffers.len()
+        ));
+    }
+
+    // `do`: takes the difference under the cursor from the other file.
+    fn diff_obtain(&mut self) {
+        self.update_diff();
+        let y = self.cursor_position.y;
+        let Some((hunk, lines)) = self.diff.as_ref().and_then(|view| {
+            let hunk = view.hunk_at(y)?;
+            Some((hunk, view.other_lines(&hunk)))
+        }) else {
+            self.status_message =
+                StatusMessage::from(String::from("No difference under the cursor"));
+            return;
+        };
+        if hunk.new_len > 0 {
+            self.document.delete_lines(hunk.new_start, hunk.new_len);
+        }
+        self.document.insert_lines(hunk.new_start, &lines);
+        let target = Motion::Line(hunk.new_start).target(&self.document, &self.cursor_position, 1);
+        self.restore_cursor(target);
+    }
+
+    // `dp`: puts the difference under the cursor into the other file, which
+    // is left unsaved like any other change.
+    fn diff_put(&mut self) {
+        self.update_diff();
+        let y = self.cursor_position.y;
+        let Some(hunk) = self.diff.as_ref().and_then(|view| view.hunk_at(y)) else {
+            self.status_message =
+                StatusMessage::from(String::from("No difference under the cursor"));
+            return;
+        };
+        let lines: Vec<String> = (hunk.new_start..hunk.new_start.saturating_add(hunk.new_len))
+            .filter_map(|y| self.document.row(y))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        let Some(view) = &mut self.diff else {
+            return;
+        };
+        if hunk.old_len > 0 {
+            view.other.delete_lines(hunk.old_start, hunk.old_len);
+        }
+        view.other.insert_lines(hunk.old_start, &lines);
+    }
+
+    // Shows a Markdown buffer rendered, or as written again.
+    fn toggle_preview(&mut self) {
+        if self.document.file_type() != "Markdown" {
+            self.status_message =
+                StatusMessage::from(String::from("Only Markdown files can be previewed"));
+            return;
+        }
+        let preview = !self.document.is_previewed();
+        self.document.set_preview(preview);
+    }
+
+    // Opens a window running the user's shell, or goes back to the one
+    // already open, with keys going to it until Esc.
+    fn open_terminal(&mut self) {
+        if self.term_window.is_none() {
+            if self.terminal.is_headless() {
+                self.status_message =
+                    StatusMessage::from(String::from("Cannot open a terminal without one"));
+                return;
+            }
+            let width = self.terminal.size().width as usize;
+            match TermWindow::open(width, self.term_window_rows(), self.terminal.event_sender()) {
+                Ok(window) => {
+                    self.term_window = Some(window);
+                    self.scroll();
+                }
+                Err(message) => {
+                    self.status_message = StatusMessage::from(message);
+                    return;
+                }
+            }
+        }
+        self.switch_mode(Mode::Terminal);
+    }
+
+    fn close_terminal(&mut self) {
+        if self.term_window.take().is_none() {
+            return;
+        }
+        if matches!(self.mode, Mode::Terminal) {
+            self.switch_mode(Mode::Normal);
+        }
+        self.status_message = StatusMessage::from(String::from("Terminal closed"));
+        self.scroll();
+    }
+
+    fn pop_tag(&mut self) {
+        let Some(TagJump { file, jump }) = self.tag_stack.pop() else {
+            self.status_message = StatusMessage::from(String::from("At bottom of tag stack"));
+            return;
+        };
+        if let Some(file) = file {
+            self.edit(&file);
+            if self.document.file_name.as_deref() != Some(file.as_str()) {
+                return;
+            }
+        }
+        self.restore_jump(jump);
+        self.scroll();
+    }
+
+    fn push_jump(&mut self) {
+        let jump = self.current_jump();
+        self.jump_list.push(jump);
+    }
+
+    fn restore_jump(&mut self, jump: Jump) {
+        self.offset = jump.offset;
+        self.restore_cursor(jump.cursor);
+    }
+
+    // Moves the cursor to `position`, clamped to the document's contents.
+    fn restore_cursor(&mut self, position: Position) {
+        let y = position.y.min(self.document.len());
+        let width = self.document.row(y).map_or(0, Row::len);
+        self.cursor_position = Position {
+            x: position.x.min(width),
+            y,
+        };
+    }
+
+    fn scroll(&mut self) {
+        let x = self.cursor_column();
+        let y = self.cursor_position.y;
+        // Scroll far enough to show both cells of a wide character
+        let x_end = self
+            .document
+            .row(y)
+            .map_or(0, |row| {
+                row.column(
+                    self.cursor_position.x.saturating_add(1),
+                    self.settings.tabstop,
+                )
+            })
+            .max(x.saturating_add(1));
+        let width = self.text_width();
+        let height = self.text_height();
+        let above = self.scrolloff();
+        // There is no context to keep below the last line.
+        let below = above.min(self.document.len().saturating_sub(y.saturating_add(1)));
+        let side = self.settings.sidescrolloff.min(width.saturating_sub(1) / 2);
+        let mut offset = &mut self.offset;
+        if y < offset.y.saturating_add(above) {
+            offset.y = y.saturating_sub(above);
+        } else if y.saturating_add(below) >= offset.y.saturating_add(height) {
+            offset.y = y
+                .saturating_add(below)
+                .saturating_sub(height)
+                .saturating_add(1);
+        }
+        if x < offset.x.saturating_add(side) {
+            offset.x = x.saturating_sub(side);
+        } else if x_end.saturating_add(side) > offset.x.saturating_add(width) {
+            offset.x = x_end.saturating_add(side).saturating_sub(width);
+        }
+        // Lines only the file the buffer is compared with has take rows too.
+        self.update_diff();
+        if self.diff.is_some() {
+            while self.offset.y < y && self.screen_row(y).saturating_add(below) >= height {
+                self.offset.y = self.offset.y.saturating_add(1);
+            }
+        }
+    }
+
+    // The row of the screen row `y` of the buffer is drawn on.
+    fn screen_row(&self, y: usize) -> usize {
+        match &self.diff {
+            Some(view) => view
+                .pair_of(y)
+                .saturating_sub(view.first_pair(self.offset.y)),
+            None => y.saturating_sub(self.offset.y),
+        }
+    }
+
+    // `scrolloff`, as far as the screen leaves room for it.
+    fn scrolloff(&self) -> usize {
+        self.settings
+            .scrolloff
+            .min(self.text_height().saturating_sub(1) / 2)
+    }
+
+    fn move_cursor(&mut self, key: Key) {
+        let Position { mut y, mut x } = self.cursor_position;
+        let height = self.document.len();
+        let terminal_height = self.text_height();
+        let mut width = if let Some(row) = self.document.row(y) {
+            row.len()
+        } else {
+            0
+        };
+        match key {
+            Key::Up | Key::Char('k') => y = y.saturating_sub(1),
+            Key::Down | Key::Char('j') => {
+                if y < height {
+                    y = y.saturating_add(1);
+                }
+            }
+            Key::Left | Key::Char('h') | Key::Backspace => {
+                if x > 0 {
+                    x -= 1;
+                } else if y > 0 {
+                    y -= 1;
+                    if let Some(row) = self.document.row(y) {
+                        x = row.len();
+                    } else {
+                        x = 0;
+                    }
+                }
+            }
+            Key::Right | Key::Char('l') => {
+                if x < width {
+                    x += 1;
+                } else if y < height {
+                    y += 1;
+                    x = 0;
+                }
+            }
+            Key::PageUp => {
+                y = if y > terminal_height {
+                    y.saturating_sub(terminal_height)
+                } else {
+                    0
+                }
+            }
+            Key::PageDown => {
+                y = if y.saturating_add(terminal_height) < height {
+                    y.saturating_add(terminal_height)
+                } else {
+                    height
+                }
+            }
+            Key::Home | Key::Char('0') if self.settings.smarthome => {
+                x = motion::first_non_blank(&self.document, y).min(width);
+                if x == self.cursor_position.x {
+                    x = 0;
+                }
+            }
+            Key::Home | Key::Char('0') => x = 0,
+            Key::End | Key::Char('$') => x = width,
+            _ => (),
+        }
+
+        // If the cursor is at the end of a line, it should stay there when the
+        // user presses the down arrow key.
+        width = if let Some(row) = self.document.row(y) {
+            row.len()
+        } else {
+            0
+        };
+
+        if x > width {
+            x = width;
+        }
+
+        self.cursor_position = Position { x, y }
+    }
+
+    fn draw_welcome_message(&self) -> String {
+        let mut welcome_message = format!("ggedit v{}", VERSION);
+        let width = self.terminal.size().width as usize;
+        let len = welcome_message.len();
+        let padding = width.saturating_sub(len) / 2;
+        let spaces = " ".repeat(padding.saturating_sub(1));
+        welcome_message = format!("~{}{}", spaces, welcome_message);
+        welcome_message.truncate(width);
+        welcome_message
+    }
+
+    pub fn draw_row(&self, y: usize, row: &Row, overlay: &[(usize, highlighting::Type)]) -> String {
+        let start = self.offset.x;
+        let end = self.offset.x.saturating_add(self.text_width());
+        let columns = self.settings.color_columns();
+        let diff = self
+            .diff
+            .as_ref()
+            .and_then(|view| view.pair_at(y))
+            .and_then(|pair| self.diff_background(pair));
+        let backdrop = Backdrop {
+            line: diff.or(self
+                .theme
+                .cursor_line
+                .bg
+                .filter(|_| self.settings.cursorline && y == self.cursor_position.y)),
+            column: self.theme.color_column.bg,
+            columns: &columns,
+        };
+        let row = row.render(start, end, &self.settings, overlay, &backdrop, &self.theme);
+        format!("{}{}{row}", self.draw_sign(y), self.draw_line_number(y))
+    }
+
+    // Shows who last changed the current line in the message bar.
+    fn blame(&mut self) {
+        let message = match git::blame(&self.document, self.cursor_position.y) {
+            Ok(blame) | Err(blame) => blame,
+        };
+        self.status_message = StatusMessage::from(message);
+    }
+
+    // The whitespace row `y` ends with, except on the line being typed in.
+    fn trailing_whitespace_columns(&self, y: usize, row: &Row) -> Vec<(usize, highlighting::Type)> {
+        if y == self.cursor_position.y && matches!(self.mode, Mode::Insert | Mode::Replace) {
+            return Vec::new();
+        }
+        let chars = row.chars();
+        let start = chars
+            .iter()
+            .rposition(|c| !c.is_whitespace())
+            .map_or(0, |x| x.saturating_add(1));
+        (start..chars.len())
+            .map(|x| (x, highlighting::Type::TrailingWhitespace))
+            .collect()
+    }
+
+    // Loads the dictionary for `spelllang` once spell checking is on.
+    fn load_dictionary(&mut self) -> Result<(), String> {
+        let language = &self.settings.spelllang;
+        if !self.settings.spell
+            || self
+                .dictionary
+                .as_ref()
+                .is_some_and(|dictionary| dictionary.language() == language)
+        {
+            return Ok(());
+        }
+        self.dictionary = Some(Dictionary::load(language)?);
+        Ok(())
+    }
+
+    // The misspelled words of `row`: all of them in prose, but only those in
+    // comments and strings in code.
+    fn misspellings(&self, row: &Row) -> Vec<(usize, usize)> {
+        let Some(dictionary) = self.dictionary.as_ref().filter(|_| self.settings.spell) else {
+            return Vec::new();
+        };
+        let prose = self.document.is_prose();
+        dictionary
+            .misspelled(&row.chars())
+            .into_iter()
+            .filter(|(start, _)| match row.highlighting_at(*start) {
+                highlighting::Type::String => !prose,
+                highlighting::Type::Comment => true,
+                _ => prose,
+            })
+            .collect()
+    }
+
+    fn misspelled_columns(&self, row: &Row) -> Vec<(usize, highlighting::Type)> {
+        self.misspellings(row)
+            .into_iter()
+            .flat_map(|(start, end)| (start..end).map(|x| (x, highlighting::Type::Misspelled)))
+            .collect()
+    }
+
+    // Moves to the next or previous misspelled word, wrapping around the
+    // file.
+    fn jump_to_misspelling(&mut self, forward: bool) {
+        if !self.settings.spell {
+            self.status_message = StatusMessage::from(String::from("Spell checking is off"));
+            return;
+        }
+        let len = self.document.len();
+        self.document.highlight_rows(0, len);
+        let Position { x, y } = self.cursor_position;
+        // The cursor's row comes first, for the words past the cursor, and
+        // last, for those before it.
+        let rows: Vec<usize> = if forward {
+            (y..len).chain(0..=y).collect()
+        } else {
+            (0..=y).rev().chain((y..len).rev()).collect()
+        };
+        let found = rows.iter().enumerate().find_map(|(index, &row_y)| {
+            let row = self.document.row(row_y)?;
+            let mut starts = self.misspellings(row).into_iter().map(|(start, _)| start);
+            let start = if forward {
+                starts.find(|&start| index > 0 || start > x)
+            } else {
+                starts.rfind(|&start| index > 0 || start < x)
+            }?;
+            Some(Position { x: start, y: row_y })
+        });
+        match found {
+            Some(position) => self.restore_cursor(position),
+            None => {
+                self.status_message = StatusMessage::from(String::from("No misspelled words"));
+            }
+        }
+    }
+
+    // Lists what the word under the cursor could be instead, numbered so
+    // its digit picks one.
+    fn suggest_spellings(&mut self) {
+        let Some(dictionary) = self.dictionary.as_ref().filter(|_| self.settings.spell) else {
+            self.status_message = StatusMessage::from(String::from("Spell checking is off"));
+            return;
+        };
+        let Some(selection) =
+            TextObject::Word.selection(&self.document, &self.cursor_position, false)
+        else {
+            return;
+        };
+        let word = self.document.text_range(&selection.start, &selection.end);
+        let suggestions = dictionary.suggest(&word);
+        if suggestions.is_empty() {
+            self.status_message = StatusMessage::from(format!("No suggestions for \"{word}\""));
+            return;
+        }
+        let mut lines = vec![format!("Change \"{word}\" to:")];
+        lines.extend(
+            suggestions.iter().enumerate().map(|(index, suggestion)| {
+                format!(" {} \"{suggestion}\"", index.saturating_add(1))
+            }),
+        );
+        lines.push(String::from(
+            "Type a number to pick one, or any other key to cancel",
+        ));
+        self.popup = Some(lines);
+        self.popup_scroll = 0;
+        self.spelling = Some((selection.start, selection.end, suggestions));
+    }
+
+    // Replaces the word `z=` listed suggestions for with the one numbered
+    // `key`, if it is one of their digits.
+    fn respell(&mut self, (start, end, suggestions): (Position, Position, Vec<String>), key: Key) {
+        let Key::Char(c) = key else {
+            return;
+        };
+        let Some(suggestion) = c
+            .to_digit(10)
+            .and_then(|digit| suggestions.get((digit as usize).checked_sub(1)?))
+        else {
+            return;
+        };
+        self.document.delete_range(&start, &end);
+        self.document.insert_text(&start, suggestion);
+        self.document.commit();
+        self.restore_cursor(start);
+    }
+
+    // Moves to the start of the next or previous changed hunk.
+    fn jump_to_hunk(&mut self, forward: bool) {
+        let y = self.cursor_position.y;
+        self.update_diff();
+        let next = match &self.diff {
+            Some(view) => view.next_hunk(y, forward),
+            None => self
+                .git_gutter()
+                .and_then(|gutter| gutter.next_hunk(y, forward)),
+        };
+        match next {
+            Some(line) => {
+                self.push_jump();
+                let target = Motion::Line(line).target(&self.document, &self.cursor_position, 1);
+                self.restore_cursor(target);
+            }
+            None => {
+                self.status_message = StatusMessage::from(String::from("No more hunks"));
+            }
+        }
+    }
+
+    // Puts back the committed lines of the hunk under the cursor.
+    fn revert_hunk(&mut self) {
+        let y = self.cursor_position.y;
+        let Some((hunk, lines)) = self.git_gutter().and_then(|gutter| {
+            let hunk = *gutter.hunk_at(y)?;
+            Some((hunk, gutter.base_lines(&hunk).to_vec()))
+        }) else {
+            self.status_message = StatusMessage::from(String::from("No hunk under the cursor"));
+            return;
+        };
+        if hunk.new_len > 0 {
+            self.document.delete_lines(hunk.new_start, hunk.new_len);
+        }
+        self.document.insert_lines(hunk.new_start, &lines);
+        self.document.commit();
+        let target = Motion::Line(hunk.new_start.min(self.document.len().saturating_sub(1)))
+            .target(&self.document, &self.cursor_position, 1);
+        self.restore_cursor(target);
+    }
+
+    // The sign column, when the file is committed or has diagnostics. A
+    // problem on the line is shown rather than how it changed.
+    fn draw_sign(&self, y: usize) -> String {
+        if self.gutter_width() == self.number_width() {
+            return String::new();
+        }
+        let severity = self
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| diagnostic.start.0 == y)
+            .map(|diagnostic| diagnostic.severity)
+            .min();
+        let (style, sign) = match severity {
+            Some(Severity::Error) => (self.theme.highlight(&highlighting::Type::Error), 'E'),
+            Some(Severity::Warning) => (self.theme.highlight(&highlighting::Type::Warning), 'W'),
+            Some(Severity::Information | Severity::Hint) => (self.theme.line_number, 'I'),
+            None => match self.git_gutter().and_then(|gutter| gutter.sign(y)) {
+                Some(git::Sign::Added) => (self.theme.git_added, '+'),
+                Some(git::Sign::Modified) => (self.theme.git_modified, '~'),
+                Some(git::Sign::Removed) => (self.theme.git_removed, '-'),
+                None => return String::from(" "),
+            },
+        };
+        let style = Style {
+            underline: false,
+            ..style
+        };
+        format!("{}{sign}{}", style.escape(), color::Fg(color::Reset))
+    }
+
+    fn diagnostics(&self) -> &[lsp::Diagnostic] {
+        self.document
+            .file_name
+            .as_deref()
+            .map_or(&[], |file_name| self.lsp.diagnostics(file_name))
+    }
+
+    // The graphemes of row `y` that diagnostics cover, to underline them.
+    fn diagnostic_columns(&self, y: usize, row: &Row) -> Vec<(usize, highlighting::Type)> {
+        let mut columns = Vec::new();
+        for diagnostic in self.diagnostics() {
+            let highlighting_type = match diagnostic.severity {
+                Severity::Error => highlighting::Type::Error,
+                Severity::Warning => highlighting::Type::Warning,
+                Severity::Information | Severity::Hint => continue,
+            };
+            if !(diagnostic.start.0..=diagnostic.end.0).contains(&y) {
+                continue;
+            }
+            let start = if diagnostic.start.0 == y {
+                lsp::grapheme_index(row, diagnostic.start.1)
+            } else {
+                0
+            };
+            let end = if diagnostic.end.0 == y {
+                lsp::grapheme_index(row, diagnostic.end.1)
+            } else {
+                row.len()
+            };
+            // An empty range still marks the character it is at.
+            let end = end.max(start.saturating_add(1)).min(row.len().max(1));
+            columns.extend((start..end).map(|x| (x, highlighting_type.clone())));
+        }
+        columns
+    }
+
+    // Lists the diagnostics of the current file with their positions.
+    fn show_diagnostics(&mut self) {
+        let mut diagnostics: Vec<&lsp::Diagnostic> = self.diagnostics().iter().collect();
+        if diagnostics.is_empty() {
+            self.status_message = StatusMessage::from(String::from("No diagnostics"));
+            return;
+        }
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.start, diagnostic.severity));
+        let lines = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let message = diagnostic.message.lines().next().unwrap_or_default();
+                format!(
+                    "{}:{}: {}: {message}",
+                    diagnostic.start.0.saturating_add(1),
+                    diagnostic.start.1.saturating_add(1),
+                    diagnostic.severity.name()
+                )
+            })
+            .collect();
+        self.show_popup(lines);
+    }
+
+    // Asks the language server about the symbol under the cursor. The
+    // answer arrives later and goes to `apply_reply`.
+    fn ask_lsp(&mut self, query: Query) {
+        let sender = self.terminal.event_sender();
+        if let Err(error) = self
+            .lsp
+            .ask(&self.document, &self.cursor_position, query, &sender)
+        {
+            self.status_message = StatusMessage::from(error);
+        }
+    }
+
+    fn apply_reply(&mut self, reply: Reply) {
+        match reply {
+            Reply::Definition(locations) => {
+                let Some(location) = locations.into_iter().next() else {
+                    self.status_message = StatusMessage::from(String::from("No definition found"));
+                    return;
+                };
+                self.push_jump();
+                self.edit(&location.file_name);
+                if self.document.file_name.as_deref() != Some(location.file_name.as_str()) {
+                    return;
+                }
+                let x = self
+                    .document
+                    .row(location.line)
+                    .map_or(0, |row| lsp::grapheme_index(row, location.character));
+                self.restore_cursor(Position {
+                    x,
+                    y: location.line,
+                });
+                self.scroll();
+            }
+            Reply::Completion(suggestions) => self.show_suggestions(suggestions),
+            Reply::Hover(lines) if lines.is_empty() => {
+                self.status_message = StatusMessage::from(String::from("No information"));
+            }
+            Reply::Hover(lines) => self.hover = Some(lines),
+            Reply::Failed(error) => self.status_message = StatusMessage::from(error),
+        }
+    }
+
+    // Tells the language server about the current file's latest text.
+    fn sync_lsp(&mut self) {
+        if self.document.is_directory() || self.document.loading_progress().is_some() {
+            return;
+        }
+        if let Some(error) = self.lsp.sync(&self.document, &self.terminal.event_sender()) {
+            self.status_message = StatusMessage::from(error);
+        }
+    }
+
+    fn git_gutter(&self) -> Option<&Gutter> {
+        let file_name = self.document.file_name.as_ref()?;
+        self.git_gutters.get(file_name)?.as_ref()
+    }
+
+    // Forgets what git said, after something that may have changed the
+    // repository, like a shell command or saving a file.
+    fn refresh_git(&mut self) {
+        self.git_gutters.clear();
+        for repository in self.repositories.values_mut() {
+            repository.requested = None;
+        }
+    }
+
+    // Asks for the git status of the current file's repository when it is
+    // missing or getting old. It arrives later as an event, so drawing never
+    // waits for git.
+    fn request_git_status(&mut self) {
+        if self.terminal.is_headless() || self.document.is_directory() {
+            return;
+        }
+        let Some(file_name) = self.document.file_name.as_deref() else {
+            return;
+        };
+        let dir = git::directory_of(file_name);
+        let repository = self.repositories.entry(dir.clone()).or_default();
+        if repository
+            .requested
+            .is_some_and(|requested| requested.elapsed() < GIT_STATUS_INTERVAL)
+        {
+            return;
+        }
+        repository.requested = Some(Instant::now());
+        git::request_status(dir, self.terminal.event_sender());
+    }
+
+    fn repo_status(&self) -> Option<&RepoStatus> {
+        let file_name = self.document.file_name.as_deref()?;
+        self.repositories
+            .get(&git::directory_of(file_name))?
+            .status
+            .as_ref()
+    }
+
+    // Diffs the document against its last commit if it changed, reading
+    // that commit the first time the file is shown.
+    fn update_git_gutter(&mut self) {
+        if self.document.is_directory() || self.document.loading_progress().is_some() {
+            return;
+        }
+        let Some(file_name) = self.document.file_name.as_ref() else {
+            return;
+        };
+        let gutter = self
+            .git_gutters
+            .entry(file_name.clone())
+            .or_insert_with(|| Gutter::load(file_name));
+        if let Some(gutter) = gutter {
+            gutter.update(&self.document);
+        }
+    }
+
+    // Columns taken by the signs and line numbers.
+    fn gutter_width(&self) -> usize {
+        let signs = usize::from(self.git_gutter().is_some() || !self.diagnostics().is_empty());
+        signs.saturating_add(self.number_width())
+    }
+
+    // Columns taken by line numbers, including the separating space. Like
+    // vim, room is kept for at least three digits.
+    fn number_width(&self) -> usize {
+        if !self.settings.number && !self.settings.relativenumber {
+            return 0;
+        }
+        let digits = self.document.len().max(1).to_string().len();
+        digits.max(3).saturating_add(1)
+    }
+
+    // The display column of the cursor, which differs from its grapheme index
+    // once tabs are involved. The horizontal offset is measured in columns.
+    fn cursor_column(&self) -> usize {
+        self.document.row(self.cursor_position.y).map_or(0, |row| {
+            row.column(self.cursor_position.x, self.settings.tabstop)
+        })
+    }
+
+    // The rows left for the text above the terminal window and the quickfix
+    // panel, when they are open.
+    fn text_height(&self) -> usize {
+        (self.terminal.size().height as usize)
+            .saturating_sub(self.term_window_height())
+            .saturating_sub(self.quickfix_height())
+    }
+
+    // A title line and the shell's screen, taking half of what is left
+    // under the quickfix panel.
+    fn term_window_height(&self) -> usize {
+        if self.term_window.is_none() {
+            return 0;
+        }
+        self.term_window_rows().saturating_add(1)
+    }
+
+    fn term_window_rows(&self) -> usize {
+        let height = (self.terminal.size().height as usize).saturating_sub(self.quickfix_height());
+        (height / 2).saturating_sub(1).max(1)
+    }
+
+    // A title line and up to `QUICKFIX_ROWS` entries, taking no more than
+    // half the screen.
+    fn quickfix_height(&self) -> usize {
+        if !self.quickfix_open {
+            return 0;
+        }
+        let half = (self.terminal.size().height as usize) / 2;
+        self.quickfix
+            .len()
+            .clamp(1, QUICKFIX_ROWS)
+            .saturating_add(1)
+            .min(half)
+    }
+
+    fn text_width(&self) -> usize {
+        self.pane_width().saturating_sub(self.gutter_width())
+    }
+
+    // The columns the buffer is drawn in: all of them, or the left half
+    // next to the file it is compared with.
+    fn pane_width(&self) -> usize {
+        let width = self.terminal.size().width as usize;
+        if self.diff.is_some() {
+            width.saturating_sub(1) / 2
+        } else {
+            width
+        }
+    }
+
+    // With both options set the cursor row shows its absolute number while the
+    // others show their distance from it.
+    fn draw_line_number(&self, y: usize) -> String {
+        let width = self.number_width();
+        if width == 0 {
+            return String::new();
+        }
+        let cursor_y = self.cursor_position.y;
+        let number = if !self.settings.relativenumber || (y == cursor_y && self.settings.number) {
+            y.saturating_add(1)
+        } else {
+            y.abs_diff(cursor_y)
+        };
+        let digits = width.saturating_sub(1);
+        let style = self.theme.line_number.escape();
+        let reset = color::Fg(color::Reset);
+        if self.settings.relativenumber && self.settings.number && y == cursor_y {
+            format!("{style}{:<digits$} {reset}", number)
+        } else {
+            format!("{style}{:>digits$} {reset}", number)
+        }
+    }
+
+    // The text area of the frame, one line per terminal row.
+    fn draw_rows(&self) -> Vec<String> {
+        let height = self.terminal.size().height;
+        let text_height = self.text_height();
+        let mut panel = self.draw_term_window();
+        panel.extend(self.draw_quickfix());
+        let popup = self.visible_popup();
+        let popup_start = (height as usize).saturating_sub(popup.len());
+        let brackets = self.bracket_pair();
+        let preview = self.substitution_preview();
+        let word_match = self.word_match_pattern();
+        let markdown = self.markdown_lines();
+        let mut lines = Vec::new();
+        for terminal_row in 0..height {
+            if let Some(line) = (terminal_row as usize)
+                .checked_sub(popup_start)
+                .and_then(|index| popup.get(index))
+            {
+                lines
+                    .push(overlay::truncate(line, self.terminal.size().width as usize).to_string());
+            } else if let Some(line) = (terminal_row as usize)
+                .checked_sub(text_height)
+                .and_then(|index| panel.get(index))
+            {
+                lines.push(line.clone());
+            } else if let Some((view, pair)) = self
+                .diff
+                .as_ref()
+                .filter(|_| (terminal_row as usize) < text_height)
+                .and_then(|view| {
+                    let first = view.first_pair(self.offset.y);
+                    let pair = view
+                        .pairs()
+                        .get(first.saturating_add(terminal_row as usize))?;
+                    Some((view, pair))
+                })
+            {
+                let left = match pair.left.and_then(|y| Some((y, self.document.row(y)?))) {
+                    Some((y, row)) => {
+                        let overlay = self.row_overlay(y, row, &brackets, word_match.as_ref());
+                        self.draw_row(y, row, &overlay)
+                    }
+                    None => self.draw_filler(self.pane_width()),
+                };
+                lines.push(self.draw_diff_row(view, pair, left));
+            } else if let Some(row) = self
+                .document
+                .row(self.offset.y.saturating_add(terminal_row as usize))
+            {
+                let y = self.offset.y.saturating_add(terminal_row as usize);
+                if let Some(kinds) = &markdown {
+                    let kind = kinds.get(y).copied().unwrap_or(markdown::Line::Text);
+                    let text = markdown::render(
+                        row.as_str(),
+                        kind,
+                        self.text_width(),
+                        self.settings.tabstop,
+                        &self.theme,
+                    );
+                    lines.push(format!(
+                        "{}{}{text}",
+                        self.draw_sign(y),
+                        self.draw_line_number(y)
+                    ));
+                    continue;
+                }
+                let substituted = preview
+                    .as_ref()
+                    .filter(|(range, _, _)| (range.start..=range.end).contains(&y))
+                    .and_then(|(_, pattern, substitute)| {
+                        row.substituted(pattern, &substitute.replacement, substitute.global)
+                    });
+                if let Some((row, replaced)) = substituted {
+                    let overlay: Vec<(usize, highlighting::Type)> = replaced
+                        .into_iter()
+                        .map(|index| (index, highlighting::Type::SearchMatch))
+                        .collect();
+                    lines.push(self.draw_row(y, &row, &overlay));
+                    continue;
+                }
+                let overlay = self.row_overlay(y, row, &brackets, word_match.as_ref());
+                lines.push(self.draw_row(y, row, &overlay));
+            } else if self.document.is_empty() && terminal_row == height / 3 {
+                lines.push(self.draw_welcome_message());
+            } else if terminal_row != 0 {
+                let style = self.theme.empty_line.escape();
+                lines.push(format!("{style}~{}", color::Fg(color::Reset)));
+            } else {
+                lines.push(String::new());
+            }
+        }
+        lines
+    }
+
+    // What the rows down to the bottom of the screen are, while the buffer
+    // is previewed. Code blocks can start anywhere above, so the rows are
+    // looked at from the top.
+    fn markdown_lines(&self) -> Option<Vec<markdown::Line>> {
+        if !self.document.is_previewed() {
+            return None;
+        }
+        let end = self.offset.y.saturating_add(self.text_height());
+        Some(markdown::classify(
+            (0..end)
+                .map_while(|y| self.document.row(y))
+                .map(Row::as_str),
+        ))
+    }
+
+    // What is drawn over row `y` of the buffer: the bracket matching the
+    // one under the cursor, the selection, misspellings and so on.
+    fn row_overlay(
+        &self,
+        y: usize,
+        row: &Row,
+        brackets: &[Position],
+        word_match: Option<&Regex>,
+    ) -> Vec<(usize, highlighting::Type)> {
+        let mut overlay: Vec<(usize, highlighting::Type)> = brackets
+            .iter()
+            .filter(|position| position.y == y)
+            .map(|position| (position.x, highlighting::Type::MatchingBracket))
+            .collect();
+        overlay.extend(
+            self.selected_columns(y, row)
+                .map(|x| (x, highlighting::Type::Selection)),
+        );
+        overlay.extend(
+            self.multi_cursor
+                .iter()
+                .flat_map(|multi_cursor| &multi_cursor.positions)
+                .filter(|position| position.y == y)
+                .map(|position| (position.x, highlighting::Type::Selection)),
+        );
+        overlay.extend(self.misspelled_columns(row));
+        overlay.extend(self.trailing_whitespace_columns(y, row));
+        overlay.extend(self.diagnostic_columns(y, row));
+        overlay.extend(self.word_match_columns(y, row, word_match));
+        overlay
+    }
+
+    // A line of the diff view: `left`, the buffer's side, padded to its
+    // width, then a separator and the other file's side.
+    fn draw_diff_row(&self, view: &DiffView, pair: &Pair, left: String) -> String {
+        let pane_width = self.pane_width();
+        let width = (self.terminal.size().width as usize)
+            .saturating_sub(pane_width)
+            .saturating_sub(1);
+        let padding = " ".repeat(pane_width.saturating_sub(overlay::width(&left)));
+        let right = match pair.right.and_then(|y| view.other.row(y)) {
+            Some(row) => {
+                let backdrop = Backdrop {
+                    line: self.diff_background(pair),
+                    ..Backdrop::default()
+                };
+                let start = self.offset.x;
+                let end = start.saturating_add(width);
+                row.render(start, end, &self.settings, &[], &backdrop, &self.theme)
+            }
+            None => self.draw_filler(width),
+        };
+        format!(
+            "{left}{padding}{}│{}{right}",
+            self.theme.status_bar.escape(),
+            Style::default().escape()
+        )
+    }
+
+    // Where one side of the diff view lacks lines the other has.
+    fn draw_filler(&self, width: usize) -> String {
+        format!(
+            "{}{}{}",
+            self.theme.diff_removed.escape(),
+            "-".repeat(width),
+            Style::default().escape()
+        )
+    }
+
+    // Lines that differ from the other side's, or that only one side has.
+    fn diff_background(&self, pair: &Pair) -> Option<color::Rgb> {
+        if !pair.changed {
+            None
+        } else if pair.left.is_some() && pair.right.is_some() {
+            self.theme.diff_changed.bg
+        } else {
+            self.theme.diff_added.bg
+        }
+    }
+
+    // The terminal window: a title bar, then what the shell has drawn.
+    fn draw_term_window(&self) -> Vec<String> {
+        let Some(window) = &self.term_window else {
+            return Vec::new();
+        };
+        let width = self.terminal.size().width as usize;
+        let reset = format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset));
+        let title = format!(" [Terminal] {}", window.title);
+        let title: String = title.chars().take(width).collect();
+        let padding = " ".repeat(width.saturating_sub(title.width()));
+        let mut lines = vec![format!(
+            "{}{title}{padding}{reset}",
+            self.theme.status_bar.escape()
+        )];
+        lines.extend(window.screen.lines());
+        lines
+    }
+
+    // The quickfix panel: a title bar, then the entries around the current
+    // one, which is highlighted.
+    fn draw_quickfix(&self) -> Vec<String> {
+        let rows = self.quickfix_height();
+        if rows == 0 {
+            return Vec::new();
+        }
+        let width = self.terminal.size().width as usize;
+        let reset = format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset));
+        let fit = |text: String| {
+            let mut fitted = String::new();
+            let mut used = 0_usize;
+            for c in text.chars().map(|c| if c == '\t' { ' ' } else { c }) {
+                used = used.saturating_add(c.width().unwrap_or(0));
+                if used > width {
+                    used = used.saturating_sub(c.width().unwrap_or(0));
+                    break;
+                }
+                fitted.push(c);
+            }
+            format!("{fitted}{}", " ".repeat(width.saturating_sub(used)))
+        };
+        let title = if self.quickfix.is_empty() {
+            format!(" [Quickfix] {}", self.quickfix.title)
+        } else {
+            format!(
+                " [Quickfix] {} ({} of {})",
+                self.quickfix.title,
+                self.quickfix.current_index().saturating_add(1),
+                self.quickfix.len()
+            )
+        };
+        let mut lines = vec![format!(
+            "{}{}{reset}",
+            self.theme.status_bar.escape(),
+            fit(title)
+        )];
+        let entries = rows.saturating_sub(1);
+        let current = self.quickfix.current_index();
+        let first = current.saturating_add(1).saturating_sub(entries);
+        for (index, entry) in self
+            .quickfix
+            .entries()
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(entries)
+        {
+            let text = fit(format!(
+                "{}:{}:{}: {}",
+                entry.file, entry.line, entry.column, entry.text
+            ));
+            if index == current {
+                let style = self
+                    .theme
+                    .highlight(&highlighting::Type::Selection)
+                    .escape();
+                lines.push(format!("{style}{text}{reset}"));
+            } else {
+                lines.push(text);
+            }
+        }
+        lines
+    }
+
+    // The finder box, centered over the text area, and the row and column
+    // of its top left corner.
+    fn draw_finder(&self, finder: &Finder) -> (Vec<String>, usize, usize) {
+        let size = self.terminal.size();
+        let (screen_width, screen_height) = (size.width as usize, size.height as usize);
+        let width = screen_width.saturating_sub(4).min(80);
+        let inner = width.saturating_sub(2);
+        let rows = screen_height.saturating_sub(6).min(15);
+        let border = |left: &str, title: &str, right: &str| {
+            let fill = inner.saturating_sub(title.width());
+            format!("{left}{title}{}{right}", "─".repeat(fill))
+        };
+        // Text inside the box, cut from the front when too long so that the
+        // file name stays in sight.
+        let fit = |text: &str| {
+            let room = inner.saturating_sub(2);
+            let mut text = text.to_string();
+            if text.width() > room {
+                while text.width() > room.saturating_sub(1) {
+                    text.remove(0);
+                }
+                text.insert(0, '…');
+            }
+            format!(" {text}{} ", " ".repeat(room.saturating_sub(text.width())))
+        };
+        let title = format!(" Files {}/{} ", finder.match_count(), finder.total());
+        let mut layer = vec![
+            border("┌", &title, "┐"),
+            format!("│{}│", fit(&format!("> {}", finder.query()))),
+            border("├", "", "┤"),
+        ];
+        // The list scrolls to keep the selection in sight.
+        let first = finder
+            .selected_index()
+            .saturating_add(1)
+            .saturating_sub(rows);
+        let mut matches = finder.matches().skip(first);
+        for index in first..first.saturating_add(rows) {
+            let text = fit(matches.next().unwrap_or_default());
+            if index == finder.selected_index() && finder.match_count() > 0 {
+                let style = self
+                    .theme
+                    .highlight(&highlighting::Type::Selection)
+                    .escape();
+                layer.push(format!(
+                    "│{style}{text}{}{}│",
+                    color::Fg(color::Reset),
+                    color::Bg(color::Reset)
+                ));
+            } else {
+                layer.push(format!("│{text}│"));
+            }
+        }
+        layer.push(border("└", "", "┘"));
+        let top = screen_height.saturating_sub(layer.len()) / 2;
+        let left = screen_width.saturating_sub(width) / 2;
+        (layer, top, left)
+    }
+
+    // The completion menu, under the start of the word being completed or
+    // above it when there is no room below. It scrolls to keep the selection
+    // in sight.
+    fn draw_suggestions(&self, suggestions: &Suggestions) -> (Vec<String>, usize, usize) {
+        let screen_width = self.terminal.size().width as usize;
+        let label_width = suggestions
+            .matching()
+            .map(|suggestion| suggestion.label.width())
+            .max()
+            .unwrap_or(0);
+        let detail_width = suggestions
+            .matching()
+            .map(|suggestion| suggestion.detail.width())
+            .max()
+            .unwrap_or(0);
+        let width = if detail_width > 0 {
+            label_width.saturating_add(detail_width).saturating_add(2)
+        } else {
+            label_width
+        }
+        .min(screen_width.saturating_sub(2).min(60));
+        let cursor_y = self.cursor_position.y.saturating_sub(self.offset.y);
+        let below = self
+            .text_height()
+            .saturating_sub(cursor_y.saturating_add(1));
+        let rows = suggestions
+            .len()
+            .min(SUGGESTION_ROWS)
+            .min(below.max(cursor_y));
+        let first = suggestions
+            .selected_index()
+            .saturating_add(1)
+            .saturating_sub(rows);
+        let selection = self
+            .theme
+            .highlight(&highlighting::Type::Selection)
+            .escape();
+        let normal = self.theme.status_bar.escape();
+        let comment = self.theme.highlight(&highlighting::Type::Comment);
+        let reset = format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset));
+        let layer: Vec<String> = suggestions
+            .matching()
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .map(|(index, suggestion)| {
+                let style = if index == suggestions.selected_index() {
+                    &selection
+                } else {
+                    &normal
+                };
+                let mut text = String::new();
+                let mut used = 0_usize;
+                let gap = label_width.saturating_sub(suggestion.label.width());
+                let line = if suggestion.detail.is_empty() {
+                    suggestion.label.clone()
+                } else {
+                    format!(
+                        "{}{}  {}",
+                        suggestion.label,
+                        " ".repeat(gap),
+                        suggestion.detail
+                    )
+                };
+                for c in line.chars() {
+                    let c_width = c.width().unwrap_or(0);
+                    if used.saturating_add(c_width) > width {
+                        break;
+                    }
+                    used = used.saturating_add(c_width);
+                    text.push(c);
+                }
+                let padding = " ".repeat(width.saturating_sub(used));
+                // The detail is dimmed like a comment, on the menu's
+                // background.
+                match text.split_at_checked(suggestion.label.len()) {
+                    Some((label, detail)) if !detail.is_empty() => {
+                        let detail_style = comment
+                            .fg
+                            .map_or_else(String::new, |fg| color::Fg(fg).to_string());
+                        format!("{style} {label}{detail_style}{detail}{padding} {reset}")
+                    }
+                    _ => format!("{style} {text}{padding} {reset}"),
+                }
+            })
+            .collect();
+        let top = if below >= layer.len() {
+            cursor_y.saturating_add(1)
+        } else {
+            cursor_y.saturating_sub(layer.len())
+        };
+        let column = self
+            .document
+            .row(suggestions.start.y)
+            .map_or(0, |row| {
+                row.column(suggestions.start.x, self.settings.tabstop)
+            })
+            .saturating_sub(self.offset.x)
+            .saturating_add(self.gutter_width())
+            .saturating_sub(1);
+        let left = column.min(screen_width.saturating_sub(width.saturating_add(2)));
+        (layer, top, left)
+    }
+
+    // A box with the hover text below the cursor, or above it when there is
+    // more room there.
+    fn draw_hover(&self, hover: &[String]) -> (Vec<String>, usize, usize) {
+        let size = self.terminal.size();
+        let screen_width = size.width as usize;
+        let inner = hover
+            .iter()
+            .map(|line| line.replace('\t', "    ").width())
+            .max()
+            .unwrap_or(0)
+            .min(screen_width.saturating_sub(4).min(80));
+        let cursor_y = self.cursor_position.y.saturating_sub(self.offset.y);
+        let below = self
+            .text_height()
+            .saturating_sub(cursor_y.saturating_add(1));
+        let above = cursor_y;
+        let rows = below.max(above).saturating_sub(2).min(hover.len());
+        let mut layer = vec![format!("┌{}┐", "─".repeat(inner.saturating_add(2)))];
+        for line in hover.iter().take(rows) {
+            let mut text = String::new();
+            let mut used = 0_usize;
+            for c in line.replace('\t', "    ").chars() {
+                let width = c.width().unwrap_or(0);
+                if used.saturating_add(width) > inner {
+                    break;
+                }
+                used = used.saturating_add(width);
+                text.push(c);
+            }
+            layer.push(format!(
+                "│ {text}{} │",
+                " ".repeat(inner.saturating_sub(used))
+            ));
+        }
+        layer.push(format!("└{}┘", "─".repeat(inner.saturating_add(2))));
+        let top = if below >= above || below >= layer.len() {
+            cursor_y.saturating_add(1)
+        } else {
+            cursor_y.saturating_sub(layer.len())
+        };
+        let column = self
+            .cursor_column()
+            .saturating_sub(self.offset.x)
+            .saturating_add(self.gutter_width());
+        let left = column.min(screen_width.saturating_sub(inner.saturating_add(4)));
+        (layer, top, left)
+    }
+
+    // The `:s` being typed, whose replacements are shown on the rows it
+    // would change without touching the document until it is run.
+    fn substitution_preview(&self) -> Option<(LineRange, Regex, Substitute)> {
+        if !matches!(self.mode, Mode::Command) {
+            return None;
+        }
+        let last = self.document.len().saturating_sub(1);
+        let mark = |name| self.document.mark(name).map(|position| position.y);
+        let (range, command) = LineRange::parse(
+            self.command_buffer.trim_start(),
+            self.cursor_position.y,
+            last,
+            &mark,
+        )
+        .ok()?;
+        let substitute = Substitute::parse(command)?.ok()?;
+        let pattern = search::compile(
+            &substitute.pattern,
+            !self.settings.regex,
+            substitute.ignore_case,
+        )
+        .ok()?;
+        let range = range.unwrap_or_else(|| LineRange::line(self.cursor_position.y));
+        Some((range, pattern, substitute))
+    }
+
+    // The graphemes of row `y` inside the Visual mode selection.
+    fn selected_columns(&self, y: usize, row: &Row) -> std::ops::Range<usize> {
+        if !matches!(self.mode, Mode::Visual) && !self.visual.searching {
+            return 0..0;
+        }
+        let (start, end) = self.visual_bounds();
+        if y < start.y || y > end.y {
+            return 0..0;
+        }
+        if self.visual.linewise {
+            return 0..row.len();
+        }
+        let from = if y == start.y { start.x } else { 0 };
+        let to = if y == end.y {
+            end.x.saturating_add(1)
+        } else {
+            row.len()
+        };
+        from..to
+    }
+
+    // The bracket under the cursor and its partner, if it has one.
+    fn bracket_pair(&self) -> Vec<Position> {
+        if !matches!(self.mode, Mode::Normal | Mode::Insert) {
+            return Vec::new();
+        }
+        self.document
+            .matching_bracket(&self.cursor_position)
+            .map_or_else(Vec::new, |matching| {
+                vec![self.cursor_position.clone(), matching]
+            })
+    }
+
+    // Options that belong to the current document rather than the editor, or
+    // None when `argument` names something else.
+    fn set_buffer_option(&mut self, argument: &str) -> Option<Result<Option<String>, String>> {
+        let (name, value) = match argument.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (argument.trim_end_matches('?'), None),
+        };
+        let query = value.is_none() && argument.ends_with('?');
+        match name {
+            "fileformat" | "ff" => Some(match value {
+                None => {
+                    let format = self.document.file_format().name();
+                    Ok(Some(format!("fileformat={format}")))
+                }
+                Some(value) => match FileFormat::from_name(value) {
+                    Some(format) => {
+                        self.document.set_file_format(format);
+                        Ok(None)
+                    }
+                    None => Err(format!("Invalid value for {name}: {value}")),
+                },
+            }),
+            "fileencoding" | "fenc" => Some(match value {
+                None => {
+                    let encoding = self.document.file_encoding().name();
+                    Ok(Some(format!("fileencoding={encoding}")))
+                }
+                Some(value) => match FileEncoding::from_label(value) {
+                    Some(encoding) => {
+                        self.document.set_file_encoding(encoding);
+                        Ok(None)
+                    }
+                    None => Err(format!("Invalid value for {name}: {value}")),
+                },
+            }),
+            "endofline" | "eol" | "noendofline" | "noeol" if value.is_none() => {
+                if query {
+                    let prefix = if self.document.end_of_line() {
+                        ""
+                    } else {
+                        "no"
+                    };
+                    return Some(Ok(Some(format!("{prefix}endofline"))));
+                }
+                self.document.set_end_of_line(!name.starts_with("no"));
+                Some(Ok(None))
+            }
+            "readonly" | "ro" | "noreadonly" | "noro" if value.is_none() => {
+                if query {
+                    let prefix = if self.document.is_read_only() {
+                        ""
+                    } else {
+                        "no"
+                    };
+                    return Some(Ok(Some(format!("{prefix}readonly"))));
+                }
+                self.document.set_read_only(!name.starts_with("no"));
+                Some(Ok(None))
+            }
+            _ => None,
+        }
+    }
+
+    fn draw_status_bar(&self) -> String {
+        let width = self.terminal.size().width as usize;
+        let draw = |segments: &[Segment]| {
+            segments
+                .iter()
+                .map(|&segment| self.draw_segment(segment))
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut status = draw(&self.status_line.left);
+        let right = draw(&self.status_line.right);
+        let len = status.width().saturating_add(right.width());
+        status.push_str(&" ".repeat(width.saturating_sub(len).max(1)));
+        status.push_str(&right);
+        status.truncate(width);
+        format!(
+            "{}{status}{}{}",
+            self.theme.status_bar.escape(),
+            color::Fg(color::Reset),
+            color::Bg(color::Reset)
+        )
+    }
+
+    // Which of the matches of the highlighted search the cursor is on, going
+    // by the last one at or before it, and how many there are.
+    fn search_match_count(&self) -> Option<(usize, usize)> {
+        let pattern = self.document.highlight_pattern()?;
+        let matches = &self.search_matches;
+        if matches.revision != self.document.revision() || matches.pattern != pattern.as_str() {
+            return None;
+        }
+        let at = (self.cursor_position.y, self.cursor_position.x);
+        let current = matches
+            .positions
+            .partition_point(|position| (position.y, position.x) <= at);
+        Some((current, matches.positions.len()))
+    }
+
+    // What the status bar shows for `segment`, empty when there is nothing to
+    // show.
+    fn draw_segment(&self, segment: Segment) -> String {
+        let lines = self.document.len();
+        let line = self.cursor_position.y.saturating_add(1);
+        match segment {
+            Segment::Pending => {
+                let mapping: String = self
+                    .pending_keys
+                    .iter()
+                    .copied()
+                    .map(keymap::notation)
+                    .collect();
+                format!("{}{mapping}", self.pending.keys)
+            }
+            Segment::Mode => format!("[ {} ]", self.mode.to_string()),
+            Segment::File if self.document.is_help() => "[Help]".to_string(),
+            Segment::File => {
+                let mut file_name = self
+                    .document
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| "[No Name]".to_string());
+                file_name.truncate(20);
+                file_name
+            }
+            Segment::Modified if self.document.is_dirty() => "[+]".to_string(),
+            Segment::ReadOnly if self.document.is_read_only() => "[RO]".to_string(),
+            Segment::Branch => self.repo_status().map_or_else(String::new, |repo| {
+                let changed = if repo.dirty { "*" } else { "" };
+                format!("[{}{changed}]", repo.branch)
+            }),
+            Segment::Diagnostics => {
+                let count = |severity: Severity| {
+                    self.diagnostics()
+                        .iter()
+                        .filter(|diagnostic| diagnostic.severity == severity)
+                        .count()
+                };
+                let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+                if errors > 0 || warnings > 0 {
+                    format!("[E{errors} W{warnings}]")
+                } else {
+                    String::new()
+                }
+            }
+            Segment::Arguments if self.arguments.len() > 1 => format!(
+                "({} of {})",
+                self.argument.saturating_add(1),
+                self.arguments.len()
+            ),
+            Segment::Search => self
+                .search_match_count()
+                .map_or_else(String::new, |(current, total)| {
+                    format!("[{current}/{total}]")
+                }),
+            Segment::Loading => self
+                .document
+                .loading_progress()
+                .map_or_else(String::new, |progress| format!("[loading {progress}%]")),
+            Segment::FileType => self.document.file_type(),
+            Segment::Encoding => self.document.file_encoding().name().to_string(),
+            Segment::FileFormat => self.document.file_format().name().to_string(),
+            Segment::Position => format!("{line}/{lines}"),
+            Segment::Line => line.to_string(),
+            Segment::Column => format!("col {}", self.cursor_column().saturating_add(1)),
+            // Like vim, how much of the file is above the screen compared to
+            // what isn't on it.
+            Segment::Percent => {
+                let above = self.offset.y;
+                let below = lines.saturating_sub(above.saturating_add(self.text_height()));
+                match (above, below) {
+                    (0, 0) => "All".to_string(),
+                    (0, _) => "Top".to_string(),
+                    (_, 0) => "Bot".to_string(),
+                    _ => format!(
+                        "{}%",
+                        above.saturating_mul(100) / above.saturating_add(below)
+                    ),
+                }
+            }
+            Segment::Modified | Segment::ReadOnly | Segment::Arguments => String::new(),
+        }
+    }
+
+    // The message line and the width of the message on it, which is where
+    // typing continues in Command mode.
+    fn draw_message_bar(&self) -> (String, usize) {
+        let message = &self.status_message;
+        let mut line = String::new();
+        if Instant::now() - message.time < Duration::new(5, 0) {
+            line = message.text.clone();
+            line.truncate(self.terminal.size().width as usize);
+        }
+        let width = line.width();
+        if let Some(completion) = &self.completion {
+            line.push_str(&self.draw_completion(completion));
+        }
+        (line, width)
+    }
+
+    // Lists the candidates after the command line.
+    fn draw_completion(&self, completion: &Completion) -> String {
+        let width = self.terminal.size().width as usize;
+        let mut column = self.command_buffer.width().saturating_add(3);
+        let mut line = String::from("  ");
+        for (index, candidate) in completion.candidates().iter().enumerate() {
+            column = column.saturating_add(candidate.width()).saturating_add(2);
+            if column > width {
+                break;
+            }
+            if completion.selected() == Some(index) {
+                line.push_str(&format!("{}{candidate}{}  ", style::Invert, style::Reset));
+            } else {
+                line.push_str(&format!("{candidate}  "));
+            }
+        }
+        line
+    }
+
+    fn switch_mode(&mut self, mode: Mode) {
+        // The last selection is remembered in the `<` and `>` marks.
+        if matches!(self.mode, Mode::Visual) && !matches!(mode, Mode::Visual) {
+            let (start, end) = self.visual_bounds();
+            self.document.set_mark('<', &start);
+            self.document.set_mark('>', &end);
+        }
+        match mode {
+            Mode::Normal => {
+                self.command_buffer.clear();
+                self.status_message = StatusMessage::from(String::from(""));
+            }
+            Mode::Insert | Mode::Replace if self.document.is_read_only() => {
+                self.report_read_only();
+                return;
+            }
+            Mode::Insert => (),
+            Mode::Replace => {
+                self.replaced.clear();
+            }
+            Mode::Command => {
+                self.status_message = StatusMessage::from(String::from(":"));
+            }
+            Mode::Search => {
+                self.position_buffer = self.cursor_position.clone();
+                self.status_message = StatusMessage::from(String::from("/"));
+            }
+            Mode::Visual | Mode::Terminal => (),
+        }
+        // Typing goes back to the text as written.
+        if matches!(mode, Mode::Insert | Mode::Replace) {
+            self.document.set_preview(false);
+        }
+        self.mode = mode;
+    }
+}
+
+// Opens `file_name`, or starts an empty document that will be saved there.
+// Large files are streamed into the buffer at index `buffer` in the
+// background so they show up before being read completely.
+// What scripts can do, with lines and columns counted from one as they are
+// shown.
+impl Host for Editor {
+    fn call(&mut self, name: &str, arguments: &[Value]) -> Option<Result<Value, String>> {
+        let string = |index| script::string_argument(arguments, index);
+        let len = self.document.len();
+        // The zero-based row of the line number at `index`, which may be one
+        // past the last line when `append`.
+        let line = |index, append: bool| {
+            let number = script::number_argument(arguments, index)?;
+            let last = if append { len.saturating_add(1) } else { len };
+            if number.fract() != 0.0 || number < 1.0 || number > last as f64 {
+                return Err(format!("no line {}", Value::Number(number)));
+            }
+            Ok((number as usize).saturating_sub(1))
+        };
+        let result = match name {
+            "line" => line(0, false).map(|y| {
+                self.document
+                    .row(y)
+                    .map_or(Value::Nil, |row| Value::from(row.as_str()))
+            }),
+            "lines" => Ok(Value::list(
+                (0..len)
+                    .filter_map(|y| self.document.row(y))
+                    .map(|row| Value::from(row.as_str()))
+                    .collect(),
+            )),
+            "line_count" => Ok(Value::from(len)),
+            "set_line" => line(0, false).and_then(|y| {
+                let lines: Vec<String> = string(1)?.split('\n').map(String::from).collect();
+                self.document.replace_lines(y, 1, &lines);
+                Ok(Value::Nil)
+            }),
+            "insert_line" => line(0, true).and_then(|y| {
+                let lines: Vec<String> = string(1)?.split('\n').map(String::from).collect();
+                self.document.insert_lines(y, &lines);
+                Ok(Value::Nil)
+            }),
+            "delete_line" => line(0, false).map(|y| {
+                self.document.delete_lines(y, 1);
+                self.restore_cursor(self.cursor_position.clone());
+                Value::Nil
+            }),
+            "cursor_line" => Ok(Value::from(self.cursor_position.y.saturating_add(1))),
+            "cursor_column" => Ok(Value::from(self.cursor_position.x.saturating_add(1))),
+            "set_cursor" => line(0, false).and_then(|y| {
+                let column = match arguments.get(1) {
+                    Some(_) => script::number_argument(arguments, 1)?,
+                    None => 1.0,
+                };
+                self.restore_cursor(Position {
+                    x: (column.max(1.0) as usize).saturating_sub(1),
+                    y,
+                });
+                Ok(Value::Nil)
+            }),
+            "message" => string(0).map(|text| {
+                self.status_message = StatusMessage::from(text);
+                Value::Nil
+            }),
+            "command" => string(0).and_then(|command| {
+                self.execute_command(&command)
+                    .map_err(|error| error.to_string())?;
+                Ok(Value::Nil)
+            }),
+            "file_name" => Ok(self
+                .document
+                .file_name
+                .as_deref()
+                .map_or(Value::Nil, Value::from)),
+            "file_type" => Ok(Value::from(self.document.file_type())),
+            "map" => string(0).and_then(|mode| {
+                self.keymap.map(
+                    &mode,
+                    keymap::parse_keys(&string(1)?),
+                    keymap::parse_keys(&string(2)?),
+                );
+                Ok(Value::Nil)
+            }),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+fn load_document(
+    terminal: &dyn Backend,
+    file_name: &str,
+    buffer: usize,
+) -> Result<Document, std::io::Error> {
+    if std::path::Path::new(file_name).is_dir() {
+        return Ok(Document::directory(file_name, &explorer::list(file_name)?));
+    }
+    if std::path::Path::new(file_name).exists() {
+        let size = std::fs::metadata(file_name)?.len();
+        if size > loader::STREAM_THRESHOLD && !terminal.is_headless() {
+            loader::spawn(file_name.to_string(), buffer, terminal.event_sender());
+            return Ok(Document::loading(file_name, size));
+        }
+        Document::open(file_name)
+    } else {
+        Ok(Document::from(file_name))
+    }
+}
+
+// A search for `text`, as a whole word when it is one.
+fn whole_word_query(text: &str) -> String {
+    let escaped = regex::escape(text);
+    if text.chars().next().map(CharClass::of) == Some(CharClass::Word) {
+        format!(r"\b{escaped}\b")
+    } else {
+        escaped
+    }
+}
+
+// Where `file` asks for the cursor to start, kept inside `document`.
+fn start_position(file: &cli::File, document: &Document) -> Position {
+    let y = file
+        .line
+        .unwrap_or(1)
+        .saturating_sub(1)
+        .min(document.len().saturating_sub(1));
+    let width = document.row(y).map_or(0, Row::len);
+    Position {
+        x: file
+            .column
+            .unwrap_or(1)
+            .saturating_sub(1)
+            .min(width.saturating_sub(1)),
+        y,
+    }
+}
+
+fn write_recovery(document: &Document, fallback: PathBuf) -> Result<PathBuf, std::io::Error> {
+    if let Some(file_name) = &document.file_name {
+        let path = PathBuf::from(format!("{file_name}.recover"));
+        if document.write_copy(&path).is_ok() {
+            return Ok(path);
+        }
+    }
+    document.write_copy(&fallback)?;
+    Ok(fallback)
+}
+
+#[cfg(test)]
+mod tests;
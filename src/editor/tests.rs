@@ -0,0 +1,1365 @@
+use super::*;
+use crate::cells;
+use crate::help;
+use crate::hex;
+use crate::keymap;
+use crate::overlay;
+use crate::tags;
+use crate::term_window::{self, Screen};
+use crate::Rope;
+use std::path::Path;
+use termion::color::Rgb;
+
+// Types `keys`, in key mapping notation, into an editor holding `text`, and
+// returns it once they run out.
+fn run(text: &str, keys: &str) -> Editor {
+    let backend = Headless::new(keymap::parse_keys(keys));
+    let mut editor = Editor::with_backend(
+        &cli::Options::default(),
+        Some(Document::read_from(text.as_bytes())),
+        Box::new(backend),
+    );
+    editor.run().unwrap();
+    editor
+}
+
+// The last frame drawn after typing `keys` into an editor holding `text`.
+fn frame(text: &str, keys: &str) -> Vec<String> {
+    let backend = Headless::new(keymap::parse_keys(keys));
+    let screen = backend.screen();
+    let mut editor = Editor::with_backend(
+        &cli::Options::default(),
+        Some(Document::read_from(text.as_bytes())),
+        Box::new(backend),
+    );
+    editor.run().unwrap();
+    let lines = screen.borrow().clone();
+    lines
+}
+
+fn lines(editor: &Editor) -> Vec<&str> {
+    (0..editor.document.len())
+        .filter_map(|y| editor.document.row(y))
+        .map(Row::as_str)
+        .collect()
+}
+
+fn cursor(editor: &Editor) -> (usize, usize) {
+    (editor.cursor_position.x, editor.cursor_position.y)
+}
+
+#[test]
+fn word_motions() {
+    let text = "one two three\nfour";
+    assert_eq!(cursor(&run(text, "w")), (4, 0));
+    assert_eq!(cursor(&run(text, "2w")), (8, 0));
+    assert_eq!(cursor(&run(text, "e")), (2, 0));
+    assert_eq!(cursor(&run(text, "$b")), (8, 0));
+    assert_eq!(cursor(&run(text, "3w")), (0, 1));
+}
+
+#[test]
+fn line_motions() {
+    let text = "  indented line\nsecond\nthird";
+    assert_eq!(cursor(&run(text, "$")), (15, 0));
+    assert_eq!(cursor(&run(text, "$0")), (0, 0));
+    assert_eq!(cursor(&run(text, "G")), (0, 2));
+    assert_eq!(cursor(&run(text, "Ggg")), (2, 0));
+    assert_eq!(cursor(&run(text, "2G")), (0, 1));
+    assert_eq!(cursor(&run(text, "jjk")), (0, 1));
+}
+
+#[test]
+fn find_motions() {
+    let text = "a(b, c) d, e";
+    assert_eq!(cursor(&run(text, "f,")), (3, 0));
+    assert_eq!(cursor(&run(text, "f,;")), (9, 0));
+    assert_eq!(cursor(&run(text, "t,")), (2, 0));
+    assert_eq!(cursor(&run(text, "$F(")), (1, 0));
+    assert_eq!(cursor(&run(text, "f(%")), (6, 0));
+}
+
+#[test]
+fn search() {
+    let text = "alpha\nbeta\nalpha beta";
+    assert_eq!(cursor(&run(text, "/beta<CR>")), (0, 1));
+    assert_eq!(cursor(&run(text, "/beta<CR>n")), (6, 2));
+    assert_eq!(cursor(&run(text, "*")), (0, 2));
+    let editor = run("one two three\nfour", "v/thr<CR>d");
+    assert_eq!(lines(&editor), ["hree", "four"]);
+    let editor = run("one two three\nfour", "vl/fo<Esc>");
+    assert!(matches!(editor.mode, Mode::Visual));
+    assert_eq!(cursor(&editor), (1, 0));
+    let editor = run(text, "/beta<CR>:s/a/x/<CR>n");
+    assert_eq!(lines(&editor), ["alpha", "betx", "alpha beta"]);
+    assert_eq!(cursor(&editor), (6, 2));
+}
+
+#[test]
+fn insert_and_escape() {
+    let editor = run("world", "ihello <Esc>");
+    assert_eq!(lines(&editor), ["hello world"]);
+    assert_eq!(cursor(&editor), (5, 0));
+    assert!(matches!(editor.mode, Mode::Normal));
+}
+
+#[test]
+fn open_lines() {
+    assert_eq!(lines(&run("a\nc", "ob<Esc>")), ["a", "b", "c"]);
+    assert_eq!(lines(&run("b\nc", "Oa<Esc>")), ["a", "b", "c"]);
+}
+
+#[test]
+fn operators() {
+    let text = "one two three";
+    assert_eq!(lines(&run(text, "dw")), ["two three"]);
+    assert_eq!(lines(&run(text, "d2w")), ["three"]);
+    assert_eq!(lines(&run(text, "wd$")), ["one "]);
+    assert_eq!(lines(&run(text, "cwsix<Esc>")), ["six two three"]);
+    assert_eq!(lines(&run("a\nb\nc", "jdd")), ["a", "c"]);
+    assert_eq!(lines(&run("a\nb\nc", "2dd")), ["c"]);
+}
+
+#[test]
+fn text_objects() {
+    assert_eq!(lines(&run("f(a, b)", "fadi(")), ["f()"]);
+    assert_eq!(
+        lines(&run("say \"hi there\"", "fhci\"bye<Esc>")),
+        ["say \"bye\""]
+    );
+    assert_eq!(lines(&run("one two", "wdaw")), ["one"]);
+}
+
+#[test]
+fn yank_and_paste() {
+    assert_eq!(lines(&run("a\nb", "yyjp")), ["a", "b", "a"]);
+    assert_eq!(lines(&run("a\nb", "yyP")), ["a", "a", "b"]);
+    assert_eq!(lines(&run("one two", "dwP")), ["one two"]);
+    assert_eq!(lines(&run("a\nb", "\"xyyj\"xp")), ["a", "b", "a"]);
+}
+
+#[test]
+fn registers_popup_fits_the_screen() {
+    let text = format!("x{}", "é".repeat(200));
+    let screen = frame(&text, "yy:reg<CR>");
+    assert!(screen.iter().any(|line| line.contains("xéé")));
+    assert!(screen.iter().all(|line| overlay::width(line) <= 80));
+}
+
+#[test]
+fn undo_and_redo() {
+    let text = "one two";
+    assert_eq!(lines(&run(text, "dwu")), ["one two"]);
+    assert_eq!(lines(&run(text, "dwu<C-r>")), ["two"]);
+    assert_eq!(lines(&run(text, "ix<Esc>iy<Esc>u")), ["xone two"]);
+}
+
+#[test]
+fn undo_tree() {
+    // Undoing x and then typing y leaves x on a branch of its own.
+    let keys = "ix<Esc>uiy<Esc>";
+    assert_eq!(lines(&run("one", keys)), ["yone"]);
+    assert_eq!(lines(&run("one", &format!("{keys}:earlier<CR>"))), ["xone"]);
+    assert_eq!(
+        lines(&run("one", &format!("{keys}:earlier<CR>:later<CR>"))),
+        ["yone"]
+    );
+    assert_eq!(
+        lines(&run("one", &format!("{keys}:earlier 1h<CR>"))),
+        ["one"]
+    );
+    assert_eq!(
+        lines(&run("one", &format!("{keys}:ea 2<CR>:lat 9<CR>"))),
+        ["yone"]
+    );
+    // Redo goes back down the branch last visited.
+    assert_eq!(
+        lines(&run("one", &format!("{keys}:earlier<CR>u<C-r>"))),
+        ["xone"]
+    );
+    let editor = run("one", &format!("{keys}:earlier 2<CR>:earlier<CR>"));
+    assert_eq!(editor.status_message.text, "Already at oldest change");
+}
+
+#[test]
+fn indent() {
+    assert_eq!(lines(&run("a\nb", ">>")), ["    a", "b"]);
+    assert_eq!(lines(&run("    a", "<<")), ["a"]);
+}
+
+#[test]
+fn ex_commands() {
+    let text = "foo\nbar foo\nbaz";
+    assert_eq!(lines(&run(text, ":%s/foo/x/<CR>")), ["x", "bar x", "baz"]);
+    assert_eq!(lines(&run(text, ":2d<CR>")), ["foo", "baz"]);
+    assert_eq!(lines(&run(text, ":1m$<CR>")), ["bar foo", "baz", "foo"]);
+    assert_eq!(
+        lines(&run(text, ":1t0<CR>")),
+        ["foo", "foo", "bar foo", "baz"]
+    );
+    assert_eq!(cursor(&run(text, ":3<CR>")), (0, 2));
+}
+
+#[test]
+fn visual_mode() {
+    assert_eq!(lines(&run("one two", "vld")), ["e two"]);
+    assert_eq!(lines(&run("a\nb\nc", "Vjd")), ["c"]);
+}
+
+#[test]
+fn private_copies() {
+    use std::os::unix::fs::PermissionsExt;
+    // A directory someone else made first is left alone.
+    let taken = std::env::temp_dir().join(format!("ggedit.{}.0", std::process::id()));
+    std::fs::create_dir_all(&taken).unwrap();
+    let document = Document::read_from("one\ntwo".as_bytes()).unwrap();
+    let (directory, path) = document.write_private_copy().unwrap();
+    assert_ne!(directory, taken);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo");
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&directory), 0o700);
+    assert_eq!(mode(&path), 0o600);
+    std::fs::remove_dir_all(&directory).unwrap();
+    std::fs::remove_dir(&taken).unwrap();
+}
+
+#[test]
+fn read_only_buffer_refuses_edits() {
+    let editor = run("text", ":set ro<CR>dd");
+    assert_eq!(lines(&editor), ["text"]);
+    assert!(editor.status_message.text.contains("readonly"));
+}
+
+#[test]
+fn frame_shows_text_and_status() {
+    let backend = Headless::new(keymap::parse_keys("ihello<Esc>"));
+    let screen = backend.screen();
+    let mut editor = Editor::with_backend(
+        &cli::Options::default(),
+        Some(Document::read_from("world".as_bytes())),
+        Box::new(backend),
+    );
+    editor.run().unwrap();
+    let screen = screen.borrow();
+    assert!(screen
+        .first()
+        .is_some_and(|line| line.contains("helloworld")));
+    assert!(screen.iter().any(|line| line.contains("[No Name] [+]")));
+    assert!(screen.iter().any(|line| line.contains("[ Normal ]")));
+}
+
+#[test]
+fn finder_opens_the_picked_file() {
+    let editor = run("", "<C-p>srcmainrs<CR>");
+    assert_eq!(editor.document.file_name.as_deref(), Some("src/main.rs"));
+    let editor = run("", "<C-p>srcmainrs<Esc>");
+    assert_eq!(editor.document.file_name, None);
+}
+
+#[test]
+fn directory_listing() {
+    let editor = run("", ":e src<CR>");
+    assert!(editor.document.is_directory());
+    assert_eq!(lines(&editor).first(), Some(&"../"));
+    assert!(lines(&editor).contains(&"editor/"));
+    assert!(lines(&editor).contains(&"main.rs"));
+    let editor = run("", ":e src<CR>-");
+    assert_eq!(editor.document.file_name.as_deref(), Some("."));
+    assert_eq!(lines(&editor).get(cursor(&editor).1), Some(&"src/"));
+    let editor = run("", ":e src<CR>-<CR>");
+    assert_eq!(editor.document.file_name.as_deref(), Some("src"));
+}
+
+#[test]
+fn grep_fills_the_quickfix_list() {
+    let root = std::env::temp_dir().join(format!("ggedit-grep-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(root.join("a.rs"), "mod one;\nmod two;\n").unwrap();
+    std::fs::write(root.join("src/b.rs"), "fn b() {}\nmod three;\n").unwrap();
+    let b = root.join("src/b.rs").to_string_lossy().into_owned();
+    let mut editor = run("", "");
+    editor.grep_in(&root, "^mod three;$");
+    assert_eq!(editor.quickfix.len(), 1);
+    assert_eq!(editor.document.file_name.as_deref(), Some(b.as_str()));
+    assert_eq!(cursor(&editor), (0, 1));
+    editor.grep_in(&root, "^mod ");
+    assert_eq!(editor.quickfix.len(), 3);
+    editor.execute_command("cnext").unwrap();
+    editor.execute_command("cnext").unwrap();
+    editor.execute_command("cprev").unwrap();
+    assert_eq!(editor.quickfix.current_index(), 1);
+    assert_eq!(lines(&editor), ["mod one;", "mod two;"]);
+    assert_eq!(cursor(&editor), (0, 1));
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn diagnostic_positions() {
+    let message = r#"{"uri":"file:///a.rs","diagnostics":[{"range":{"start":{"line":0,"character":3},"end":{"line":0,"character":4}},"severity":2,"message":"unused"}]}"#;
+    let params = crate::json::Value::parse(message).unwrap();
+    let Some(lsp::Message::Diagnostics { uri, diagnostics }) = lsp::parse_diagnostics(&params)
+    else {
+        panic!("no diagnostics");
+    };
+    assert_eq!(uri, "file:///a.rs");
+    let editor = run("é😀x", "");
+    let row = editor.document.row(0).unwrap();
+    // The emoji takes two UTF-16 units, so column 3 is the `x`.
+    assert_eq!(lsp::grapheme_index(row, diagnostics[0].start.1), 2);
+}
+
+#[test]
+fn definition_and_hover_replies() {
+    let mut editor = run("", "");
+    editor.apply_reply(Reply::Definition(vec![lsp::Location {
+        file_name: String::from("src/main.rs"),
+        line: 2,
+        character: 4,
+    }]));
+    assert_eq!(editor.document.file_name.as_deref(), Some("src/main.rs"));
+    assert_eq!(cursor(&editor), (4, 2));
+    editor.apply_reply(Reply::Hover(vec![String::from("fn main()")]));
+    assert!(editor.hover.is_some());
+    editor.process_keypress(Key::Char('j')).unwrap();
+    assert!(editor.hover.is_none());
+}
+
+#[test]
+fn completion_menu_falls_back_to_buffer_words() {
+    let mut editor = run("format fox", "ofo");
+    editor.show_suggestions(Vec::new());
+    assert_eq!(editor.suggestions.as_ref().map(Suggestions::len), Some(2));
+    editor.process_keypress(Key::Ctrl('n')).unwrap();
+    editor.process_keypress(Key::Char('\n')).unwrap();
+    assert_eq!(lines(&editor), ["format fox", "fox"]);
+    assert_eq!(cursor(&editor), (3, 1));
+    assert!(editor.suggestions.is_none());
+}
+
+#[test]
+fn buffer_word_completion() {
+    let editor = run("alpha alphabet\nbeta", "Goal<C-n><C-n><CR>");
+    assert_eq!(lines(&editor), ["alpha alphabet", "beta", "alphabet"]);
+    let editor = run("alpha alphabet\nbeta", "Goal<C-p><Tab><Esc>");
+    assert_eq!(lines(&editor), ["alpha alphabet", "beta", "alphabet"]);
+    let editor = run("alpha", "oxyz<C-n><CR>");
+    assert_eq!(lines(&editor), ["alpha", "xyz", ""]);
+}
+
+#[test]
+fn comment_toggling() {
+    // The comment markers come from the file type, so the text is in a file.
+    let path = std::env::temp_dir().join(format!("ggedit-comment-{}.rs", std::process::id()));
+    std::fs::write(&path, "// a\nfn a() {\n    let b = 1;\n    let c = 2;\n}\n").unwrap();
+    let path = path.to_string_lossy();
+    let editor = run("", &format!(":e {path}<CR>jgcj"));
+    assert_eq!(
+        lines(&editor).get(1..3).unwrap(),
+        ["// fn a() {", "//     let b = 1;"]
+    );
+    let editor = run("", &format!(":e {path}<CR>jjVjgc"));
+    assert_eq!(
+        lines(&editor).get(2..4).unwrap(),
+        ["    // let b = 1;", "    // let c = 2;"]
+    );
+    let editor = run("", &format!(":e {path}<CR>jgcjgcc"));
+    assert_eq!(
+        lines(&editor).get(1..3).unwrap(),
+        ["fn a() {", "//     let b = 1;"]
+    );
+    let editor = run("", &format!(":e {path}<CR>jgcjgc2c"));
+    assert_eq!(
+        lines(&editor).get(1..3).unwrap(),
+        ["fn a() {", "    let b = 1;"]
+    );
+    std::fs::remove_file(path.as_ref()).unwrap();
+    let editor = run("text", "gcc");
+    assert_eq!(lines(&editor), ["text"]);
+}
+
+#[test]
+fn surroundings() {
+    let editor = run("say hello there", "wysiw\"");
+    assert_eq!(lines(&editor), ["say \"hello\" there"]);
+    assert_eq!(cursor(&editor), (4, 0));
+    let editor = run("say hello there", "wysw(");
+    assert_eq!(lines(&editor), ["say ( hello ) there"]);
+    let editor = run("  let x = 1;", "yss]");
+    assert_eq!(lines(&editor), ["  [let x = 1;]"]);
+    let editor = run("f(\"a\", 'b')", "facs\"'");
+    assert_eq!(lines(&editor), ["f('a', 'b')"]);
+    let editor = run("f(\"a\", 'b')", "facs\"'fbds'");
+    assert_eq!(lines(&editor), ["f('a', b)"]);
+    let editor = run("f( \"a\" )", "fads(");
+    assert_eq!(lines(&editor), ["f\"a\""]);
+    let editor = run("f(\n  x\n)", "jcs)}u");
+    assert_eq!(lines(&editor), ["f(", "  x", ")"]);
+    let editor = run("f(\n  x\n)", "jcs)}");
+    assert_eq!(lines(&editor), ["f{", "  x", "}"]);
+}
+
+#[test]
+fn spell_checking() {
+    let dictionary = Dictionary::hunspell(
+        "en_US",
+        "3\nword/S\ncat/S\ntry/S\n",
+        "SFX S Y 2\nSFX S 0 s [^y]\nSFX S y ies y\n",
+    );
+    assert!(dictionary.check("tries") && dictionary.check("Cats") && dictionary.check("WORDS"));
+    assert!(!dictionary.check("trys"));
+    assert_eq!(dictionary.suggest("wrod"), ["word", "words"]);
+    let mut editor = run("the cta sat", "");
+    editor.settings.spell = true;
+    editor.dictionary = Some(dictionary);
+    for key in keymap::parse_keys("]sz=1]s") {
+        editor.process_keypress(key).unwrap();
+    }
+    assert_eq!(lines(&editor), ["the cat sat"]);
+    assert_eq!(cursor(&editor), (8, 0));
+}
+
+#[test]
+fn trailing_whitespace_is_trimmed_on_save() {
+    let path = std::env::temp_dir().join(format!("ggedit-trim-{}.txt", std::process::id()));
+    let path = path.to_string_lossy();
+    let editor = run(
+        "a  \nb\t",
+        &format!(":set trimtrailingwhitespace<CR>$:w {path}<CR>"),
+    );
+    let written = fs::read_to_string(path.as_ref()).unwrap();
+    fs::remove_file(path.as_ref()).unwrap();
+    assert_eq!(written, "a\nb");
+    assert!(!editor.document.is_dirty());
+    assert_eq!(cursor(&editor), (1, 0));
+    let row = editor.document.row(0).unwrap();
+    assert!(editor.trailing_whitespace_columns(0, row).is_empty());
+    let editor = run("a  \nb\t", "");
+    let row = editor.document.row(0).unwrap();
+    assert_eq!(editor.trailing_whitespace_columns(0, row).len(), 2);
+}
+
+#[test]
+fn special_characters() {
+    let editor = run(
+        "",
+        "i<C-v>u2713<C-k>a:<C-k>->x<C-v>065<C-v><Tab><C-v>u41!<Esc>",
+    );
+    assert_eq!(lines(&editor), ["✓ä→xA\tA!"]);
+    let editor = run("", "i<C-k>:e<C-k>zz<Esc>");
+    assert_eq!(lines(&editor), ["ëz"]);
+}
+
+#[test]
+fn scrolling_keys() {
+    let text = (1..=100)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let editor = run(&text, "<C-d><C-d><C-u>");
+    let half = editor.text_height() / 2;
+    assert_eq!(cursor(&editor), (0, half));
+    assert_eq!(editor.offset.y, half);
+    let editor = run(&text, "5j3<C-e>");
+    assert_eq!(cursor(&editor), (0, 5));
+    assert_eq!(editor.offset.y, 3);
+    let editor = run(&text, "10<C-e><C-y>");
+    assert_eq!(cursor(&editor), (0, 10));
+    assert_eq!(editor.offset.y, 9);
+    let editor = run(&text, "G<C-d>");
+    assert_eq!(cursor(&editor), (0, 99));
+}
+
+#[test]
+fn aligning_the_view() {
+    let text = (1..=100)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let editor = run(&text, "50Gzt");
+    assert_eq!(editor.offset.y, 49);
+    let editor = run(&text, "50Gzz");
+    assert_eq!(editor.offset.y, 49 - editor.text_height() / 2);
+    let editor = run(&text, "50Gzb");
+    assert_eq!(editor.offset.y, 50 - editor.text_height());
+    assert_eq!(cursor(&editor), (0, 49));
+}
+
+#[test]
+fn scrolloff_keeps_context() {
+    let text = (1..=100)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let editor = run(&text, ":set so=5<CR>30j");
+    assert_eq!(editor.offset.y, 36 - editor.text_height());
+    let editor = run(&text, ":set so=5<CR>G");
+    assert_eq!(editor.offset.y, 100 - editor.text_height());
+    let editor = run(&text, ":set so=5<CR>3<C-e>");
+    assert_eq!(cursor(&editor), (0, 8));
+    assert_eq!(editor.offset.y, 3);
+    let editor = run(&"x".repeat(200), ":set siso=10<CR>100l");
+    assert_eq!(editor.offset.x, 111 - editor.text_width());
+}
+
+#[test]
+fn cursor_line_and_color_columns() {
+    let editor = run("one\ntwo", ":set cul cc=80,x,1,120<CR>");
+    assert!(editor.settings.cursorline);
+    assert_eq!(editor.settings.color_columns(), vec![79, 0, 119]);
+    let backdrop = Backdrop {
+        line: Some(Rgb(1, 2, 3)),
+        column: Some(Rgb(4, 5, 6)),
+        columns: &[1],
+    };
+    let row = Row::from("ab");
+    let plain = row.render(
+        0,
+        4,
+        &editor.settings,
+        &[],
+        &Backdrop::default(),
+        &editor.theme,
+    );
+    let rendered = row.render(0, 4, &editor.settings, &[], &backdrop, &editor.theme);
+    assert!(!plain.contains(&color::Bg(Rgb(1, 2, 3)).to_string()));
+    assert!(rendered.contains(&color::Bg(Rgb(1, 2, 3)).to_string()));
+    assert!(rendered.contains(&color::Bg(Rgb(4, 5, 6)).to_string()));
+    // The cursor line is padded out to the end of the screen.
+    let escapes = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    assert_eq!(escapes.replace_all(&plain, ""), "ab");
+    assert_eq!(escapes.replace_all(&rendered, ""), "ab  ");
+}
+
+#[test]
+fn invisible_characters() {
+    let editor = run("\tx  ", ":set list<CR>");
+    let escapes = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    let row = editor.document.row(0).unwrap();
+    let render = |start, end| {
+        let rendered = row.render(
+            start,
+            end,
+            &editor.settings,
+            &[],
+            &Backdrop::default(),
+            &editor.theme,
+        );
+        escapes.replace_all(&rendered, "").into_owned()
+    };
+    assert_eq!(render(0, 20), "»···x··¬");
+    assert_eq!(render(2, 6), "··x·");
+    let rendered = row.render(
+        0,
+        20,
+        &Settings::default(),
+        &[],
+        &Backdrop::default(),
+        &editor.theme,
+    );
+    assert_eq!(escapes.replace_all(&rendered, ""), "    x  ");
+}
+
+#[test]
+fn status_line_segments() {
+    let escapes = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    let mut editor = run("one\ntwo\nthree\nfour", "jlx");
+    let status = editor.draw_status_bar();
+    let status = escapes.replace_all(&status, "");
+    assert!(status.starts_with("[No Name] [+] "));
+    assert!(status.ends_with(" [ Normal ] utf-8 unix 2/4 col 2 All"));
+    editor.status_line = StatusLine::parse(
+        "[statusline]\nleft = [\"readonly\", \"mode\"]\nright = [\"filetype\", \"percent\", \"line\", \"column\"]",
+    )
+    .unwrap();
+    let status = editor.draw_status_bar();
+    let status = escapes.replace_all(&status, "");
+    assert!(status.starts_with("[ Normal ] "));
+    assert!(status.ends_with(" No filetype All 2 col 2"));
+    let text = (1..=100)
+        .map(|n| format!("\t{n}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut editor = run(&text, "50Gzt0ll");
+    assert_eq!(editor.draw_segment(Segment::Column), "col 6");
+    let above = 49_usize;
+    let below = 100_usize.saturating_sub(49 + editor.text_height());
+    assert_eq!(
+        editor.draw_segment(Segment::Percent),
+        format!("{}%", above * 100 / (above + below))
+    );
+    editor.offset.y = 0;
+    assert_eq!(editor.draw_segment(Segment::Percent), "Top");
+    editor.offset.y = 99;
+    assert_eq!(editor.draw_segment(Segment::Percent), "Bot");
+    assert!(StatusLine::parse("[statusline]\nleft = [\"nothing\"]").is_err());
+}
+
+#[test]
+fn search_match_count() {
+    let mut editor = run("a b a\nb a", "/a<CR>n");
+    editor.note_search_matches();
+    assert_eq!(editor.draw_segment(Segment::Search), "[2/3]");
+    let mut editor = run("a b a\nb a", "/a<CR>ndlgg");
+    editor.note_search_matches();
+    assert_eq!(editor.draw_segment(Segment::Search), "[1/2]");
+    let mut editor = run("a b a\nb a", "/a<CR>:noh<CR>");
+    editor.note_search_matches();
+    assert_eq!(editor.draw_segment(Segment::Search), "");
+}
+
+#[test]
+fn message_history() {
+    let editor = run(
+        "one",
+        ":nosuchcommand<CR>:set nosuchoption<CR>:messages<CR>",
+    );
+    let popup = editor.popup.clone().unwrap();
+    assert_eq!(popup.len(), 4);
+    assert!(popup[1].contains("nosuchcommand"));
+    assert!(popup[2].contains("nosuchoption"));
+    let mut editor = run("one", "");
+    let height = editor.terminal.size().height as usize;
+    editor.show_popup((0..height * 2).map(|n| n.to_string()).collect());
+    assert_eq!(editor.visible_popup()[0], "0");
+    assert!(editor.scroll_popup(Key::Char('j')));
+    assert_eq!(editor.visible_popup()[0], "1");
+    assert!(editor.scroll_popup(Key::Char('G')));
+    let visible = editor.visible_popup();
+    assert_eq!(visible.len(), height);
+    assert_eq!(visible[height - 2], (height * 2 - 1).to_string());
+    assert!(!editor.scroll_popup(Key::Char('q')));
+}
+
+#[test]
+fn pending_keys_and_hints() {
+    let mut editor = run("one two", "\"a2d");
+    assert_eq!(editor.draw_segment(Segment::Pending), "\"a2d");
+    assert!(editor.pending_hints().is_empty());
+    editor.pending.since = Some(Instant::now() - HINT_DELAY);
+    let hints = editor.pending_hints();
+    assert_eq!(hints[0], "\"a2d");
+    assert!(hints
+        .iter()
+        .any(|hint| hint.contains("w e b") && hint.contains("words")));
+    assert_eq!(editor.visible_popup(), hints);
+    let editor = run("one two", "\"a2dw");
+    assert_eq!(editor.draw_segment(Segment::Pending), "");
+    assert_eq!(lines(&editor), vec![""]);
+    let mut editor = run("one two", "z");
+    editor.pending.since = Some(Instant::now() - HINT_DELAY);
+    assert!(editor
+        .pending_hints()
+        .iter()
+        .any(|hint| hint.contains("spelling")));
+    // What each wait lists is what the help says can follow.
+    let help = help::text(&[]);
+    for keys in [
+        "\"", "\"a", "f", "r", "m", "'", "g", "dg", "z", "]", "di", "ds", "cs(", "ysw",
+    ] {
+        let mut editor = run("(one two)", keys);
+        editor.pending.since = Some(Instant::now() - HINT_DELAY);
+        let hints = editor.pending_hints();
+        assert!(hints.len() > 1, "no hints after {keys}");
+        assert!(
+            hints.iter().skip(1).all(|hint| help.contains(hint)),
+            "hints after {keys} missing from the help"
+        );
+    }
+}
+
+#[test]
+fn digraphs_fit_the_screen() {
+    let screen = frame("", ":digraphs<CR>");
+    assert!(screen.iter().any(|line| line.contains("e' é")));
+    assert!(screen.iter().all(|line| overlay::width(line) <= 80));
+    let editor = run("", ":digraphs<CR>");
+    let popup = editor.popup.unwrap();
+    assert!(popup.iter().all(|line| line.width() <= 80));
+    assert!(popup.iter().any(|line| line.width() > 70));
+}
+
+#[test]
+fn help_buffer() {
+    let editor = run("one", ":help<CR>");
+    assert!(editor.document.is_help());
+    assert!(lines(&editor)[0].starts_with("ggedit help"));
+    let editor = run("one", ":help :set<CR>");
+    let line = lines(&editor)[cursor(&editor).1];
+    assert!(line.trim_start().starts_with(":set"));
+    let editor = run("one", ":help zz<CR>/spell<CR>");
+    assert!(lines(&editor)[cursor(&editor).1].contains("spell"));
+    let editor = run("one", ":help<CR>dd:h<CR>:bp<CR>");
+    assert!(!editor.document.is_help());
+    assert_eq!(lines(&editor), vec!["one"]);
+}
+
+#[test]
+fn paragraph_and_sentence_motions() {
+    let text = "One. Two (three!) four\nfive? Six\n\nseven\neight\n\n\nnine";
+    assert_eq!(cursor(&run(text, "}")), (0, 2));
+    assert_eq!(cursor(&run(text, "2}")), (0, 5));
+    assert_eq!(cursor(&run(text, "3}")), (4, 7));
+    assert_eq!(cursor(&run(text, "G{")), (0, 6));
+    assert_eq!(cursor(&run(text, "G2{")), (0, 2));
+    assert_eq!(cursor(&run(text, "G3{")), (0, 0));
+    assert_eq!(cursor(&run(text, ")")), (5, 0));
+    assert_eq!(cursor(&run(text, "2)")), (18, 0));
+    assert_eq!(cursor(&run(text, "3)")), (6, 1));
+    assert_eq!(cursor(&run(text, "4)")), (0, 2));
+    assert_eq!(cursor(&run(text, "5)")), (0, 3));
+    assert_eq!(cursor(&run(text, "$(")), (18, 0));
+    assert_eq!(cursor(&run(text, "j$2(")), (18, 0));
+    assert_eq!(
+        lines(&run(text, "d}")),
+        vec!["", "", "seven", "eight", "", "", "nine"]
+    );
+    assert_eq!(
+        lines(&run(text, ")d)")),
+        vec![
+            "One. four",
+            "five? Six",
+            "",
+            "seven",
+            "eight",
+            "",
+            "",
+            "nine"
+        ]
+    );
+}
+
+#[test]
+fn first_non_blank_and_smart_home() {
+    assert_eq!(cursor(&run("    one two", "$^")), (4, 0));
+    assert_eq!(lines(&run("    one two", "$d^")), vec!["    "]);
+    assert_eq!(cursor(&run("    one two", "$0")), (0, 0));
+    assert_eq!(cursor(&run("    one two", ":set smarthome<CR>$0")), (4, 0));
+    assert_eq!(cursor(&run("    one two", ":set smarthome<CR>$00")), (0, 0));
+    assert_eq!(
+        cursor(&run("    one two", ":set smarthome<CR>$<Home><Home><Home>")),
+        (4, 0)
+    );
+}
+
+#[test]
+fn multiple_cursors() {
+    let text = "let foo = foo + 1;\nfoo();";
+    assert_eq!(
+        lines(&run(text, "w<C-n><C-n><C-n>cbar<Esc>")),
+        ["let bar = bar + 1;", "bar();"]
+    );
+    assert_eq!(
+        lines(&run(text, "w<C-n><C-n>ix<Esc>")),
+        ["let xfoo = xfoo + 1;", "foo();"]
+    );
+    assert_eq!(
+        lines(&run(text, "w<C-n><C-n><C-n>a<CR>!<BS><Esc>")),
+        ["let foo", " = foo", " + 1;", "foo", "();"]
+    );
+    // The edits are undone together, and Esc leaves a single cursor.
+    let editor = run(text, "w<C-n><C-n>cx<Esc>");
+    assert!(editor.multi_cursor.is_none());
+    assert_eq!(
+        lines(&run(text, "w<C-n><C-n>cx<Esc>u")),
+        ["let foo = foo + 1;", "foo();"]
+    );
+    assert_eq!(cursor(&run(text, "w<C-n><C-n><C-n><C-n>")), (0, 1));
+}
+
+#[test]
+fn abbreviations() {
+    let define = ":iab teh the<CR>";
+    assert_eq!(
+        lines(&run("", &format!("{define}iteh cat<Esc>"))),
+        ["the cat"]
+    );
+    assert_eq!(
+        lines(&run("", &format!("{define}iteh.<CR><Esc>"))),
+        ["the.", ""]
+    );
+    // Only whole words expand, Esc doesn't and Ctrl-V types the next
+    // character as it is.
+    assert_eq!(
+        lines(&run("", &format!("{define}ixteh teh<Esc>"))),
+        ["xteh teh"]
+    );
+    assert_eq!(
+        lines(&run("", &format!("{define}iteh<C-v> <Esc>"))),
+        ["teh "]
+    );
+    assert_eq!(
+        lines(&run("", ":iab kr Kind regards<CR>ikr,<Esc>")),
+        ["Kind regards,"]
+    );
+    assert_eq!(
+        lines(&run("", &format!("{define}:iuna teh<CR>iteh <Esc>"))),
+        ["teh "]
+    );
+}
+
+#[test]
+fn autocommands() {
+    let path = std::env::temp_dir().join(format!("ggedit-autocmd-{}.txt", std::process::id()));
+    let path = path.to_string_lossy();
+    let editor = run(
+        "a  ",
+        &format!(
+            ":w {path}<CR>:au BufWritePre *.txt %s/\\s+$//<CR>\
+             :au BufWritePre *.md %s/a/b/<CR>:au BufWritePost * set ts=2<CR>:w<CR>"
+        ),
+    );
+    let written = fs::read_to_string(path.as_ref()).unwrap();
+    fs::remove_file(path.as_ref()).unwrap();
+    assert_eq!(written, "a");
+    assert_eq!(editor.settings.tabstop, 2);
+    // Files nobody has, so the buffers start out empty.
+    let directory = std::env::temp_dir().join(format!("ggedit-autocmd-{}", std::process::id()));
+    let directory = directory.to_string_lossy();
+    let editor = run(
+        "",
+        &format!(
+            ":autocmd FileType rust,go set sw=8<CR>:au BufRead {directory}/*.rs set list<CR>\
+             :e {directory}/x.rs<CR>"
+        ),
+    );
+    assert_eq!(editor.settings.shiftwidth, 8);
+    assert!(editor.settings.list);
+    let editor = run(
+        "",
+        &format!(":au BufRead *.rs set list<CR>:e {directory}/x.py<CR>"),
+    );
+    assert!(!editor.settings.list);
+}
+
+#[test]
+fn scripts() {
+    let editor = run(
+        "one\ntwo",
+        ":script function Shout() set_line(cursor_line(), upper(line(cursor_line()))) end<CR>\
+         j:Shout<CR>",
+    );
+    assert_eq!(lines(&editor), ["one", "TWO"]);
+    let editor = run(
+        "a b c",
+        ":script local words = split(line(1), ' ') \
+         local reversed = [] while len(words) > 0 do push(reversed, pop(words)) end \
+         insert_line(2, join(reversed, ',')) set_cursor(2, 3) \
+         local n = 0 for i = 1, 3 do n = n + i end for w in reversed do n = n + 1 end \
+         message('done ' .. n)<CR>",
+    );
+    assert_eq!(lines(&editor), ["a b c", "c,b,a"]);
+    assert_eq!(cursor(&editor), (2, 1));
+    assert_eq!(editor.status_message.text, "done 9");
+    let editor = run(
+        "x",
+        ":script function Sum(a, b) command('s/x/' .. tonumber(a) + tonumber(b) .. '/') end<CR>\
+         :Sum 2 3<CR>",
+    );
+    assert_eq!(lines(&editor), ["5"]);
+    let editor = run("", ":script nope()<CR>");
+    assert_eq!(
+        editor.status_message.text,
+        "Script error: line 1: unknown function nope"
+    );
+    let editor = run("", ":script while true do end<CR>");
+    assert_eq!(
+        editor.status_message.text,
+        "Script error: line 1: script ran too long"
+    );
+    let editor = run(
+        "",
+        ":script local a = ['x'] push(a, a) message(tostring(a))<CR>",
+    );
+    assert_eq!(editor.status_message.text, "[\"x\", [...]]");
+    let editor = run(
+        "",
+        ":script function Loop() command('Loop') end<CR>:Loop<CR>",
+    );
+    assert_eq!(
+        editor.status_message.text,
+        "Script error: too many nested calls"
+    );
+}
+
+#[test]
+fn user_commands() {
+    let editor = run(
+        "a\nb\nc",
+        ":command Swap s/<args>/x\\|y/ | $<CR>:2<CR>:Swap b<CR>",
+    );
+    assert_eq!(lines(&editor), ["a", "x|y", "c"]);
+    assert_eq!(cursor(&editor), (0, 2));
+    let editor = run("", ":com Loop Loop<CR>:Loop<CR>");
+    assert_eq!(
+        editor.status_message.text,
+        "User commands nested too deeply"
+    );
+    let editor = run("", ":com Up s/a/A/<CR>:com Up s/a/B/<CR>");
+    assert_eq!(
+        editor.status_message.text,
+        "Command already exists, add ! to replace it: Up"
+    );
+    let editor = run(
+        "a",
+        ":com Up s/a/A/<CR>:com! Up s/a/B/<CR>:Up<CR>:delc Up<CR>:Up<CR>",
+    );
+    assert_eq!(lines(&editor), ["B"]);
+    assert_eq!(editor.status_message.text, "Unrecognized command: Up");
+}
+
+#[test]
+fn file_type_indentation() {
+    // Files nobody has, so the buffers start out empty.
+    let directory = std::env::temp_dir().join(format!("ggedit-indent-{}", std::process::id()));
+    let directory = directory.to_string_lossy();
+    let editor = run(
+        "",
+        &format!(":set sw=2 noet<CR>:e {directory}/a.py<CR>idef f():<CR>if x:<Esc>oreturn 1<Esc>"),
+    );
+    assert_eq!(
+        lines(&editor),
+        ["def f():", "    if x:", "        return 1"]
+    );
+    assert!(editor.settings.expandtab);
+    let editor = run(
+        "",
+        &format!(":e {directory}/a.rs<CR>ifn f() {{<CR>x<CR>}}<Esc>"),
+    );
+    assert_eq!(lines(&editor), ["fn f() {", "    x", "}"]);
+    let editor = run(
+        "",
+        &format!(":e {directory}/Makefile<CR>iall:<CR>\tcc<Esc>"),
+    );
+    assert_eq!(lines(&editor), ["all:", "\tcc"]);
+    assert!(!editor.settings.expandtab);
+    let editor = run("", &format!(":e {directory}/Makefile<CR>:bp<CR>"));
+    assert!(editor.settings.expandtab);
+}
+
+#[test]
+fn move_and_duplicate_lines() {
+    let editor = run("one\ntwo\nthree", "ll<A-j><A-j><A-j>");
+    assert_eq!(lines(&editor), ["two", "three", "one"]);
+    assert_eq!(cursor(&editor), (2, 2));
+    let editor = run("one\ntwo\nthree", "jVj<A-k><Esc>");
+    assert_eq!(lines(&editor), ["two", "three", "one"]);
+    assert_eq!(cursor(&editor), (0, 1));
+    let editor = run("one\ntwo\nthree", "jl<A-J>");
+    assert_eq!(lines(&editor), ["one", "two", "two", "three"]);
+    assert_eq!(cursor(&editor), (1, 2));
+    let editor = run("one\ntwo\nthree", "Vj<A-K>d");
+    assert_eq!(lines(&editor), ["one", "two", "three"]);
+    let editor = run("one\ntwo\nthree", "<A-j>u");
+    assert_eq!(lines(&editor), ["one", "two", "three"]);
+    let editor = run("one\ntwo\nthree", ":m+1<CR>:t.<CR>");
+    assert_eq!(lines(&editor), ["two", "one", "one", "three"]);
+}
+
+#[test]
+fn word_matches() {
+    let columns = |editor: &Editor, y| {
+        let row = editor.document.row(y).unwrap();
+        let pattern = editor.word_match_pattern();
+        editor
+            .word_match_columns(y, row, pattern.as_ref())
+            .into_iter()
+            .map(|(x, _)| x)
+            .collect::<Vec<_>>()
+    };
+    let mut editor = run("foo bar foo\nfoobar foo", "");
+    assert!(columns(&editor, 0).is_empty());
+    editor.rest.since = Instant::now().checked_sub(Duration::from_secs(1)).unwrap();
+    assert_eq!(columns(&editor, 0), [8, 9, 10]);
+    assert_eq!(columns(&editor, 1), [7, 8, 9]);
+    let mut editor = run("foo bar foo", "3l");
+    editor.rest.since = Instant::now().checked_sub(Duration::from_secs(1)).unwrap();
+    assert!(columns(&editor, 0).is_empty());
+}
+
+#[test]
+fn todos() {
+    let path = std::env::temp_dir().join(format!("ggedit-todos-{}.rs", std::process::id()));
+    let path = path.to_string_lossy();
+    let text = "// TODO: one\nlet todo = \"FIXME\";\nfn x() {} // NOTE and XXX\n// TODOS, NOTED";
+    let editor = run(text, &format!(":w {path}<CR>:todos<CR>"));
+    fs::remove_file(path.as_ref()).unwrap();
+    let entries: Vec<(usize, usize)> = editor
+        .quickfix
+        .entries()
+        .iter()
+        .map(|entry| (entry.line, entry.column))
+        .collect();
+    assert_eq!(entries, [(1, 4), (3, 14), (3, 23)]);
+    assert_eq!(cursor(&editor), (3, 0));
+    let row = editor.document.row(0).unwrap();
+    assert!(*row.highlighting_at(3) == highlighting::Type::Todo);
+    assert!(*row.highlighting_at(8) == highlighting::Type::Comment);
+    let editor = run("// TODO", ":todos<CR>");
+    assert!(editor.quickfix.is_empty());
+}
+
+// Row `text` of a Rust file, with each grapheme shown as the letter
+// `letter` gives its highlighting, or `.` for none.
+fn highlighted(text: &str, letter: impl Fn(&highlighting::Type) -> Option<char>) -> String {
+    let mut row = Row::from(text);
+    let file_type = crate::FileType::from("x.rs");
+    row.highlight(
+        file_type.highlight_options(),
+        None,
+        1,
+        highlighting::Context::Normal,
+    );
+    (0..row.len())
+        .map(|x| letter(row.highlighting_at(x)).unwrap_or('.'))
+        .collect()
+}
+
+#[test]
+fn number_highlighting() {
+    let numbers = |text: &str| {
+        highlighted(text, |highlighted| {
+            (*highlighted == highlighting::Type::Number).then_some('n')
+        })
+    };
+    assert_eq!(numbers("0xff_ff 0b1010 0o17"), "nnnnnnn.nnnnnn.nnnn");
+    assert_eq!(numbers("1_000_000 3.14 1e-9"), "nnnnnnnnn.nnnn.nnnn");
+    assert_eq!(numbers("2.5E+10f64 7u8"), "nnnnnnnnnn.nnn");
+    assert_eq!(
+        numbers("foo2bar x_1 0..10 1.max"),
+        "............n..nn.n...."
+    );
+}
+
+#[test]
+fn escape_highlighting() {
+    let strings = |text: &str| {
+        highlighted(text, |highlighted| match highlighted {
+            highlighting::Type::String => Some('s'),
+            highlighting::Type::Escape => Some('e'),
+            _ => None,
+        })
+    };
+    assert_eq!(strings(r#""a\nb\"c" x"#), "sseeseess..");
+    assert_eq!(strings(r#""\x7f\u{1F600}\t""#), "seeeeeeeeeeeeeees");
+    assert_eq!(strings(r#""\\" "\"#), "sees.ss");
+}
+
+#[test]
+fn local_definition() {
+    let editor = run("foo bar\nbaz foo", "jwgd");
+    assert_eq!(cursor(&editor), (0, 0));
+    let editor = run("foo bar\nbaz foo", "jwgd<C-o>");
+    assert_eq!(cursor(&editor), (4, 1));
+    let path = std::env::temp_dir().join(format!("ggedit-gd-{}.rs", std::process::id()));
+    let path = path.to_string_lossy();
+    let text = "// calls helper\nlet y = helper();\npub fn helper() {}";
+    let editor = run(text, &format!(":w {path}<CR>j3wgd"));
+    fs::remove_file(path.as_ref()).unwrap();
+    assert_eq!(cursor(&editor), (7, 2));
+}
+
+#[test]
+fn tags() {
+    let contents = "!_TAG_FILE_SORTED\t1\t/0=unsorted/\n\
+                    main\tsrc/main.rs\t/^fn main() {$/;\"\tf\n\
+                    Editor\tsrc/editor.rs\t42;\"\ts\n\
+                    path\tsrc/a.rs\t/a\\/b/\n";
+    let tags = Tags::parse(contents, Path::new("/project"), Path::new("/project"));
+    assert!(tags.find("_TAG_FILE_SORTED").is_empty());
+    let main = tags.find("main");
+    assert_eq!(main.len(), 1);
+    assert_eq!(main[0].file, "src/main.rs");
+    assert_eq!(tags.find("Editor")[0].address, tags::Address::Line(42));
+    assert_eq!(
+        tags.find("path")[0].address,
+        tags::Address::Pattern {
+            text: String::from("a/b"),
+            at_start: false,
+            at_end: false,
+        }
+    );
+    assert_eq!(tags.complete("m"), ["main"]);
+    let document = Document::read_from("use x;\n\nfn main() {\n}".as_bytes()).unwrap();
+    assert!(main[0].position(&document) == Some(Position { x: 3, y: 2 }));
+    let editor = run("", "<C-t>");
+    assert_eq!(editor.status_message.text, "At bottom of tag stack");
+}
+
+#[test]
+fn make() {
+    let output = "   Compiling ggedit v0.1.0\n\
+                  error[E0308]: mismatched types\n  \
+                  --> src/main.rs:4:5\n   \
+                  |\n\
+                  src/editor.rs:12:3: error: expected `;`\n\
+                  missing.c:1:1: error: no such file\n\
+                  Finished at 12:30:00\n";
+    let entries = make::parse(output);
+    let entries: Vec<(&str, usize, usize, &str)> = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.file.as_str(),
+                entry.line,
+                entry.column,
+                entry.text.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("src/main.rs", 4, 5, "error[E0308]: mismatched types"),
+            ("src/editor.rs", 12, 3, "error: expected `;`"),
+        ]
+    );
+    let editor = run(
+        "",
+        ":set makeprg=printf\\ 'src/main.rs:3:2:\\ oops\\n'<CR>:make<CR>",
+    );
+    assert_eq!(editor.settings.makeprg, "printf 'src/main.rs:3:2: oops\\n'");
+    assert_eq!(editor.document.file_name.as_deref(), Some("src/main.rs"));
+    assert_eq!(cursor(&editor), (1, 2));
+    let editor = run("", ":compile true<CR>");
+    assert_eq!(editor.status_message.text, "true succeeded");
+}
+
+#[test]
+fn terminal() {
+    let mut screen = Screen::new(8, 3);
+    screen.feed(b"$ ls\r\none two\x1b[31m three\x1b[0m\r\n");
+    assert_eq!(screen.lines(), ["one two ", "three   ", "        "]);
+    assert_eq!(screen.cursor(), (0, 2));
+    // A character split between reads, a title and erasing the line
+    screen.feed(b"\x1b]0;title\x07caf\xc3");
+    screen.feed(b"\xa9!\x08\x1b[K");
+    assert_eq!(screen.lines()[2], "café    ");
+    assert_eq!(screen.cursor(), (4, 2));
+    screen.feed(b"\x1b[2J\x1b[2;3Hx");
+    assert_eq!(screen.lines(), ["        ", "  x     ", "        "]);
+    assert_eq!(term_window::key_bytes(Key::Ctrl('c')), [3]);
+    assert_eq!(term_window::key_bytes(Key::Char('\n')), b"\r");
+    assert_eq!(term_window::key_bytes(Key::Up), b"\x1b[A");
+    let editor = run("", ":terminal<CR>");
+    assert!(matches!(editor.mode, Mode::Normal));
+    assert_eq!(
+        editor.status_message.text,
+        "Cannot open a terminal without one"
+    );
+}
+
+#[test]
+fn markdown_preview() {
+    let text = "# Title #\n\
+                Some **bold**, *italic*, `code` and snake_case.\n\
+                - [x] done\n\
+                See [docs](http://example.com).\n\
+                ```rust\n\
+                let x = 1;\n\
+                ```";
+    let lines: Vec<&str> = text.lines().collect();
+    let kinds = markdown::classify(lines.iter().copied());
+    use markdown::Line::{Code, Fence, Text};
+    assert_eq!(kinds, [Text, Text, Text, Text, Fence, Code, Fence]);
+    let theme = Theme::default();
+    let escapes = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    let rendered: Vec<String> = lines
+        .iter()
+        .zip(kinds)
+        .map(|(line, kind)| {
+            let rendered = markdown::render(line, kind, 12, 4, &theme);
+            escapes.replace_all(&rendered, "").into_owned()
+        })
+        .collect();
+    assert_eq!(
+        rendered,
+        [
+            "Title",
+            "Some bold, i",
+            "☑ done",
+            "See docs.",
+            "── rust ────",
+            "let x = 1;",
+            "────────────",
+        ]
+    );
+    let bold = markdown::render("**b**", Text, 12, 4, &theme);
+    assert!(bold.contains(&termion::style::Bold.to_string()));
+    let editor = run("# Title", ":preview<CR>");
+    assert_eq!(
+        editor.status_message.text,
+        "Only Markdown files can be previewed"
+    );
+}
+
+#[test]
+fn hex_editing() {
+    let bytes: Vec<u8> = (0..20).collect();
+    let dump = hex::dump(&bytes);
+    assert_eq!(
+        dump[0],
+        "00000000: 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f  ................"
+    );
+    assert_eq!(
+        dump[1],
+        format!("00000010: 10 11 12 13{}  ....", " ".repeat(36))
+    );
+    assert_eq!(hex::parse(dump.iter().map(String::as_str)), Ok(bytes));
+    assert_eq!(
+        hex::parse(["00000000: 0g"]),
+        Err(String::from("Invalid byte 0g on line 1"))
+    );
+    // Latin-1 that isn't valid UTF-8 comes back byte for byte.
+    let path = std::env::temp_dir().join(format!("ggedit-hex-{}.bin", std::process::id()));
+    std::fs::write(&path, b"caf\xe9\n\x00\x01").unwrap();
+    let path = path.to_string_lossy();
+    let editor = run("", &format!(":e {path}<CR>:hex<CR>"));
+    assert_eq!(
+        lines(&editor),
+        [format!(
+            "00000000: 63 61 66 e9 0a 00 01{}  caf....",
+            " ".repeat(27)
+        )]
+    );
+    let editor = run(
+        "",
+        &format!(":e {path}<CR>:hex<CR>:s/e9/41<CR>:w<CR>:hex<CR>"),
+    );
+    assert_eq!(std::fs::read(path.as_ref()).unwrap(), b"cafA\n\x00\x01");
+    assert_eq!(lines(&editor), ["cafA", "\0\u{1}"]);
+    std::fs::remove_file(path.as_ref()).unwrap();
+}
+
+#[test]
+fn diff_mode() {
+    let directory = std::env::temp_dir().join(format!("ggedit-diff-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let (a, b) = (directory.join("a.txt"), directory.join("b.txt"));
+    std::fs::write(&a, "one\ntwo\nthree\nfour\nfive\n").unwrap();
+    std::fs::write(&b, "one\nTWO\nthree\nfive\nsix\n").unwrap();
+    let (a, b) = (a.to_string_lossy(), b.to_string_lossy());
+    let pair = |left, right, changed| Pair {
+        left,
+        right,
+        changed,
+    };
+    let mut editor = run("", &format!(":e {a}<CR>:diffsplit {b}<CR>]c]c"));
+    assert_eq!(cursor(&editor), (0, 3));
+    editor.update_diff();
+    assert_eq!(
+        editor.diff.as_ref().unwrap().pairs(),
+        [
+            pair(Some(0), Some(0), false),
+            pair(Some(1), Some(1), true),
+            pair(Some(2), Some(2), false),
+            pair(Some(3), None, true),
+            pair(Some(4), Some(3), false),
+            pair(None, Some(4), true),
+        ]
+    );
+    let editor = run("", &format!(":e {a}<CR>:diffsplit {b}<CR>jdoGdo"));
+    assert_eq!(
+        lines(&editor),
+        ["one", "TWO", "three", "four", "five", "six"]
+    );
+    let editor = run("", &format!(":e {a}<CR>:diffsplit {b}<CR>3jdp:q<CR>"));
+    let other = &editor.diff.as_ref().unwrap().other;
+    assert_eq!(
+        (0..other.len())
+            .filter_map(|y| other.row(y))
+            .map(Row::as_str)
+            .collect::<Vec<_>>(),
+        ["one", "TWO", "three", "four", "five", "six"]
+    );
+    assert!(other.is_dirty());
+    assert!(!editor.should_quit);
+    assert_eq!(
+        std::fs::read_to_string(b.as_ref()).unwrap(),
+        "one\nTWO\nthree\nfive\nsix\n"
+    );
+    let editor = run(
+        "",
+        &format!(":e {a}<CR>:diffsplit {b}<CR>3jdp:diffoff<CR>:bn<CR>:w<CR>"),
+    );
+    assert!(editor.diff.is_none());
+    assert_eq!(editor.document.file_name.as_deref(), Some(b.as_ref()));
+    assert_eq!(
+        std::fs::read_to_string(b.as_ref()).unwrap(),
+        "one\nTWO\nthree\nfour\nfive\nsix\n"
+    );
+    assert!(matches!(
+        cli::parse(["-d", "a.txt"].map(String::from)),
+        Err(message) if message == "-d needs two files"
+    ));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn rope_matches_a_vec() {
+    let mut rope: Rope = (0..3000)
+        .map(|n| Row::from(n.to_string().as_str()))
+        .collect();
+    let mut model: Vec<String> = (0..3000).map(|n| n.to_string()).collect();
+    let new_rows = |count: usize, tag: &str| -> Vec<String> {
+        (0..count).map(|n| format!("{tag}{n}")).collect()
+    };
+    // Splices within a chunk, across chunks, of whole chunks, at both ends,
+    // and inserts and removes that split and empty chunks.
+    for (step, (y, count, added)) in [
+        (5, 3, 1),
+        (1000, 100, 2500),
+        (0, 1500, 0),
+        (200, 0, 1),
+        (2800, 900, 5),
+        (0, 0, 3),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let added = new_rows(added, &format!("s{step}-"));
+        rope.splice(y, count, added.iter().map(|line| Row::from(line.as_str())));
+        let y = y.min(model.len());
+        let end = y.saturating_add(count).min(model.len());
+        model.splice(y..end, added);
+    }
+    for y in [0, 1, 700, 1023, 1024, 1025, 2000] {
+        rope.insert(y, Row::from("inserted"));
+        model.insert(y, String::from("inserted"));
+    }
+    for _ in 0..1100 {
+        rope.remove(10);
+        model.remove(10);
+    }
+    assert_eq!(rope.len(), model.len());
+    assert!(rope
+        .iter()
+        .map(Row::as_str)
+        .eq(model.iter().map(String::as_str)));
+    assert!(
+        (0..model.len()).all(|y| rope.get(y).map(Row::as_str) == model.get(y).map(String::as_str))
+    );
+    assert!(rope.get(model.len()).is_none());
+}
+
+#[test]
+fn frame_cell_changes() {
+    let changes = |old: &str, new: &str| {
+        cells::changes(&cells::parse(old).unwrap(), &cells::parse(new).unwrap(), 1)
+    };
+    // Only the cell that changed is written, in the colors it has.
+    let red = format!("{}", termion::color::Fg(termion::color::Red));
+    assert_eq!(
+        changes(&format!("ab{red}c"), &format!("ab{red}d")),
+        "\x1b[1;3H\x1b[0;38;5;1md\x1b[0m"
+    );
+    assert_eq!(
+        changes("abc", "aXc\u{301}"),
+        "\x1b[1;2H\x1b[0mXc\u{301}\x1b[0m"
+    );
+    assert_eq!(changes("abc", "abc"), "");
+    // A wide character is written whole, and a shorter line clears the rest.
+    assert_eq!(changes("a漢x", "a字x"), "\x1b[1;2H\x1b[0m字\x1b[0m");
+    assert_eq!(changes("漢z", "漢y"), "\x1b[1;3H\x1b[0my\x1b[0m");
+    assert_eq!(changes("abcd", "ab"), "\x1b[1;3H\x1b[0m\x1b[K");
+    assert!(cells::parse("\x1b[2Jx").is_none());
+}
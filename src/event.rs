@@ -0,0 +1,51 @@
+use crate::git::RepoStatus;
+use crate::lsp;
+use crate::make;
+use crate::FileEncoding;
+use crate::FileFormat;
+use std::io;
+use std::path::PathBuf;
+use termion::event::{Key, MouseEvent};
+
+// Everything the editor reacts to, delivered through one channel so input
+// and background work can be interleaved.
+pub enum Event {
+    Key(io::Result<Key>),
+    Mouse(MouseEvent),
+    // Sent every second, for things that change with time rather than input.
+    Tick,
+    Load {
+        buffer: usize,
+        load: Load,
+    },
+    // The git status of the repository holding the directory `dir`.
+    GitStatus {
+        dir: PathBuf,
+        status: Option<RepoStatus>,
+    },
+    // Something the language server for `file_type` sent.
+    Lsp {
+        file_type: String,
+        message: lsp::Message,
+    },
+    // How the build started by `:make` went.
+    Make(make::Build),
+    // What the shell in the `:terminal` window printed.
+    TerminalOutput(Vec<u8>),
+    // The shell in the `:terminal` window is gone.
+    TerminalClosed,
+}
+
+// Progress of a file being streamed into the buffer at index `buffer`.
+pub enum Load {
+    Rows {
+        rows: Vec<String>,
+        bytes_read: u64,
+    },
+    Finished {
+        file_encoding: FileEncoding,
+        file_format: FileFormat,
+        end_of_line: bool,
+    },
+    Failed(io::Error),
+}
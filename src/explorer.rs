@@ -0,0 +1,71 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+// The entries of `dir` for its listing: directories first, each with a
+// trailing slash, then files, both in name order.
+pub fn list(dir: &str) -> Result<Vec<String>, io::Error> {
+    let mut directories = Vec::new();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Links to directories are browsed like them.
+        if entry.path().is_dir() {
+            directories.push(format!("{name}/"));
+        } else {
+            files.push(name);
+        }
+    }
+    directories.sort();
+    files.sort();
+    directories.extend(files);
+    Ok(directories)
+}
+
+// The path of `entry`, a row of the listing of `dir`, without `.` and with
+// each `..` taking away the directory before it when there is one, so that
+// going down and back up leads to the same name.
+pub fn join(dir: &str, entry: &str) -> String {
+    let mut path = PathBuf::new();
+    for component in Path::new(dir).join(entry).components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if path == Path::new("/") => (),
+            Component::ParentDir
+                if matches!(path.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                path.pop();
+            }
+            component => path.push(component),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        String::from(".")
+    } else {
+        path.to_string_lossy().into_owned()
+    }
+}
+
+// Makes a directory when `path` ends with a slash, or else an empty file,
+// failing if something is already there.
+pub fn create(path: &str) -> Result<(), io::Error> {
+    if path.ends_with('/') {
+        fs::create_dir(path)
+    } else {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map(drop)
+    }
+}
+
+// Deletes a file, or a directory as long as it is empty.
+pub fn delete(path: &str) -> Result<(), io::Error> {
+    if Path::new(path).is_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
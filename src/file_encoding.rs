@@ -0,0 +1,136 @@
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::io::{self, Write};
+
+// The character encoding a file is read and written in. Whether the file
+// started with a byte order mark is remembered so saving keeps it.
+#[derive(Copy, Clone, PartialEq)]
+pub struct FileEncoding {
+    encoding: &'static Encoding,
+    bom: bool,
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl FileEncoding {
+    // Honours a byte order mark, then guesses UTF-16 from NUL bytes and falls
+    // back to Latin-1 for anything that isn't valid UTF-8.
+    pub fn decode(bytes: &[u8]) -> (Self, String) {
+        let file_encoding = Self::detect(bytes);
+        let (contents, _) = file_encoding.encoding.decode_with_bom_removal(bytes);
+        (file_encoding, contents.into_owned())
+    }
+
+    // Works on a prefix of the file too, as used when streaming it.
+    pub fn detect(bytes: &[u8]) -> Self {
+        match Encoding::for_bom(bytes) {
+            Some((encoding, _)) => Self {
+                encoding,
+                bom: true,
+            },
+            None => Self {
+                encoding: Self::guess(bytes),
+                bom: false,
+            },
+        }
+    }
+
+    // What to read a file in again when it turns out not to be valid in
+    // this encoding past the part it was guessed from: Latin-1, like `decode`
+    // does, for UTF-8 without a byte order mark.
+    pub fn fallback(self) -> Option<Self> {
+        (self.encoding == UTF_8 && !self.bom).then_some(Self {
+            encoding: WINDOWS_1252,
+            bom: false,
+        })
+    }
+
+    pub fn new_decoder(self) -> Decoder {
+        self.encoding.new_decoder_with_bom_removal()
+    }
+
+    fn guess(bytes: &[u8]) -> &'static Encoding {
+        let sample = bytes.get(..bytes.len().min(1024)).unwrap_or_default();
+        let nuls_at = |parity: usize| {
+            sample
+                .iter()
+                .skip(parity)
+                .step_by(2)
+                .filter(|byte| **byte == 0)
+                .count()
+        };
+        let half = sample.len().checked_div(4).unwrap_or(0);
+        if sample.len() >= 2 && nuls_at(1) > half && nuls_at(0) == 0 {
+            UTF_16LE
+        } else if sample.len() >= 2 && nuls_at(0) > half && nuls_at(1) == 0 {
+            UTF_16BE
+        } else if std::str::from_utf8(bytes).map_or_else(
+            // A character cut off at the end of a prefix is still valid
+            |error| error.error_len().is_none(),
+            |_| true,
+        ) {
+            UTF_8
+        } else {
+            WINDOWS_1252
+        }
+    }
+
+    // Accepts the labels browsers do, e.g. `latin1`, `utf-16le` or `sjis`.
+    pub fn from_label(label: &str) -> Option<Self> {
+        Encoding::for_label(label.as_bytes()).map(|encoding| Self {
+            encoding,
+            bom: false,
+        })
+    }
+
+    pub fn name(self) -> String {
+        self.encoding.name().to_ascii_lowercase()
+    }
+
+    // Writes the byte order mark if the file had one, returning its length.
+    pub fn write_bom(self, writer: &mut impl Write) -> io::Result<usize> {
+        if !self.bom {
+            return Ok(0);
+        }
+        let bom: &[u8] = if self.encoding == UTF_16LE {
+            &[0xFF, 0xFE]
+        } else if self.encoding == UTF_16BE {
+            &[0xFE, 0xFF]
+        } else {
+            &[0xEF, 0xBB, 0xBF]
+        };
+        writer.write_all(bom)?;
+        Ok(bom.len())
+    }
+
+    // Encodes and writes `text`, returning the number of bytes written.
+    // encoding_rs only encodes UTF-16 as UTF-8, so those are done by hand.
+    pub fn write(self, writer: &mut impl Write, text: &str) -> io::Result<usize> {
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let little_endian = self.encoding == UTF_16LE;
+            let mut size = 0_usize;
+            for unit in text.encode_utf16() {
+                writer.write_all(&Self::utf16_bytes(unit, little_endian))?;
+                size = size.saturating_add(2);
+            }
+            return Ok(size);
+        }
+        let (encoded, _, _) = self.encoding.encode(text);
+        writer.write_all(&encoded)?;
+        Ok(encoded.len())
+    }
+
+    fn utf16_bytes(unit: u16, little_endian: bool) -> [u8; 2] {
+        if little_endian {
+            unit.to_le_bytes()
+        } else {
+            unit.to_be_bytes()
+        }
+    }
+}
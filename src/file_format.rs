@@ -0,0 +1,39 @@
+// How rows are separated on disk, named after vim's `fileformat` values.
+#[derive(Copy, Clone, Default, PartialEq)]
+pub enum FileFormat {
+    #[default]
+    Unix,
+    Dos,
+}
+
+impl FileFormat {
+    // Files are dos when their first line ends in CRLF.
+    pub fn detect(contents: &str) -> Self {
+        match contents.find('\n') {
+            Some(index) if contents[..index].ends_with('\r') => Self::Dos,
+            _ => Self::Unix,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(Self::Unix),
+            "dos" => Some(Self::Dos),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unix => "unix",
+            Self::Dos => "dos",
+        }
+    }
+
+    pub fn line_ending(self) -> &'static str {
+        match self {
+            Self::Unix => "\n",
+            Self::Dos => "\r\n",
+        }
+    }
+}
@@ -1,14 +1,49 @@
-#[derive(Default, Copy, Clone)]
+#[derive(Default, Clone)]
 pub struct HighlightingOptions {
     numbers: bool,
-    strings: bool,
+    string_quotes: Vec<char>,
+    // Quotes whose strings may continue on the next row.
+    multiline_quotes: Vec<char>,
     characters: bool,
-    comments: bool,
+    line_comment: Option<String>,
+    block_comment: Option<(String, String)>,
+    nested_comments: bool,
+    raw_strings: bool,
+    primary_keywords: Vec<String>,
+    secondary_keywords: Vec<String>,
+}
+
+#[derive(Copy, Clone)]
+pub struct IndentOptions {
+    autoindent: bool,
+    dedent_closing_brace: bool,
+}
+
+// How a file type is usually indented: the options it sets when a file of
+// that type is opened, and the characters that make the line after the one
+// they end indent a level deeper. What isn't set is left as it is.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Indentation {
+    pub tabstop: Option<usize>,
+    pub shiftwidth: Option<usize>,
+    pub expandtab: Option<bool>,
+    pub indent_after: Option<String>,
 }
 
 pub struct FileType {
     name: String,
     hl_options: HighlightingOptions,
+    indent_options: IndentOptions,
+    indentation: Indentation,
+}
+
+impl Default for IndentOptions {
+    fn default() -> Self {
+        Self {
+            autoindent: true,
+            dedent_closing_brace: false,
+        }
+    }
 }
 
 impl Default for FileType {
@@ -16,6 +51,8 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             hl_options: HighlightingOptions::default(),
+            indent_options: IndentOptions::default(),
+            indentation: Indentation::default(),
         }
     }
 }
@@ -23,8 +60,73 @@ impl FileType {
     pub fn name(&self) -> String {
         self.name.clone()
     }
-    pub fn highlight_options(&self) -> HighlightingOptions {
-        self.hl_options
+    // Plain text and Markdown are spell checked throughout, code only in its
+    // comments and strings.
+    pub fn is_prose(&self) -> bool {
+        matches!(self.name.as_str(), "No filetype" | "Markdown")
+    }
+    // The keywords a name follows where it is defined, which `gd` looks for
+    // when there is no language server to ask.
+    pub fn definition_keywords(&self) -> &'static [&'static str] {
+        match self.name.as_str() {
+            "Rust" => &[
+                "fn", "let", "mut", "struct", "enum", "union", "trait", "type", "const", "static",
+                "mod",
+            ],
+            "Python" => &["def", "class"],
+            "C" | "C++" => &[
+                "struct",
+                "enum",
+                "union",
+                "class",
+                "typedef",
+                "define",
+                "namespace",
+            ],
+            "JavaScript" | "TypeScript" => &[
+                "function",
+                "class",
+                "let",
+                "const",
+                "var",
+                "interface",
+                "type",
+                "enum",
+            ],
+            "Go" => &["func", "type", "var", "const"],
+            "Shell" => &["function"],
+            _ => &[],
+        }
+    }
+    pub fn highlight_options(&self) -> &HighlightingOptions {
+        &self.hl_options
+    }
+    pub fn indent_options(&self) -> IndentOptions {
+        self.indent_options
+    }
+    pub fn indentation(&self) -> &Indentation {
+        &self.indentation
+    }
+}
+
+impl Indentation {
+    // These settings, with what they leave unset taken from `defaults`.
+    pub fn or(self, defaults: &Self) -> Self {
+        Self {
+            tabstop: self.tabstop.or(defaults.tabstop),
+            shiftwidth: self.shiftwidth.or(defaults.shiftwidth),
+            expandtab: self.expandtab.or(defaults.expandtab),
+            indent_after: self.indent_after.or_else(|| defaults.indent_after.clone()),
+        }
+    }
+}
+
+impl IndentOptions {
+    pub fn autoindent(&self) -> bool {
+        self.autoindent
+    }
+    pub fn dedent_closing_brace(&self) -> bool {
+        self.dedent_closing_brace
     }
 }
 
@@ -32,31 +134,412 @@ impl HighlightingOptions {
     pub fn numbers(&self) -> bool {
         self.numbers
     }
-
-    pub fn strings(&self) -> bool {
-        self.strings
+    pub fn string_quotes(&self) -> &[char] {
+        &self.string_quotes
+    }
+    pub fn multiline_quotes(&self) -> &[char] {
+        &self.multiline_quotes
     }
     pub fn characters(&self) -> bool {
         self.characters
     }
-    pub fn comments(&self) -> bool {
-        self.comments
+    pub fn line_comment(&self) -> Option<&str> {
+        self.line_comment.as_deref()
+    }
+    pub fn block_comment(&self) -> Option<(&str, &str)> {
+        self.block_comment
+            .as_ref()
+            .map(|(start, end)| (start.as_str(), end.as_str()))
+    }
+    pub fn nested_comments(&self) -> bool {
+        self.nested_comments
+    }
+    pub fn raw_strings(&self) -> bool {
+        self.raw_strings
+    }
+    pub fn primary_keywords(&self) -> &[String] {
+        &self.primary_keywords
+    }
+    pub fn secondary_keywords(&self) -> &[String] {
+        &self.secondary_keywords
     }
 }
 
 impl From<&str> for FileType {
     fn from(file_name: &str) -> Self {
-        if file_name.ends_with(".rs") {
-            return Self {
-                name: String::from("Rust"),
-                hl_options: HighlightingOptions {
-                    numbers: true,
-                    strings: true,
-                    characters: true,
-                    comments: true,
-                },
-            };
-        }
-        Self::default()
+        let base_name = file_name.rsplit('/').next().unwrap_or(file_name);
+        if matches!(base_name, "Makefile" | "makefile" | "GNUmakefile") {
+            return Self::makefile();
+        }
+        let extension = file_name
+            .rsplit_once('.')
+            .map_or("", |(_, extension)| extension);
+        match extension {
+            "rs" => Self::rust(),
+            "py" => Self::python(),
+            "c" | "h" => Self::c("C"),
+            "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => Self::c("C++"),
+            "js" | "mjs" | "cjs" | "jsx" => Self::javascript("JavaScript"),
+            "ts" | "tsx" => Self::javascript("TypeScript"),
+            "go" => Self::go(),
+            "toml" => Self::toml(),
+            "json" => Self::json(),
+            "md" | "markdown" => Self::markdown(),
+            "sh" | "bash" | "zsh" => Self::shell(),
+            "mk" => Self::makefile(),
+            _ => Self::default(),
+        }
+    }
+}
+
+// Languages with C-like braces get `}` dedented as it is typed.
+const BRACES: IndentOptions = IndentOptions {
+    autoindent: true,
+    dedent_closing_brace: true,
+};
+
+// Blocks open with `{`, so the line after one is indented.
+fn blocks() -> Indentation {
+    Indentation {
+        indent_after: Some(String::from("{")),
+        ..Indentation::default()
     }
 }
+
+impl FileType {
+    fn rust() -> Self {
+        Self {
+            name: String::from("Rust"),
+            hl_options: HighlightingOptions {
+                numbers: true,
+                string_quotes: vec!['"'],
+                multiline_quotes: vec!['"'],
+                characters: true,
+                line_comment: Some(String::from("//")),
+                block_comment: Some((String::from("/*"), String::from("*/"))),
+                nested_comments: true,
+                raw_strings: true,
+                primary_keywords: words(&[
+                    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else",
+                    "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop",
+                    "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+                    "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+                    "while",
+                ]),
+                secondary_keywords: words(&[
+                    "bool", "char", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32",
+                    "u64", "u128", "usize", "f32", "f64", "str", "String", "Vec", "Option",
+                    "Result", "Box", "Some", "None", "Ok", "Err",
+                ]),
+            },
+            indent_options: BRACES,
+            indentation: blocks(),
+        }
+    }
+
+    fn python() -> Self {
+        Self {
+            name: String::from("Python"),
+            hl_options: HighlightingOptions {
+                numbers: true,
+                string_quotes: vec!['"', '\''],
+                line_comment: Some(String::from("#")),
+                primary_keywords: words(&[
+                    "and", "as", "assert", "async", "await", "break", "class", "continue", "def",
+                    "del", "elif", "else", "except", "False", "finally", "for", "from", "global",
+                    "if", "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass",
+                    "raise", "return", "True", "try", "while", "with", "yield",
+                ]),
+                secondary_keywords: words(&[
+                    "bool", "bytes", "dict", "float", "int", "list", "object", "self", "set",
+                    "str", "tuple",
+                ]),
+                ..HighlightingOptions::default()
+            },
+            indent_options: IndentOptions::default(),
+            indentation: Indentation {
+                shiftwidth: Some(4),
+                expandtab: Some(true),
+                indent_after: Some(String::from(":")),
+                ..Indentation::default()
+            },
+        }
+    }
+
+    fn c(name: &str) -> Self {
+        let mut primary_keywords = words(&[
+            "break", "case", "const", "continue", "default", "do", "else", "enum", "extern", "for",
+            "goto", "if", "inline", "register", "return", "sizeof", "static", "struct", "switch",
+            "typedef", "union", "volatile", "while",
+        ]);
+        if name == "C++" {
+            primary_keywords.extend(words(&[
+                "auto",
+                "catch",
+                "class",
+                "constexpr",
+                "delete",
+                "false",
+                "namespace",
+                "new",
+                "nullptr",
+                "operator",
+                "private",
+                "protected",
+                "public",
+                "template",
+                "this",
+                "throw",
+                "true",
+                "try",
+                "typename",
+                "using",
+                "virtual",
+            ]));
+        }
+        Self {
+            name: String::from(name),
+            hl_options: HighlightingOptions {
+                numbers: true,
+                string_quotes: vec!['"'],
+                characters: true,
+                line_comment: Some(String::from("//")),
+                block_comment: Some((String::from("/*"), String::from("*/"))),
+                primary_keywords,
+                secondary_keywords: words(&[
+                    "bool", "char", "double", "float", "int", "long", "short", "signed",
+                    "unsigned", "void", "size_t", "int8_t", "int16_t", "int32_t", "int64_t",
+                    "uint8_t", "uint16_t", "uint32_t", "uint64_t", "NULL",
+                ]),
+                ..HighlightingOptions::default()
+            },
+            indent_options: BRACES,
+            indentation: blocks(),
+        }
+    }
+
+    fn javascript(name: &str) -> Self {
+        let mut secondary_keywords = words(&[
+            "Array", "Boolean", "Date", "Error", "Map", "Number", "Object", "Promise", "Set",
+            "String",
+        ]);
+        if name == "TypeScript" {
+            secondary_keywords.extend(words(&[
+                "any", "boolean", "never", "number", "string", "unknown", "void",
+            ]));
+        }
+        Self {
+            name: String::from(name),
+            hl_options: HighlightingOptions {
+                numbers: true,
+                string_quotes: vec!['"', '\'', '`'],
+                multiline_quotes: vec!['`'],
+                line_comment: Some(String::from("//")),
+                block_comment: Some((String::from("/*"), String::from("*/"))),
+                primary_keywords: words(&[
+                    "async",
+                    "await",
+                    "break",
+                    "case",
+                    "catch",
+                    "class",
+                    "const",
+                    "continue",
+                    "default",
+                    "delete",
+                    "do",
+                    "else",
+                    "export",
+                    "extends",
+                    "false",
+                    "finally",
+                    "for",
+                    "from",
+                    "function",
+                    "if",
+                    "import",
+                    "in",
+                    "instanceof",
+                    "interface",
+                    "let",
+                    "new",
+                    "null",
+                    "of",
+                    "return",
+                    "static",
+                    "super",
+                    "switch",
+                    "this",
+                    "throw",
+                    "true",
+                    "try",
+                    "type",
+                    "typeof",
+                    "undefined",
+                    "var",
+                    "while",
+                    "yield",
+                ]),
+                secondary_keywords,
+                ..HighlightingOptions::default()
+            },
+            indent_options: BRACES,
+            indentation: blocks(),
+        }
+    }
+
+    fn go() -> Self {
+        Self {
+            name: String::from("Go"),
+            hl_options: HighlightingOptions {
+                numbers: true,
+                string_quotes: vec!['"', '`'],
+                multiline_quotes: vec!['`'],
+                characters: true,
+                line_comment: Some(String::from("//")),
+                block_comment: Some((String::from("/*"), String::from("*/"))),
+                primary_keywords: words(&[
+                    "break",
+                    "case",
+                    "chan",
+                    "const",
+                    "continue",
+                    "default",
+                    "defer",
+                    "else",
+                    "fallthrough",
+                    "false",
+                    "for",
+                    "func",
+                    "go",
+                    "goto",
+                    "if",
+                    "import",
+                    "interface",
+                    "iota",
+                    "map",
+                    "nil",
+                    "package",
+                    "range",
+                    "return",
+                    "select",
+                    "struct",
+                    "switch",
+                    "true",
+                    "type",
+                    "var",
+                ]),
+                secondary_keywords: words(&[
+                    "bool",
+                    "byte",
+                    "complex64",
+                    "complex128",
+                    "error",
+                    "float32",
+                    "float64",
+                    "int",
+                    "int8",
+                    "int16",
+                    "int32",
+                    "int64",
+                    "rune",
+                    "string",
+                    "uint",
+                    "uint8",
+                    "uint16",
+                    "uint32",
+                    "uint64",
+                    "uintptr",
+                ]),
+                ..HighlightingOptions::default()
+            },
+            indent_options: BRACES,
+            // gofmt indents with tabs.
+            indentation: Indentation {
+                expandtab: Some(false),
+                ..blocks()
+            },
+        }
+    }
+
+    fn toml() -> Self {
+        Self {
+            name: String::from("TOML"),
+            hl_options: HighlightingOptions {
+                numbers: true,
+                string_quotes: vec!['"', '\''],
+                line_comment: Some(String::from("#")),
+                primary_keywords: words(&["true", "false"]),
+                ..HighlightingOptions::default()
+            },
+            indent_options: IndentOptions::default(),
+            indentation: Indentation::default(),
+        }
+    }
+
+    fn json() -> Self {
+        Self {
+            name: String::from("JSON"),
+            hl_options: HighlightingOptions {
+                numbers: true,
+                string_quotes: vec!['"'],
+                primary_keywords: words(&["true", "false", "null"]),
+                ..HighlightingOptions::default()
+            },
+            indent_options: BRACES,
+            indentation: blocks(),
+        }
+    }
+
+    // Inline code spans are shown like strings.
+    fn markdown() -> Self {
+        Self {
+            name: String::from("Markdown"),
+            hl_options: HighlightingOptions {
+                string_quotes: vec!['`'],
+                ..HighlightingOptions::default()
+            },
+            indent_options: IndentOptions::default(),
+            indentation: Indentation::default(),
+        }
+    }
+
+    fn shell() -> Self {
+        Self {
+            name: String::from("Shell"),
+            hl_options: HighlightingOptions {
+                numbers: true,
+                string_quotes: vec!['"', '\''],
+                multiline_quotes: vec!['"', '\''],
+                line_comment: Some(String::from("#")),
+                primary_keywords: words(&[
+                    "case", "do", "done", "elif", "else", "esac", "export", "fi", "for",
+                    "function", "if", "in", "local", "return", "select", "then", "until", "while",
+                ]),
+                ..HighlightingOptions::default()
+            },
+            indent_options: IndentOptions::default(),
+            indentation: Indentation::default(),
+        }
+    }
+
+    // Recipes have to start with a tab.
+    fn makefile() -> Self {
+        Self {
+            name: String::from("Makefile"),
+            hl_options: HighlightingOptions {
+                line_comment: Some(String::from("#")),
+                ..HighlightingOptions::default()
+            },
+            indent_options: IndentOptions::default(),
+            indentation: Indentation {
+                expandtab: Some(false),
+                ..Indentation::default()
+            },
+        }
+    }
+}
+
+fn words(list: &[&str]) -> Vec<String> {
+    list.iter().map(|word| (*word).to_string()).collect()
+}
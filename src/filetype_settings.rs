@@ -0,0 +1,71 @@
+use crate::config;
+use crate::filetype::Indentation;
+use std::collections::HashMap;
+
+// How files of each type are indented, read from the `[filetype.<name>]`
+// tables of `config.toml`, over the defaults the file types come with:
+//
+//     [filetype.python]
+//     shiftwidth = 2
+//     indent_after = ":"
+//
+//     [filetype.markdown]
+//     tabstop = 2
+//     expandtab = true
+//
+// Names are file types in lower case, as `:autocmd FileType` matches them.
+#[derive(Default)]
+pub struct FileTypeSettings {
+    types: HashMap<String, Indentation>,
+}
+
+impl FileTypeSettings {
+    pub fn load() -> Result<Self, String> {
+        let mut settings = Self::default();
+        let Some(types) = config::section("filetype")? else {
+            return Ok(settings);
+        };
+        for (name, options) in &types {
+            let options = options
+                .as_table()
+                .ok_or_else(|| format!("filetype.{name} must be a table"))?;
+            let mut indentation = Indentation::default();
+            for (option, value) in options {
+                let invalid = || format!("Invalid value for {option} in filetype.{name}");
+                match option.as_str() {
+                    "tabstop" | "shiftwidth" => {
+                        let width = value
+                            .as_integer()
+                            .and_then(|width| usize::try_from(width).ok())
+                            .filter(|width| *width > 0)
+                            .ok_or_else(invalid)?;
+                        if option == "tabstop" {
+                            indentation.tabstop = Some(width);
+                        } else {
+                            indentation.shiftwidth = Some(width);
+                        }
+                    }
+                    "expandtab" => {
+                        indentation.expandtab = Some(value.as_bool().ok_or_else(invalid)?);
+                    }
+                    "indent_after" => {
+                        indentation.indent_after =
+                            Some(value.as_str().ok_or_else(invalid)?.to_string());
+                    }
+                    _ => return Err(format!("Unknown option in filetype.{name}: {option}")),
+                }
+            }
+            settings.types.insert(name.to_lowercase(), indentation);
+        }
+        Ok(settings)
+    }
+
+    // How files of the type named `file_type` are indented, given the
+    // type's `defaults`.
+    pub fn indentation(&self, file_type: &str, defaults: &Indentation) -> Indentation {
+        match self.types.get(&file_type.to_lowercase()) {
+            Some(indentation) => indentation.clone().or(defaults),
+            None => defaults.clone(),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::fs;
+use std::path::Path;
+
+// Enough for any project, while a stray start in `/` stays quick.
+const MAX_FILES: usize = 20_000;
+
+// A `.gitignore` line, applying under the directory `base`.
+struct Pattern {
+    base: String,
+    glob: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    // Patterns with a slash before the end match the path from `base`,
+    // the others just the name.
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(base: &str, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        Some(Self {
+            base: base.to_string(),
+            glob: line.trim_start_matches('/').chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Some(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let text = if self.anchored {
+            relative
+        } else {
+            relative.rsplit('/').next().unwrap_or(relative)
+        };
+        glob_matches(&self.glob, &text.chars().collect::<Vec<char>>())
+    }
+}
+
+// `*` matches within a path component, `**` across them, `?` any one
+// character and `[abc]` or `[a-z]` one of a set.
+pub fn glob_matches(glob: &[char], text: &[char]) -> bool {
+    match glob {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=text.len()).any(|skip| glob_matches(rest, text.get(skip..).unwrap_or_default()))
+        }
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&skip| skip == 0 || text.get(skip.saturating_sub(1)) != Some(&'/'))
+            .any(|skip| glob_matches(rest, text.get(skip..).unwrap_or_default())),
+        ['?', rest @ ..] => match text {
+            [c, text @ ..] if *c != '/' => glob_matches(rest, text),
+            _ => false,
+        },
+        ['[', rest @ ..] => {
+            let Some(end) = rest.iter().position(|&c| c == ']') else {
+                return text.first() == Some(&'[')
+                    && glob_matches(rest, text.get(1..).unwrap_or_default());
+            };
+            let (set, rest) = rest.split_at(end);
+            let Some((c, text)) = text.split_first() else {
+                return false;
+            };
+            in_set(set, *c) && glob_matches(rest.get(1..).unwrap_or_default(), text)
+        }
+        [g, rest @ ..] => {
+            text.first() == Some(g) && glob_matches(rest, text.get(1..).unwrap_or_default())
+        }
+    }
+}
+
+fn in_set(set: &[char], c: char) -> bool {
+    let (negated, set) = match set {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, set),
+    };
+    let mut found = false;
+    let mut index = 0;
+    while let Some(&first) = set.get(index) {
+        if let (Some('-'), Some(&last)) = (
+            set.get(index.saturating_add(1)),
+            set.get(index.saturating_add(2)),
+        ) {
+            found = found || (first..=last).contains(&c);
+            index = index.saturating_add(3);
+        } else {
+            found = found || first == c;
+            index = index.saturating_add(1);
+        }
+    }
+    found != negated
+}
+
+// Whether the last pattern that matches `path` ignores it.
+fn is_ignored(patterns: &[Pattern], path: &str, is_dir: bool) -> bool {
+    patterns
+        .iter()
+        .rev()
+        .find(|pattern| pattern.matches(path, is_dir))
+        .is_some_and(|pattern| !pattern.negated)
+}
+
+// The files under `root`, as paths relative to it, leaving out what the
+// `.gitignore` files on the way ignore and `.git` itself.
+pub fn list_files(root: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut patterns = Vec::new();
+    walk(root, "", &mut patterns, &mut files);
+    files.sort();
+    files
+}
+
+fn walk(dir: &Path, prefix: &str, patterns: &mut Vec<Pattern>, files: &mut Vec<String>) {
+    let inherited = patterns.len();
+    if let Ok(gitignore) = fs::read_to_string(dir.join(".gitignore")) {
+        patterns.extend(
+            gitignore
+                .lines()
+                .filter_map(|line| Pattern::parse(prefix, line)),
+        );
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        patterns.truncate(inherited);
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(fs::DirEntry::file_name);
+    for entry in entries {
+        if files.len() >= MAX_FILES {
+            break;
+        }
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+        let path = format!("{prefix}{name}");
+        if name == ".git" || is_ignored(patterns, &path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk(&entry.path(), &format!("{path}/"), patterns, files);
+        } else {
+            files.push(path);
+        }
+    }
+    patterns.truncate(inherited);
+}
+
+// How well `query` matches `candidate`, or `None` when its characters don't
+// all appear in order. Runs of characters and matches at the start of a
+// word or of the file name score higher. The case is ignored unless the
+// query has capitals.
+pub fn score(query: &str, candidate: &str) -> Option<usize> {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let name_start = candidate
+        .rfind('/')
+        .map_or(0, |slash| slash.saturating_add(1));
+    let mut score = 0_usize;
+    let mut previous: Option<(usize, char)> = None;
+    let mut last_match: Option<usize> = None;
+    let mut chars = candidate.char_indices();
+    for wanted in query.chars() {
+        loop {
+            let (index, c) = chars.next()?;
+            let before = previous.map(|(_, c)| c);
+            previous = Some((index, c));
+            let same = if ignore_case {
+                c.to_lowercase().eq(wanted.to_lowercase())
+            } else {
+                c == wanted
+            };
+            if !same {
+                continue;
+            }
+            score = score.saturating_add(1);
+            if last_match
+                .is_some_and(|last| last.saturating_add(before.map_or(0, char::len_utf8)) == index)
+            {
+                score = score.saturating_add(5);
+            }
+            let boundary = match before {
+                None => true,
+                Some(before) => {
+                    matches!(before, '/' | '_' | '-' | '.' | ' ')
+                        || (before.is_lowercase() && c.is_uppercase())
+                }
+            };
+            if boundary {
+                score = score.saturating_add(8);
+            }
+            if index >= name_start {
+                score = score.saturating_add(2);
+            }
+            last_match = Some(index);
+            break;
+        }
+    }
+    Some(score)
+}
+
+// The files matching what has been typed so far, best first.
+pub struct Finder {
+    files: Vec<String>,
+    query: String,
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl Finder {
+    pub fn new(files: Vec<String>) -> Self {
+        let mut finder = Self {
+            files,
+            query: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+        };
+        finder.filter();
+        finder
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.filter();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.filter();
+    }
+
+    // Moves the selection by `delta` matches, wrapping around.
+    pub fn select(&mut self, delta: isize) {
+        let count = self.matches.len();
+        if count == 0 {
+            return;
+        }
+        let step = delta.unsigned_abs().checked_rem(count).unwrap_or(0);
+        self.selected = if delta < 0 {
+            self.selected.saturating_add(count).saturating_sub(step)
+        } else {
+            self.selected.saturating_add(step)
+        }
+        .checked_rem(count)
+        .unwrap_or(0);
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        let index = *self.matches.get(self.selected)?;
+        self.files.get(index).map(String::as_str)
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn total(&self) -> usize {
+        self.files.len()
+    }
+
+    // The matching files, best first.
+    pub fn matches(&self) -> impl Iterator<Item = &str> {
+        self.matches
+            .iter()
+            .filter_map(|&index| self.files.get(index).map(String::as_str))
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(usize, usize)> = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(index, file)| Some((score(&self.query, file)?, index)))
+            .collect();
+        // Better scores first, then shorter paths, then the listing order.
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            let a_len = self.files.get(*a).map_or(0, String::len);
+            let b_len = self.files.get(*b).map_or(0, String::len);
+            b_score.cmp(a_score).then(a_len.cmp(&b_len)).then(a.cmp(b))
+        });
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.selected = 0;
+    }
+}
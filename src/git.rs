@@ -0,0 +1,286 @@
+use crate::diff::{self, Hunk};
+use crate::event::Event;
+use crate::Document;
+use crate::Row;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+// What the gutter shows next to a line.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Sign {
+    Added,
+    Modified,
+    // Lines were removed below this one.
+    Removed,
+}
+
+// A file as of the last commit, and how its buffer differs from it.
+pub struct Gutter {
+    base: Vec<String>,
+    // The document revision `hunks` were worked out for.
+    revision: Option<u64>,
+    hunks: Vec<Hunk>,
+}
+
+impl Gutter {
+    // `None` when `file_name` isn't committed in a git repository.
+    pub fn load(file_name: &str) -> Option<Self> {
+        let name = Path::new(file_name).file_name()?.to_string_lossy();
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(directory_of(file_name))
+            .arg("show")
+            .arg(format!("HEAD:./{name}"))
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let contents = String::from_utf8_lossy(&output.stdout);
+        Some(Self {
+            base: contents.lines().map(String::from).collect(),
+            revision: None,
+            hunks: Vec::new(),
+        })
+    }
+
+    // Diffs the document again if it changed since the last time.
+    pub fn update(&mut self, document: &Document) {
+        if self.revision == Some(document.revision()) {
+            return;
+        }
+        let base: Vec<&str> = self.base.iter().map(String::as_str).collect();
+        let lines: Vec<&str> = (0..document.len())
+            .filter_map(|y| document.row(y))
+            .map(Row::as_str)
+            .collect();
+        self.hunks = diff::hunks(&base, &lines);
+        self.revision = Some(document.revision());
+    }
+
+    pub fn sign(&self, y: usize) -> Option<Sign> {
+        let hunk = self.hunk_at(y)?;
+        if hunk.new_len == 0 {
+            Some(Sign::Removed)
+        } else if y < hunk.new_start.saturating_add(hunk.old_len) {
+            Some(Sign::Modified)
+        } else {
+            Some(Sign::Added)
+        }
+    }
+
+    // The hunk marked on line `y`. Removed lines are marked on the line
+    // above them, or the first line when they were at the top.
+    pub fn hunk_at(&self, y: usize) -> Option<&Hunk> {
+        self.hunks
+            .iter()
+            .find(|hunk| (first_line(hunk)..=last_line(hunk)).contains(&y))
+    }
+
+    // The first line of the next hunk after line `y`, or of the one before
+    // it when going backward.
+    pub fn next_hunk(&self, y: usize, forward: bool) -> Option<usize> {
+        if forward {
+            self.hunks.iter().map(first_line).find(|&start| start > y)
+        } else {
+            self.hunks
+                .iter()
+                .rev()
+                .find(|hunk| last_line(hunk) < y)
+                .map(first_line)
+        }
+    }
+
+    // The committed lines `hunk` replaced.
+    pub fn base_lines(&self, hunk: &Hunk) -> &[String] {
+        let end = hunk.old_start.saturating_add(hunk.old_len);
+        self.base.get(hunk.old_start..end).unwrap_or_default()
+    }
+}
+
+fn first_line(hunk: &Hunk) -> usize {
+    if hunk.new_len == 0 {
+        hunk.new_start.saturating_sub(1)
+    } else {
+        hunk.new_start
+    }
+}
+
+fn last_line(hunk: &Hunk) -> usize {
+    first_line(hunk).max(
+        hunk.new_start
+            .saturating_add(hunk.new_len)
+            .saturating_sub(1),
+    )
+}
+
+// The branch checked out in a repository, and whether its tracked files
+// have changes that aren't committed.
+pub struct RepoStatus {
+    pub branch: String,
+    pub dirty: bool,
+}
+
+// The directory holding `file_name`, which git commands run in.
+pub fn directory_of(file_name: &str) -> PathBuf {
+    Path::new(file_name)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+}
+
+// Asks git for the status of the repository holding `dir` on another
+// thread, since it can take a while in a large one, and sends it back as
+// an `Event::GitStatus`, with `None` outside of a repository.
+pub fn request_status(dir: PathBuf, sender: Sender<Event>) {
+    thread::spawn(move || {
+        let status = status(&dir);
+        let _ = sender.send(Event::GitStatus { dir, status });
+    });
+}
+
+fn status(dir: &Path) -> Option<RepoStatus> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["status", "--porcelain", "--branch", "--untracked-files=no"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    // The first line is like `## main...origin/main [ahead 1]`, or
+    // `## No commits yet on main` in a new repository.
+    let header = lines.next()?.strip_prefix("## ")?;
+    let header = header.strip_prefix("No commits yet on ").unwrap_or(header);
+    let branch = header
+        .split("...")
+        .next()
+        .unwrap_or(header)
+        .split(" [")
+        .next()
+        .unwrap_or(header);
+    let branch = if branch.starts_with("HEAD (") {
+        "HEAD"
+    } else {
+        branch
+    };
+    Some(RepoStatus {
+        branch: branch.to_string(),
+        dirty: lines.next().is_some(),
+    })
+}
+
+// Who last changed line `y` of `document`, and when and why, on one line.
+// The buffer is what gets blamed, so lines that were edited but not saved
+// or committed show as such.
+pub fn blame(document: &Document, y: usize) -> Result<String, String> {
+    let file_name = document
+        .file_name
+        .as_deref()
+        .ok_or_else(|| String::from("No file name"))?;
+    let name = Path::new(file_name)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let contents: String = (0..document.len())
+        .filter_map(|y| document.row(y))
+        .map(|row| format!("{}\n", row.as_str()))
+        .collect();
+    let line = y.saturating_add(1);
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(directory_of(file_name))
+        .args(["blame", "--porcelain", "--contents", "-"])
+        .arg(format!("-L{line},{line}"))
+        .arg("--")
+        .arg(&name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("Cannot run git: {error}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Written from another thread so git can't fill its output pipe
+        // while the buffer is still going in.
+        thread::spawn(move || stdin.write_all(contents.as_bytes()));
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|error| format!("Cannot run git: {error}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr
+            .lines()
+            .next()
+            .unwrap_or("git blame failed")
+            .trim_start_matches("fatal: ")
+            .to_string());
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    let hash = lines
+        .next()
+        .and_then(|header| header.split(' ').next())
+        .unwrap_or_default();
+    if hash.bytes().all(|byte| byte == b'0') {
+        return Ok(String::from("Not committed yet"));
+    }
+    let field = |name: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap_or_default()
+    };
+    let date = field("author-time")
+        .parse::<i64>()
+        .map(date_of)
+        .unwrap_or_default();
+    Ok(format!(
+        "{} {} {date} {}",
+        hash.get(..8).unwrap_or(hash),
+        field("author"),
+        field("summary")
+    ))
+}
+
+// The `YYYY-MM-DD` date of a Unix time, in UTC.
+fn date_of(time: i64) -> String {
+    // From Howard Hinnant's `civil_from_days`.
+    let days = time.div_euclid(86_400).saturating_add(719_468);
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = day_of_era
+        .saturating_sub(day_of_era / 1460)
+        .saturating_add(day_of_era / 36_524)
+        .saturating_sub(day_of_era / 146_096)
+        / 365;
+    let day_of_year = day_of_era.saturating_sub(
+        year_of_era
+            .saturating_mul(365)
+            .saturating_add(year_of_era / 4)
+            .saturating_sub(year_of_era / 100),
+    );
+    let month_index = day_of_year.saturating_mul(5).saturating_add(2) / 153;
+    let day = day_of_year
+        .saturating_sub(month_index.saturating_mul(153).saturating_add(2) / 5)
+        .saturating_add(1);
+    let month = if month_index < 10 {
+        month_index.saturating_add(3)
+    } else {
+        month_index.saturating_sub(9)
+    };
+    let year = year_of_era
+        .saturating_add(era.saturating_mul(400))
+        .saturating_add(i64::from(month <= 2));
+    format!("{year:04}-{month:02}-{day:02}")
+}
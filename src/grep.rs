@@ -0,0 +1,60 @@
+use crate::finder;
+use crate::quickfix::Entry;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+// Searches the files under `root` that the finder would list, spread over a
+// thread per core. Files that aren't text are skipped. Matches come in the
+// order of the files and of the lines in them.
+pub fn search(root: &Path, pattern: &Regex) -> Vec<Entry> {
+    let files = finder::list_files(root);
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    let chunk = files.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let workers: Vec<_> = files
+            .chunks(chunk)
+            .map(|files| {
+                scope.spawn(move || {
+                    files
+                        .iter()
+                        .flat_map(|file| search_file(root, file, pattern))
+                        .collect::<Vec<Entry>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect()
+    })
+}
+
+fn search_file(root: &Path, file: &str, pattern: &Regex) -> Vec<Entry> {
+    let Ok(bytes) = fs::read(root.join(file)) else {
+        return Vec::new();
+    };
+    let Ok(contents) = String::from_utf8(bytes) else {
+        return Vec::new();
+    };
+    if contents.contains('\0') {
+        return Vec::new();
+    }
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let found = pattern.find(line)?;
+            Some(Entry {
+                file: file.to_string(),
+                line: index.saturating_add(1),
+                column: line
+                    .get(..found.start())
+                    .map_or(0, |before| before.chars().count())
+                    .saturating_add(1),
+                text: line.trim().to_string(),
+            })
+        })
+        .collect()
+}
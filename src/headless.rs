@@ -0,0 +1,130 @@
+use crate::backend::{Backend, CursorStyle, Size};
+use crate::event::Event;
+use crate::terminal::input_closed;
+use crate::Position;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+use termion::event::Key;
+
+// A backend without a terminal, for scripts and tests. Its input is a list
+// of keys given up front, which ends when they run out, and its frames are
+// kept instead of shown.
+pub struct Headless {
+    size: Size,
+    sender: Sender<Event>,
+    events: Receiver<Event>,
+    // An event taken to see whether there was one, by `has_input`.
+    deferred: RefCell<VecDeque<Event>>,
+    screen: Rc<RefCell<Vec<String>>>,
+}
+
+impl Headless {
+    pub fn new(keys: Vec<Key>) -> Self {
+        let (sender, events) = mpsc::channel();
+        for key in keys {
+            let _ = sender.send(Event::Key(Ok(key)));
+        }
+        Self {
+            size: Size {
+                width: 80,
+                height: 22,
+            },
+            sender,
+            events,
+            deferred: RefCell::new(VecDeque::new()),
+            screen: Rc::default(),
+        }
+    }
+
+    // The last frame drawn, which stays readable after the editor that
+    // owns the backend is done with it.
+    #[cfg(test)]
+    pub fn screen(&self) -> Rc<RefCell<Vec<String>>> {
+        Rc::clone(&self.screen)
+    }
+}
+
+impl Backend for Headless {
+    fn size(&self) -> &Size {
+        &self.size
+    }
+
+    fn draw(
+        &mut self,
+        lines: Vec<String>,
+        _cursor: &Position,
+        _style: CursorStyle,
+    ) -> Result<(), io::Error> {
+        *self.screen.borrow_mut() = lines;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), io::Error> {
+        self.screen.borrow_mut().clear();
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {}
+
+    // Waiting would be forever, since all the input there will be is
+    // already queued, so running out closes the input instead.
+    fn next_event(&self) -> Result<Event, io::Error> {
+        if let Some(event) = self.deferred.borrow_mut().pop_front() {
+            return Ok(event);
+        }
+        self.events.try_recv().map_err(|_| input_closed())
+    }
+
+    fn next_event_timeout(&self, _timeout: Duration) -> Result<Option<Event>, io::Error> {
+        if let Some(event) = self.deferred.borrow_mut().pop_front() {
+            return Ok(Some(event));
+        }
+        Ok(self.events.try_recv().ok())
+    }
+
+    // Files aren't streamed without a terminal, so keys are all there is.
+    fn read_key(&self) -> Result<Key, io::Error> {
+        loop {
+            if let Event::Key(key) = self.next_event()? {
+                return key;
+            }
+        }
+    }
+
+    fn has_input(&self) -> bool {
+        if !self.deferred.borrow().is_empty() {
+            return true;
+        }
+        match self.events.try_recv() {
+            Ok(event) => {
+                self.deferred.borrow_mut().push_back(event);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn event_sender(&self) -> Sender<Event> {
+        self.sender.clone()
+    }
+
+    fn suspend_raw_mode(&self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn activate_raw_mode(&self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn suspend(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn is_headless(&self) -> bool {
+        true
+    }
+}
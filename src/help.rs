@@ -0,0 +1,571 @@
+// What `:help` shows, section by section: the keys or commands of each and
+// what they do.
+const SECTIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Normal mode",
+        &[
+            ("h j k l", "move left, down, up, right"),
+            ("w e b", "next word, end of word, previous word"),
+            ("} {", "next/previous paragraph"),
+            (") (", "next/previous sentence"),
+            ("0 ^ $", "start, first non-blank, end of line"),
+            ("gg G", "first line, last line or line {count}"),
+            ("f t F T", "to or till a character, forward or back"),
+            ("; ,", "repeat the last f/t/F/T, the same or the other way"),
+            ("%", "matching bracket"),
+            ("{count}", "repeat a motion or command, like 3w or 2dd"),
+            ("i a", "insert before/after the cursor"),
+            ("o O", "open a line below/above"),
+            ("R", "Replace mode"),
+            ("r{char}", "replace the character under the cursor"),
+            ("v V", "select characters/lines"),
+            (
+                "Ctrl-N",
+                "a cursor on the word, then on its next occurrences",
+            ),
+            (
+                "d c y",
+                "delete, change or yank over a motion, or the line when doubled",
+            ),
+            (
+                "> <",
+                "indent or dedent over a motion, or the line when doubled",
+            ),
+            (
+                "gc gcc",
+                "comment out or uncomment over a motion, or the line",
+            ),
+            (
+                "ys{motion}{char}",
+                "surround with a character, yss for the line",
+            ),
+            ("ds{char} cs{old}{new}", "delete or change surroundings"),
+            ("iw aw ip i\" i( i{ ...", "text objects after an operator"),
+            ("p P", "paste after/before"),
+            ("Alt-j Alt-k", "move the line down/up"),
+            ("Alt-J Alt-K", "duplicate the line below/above"),
+            (
+                "\"{a-z}",
+                "use a register for the next delete, yank or paste",
+            ),
+            ("u Ctrl-R", "undo, redo"),
+            ("m{a-z}", "set a mark"),
+            ("'{a-z} `{a-z}", "jump to the line/position of a mark"),
+            ("Ctrl-O Ctrl-I", "back/forward through the jump list"),
+            ("/ n N", "search, next/previous match"),
+            ("* #", "search for the word under the cursor"),
+            ("Ctrl-D Ctrl-U", "scroll half a screen down/up"),
+            ("Ctrl-E Ctrl-Y", "scroll the view a line down/up"),
+            ("zz zt zb", "line to the middle/top/bottom of the screen"),
+            ("]c [c", "next/previous git hunk or difference"),
+            ("do dp", "take/put the difference from/into the other file"),
+            (
+                "]s [s z=",
+                "next/previous misspelled word, spelling suggestions",
+            ),
+            ("gd K", "go to definition, show documentation"),
+            (
+                "Ctrl-] Ctrl-T",
+                "jump to the tag under the cursor, and back",
+            ),
+            ("gb", "blame the current line"),
+            ("Ctrl-P", "find a file"),
+            ("Ctrl-Z", "suspend"),
+            (":", "Command mode"),
+        ],
+    ),
+    (
+        "Multiple cursors",
+        &[
+            (
+                "Ctrl-N",
+                "a cursor on the word, then on each next occurrence",
+            ),
+            (
+                "c i a",
+                "change, insert before or after the word at every cursor",
+            ),
+            ("u", "undo the change made at every cursor at once"),
+            ("Esc", "back to one cursor"),
+        ],
+    ),
+    (
+        "Undo",
+        &[
+            ("u Ctrl-R", "undo, redo"),
+            ("branches", "a change after undoing keeps what was undone"),
+            (
+                ":earlier :later [n]",
+                "through every change in the order made",
+            ),
+            (
+                ":earlier 10s 5m 1h 2d",
+                "back to how the text was a while ago",
+            ),
+            ("undofile udf", "keep the history in ~/.config/ggedit/undo"),
+        ],
+    ),
+    (
+        "Visual mode",
+        &[
+            ("o", "go to the other end of the selection"),
+            ("d x c y", "delete, change or yank the selection"),
+            ("> <", "indent or dedent the selected lines"),
+            ("gc", "comment out or uncomment the selected lines"),
+            (
+                "Alt-j Alt-k Alt-J Alt-K",
+                "move or duplicate the selected lines",
+            ),
+            ("iw a( ...", "select a text object"),
+            ("/ n N", "extend the selection to a match"),
+            (":", "a command on the selected lines"),
+            ("Esc", "back to Normal mode"),
+        ],
+    ),
+    (
+        "Insert mode",
+        &[
+            ("Esc", "back to Normal mode"),
+            ("Ctrl-N Ctrl-P", "complete the word being typed"),
+            (
+                "Ctrl-V {code}",
+                "a character by its code, or the next key as it is",
+            ),
+            ("Ctrl-K {a}{b}", "a digraph, see :digraphs"),
+        ],
+    ),
+    (
+        "Commands",
+        &[
+            (":w [file]", "write"),
+            (":q :q! :wq", "quit, quit without saving, write and quit"),
+            (":e {file} :e!", "edit a file, reload the current one"),
+            (":view {file}", "open a file read-only"),
+            (
+                ":bn :bp :b {n} :ls",
+                "next, previous or numbered buffer, list buffers",
+            ),
+            (
+                ":next :prev :first :last :args",
+                "go through the argument list",
+            ),
+            (":{line}", "go to a line"),
+            (":[range]s/pattern/replacement/[gci]", "substitute"),
+            (
+                ":[range]d :[range]y [x]",
+                "delete or yank lines into a register",
+            ),
+            (":[range]m :[range]t {address}", "move or copy lines"),
+            (
+                ":!cmd :[range]!cmd",
+                "run a command, filter lines through it",
+            ),
+            (
+                ":r !cmd :r {file}",
+                "insert output or a file below the cursor",
+            ),
+            (":w !cmd", "pipe the buffer into a command"),
+            (":grep {pattern}", "search every file, see :copen"),
+            (
+                ":cnext :cprev :copen :cclose",
+                "go through the matches of :grep",
+            ),
+            (
+                ":diffsplit {file} :diffoff",
+                "compare with a file side by side, stop",
+            ),
+            (":todos", "list TODO, FIXME, XXX and NOTE comments"),
+            (":preview", "show a Markdown file rendered, or as written"),
+            (":hex", "edit the bytes of the file in hex, or the text"),
+            (
+                ":make [args] :compile {cmd}",
+                "build in the background, list the errors",
+            ),
+            (":tag {name}", "jump to a tag from the tags file"),
+            (":terminal", "open a shell in a window, Esc to leave it"),
+            (
+                ":registers :marks :messages",
+                "list registers, marks or messages",
+            ),
+            (":digraphs", "list digraphs"),
+            (
+                ":iabbrev [word] [text] :iunabbrev {word}",
+                "add, list or remove Insert mode abbreviations",
+            ),
+            (":diagnostics", "list the language server's problems"),
+            (":GitHunkRevert", "undo the git hunk under the cursor"),
+            (":blame", "blame the current line"),
+            (
+                ":earlier :later [n|{n}s|{n}m|{n}h]",
+                "through the undo tree by changes or time",
+            ),
+            (":noh", "clear search highlighting"),
+            (
+                ":mksession [file] :source {file}",
+                "save and reopen the open buffers",
+            ),
+            (
+                ":autocmd [event pattern command]",
+                "run a command on BufRead, BufWritePre/Post or FileType",
+            ),
+            (
+                ":command[!] [Name] [cmd] :delcommand {Name}",
+                "add, list or remove user commands",
+            ),
+            (
+                ":script {code} :{Name} [args]",
+                "run script code, or a script's command",
+            ),
+            (":colorscheme {name}", "switch themes"),
+            (":set {option}", "set an option, see below"),
+            (":suspend", "suspend"),
+            (":help [topic]", "this help, at a topic"),
+        ],
+    ),
+    (
+        "Terminal mode",
+        &[
+            (
+                ":terminal",
+                "open $SHELL in a window under the text, or go back to it",
+            ),
+            ("Esc", "back to Normal mode, the shell keeps running"),
+            ("exit", "the window closes when the shell exits"),
+            (
+                "TERM=dumb",
+                "only plain text and simple cursor movements are drawn",
+            ),
+        ],
+    ),
+    (
+        "Markdown preview",
+        &[
+            (":preview", "show the file rendered, or as written"),
+            ("# Heading", "drawn without its #s"),
+            ("**bold** *italic* `code`", "drawn without their markers"),
+            ("[text](url)", "drawn as its text"),
+            ("- item  - [ ] task", "drawn with a bullet or a check box"),
+            ("---", "a rule across the screen"),
+            ("```lang", "code blocks between rules naming their language"),
+            ("typing", "shows the text as written again"),
+        ],
+    ),
+    (
+        "Hex editing",
+        &[
+            (":hex", "edit the bytes of the file, or go back to the text"),
+            (
+                "offset  hex  ASCII",
+                "each line: sixteen bytes in hex and as ASCII",
+            ),
+            (
+                "hex column",
+                "change, add or remove bytes; :w writes them as they are",
+            ),
+            ("ASCII column", "catches up the next time :hex is used"),
+            (
+                "ggedit -c hex {file}",
+                "edit any file, even one that isn't UTF-8",
+            ),
+            ("undo", "switching clears the undo history"),
+        ],
+    ),
+    (
+        "Diff mode",
+        &[
+            (
+                ":diffsplit {file}",
+                "compare with a file side by side, scrolling together",
+            ),
+            ("ggedit -d {a} {b}", "compare two files"),
+            ("added", "lines only one side has, across from a row of -"),
+            ("changed", "lines that differ, on both sides"),
+            ("]c [c", "next or previous difference"),
+            ("do", "take the difference from the other file"),
+            ("dp", "put the difference into the other file, unsaved"),
+            (":diffoff", "stop comparing, as does switching buffers"),
+        ],
+    ),
+    (
+        "Language servers",
+        &[
+            ("[lsp]", "the command for each file type, in config.toml"),
+            (
+                "E W",
+                "errors and warnings, marked in the gutter and underlined",
+            ),
+            ("[E2 W1]", "how many there are, in the status bar"),
+            (":diagnostics", "list them"),
+            ("gd", "jump to the definition, in whichever file it is"),
+            ("K", "show the documentation next to the symbol"),
+            (
+                "gd without a server",
+                "where the word follows fn, let or def, or first appears",
+            ),
+        ],
+    ),
+    (
+        "Indentation",
+        &[
+            ("Python", "4-space soft tabs, indents after :"),
+            ("Go, Makefiles", "real tabs"),
+            ("braces", "indent after {"),
+            ("[filetype.<name>]", "a file type's settings in config.toml"),
+            ("tabstop shiftwidth", "columns per tab and per indent"),
+            ("expandtab", "indent with spaces"),
+            (
+                "indent_after",
+                "what ends a line that indents the next, like :",
+            ),
+            ("buffers", "each keeps its own tab settings"),
+        ],
+    ),
+    (
+        "Autocommands",
+        &[
+            ("BufRead", "when a file's buffer is first shown"),
+            ("BufWritePre BufWritePost", "before and after saving"),
+            ("FileType", "matched against the file type, like rust"),
+            (
+                ":autocmd [{event} {pattern} {cmd}]",
+                "add one, or list them",
+            ),
+            ("[[autocmd]]", "event, pattern and command in config.toml"),
+            ("command = [...]", "several commands for one autocommand"),
+            ("options", "what they :set is global"),
+        ],
+    ),
+    (
+        "Scripts",
+        &[
+            (
+                "scripts/*.gg",
+                "run on startup from ~/.config/ggedit, in name order",
+            ),
+            (":script {code}", "run code directly"),
+            (
+                "function Name()",
+                "a command :Name, given its arguments as strings",
+            ),
+            ("local if elseif else while", "variables and control flow"),
+            ("for i = 1, n  for x in list", "loops"),
+            ("function return", "functions"),
+            ("a .. b  [1, 2]", "join strings, a list counted from 1"),
+            ("line(n) lines() line_count()", "read the buffer"),
+            (
+                "set_line(n, text) insert_line(n, text)",
+                "change or add a line",
+            ),
+            ("delete_line(n)", "delete a line"),
+            (
+                "cursor_line() cursor_column()",
+                "where the cursor is, see set_cursor(line, column)",
+            ),
+            ("message(text) command(ex)", "show a message, run a command"),
+            ("file_name() file_type()", "the current file"),
+            (
+                "map(mode, keys, to)",
+                "map keys, like map(\"normal\", \"gt\", \":Title<CR>\")",
+            ),
+            ("len upper lower trim sub find replace", "work with strings"),
+            (
+                "matches substitute",
+                "match or replace a regular expression",
+            ),
+            ("split join push pop", "work with lists"),
+            ("tostring tonumber type", "convert values, name their type"),
+        ],
+    ),
+    (
+        "Options",
+        &[
+            ("shiftwidth sw", "columns per indent"),
+            ("tabstop ts", "columns per tab"),
+            ("expandtab et", "indent with spaces"),
+            ("number nu", "line numbers"),
+            ("relativenumber rnu", "line numbers relative to the cursor"),
+            ("cursorline cul", "highlight the cursor line"),
+            ("colorcolumn cc", "columns to mark, like 80,120"),
+            ("list", "show tabs, trailing spaces and line ends"),
+            (
+                "scrolloff so",
+                "lines kept in sight above and below the cursor",
+            ),
+            (
+                "sidescrolloff siso",
+                "columns kept in sight either side of the cursor",
+            ),
+            ("smarthome", "0 and Home go to the first non-blank first"),
+            ("undofile udf", "keep the undo history between sessions"),
+            ("regex", "search with regular expressions"),
+            ("spell", "underline misspelled words"),
+            ("spelllang spl", "the dictionary to spell check with"),
+            (
+                "trimtrailingwhitespace",
+                "strip trailing whitespace when saving",
+            ),
+            ("backup bk", "copy a file before overwriting it"),
+            ("backupdir bdir", "where to keep backups"),
+            ("makeprg mp", "the command :make runs"),
+            ("fileformat", "unix or dos line endings"),
+            ("fileencoding", "the encoding to save with"),
+            ("endofline", "end the file with a newline"),
+            ("readonly", "refuse changes"),
+        ],
+    ),
+];
+
+// What can follow the keys of a command that waits for more, with what it
+// does. Normal mode lists these once it has waited a moment, and the help
+// has a section for each, after what is typed first.
+const PENDING: &[(&str, &[(&str, &str)])] = &[
+    ("\"", &[("a-z", "a register"), ("A-Z", "append to it")]),
+    (
+        "\"{register}",
+        &[("d c y", "into the register"), ("p P", "paste from it")],
+    ),
+    (
+        "an operator",
+        &[
+            ("w e b", "words"),
+            ("} {", "paragraphs"),
+            (") (", "sentences"),
+            ("0 ^ $", "start, first non-blank, end of line"),
+            ("gg G", "first/last line"),
+            ("f t F T", "to a character"),
+            ("i a", "inside/around a text object"),
+        ],
+    ),
+    ("d c y", &[("s", "surroundings")]),
+    (
+        "an operator and i or a",
+        &[
+            ("w", "word"),
+            ("p", "paragraph"),
+            ("\" ' `", "quoted text"),
+            ("( ) b", "parentheses"),
+            ("{ } B", "braces"),
+            ("[ ]", "square brackets"),
+            ("< >", "angle brackets"),
+        ],
+    ),
+    ("an operator and g", &[("g", "to the first line")]),
+    ("f t F T", &[("{char}", "the character to go to")]),
+    ("r", &[("{char}", "the character to replace with")]),
+    ("m", &[("a-z", "the mark to set")]),
+    ("' `", &[("a-z < >", "the mark to go to")]),
+    (
+        "g",
+        &[
+            ("g", "first line"),
+            ("c", "comment"),
+            ("d", "go to definition"),
+            ("b", "blame"),
+        ],
+    ),
+    (
+        "z",
+        &[
+            ("z", "line to the middle"),
+            ("t", "line to the top"),
+            ("b", "line to the bottom"),
+            ("=", "spelling suggestions"),
+        ],
+    ),
+    (
+        "] [",
+        &[("c", "hunk or difference"), ("s", "misspelled word")],
+    ),
+    (
+        "ds cs",
+        &[
+            ("( [ { <", "brackets and the spaces inside"),
+            (") ] } > b B", "brackets"),
+            ("\" ' ` ...", "punctuation"),
+        ],
+    ),
+    (
+        "cs{old}",
+        &[
+            ("( [ { <", "brackets, spaces inside"),
+            (") ] } > b B", "brackets"),
+            ("\" ' ` ...", "punctuation"),
+        ],
+    ),
+    (
+        "ys{motion}",
+        &[
+            ("( [ { <", "surround with brackets, spaces inside"),
+            (") ] } > b B", "surround with brackets"),
+            ("\" ' ` ...", "surround with punctuation"),
+        ],
+    ),
+];
+
+// The keys that can follow `first`, as `PENDING` lists them.
+pub fn pending(first: &str) -> &'static [(&'static str, &'static str)] {
+    PENDING
+        .iter()
+        .find(|(keys, _)| *keys == first)
+        .map_or(&[], |(_, entries)| entries)
+}
+
+// The lines of the help buffer, with the user's key mappings, as mode, keys
+// and what they are mapped to, in a last section.
+pub fn text(mappings: &[(String, String, String)]) -> Vec<String> {
+    let mut lines = vec![
+        String::from("ggedit help. Search with / and leave with :bp or :b {n}."),
+        String::new(),
+    ];
+    let mut section = |title: &str, entries: Vec<(String, String)>| {
+        let width = entries
+            .iter()
+            .map(|(keys, _)| keys.chars().count())
+            .max()
+            .unwrap_or(0);
+        lines.push(title.to_string());
+        lines.extend(
+            entries
+                .iter()
+                .map(|(keys, description)| format!("  {keys:<width$}  {description}")),
+        );
+        lines.push(String::new());
+    };
+    for (title, entries) in SECTIONS {
+        section(
+            title,
+            entries
+                .iter()
+                .map(|(keys, description)| ((*keys).to_string(), (*description).to_string()))
+                .collect(),
+        );
+    }
+    for (first, entries) in PENDING {
+        section(
+            &format!("After {first}"),
+            entries
+                .iter()
+                .map(|(keys, description)| ((*keys).to_string(), (*description).to_string()))
+                .collect(),
+        );
+    }
+    if !mappings.is_empty() {
+        section(
+            "Key mappings",
+            mappings
+                .iter()
+                .map(|(mode, from, to)| (format!("{mode} {from}"), to.clone()))
+                .collect(),
+        );
+    }
+    lines
+}
+
+// The line `topic` is described on: the first entry or title starting with
+// it, or else the first line mentioning it.
+pub fn find(lines: &[String], topic: &str) -> Option<usize> {
+    lines
+        .iter()
+        .position(|line| line.trim_start().starts_with(topic))
+        .or_else(|| lines.iter().position(|line| line.contains(topic)))
+}
@@ -0,0 +1,61 @@
+// The bytes of a file as `:hex` shows them, sixteen to a line: the offset
+// of the first, the bytes in hex and what they are as ASCII, with a dot for
+// anything unprintable.
+//
+//     00000000: 48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a  Hello, world!.
+//
+// Only the hex column counts when turning the lines back into bytes, so
+// bytes are changed, added or removed there, and the other columns are
+// brought up to date the next time the bytes are shown.
+
+const BYTES_PER_LINE: usize = 16;
+
+pub fn dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' {
+                        char::from(*byte)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}: {:<width$}  {ascii}",
+                index.saturating_mul(BYTES_PER_LINE),
+                hex.join(" "),
+                width = BYTES_PER_LINE.saturating_mul(3).saturating_sub(1)
+            )
+        })
+        .collect()
+}
+
+// The bytes in the hex columns of `lines`, which end at the first double
+// space. Blank lines are skipped.
+pub fn parse<'a>(lines: impl IntoIterator<Item = &'a str>) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for (index, line) in lines.into_iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let number = index.saturating_add(1);
+        let (_, rest) = line
+            .split_once(": ")
+            .ok_or_else(|| format!("No offset on line {number}"))?;
+        let hex = rest.split_once("  ").map_or(rest, |(hex, _)| hex);
+        for pair in hex.split_whitespace() {
+            let byte = Some(pair)
+                .filter(|pair| pair.len() == 2 && pair.chars().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("Invalid byte {pair} on line {number}"))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
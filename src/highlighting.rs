@@ -1,51 +1,40 @@
-use termion::color::{self, Bg, Fg};
+// A construct that can span rows, carried from the end of one row to the
+// start of the next when highlighting.
+#[derive(Copy, Clone, Default, PartialEq)]
+pub enum Context {
+    #[default]
+    Normal,
+    // Rust block comments nest, so their depth is kept.
+    BlockComment(usize),
+    // Strings remember their opening quote.
+    String(char),
+    // The number of `#`s the raw string has to be closed with.
+    RawString(usize),
+}
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum Type {
     None,
     Number,
     SearchMatch,
     String,
+    // An escape sequence like `\n` within a string.
+    Escape,
     Character,
     Comment,
-}
-
-impl Type {
-    pub fn to_bg_color(&self) -> Option<color::Bg<color::Rgb>> {
-        match self {
-            Type::None => None,
-            Type::Number => None,
-            Type::Character => None,
-            Type::SearchMatch => Some(Bg(color::Rgb(255, 255, 0))),
-            Type::String => None,
-            Type::Comment => None,
-        }
-    }
-
-    pub fn to_fg_color(&self) -> Option<color::Fg<color::Rgb>> {
-        match self {
-            Type::None => None,
-            Type::Character => Some(Fg(color::Rgb(255, 234, 96))),
-            Type::Comment => Some(Fg(color::Rgb(124, 124, 124))),
-            Type::String => Some(Fg(color::Rgb(211, 54, 130))),
-            Type::Number => Some(Fg(color::Rgb(232, 165, 165))),
-            Type::SearchMatch => Some(Fg(color::Rgb(0, 0, 0))),
-        }
-    }
-}
-
-impl ToString for Type {
-    fn to_string(&self) -> String {
-        format!(
-            "{}{}",
-            match self.to_bg_color() {
-                None => Bg(color::Reset).to_string(),
-                Some(color) => color.to_string(),
-            },
-            match self.to_fg_color() {
-                None => Fg(color::Reset).to_string(),
-                Some(color) => color.to_string(),
-            }
-        )
-    }
+    // A TODO, FIXME, XXX or NOTE marker within a comment.
+    Todo,
+    PrimaryKeywords,
+    SecondaryKeywords,
+    MatchingBracket,
+    Selection,
+    // Text a language server reported a problem with.
+    Error,
+    Warning,
+    // A word the dictionary doesn't know, with `:set spell`.
+    Misspelled,
+    TrailingWhitespace,
+    // The other occurrences of the word the cursor rests on, drawn over the
+    // highlighting rather than in place of it.
+    WordMatch,
 }
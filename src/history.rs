@@ -0,0 +1,107 @@
+use crate::config;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const MAX_ENTRIES: usize = 200;
+
+// Previously entered `:` commands or `/` searches, oldest first, kept in a
+// file in the config directory so they survive restarts.
+#[derive(Default)]
+pub struct History {
+    file_name: &'static str,
+    entries: Vec<String>,
+    // The entry shown while going through the history with Up and Down, and
+    // what had been typed before, which only matching entries start with.
+    browsing: Option<(usize, String)>,
+}
+
+impl History {
+    pub fn load(file_name: &'static str) -> Self {
+        let entries = path(file_name)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| contents.lines().map(String::from).collect())
+            .unwrap_or_default();
+        Self {
+            file_name,
+            entries,
+            browsing: None,
+        }
+    }
+
+    // Adds an entry, moving it to the end if it was already there, and saves
+    // the history.
+    pub fn push(&mut self, entry: &str) -> Result<(), io::Error> {
+        self.browsing = None;
+        if entry.is_empty() {
+            return Ok(());
+        }
+        self.entries.retain(|existing| existing != entry);
+        self.entries.push(entry.to_string());
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+        self.save()
+    }
+
+    // Stops browsing, so the next Up starts again from the newest entry.
+    pub fn reset(&mut self) {
+        self.browsing = None;
+    }
+
+    // The entry before the one shown, for Up. `typed` is what is on the
+    // command line, which is remembered the first time.
+    pub fn previous_entry(&mut self, typed: &str) -> Option<String> {
+        let (index, prefix) = self
+            .browsing
+            .take()
+            .unwrap_or_else(|| (self.entries.len(), typed.to_string()));
+        let found = self
+            .entries
+            .get(..index)
+            .and_then(|older| older.iter().rposition(|entry| entry.starts_with(&prefix)));
+        let index = found.unwrap_or(index);
+        let entry = found.and_then(|index| self.entries.get(index)).cloned();
+        self.browsing = Some((index, prefix));
+        entry
+    }
+
+    // The entry after the one shown, for Down. Going past the newest gives
+    // back what was typed.
+    pub fn next_entry(&mut self) -> Option<String> {
+        let (index, prefix) = self.browsing.take()?;
+        let newer = self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(index.saturating_add(1))
+            .find(|(_, entry)| entry.starts_with(&prefix));
+        match newer {
+            Some((index, entry)) => {
+                let entry = entry.clone();
+                self.browsing = Some((index, prefix));
+                Some(entry)
+            }
+            None => Some(prefix),
+        }
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        // A default history isn't kept anywhere.
+        if self.file_name.is_empty() {
+            return Ok(());
+        }
+        let Some(path) = path(self.file_name) else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut contents = self.entries.join("\n");
+        contents.push('\n');
+        fs::write(path, contents)
+    }
+}
+
+fn path(file_name: &str) -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join(file_name))
+}
@@ -0,0 +1,49 @@
+use crate::Position;
+
+#[derive(Clone)]
+pub struct Jump {
+    pub cursor: Position,
+    pub offset: Position,
+}
+
+#[derive(Default)]
+pub struct JumpList {
+    jumps: Vec<Jump>,
+    index: usize,
+}
+
+const MAX_JUMPS: usize = 100;
+
+impl JumpList {
+    // Records the position being jumped away from, dropping any jumps that
+    // were ahead of the current one and older jumps on the same line.
+    pub fn push(&mut self, jump: Jump) {
+        self.jumps.truncate(self.index);
+        self.jumps.retain(|old| old.cursor.y != jump.cursor.y);
+        self.jumps.push(jump);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.index = self.jumps.len();
+    }
+
+    pub fn back(&mut self, current: Jump) -> Option<Jump> {
+        if self.index == 0 {
+            return None;
+        }
+        if self.index == self.jumps.len() {
+            self.push(current);
+            self.index = self.jumps.len().saturating_sub(1);
+        }
+        self.index = self.index.checked_sub(1)?;
+        self.jumps.get(self.index).cloned()
+    }
+
+    pub fn forward(&mut self) -> Option<Jump> {
+        if self.index.saturating_add(1) >= self.jumps.len() {
+            return None;
+        }
+        self.index = self.index.saturating_add(1);
+        self.jumps.get(self.index).cloned()
+    }
+}
@@ -0,0 +1,176 @@
+use crate::config;
+use std::collections::HashMap;
+use termion::event::Key;
+
+// How long to wait for the rest of a mapping, like vim's `timeoutlen`.
+pub const TIMEOUT_MS: u64 = 1000;
+
+// The keys typed and the keys they stand for.
+type Mapping = (Vec<Key>, Vec<Key>);
+
+pub enum Resolution {
+    // The keys form a mapping and should be replaced by these.
+    Mapped(Vec<Key>),
+    // The keys start a longer mapping; wait for more.
+    Pending,
+    Unmapped,
+}
+
+// Key sequences remapped per mode, read from the `[keymap.<mode>]` tables of
+// `config.toml`:
+//
+//     [keymap.insert]
+//     jk = "<Esc>"
+//
+//     [keymap.normal]
+//     H = "0"
+#[derive(Default)]
+pub struct Keymap {
+    modes: HashMap<String, Vec<Mapping>>,
+}
+
+impl Keymap {
+    pub fn load() -> Result<Self, String> {
+        let mut keymap = Self::default();
+        let Some(modes) = config::section("keymap")? else {
+            return Ok(keymap);
+        };
+        for (mode, mappings) in &modes {
+            let Some(mappings) = mappings.as_table() else {
+                return Err(format!("keymap.{mode} must be a table"));
+            };
+            for (from, to) in mappings {
+                let to = to
+                    .as_str()
+                    .ok_or_else(|| format!("Mapping for {from} must be a string"))?;
+                keymap.map(mode, parse_keys(from), parse_keys(to));
+            }
+        }
+        Ok(keymap)
+    }
+
+    // Every mapping as its mode, keys and what they stand for, in key
+    // notation and by mode.
+    pub fn mappings(&self) -> Vec<(String, String, String)> {
+        let notation = |keys: &[Key]| keys.iter().copied().map(notation).collect::<String>();
+        let mut mappings: Vec<_> = self
+            .modes
+            .iter()
+            .flat_map(|(mode, mappings)| {
+                mappings
+                    .iter()
+                    .map(|(from, to)| (mode.clone(), notation(from), notation(to)))
+            })
+            .collect();
+        mappings.sort_by(|a, b| a.0.cmp(&b.0));
+        mappings
+    }
+
+    pub fn map(&mut self, mode: &str, from: Vec<Key>, to: Vec<Key>) {
+        if from.is_empty() {
+            return;
+        }
+        let mappings = self.modes.entry(mode.to_lowercase()).or_default();
+        mappings.retain(|(existing, _)| *existing != from);
+        mappings.push((from, to));
+    }
+
+    // Once `timed_out`, a mapping that is also the start of a longer one is
+    // used instead of waiting for it.
+    pub fn resolve(&self, mode: &str, keys: &[Key], timed_out: bool) -> Resolution {
+        let Some(mappings) = self.modes.get(mode) else {
+            return Resolution::Unmapped;
+        };
+        let exact = mappings.iter().find(|(from, _)| from == keys);
+        let longer = mappings
+            .iter()
+            .any(|(from, _)| from.len() > keys.len() && from.starts_with(keys));
+        match exact {
+            _ if longer && !timed_out => Resolution::Pending,
+            Some((_, to)) => Resolution::Mapped(to.clone()),
+            None => Resolution::Unmapped,
+        }
+    }
+}
+
+// Parses key notation like `jk`, `<Esc>`, `<C-s>` or `<lt>`.
+pub fn parse_keys(notation: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let name = rest.get(1..end).unwrap_or_default();
+                if let Some(key) = named_key(name) {
+                    keys.push(key);
+                    rest = rest.get(end.saturating_add(1)..).unwrap_or_default();
+                    continue;
+                }
+            }
+        }
+        keys.push(Key::Char(c));
+        rest = rest.get(c.len_utf8()..).unwrap_or_default();
+    }
+    keys
+}
+
+// How `key` is written in key notation, the other way from `parse_keys`.
+pub fn notation(key: Key) -> String {
+    match key {
+        Key::Char('\n') => String::from("<CR>"),
+        Key::Char('\t') => String::from("<Tab>"),
+        Key::Char(' ') => String::from("<Space>"),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("<C-{c}>"),
+        Key::Alt(c) => format!("<A-{c}>"),
+        Key::Esc => String::from("<Esc>"),
+        Key::Backspace => String::from("<BS>"),
+        Key::Delete => String::from("<Del>"),
+        Key::Up => String::from("<Up>"),
+        Key::Down => String::from("<Down>"),
+        Key::Left => String::from("<Left>"),
+        Key::Right => String::from("<Right>"),
+        Key::Home => String::from("<Home>"),
+        Key::End => String::from("<End>"),
+        Key::PageUp => String::from("<PageUp>"),
+        Key::PageDown => String::from("<PageDown>"),
+        _ => String::new(),
+    }
+}
+
+fn named_key(name: &str) -> Option<Key> {
+    let modified = |prefix: &str| {
+        let rest = name
+            .get(..2)?
+            .eq_ignore_ascii_case(prefix)
+            .then(|| name.get(2..))??;
+        let mut chars = rest.chars();
+        let c = chars.next()?;
+        chars.next().is_none().then_some(c)
+    };
+    if let Some(c) = modified("c-") {
+        return Some(Key::Ctrl(c.to_ascii_lowercase()));
+    }
+    if let Some(c) = modified("a-").or_else(|| modified("m-")) {
+        return Some(Key::Alt(c));
+    }
+    let key = match name.to_ascii_lowercase().as_str() {
+        "esc" => Key::Esc,
+        "cr" | "enter" | "return" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "bs" | "backspace" => Key::Backspace,
+        "del" | "delete" => Key::Delete,
+        "space" => Key::Char(' '),
+        "lt" => Key::Char('<'),
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        _ => return None,
+    };
+    Some(key)
+}
@@ -0,0 +1,131 @@
+// The lines an Ex command like `:1,5s/a/b/` applies to, as inclusive 0-based
+// row indices.
+#[derive(Clone, Copy, PartialEq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    pub fn line(y: usize) -> Self {
+        Self { start: y, end: y }
+    }
+
+    pub fn len(self) -> usize {
+        self.end.saturating_sub(self.start).saturating_add(1)
+    }
+
+    // A range always has at least one line in it.
+    pub fn is_empty(self) -> bool {
+        false
+    }
+
+    // Parses the range at the start of `command`, returning it along with the
+    // rest of the command. Addresses are line numbers, `.` for the current
+    // line, `$` for the last and `'a` for the line of mark `a`, looked up with
+    // `mark`, each optionally followed by `+n` or `-n`; `%` is the whole file.
+    // A backwards range is swapped.
+    pub fn parse<'a>(
+        command: &'a str,
+        current: usize,
+        last: usize,
+        mark: &dyn Fn(char) -> Option<usize>,
+    ) -> Result<(Option<Self>, &'a str), String> {
+        if let Some(rest) = command.strip_prefix('%') {
+            return Ok((
+                Some(Self {
+                    start: 0,
+                    end: last,
+                }),
+                rest,
+            ));
+        }
+        let (start, rest) = parse_address(command, current, last, mark)?;
+        let Some(start) = start else {
+            return Ok((None, rest));
+        };
+        let Some(after_comma) = rest.strip_prefix(',') else {
+            return Ok((Some(Self::line(start)), rest));
+        };
+        let (end, rest) = parse_address(after_comma, current, last, mark)?;
+        let end = end.unwrap_or(current);
+        Ok((
+            Some(Self {
+                start: start.min(end),
+                end: start.max(end),
+            }),
+            rest,
+        ))
+    }
+
+    // Parses the address `:m` and `:t` put lines after, returning the row
+    // they are inserted at: `0` puts them above the first line.
+    pub fn destination(
+        text: &str,
+        current: usize,
+        last: usize,
+        mark: &dyn Fn(char) -> Option<usize>,
+    ) -> Result<usize, String> {
+        let text = text.trim();
+        if text == "0" {
+            return Ok(0);
+        }
+        match parse_address(text, current, last, mark)? {
+            (Some(line), "") => Ok(line.saturating_add(1)),
+            (None, _) => Err(String::from("Destination required")),
+            (Some(_), rest) => Err(format!("Trailing characters: {rest}")),
+        }
+    }
+}
+
+fn parse_address<'a>(
+    text: &'a str,
+    current: usize,
+    last: usize,
+    mark: &dyn Fn(char) -> Option<usize>,
+) -> Result<(Option<usize>, &'a str), String> {
+    let (mut line, mut rest) = if let Some(rest) = text.strip_prefix('.') {
+        (Some(current), rest)
+    } else if let Some(rest) = text.strip_prefix('$') {
+        (Some(last), rest)
+    } else if let Some(after_quote) = text.strip_prefix('\'') {
+        let mut chars = after_quote.chars();
+        let name = chars
+            .next()
+            .ok_or_else(|| format!("Invalid range: {text}"))?;
+        let line = mark(name).ok_or_else(|| String::from("Mark not set"))?;
+        (Some(line), chars.as_str())
+    } else {
+        let (digits, rest) = split_digits(text);
+        match digits.parse::<usize>() {
+            // Lines are numbered from 1, but `:0` is taken as the first line.
+            Ok(number) => (Some(number.saturating_sub(1)), rest),
+            Err(_) => (None, text),
+        }
+    };
+    while let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+        let (digits, after) = split_digits(rest.get(1..).unwrap_or_default());
+        let offset = if digits.is_empty() {
+            1
+        } else {
+            digits
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid range: {text}"))?
+        };
+        let base = line.unwrap_or(current);
+        line = Some(if sign == '+' {
+            base.saturating_add(offset)
+        } else {
+            base.saturating_sub(offset)
+        });
+        rest = after;
+    }
+    Ok((line.map(|line| line.min(last)), rest))
+}
+
+fn split_digits(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
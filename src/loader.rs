@@ -0,0 +1,111 @@
+use crate::event::{Event, Load};
+use crate::FileEncoding;
+use crate::FileFormat;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+// Files bigger than this are streamed in the background instead of being
+// read before the first frame.
+pub const STREAM_THRESHOLD: u64 = 1024 * 1024;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Reads `file_name` on its own thread and sends its rows as they are decoded.
+pub fn spawn(file_name: String, buffer: usize, events: Sender<Event>) {
+    thread::spawn(move || {
+        if let Err(error) = stream(&file_name, buffer, &events) {
+            let _ = events.send(Event::Load {
+                buffer,
+                load: Load::Failed(error),
+            });
+        }
+    });
+}
+
+fn stream(file_name: &str, buffer: usize, events: &Sender<Event>) -> io::Result<()> {
+    let send = |load: Load| {
+        events
+            .send(Event::Load { buffer, load })
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Editor has quit"))
+    };
+    let decoded = decode(File::open(file_name)?, None, |rows, bytes_read| {
+        send(Load::Rows { rows, bytes_read })
+    })?;
+    send(Load::Finished {
+        file_encoding: decoded.file_encoding,
+        file_format: decoded.file_format,
+        end_of_line: decoded.end_of_line,
+    })
+}
+
+// What decoding a file found out about it besides its rows.
+pub struct Decoded {
+    pub file_encoding: FileEncoding,
+    pub file_format: FileFormat,
+    pub end_of_line: bool,
+    // Whether anything couldn't be decoded and was replaced.
+    pub replaced: bool,
+}
+
+// Decodes what `reader` gives a chunk at a time, handing the complete lines
+// of each to `rows` with how many bytes have been read so far, so the whole
+// text is never held at once. The encoding is guessed from the first chunk
+// unless `file_encoding` is given.
+pub fn decode(
+    mut reader: impl Read,
+    file_encoding: Option<FileEncoding>,
+    mut rows: impl FnMut(Vec<String>, u64) -> io::Result<()>,
+) -> io::Result<Decoded> {
+    let mut bytes = vec![0; CHUNK_SIZE];
+    let mut file_encoding = file_encoding;
+    let mut file_format = None;
+    let mut decoder = file_encoding.map(FileEncoding::new_decoder);
+    let mut partial = String::new();
+    let mut bytes_read = 0_u64;
+    let mut replaced = false;
+    loop {
+        let read = reader.read(&mut bytes)?;
+        let chunk = bytes.get(..read).unwrap_or_default();
+        let last = read == 0;
+        let decoder = decoder.get_or_insert_with(|| {
+            let encoding = FileEncoding::detect(chunk);
+            file_encoding = Some(encoding);
+            encoding.new_decoder()
+        });
+        if let Some(capacity) = decoder.max_utf8_buffer_length(read) {
+            partial.reserve(capacity);
+        }
+        let (_, _, had_replacements) = decoder.decode_to_string(chunk, &mut partial, last);
+        replaced = replaced || had_replacements;
+        bytes_read = bytes_read.saturating_add(read as u64);
+
+        // Only complete lines are sent; the rest waits for the next chunk.
+        if file_format.is_none() && partial.contains('\n') {
+            file_format = Some(FileFormat::detect(&partial));
+        }
+        let complete = partial
+            .rfind('\n')
+            .map_or(0, |index| index.saturating_add(1));
+        let rest = partial.split_off(complete);
+        let mut lines: Vec<String> = partial.lines().map(String::from).collect();
+        partial = rest;
+        let end_of_line = partial.is_empty();
+        if last && !partial.is_empty() {
+            lines.push(mem::take(&mut partial));
+        }
+        if !lines.is_empty() {
+            rows(lines, bytes_read)?;
+        }
+        if last {
+            return Ok(Decoded {
+                file_encoding: file_encoding.unwrap_or_default(),
+                file_format: file_format.unwrap_or_default(),
+                end_of_line,
+                replaced,
+            });
+        }
+    }
+}
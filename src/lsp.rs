@@ -0,0 +1,779 @@
+use crate::config;
+use crate::event::Event;
+use crate::json::Value;
+use crate::Document;
+use crate::Position;
+use crate::Row;
+use crate::Suggestion;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+
+// The id of the `initialize` request, which is answered before any other.
+const INITIALIZE: u64 = 0;
+
+// How long a server has to exit once told to, when the editor quits.
+const EXIT_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+// A problem a server found. Positions are as servers count them: zero-based
+// lines, and columns in UTF-16 code units.
+pub struct Diagnostic {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub severity: Severity,
+    pub message: String,
+}
+
+// What the thread reading a server passes on to the editor.
+pub enum Message {
+    Initialized,
+    Diagnostics {
+        uri: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    // The answer to request `id`, or the error it failed with.
+    Response {
+        id: u64,
+        result: Result<Value, String>,
+    },
+    Exited,
+}
+
+// What the editor can ask a server about a position in a file.
+#[derive(Clone, Copy)]
+pub enum Query {
+    Completion,
+    Definition,
+    Hover,
+}
+
+impl Query {
+    fn method(self) -> &'static str {
+        match self {
+            Self::Completion => "textDocument/completion",
+            Self::Definition => "textDocument/definition",
+            Self::Hover => "textDocument/hover",
+        }
+    }
+}
+
+// A server's answer to a query, for the editor to act on.
+pub enum Reply {
+    Completion(Vec<Suggestion>),
+    Definition(Vec<Location>),
+    // The lines of text to show, without markdown code fences.
+    Hover(Vec<String>),
+    Failed(String),
+}
+
+// A position in a file, with its line and UTF-16 column as the server gave
+// them.
+pub struct Location {
+    pub file_name: String,
+    pub line: usize,
+    pub character: usize,
+}
+
+// The command starting the language server of each file type, from the
+// `[lsp]` table of `config.toml`:
+//
+//     [lsp]
+//     Rust = "rust-analyzer"
+//     Python = "pylsp --verbose"
+pub fn load_servers() -> Result<HashMap<String, String>, String> {
+    let Some(servers) = config::section("lsp")? else {
+        return Ok(HashMap::new());
+    };
+    servers
+        .iter()
+        .map(|(file_type, command)| {
+            let command = command
+                .as_str()
+                .ok_or_else(|| format!("Language server for {file_type} must be a string"))?;
+            Ok((file_type.clone(), command.to_string()))
+        })
+        .collect()
+}
+
+// A running server, and the documents it was told about.
+struct Client {
+    child: Child,
+    stdin: Arc<Mutex<ChildStdin>>,
+    next_id: u64,
+    ready: bool,
+    // Messages held back until the server has answered `initialize`.
+    queued: Vec<Value>,
+    // What each request waiting for an answer asked.
+    pending: HashMap<u64, Query>,
+    // The URI of each open file, with the document revision the server last
+    // saw and the version number it was sent under.
+    documents: HashMap<String, (String, u64, u64)>,
+}
+
+impl Client {
+    fn start(command: &str, file_type: &str, sender: Sender<Event>) -> Result<Self, io::Error> {
+        let mut words = command.split_whitespace();
+        let program = words.next().unwrap_or_default();
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("No pipes to the server"));
+        };
+        let stdin = Arc::new(Mutex::new(stdin));
+        let writer = Arc::clone(&stdin);
+        let file_type = file_type.to_string();
+        thread::spawn(move || read_messages(stdout, &writer, &file_type, &sender));
+        let root = env::current_dir().unwrap_or_default();
+        let hover = Value::object([(
+            "contentFormat",
+            Value::Array(vec!["plaintext".into(), "markdown".into()]),
+        )]);
+        write_message(
+            &stdin,
+            &Value::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", INITIALIZE.into()),
+                ("method", "initialize".into()),
+                (
+                    "params",
+                    Value::object([
+                        ("processId", u64::from(std::process::id()).into()),
+                        ("rootUri", uri(&root.to_string_lossy()).into()),
+                        (
+                            "capabilities",
+                            Value::object([(
+                                "textDocument",
+                                Value::object([
+                                    ("publishDiagnostics", Value::object([])),
+                                    ("hover", hover),
+                                    ("completion", Value::object([])),
+                                ]),
+                            )]),
+                        ),
+                    ]),
+                ),
+            ]),
+        )?;
+        Ok(Self {
+            child,
+            stdin,
+            next_id: INITIALIZE.saturating_add(1),
+            ready: false,
+            queued: Vec::new(),
+            pending: HashMap::new(),
+            documents: HashMap::new(),
+        })
+    }
+
+    fn request(&mut self, query: Query, params: Value) -> Result<(), io::Error> {
+        let id = self.next_id;
+        self.next_id = self.next_id.saturating_add(1);
+        self.pending.insert(id, query);
+        self.send(Value::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id.into()),
+            ("method", query.method().into()),
+            ("params", params),
+        ]))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<(), io::Error> {
+        self.send(Value::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]))
+    }
+
+    fn send(&mut self, message: Value) -> Result<(), io::Error> {
+        if self.ready {
+            write_message(&self.stdin, &message)
+        } else {
+            self.queued.push(message);
+            Ok(())
+        }
+    }
+
+    fn initialized(&mut self) -> Result<(), io::Error> {
+        self.ready = true;
+        self.notify("initialized", Value::object([]))?;
+        for message in std::mem::take(&mut self.queued) {
+            write_message(&self.stdin, &message)?;
+        }
+        Ok(())
+    }
+
+    // Opens `file_name` on the server, or sends its whole text again when it
+    // changed since the last time.
+    fn sync(&mut self, file_name: &str, document: &Document) -> Result<(), io::Error> {
+        let revision = document.revision();
+        let (uri, version) = match self.documents.get(file_name) {
+            Some((_, seen, _)) if *seen == revision => return Ok(()),
+            Some((uri, _, version)) => (uri.clone(), version.saturating_add(1)),
+            None => {
+                let path = fs::canonicalize(file_name).unwrap_or_else(|_| file_name.into());
+                (uri(&path.to_string_lossy()), 0)
+            }
+        };
+        self.documents
+            .insert(file_name.to_string(), (uri.clone(), revision, version));
+        if version == 0 {
+            self.notify(
+                "textDocument/didOpen",
+                Value::object([(
+                    "textDocument",
+                    Value::object([
+                        ("uri", uri.into()),
+                        ("languageId", language_id(&document.file_type()).into()),
+                        ("version", version.into()),
+                        ("text", text(document).into()),
+                    ]),
+                )]),
+            )
+        } else {
+            self.notify(
+                "textDocument/didChange",
+                Value::object([
+                    (
+                        "textDocument",
+                        Value::object([("uri", uri.into()), ("version", version.into())]),
+                    ),
+                    (
+                        "contentChanges",
+                        Value::Array(vec![Value::object([("text", text(document).into())])]),
+                    ),
+                ]),
+            )
+        }
+    }
+
+    fn file_name_of(&self, uri: &str) -> Option<&str> {
+        self.documents
+            .iter()
+            .find(|(_, (document_uri, _, _))| document_uri == uri)
+            .map(|(file_name, _)| file_name.as_str())
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = write_message(
+            &self.stdin,
+            &Value::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", self.next_id.into()),
+                ("method", "shutdown".into()),
+            ]),
+        );
+        let _ = write_message(
+            &self.stdin,
+            &Value::object([("jsonrpc", "2.0".into()), ("method", "exit".into())]),
+        );
+        // Servers get a moment to exit cleanly before they are killed.
+        let deadline = Instant::now() + EXIT_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// The language servers, started the first time a file of their type is
+// shown, and the diagnostics they sent for each file.
+#[derive(Default)]
+pub struct Lsp {
+    commands: HashMap<String, String>,
+    clients: HashMap<String, Client>,
+    // File types whose server couldn't be started, so it isn't tried again.
+    failed: HashSet<String>,
+    diagnostics: HashMap<String, Vec<Diagnostic>>,
+}
+
+impl Lsp {
+    pub fn new(commands: HashMap<String, String>) -> Self {
+        Self {
+            commands,
+            ..Self::default()
+        }
+    }
+
+    // Tells the server for the document's file type about its latest text,
+    // starting the server if needed. Returns an error to show when that
+    // fails.
+    pub fn sync(&mut self, document: &Document, sender: &Sender<Event>) -> Option<String> {
+        let file_name = document.file_name.as_deref()?;
+        let file_type = document.file_type();
+        let command = self.commands.get(&file_type)?;
+        if self.failed.contains(&file_type) {
+            return None;
+        }
+        if !self.clients.contains_key(&file_type) {
+            match Client::start(command, &file_type, sender.clone()) {
+                Ok(client) => {
+                    self.clients.insert(file_type.clone(), client);
+                }
+                Err(error) => {
+                    self.failed.insert(file_type);
+                    return Some(format!("Cannot start {command}: {error}"));
+                }
+            }
+        }
+        let client = self.clients.get_mut(&file_type)?;
+        if let Err(error) = client.sync(file_name, document) {
+            self.clients.remove(&file_type);
+            self.failed.insert(file_type);
+            return Some(format!("Language server {command} failed: {error}"));
+        }
+        None
+    }
+
+    // Asks the server for the document's file type about the grapheme at
+    // `position`, once it has the latest text. The answer comes back later
+    // from `handle`.
+    pub fn ask(
+        &mut self,
+        document: &Document,
+        position: &Position,
+        query: Query,
+        sender: &Sender<Event>,
+    ) -> Result<(), String> {
+        if let Some(error) = self.sync(document, sender) {
+            return Err(error);
+        }
+        let file_type = document.file_type();
+        let file_name = document.file_name.as_deref().unwrap_or_default();
+        let Some(client) = self.clients.get_mut(&file_type) else {
+            return Err(format!("No language server for {file_type}"));
+        };
+        let Some((uri, _, _)) = client.documents.get(file_name) else {
+            return Err(format!("No language server for {file_type}"));
+        };
+        let character = document
+            .row(position.y)
+            .map_or(0, |row| utf16_column(row, position.x));
+        let params = Value::object([
+            ("textDocument", Value::object([("uri", uri.clone().into())])),
+            (
+                "position",
+                Value::object([("line", position.y.into()), ("character", character.into())]),
+            ),
+        ]);
+        client
+            .request(query, params)
+            .map_err(|error| format!("Language server failed: {error}"))
+    }
+
+    // Takes in what a server sent, returning the answer to a query if that
+    // is what it was.
+    pub fn handle(&mut self, file_type: &str, message: Message) -> Option<Reply> {
+        match message {
+            Message::Initialized => {
+                let client = self.clients.get_mut(file_type)?;
+                if client.initialized().is_err() {
+                    self.clients.remove(file_type);
+                }
+            }
+            Message::Diagnostics { uri, diagnostics } => {
+                let file_name = self.clients.get(file_type)?.file_name_of(&uri)?;
+                self.diagnostics.insert(file_name.to_string(), diagnostics);
+            }
+            Message::Response { id, result } => {
+                let query = self.clients.get_mut(file_type)?.pending.remove(&id)?;
+                let result = match result {
+                    Ok(result) => result,
+                    Err(error) => return Some(Reply::Failed(error)),
+                };
+                return Some(match query {
+                    Query::Completion => Reply::Completion(parse_completion(&result)),
+                    Query::Definition => Reply::Definition(parse_locations(&result)),
+                    Query::Hover => Reply::Hover(parse_hover(&result)),
+                });
+            }
+            Message::Exited => {
+                self.clients.remove(file_type);
+                self.failed.insert(file_type.to_string());
+            }
+        }
+        None
+    }
+
+    // Whether files of `file_type` have a server that hasn't failed.
+    pub fn has_server(&self, file_type: &str) -> bool {
+        self.commands.contains_key(file_type) && !self.failed.contains(file_type)
+    }
+
+    pub fn diagnostics(&self, file_name: &str) -> &[Diagnostic] {
+        self.diagnostics.get(file_name).map_or(&[], Vec::as_slice)
+    }
+}
+
+// Reads what the server sends until it goes away. Requests from the server
+// are answered with an empty result, since none of them are supported.
+fn read_messages(
+    stdout: impl Read,
+    writer: &Mutex<ChildStdin>,
+    file_type: &str,
+    sender: &Sender<Event>,
+) {
+    let mut reader = BufReader::new(stdout);
+    while let Some(message) = read_message(&mut reader) {
+        let method = message.get("method").and_then(Value::as_str);
+        let id = message.get("id");
+        let forwarded = match (method, id) {
+            (None, Some(id)) if id.as_u64() == Some(INITIALIZE) => Some(Message::Initialized),
+            (None, Some(id)) => id.as_u64().map(|id| Message::Response {
+                id,
+                result: match message.get("error") {
+                    Some(error) => Err(error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or("Request failed")
+                        .to_string()),
+                    None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                },
+            }),
+            (Some("textDocument/publishDiagnostics"), _) => {
+                message.get("params").and_then(parse_diagnostics)
+            }
+            (Some(_), Some(id)) => {
+                // `workspace/configuration` wants one entry per item asked
+                // about.
+                let items = message
+                    .get("params")
+                    .and_then(|params| params.get("items"))
+                    .and_then(Value::as_array)
+                    .map(|items| Value::Array(vec![Value::Null; items.len()]));
+                let _ = write_message(
+                    writer,
+                    &Value::object([
+                        ("jsonrpc", "2.0".into()),
+                        ("id", id.clone()),
+                        ("result", items.unwrap_or(Value::Null)),
+                    ]),
+                );
+                None
+            }
+            _ => None,
+        };
+        if let Some(forwarded) = forwarded {
+            let event = Event::Lsp {
+                file_type: file_type.to_string(),
+                message: forwarded,
+            };
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+    }
+    let _ = sender.send(Event::Lsp {
+        file_type: file_type.to_string(),
+        message: Message::Exited,
+    });
+}
+
+// One message, after headers giving its length, or `None` once the server
+// is gone.
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).ok()? == 0 {
+                return None;
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(length) = length else {
+            continue;
+        };
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).ok()?;
+        if let Ok(message) = Value::parse(&String::from_utf8_lossy(&body)) {
+            return Some(message);
+        }
+    }
+}
+
+fn write_message(writer: &Mutex<ChildStdin>, message: &Value) -> Result<(), io::Error> {
+    let body = message.to_string();
+    let mut writer = writer
+        .lock()
+        .map_err(|_| io::Error::other("Server pipe poisoned"))?;
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+pub fn parse_diagnostics(params: &Value) -> Option<Message> {
+    let uri = params.get("uri")?.as_str()?.to_string();
+    let position = |value: &Value| -> Option<(usize, usize)> {
+        Some((
+            usize::try_from(value.get("line")?.as_u64()?).ok()?,
+            usize::try_from(value.get("character")?.as_u64()?).ok()?,
+        ))
+    };
+    let diagnostics = params
+        .get("diagnostics")?
+        .as_array()?
+        .iter()
+        .filter_map(|diagnostic| {
+            let range = diagnostic.get("range")?;
+            let severity = match diagnostic.get("severity").and_then(Value::as_u64) {
+                Some(2) => Severity::Warning,
+                Some(3) => Severity::Information,
+                Some(4) => Severity::Hint,
+                _ => Severity::Error,
+            };
+            Some(Diagnostic {
+                start: position(range.get("start")?)?,
+                end: position(range.get("end")?)?,
+                severity,
+                message: diagnostic.get("message")?.as_str()?.to_string(),
+            })
+        })
+        .collect();
+    Some(Message::Diagnostics { uri, diagnostics })
+}
+
+// The grapheme of `row` that a server's column, in UTF-16 code units,
+// falls on.
+pub fn grapheme_index(row: &Row, column: usize) -> usize {
+    let mut units = 0_usize;
+    for (index, grapheme) in row.as_str().graphemes(true).enumerate() {
+        units = units.saturating_add(grapheme.encode_utf16().count());
+        if units > column {
+            return index;
+        }
+    }
+    row.len()
+}
+
+// The column in UTF-16 code units, as servers count, where the grapheme of
+// `row` at `x` starts.
+fn utf16_column(row: &Row, x: usize) -> usize {
+    row.as_str()
+        .graphemes(true)
+        .take(x)
+        .map(|grapheme| grapheme.encode_utf16().count())
+        .sum()
+}
+
+// A definition is answered with one location, a list of them, or a list of
+// links to them.
+fn parse_locations(result: &Value) -> Vec<Location> {
+    let locations = match result {
+        Value::Array(locations) => locations.as_slice(),
+        Value::Null => &[],
+        location => std::slice::from_ref(location),
+    };
+    locations
+        .iter()
+        .filter_map(|location| {
+            let uri = location
+                .get("uri")
+                .or_else(|| location.get("targetUri"))?
+                .as_str()?;
+            let start = location
+                .get("range")
+                .or_else(|| location.get("targetSelectionRange"))?
+                .get("start")?;
+            Some(Location {
+                file_name: file_name(uri)?,
+                line: usize::try_from(start.get("line")?.as_u64()?).ok()?,
+                character: usize::try_from(start.get("character")?.as_u64()?).ok()?,
+            })
+        })
+        .collect()
+}
+
+// Completion is answered with a list of items, or an object holding one.
+fn parse_completion(result: &Value) -> Vec<Suggestion> {
+    let items = result
+        .as_array()
+        .or_else(|| result.get("items").and_then(Value::as_array))
+        .unwrap_or_default();
+    items
+        .iter()
+        .filter_map(|item| {
+            let label = item.get("label")?.as_str()?.trim().to_string();
+            let text = item
+                .get("textEdit")
+                .and_then(|edit| edit.get("newText"))
+                .or_else(|| item.get("insertText"))
+                .and_then(Value::as_str)
+                .unwrap_or(&label);
+            // Snippets are inserted as plain text, with their placeholders.
+            let snippet = item.get("insertTextFormat").and_then(Value::as_u64) == Some(2);
+            let text = if snippet {
+                without_tabstops(text)
+            } else {
+                text.to_string()
+            };
+            let detail = item
+                .get("detail")
+                .and_then(Value::as_str)
+                .and_then(|detail| detail.lines().next())
+                .unwrap_or_default()
+                .to_string();
+            Some(Suggestion {
+                label,
+                text,
+                detail,
+            })
+        })
+        .collect()
+}
+
+// A snippet's text with `$1` and `${1:default}` tab stops replaced by their
+// defaults.
+fn without_tabstops(snippet: &str) -> String {
+    let mut text = String::new();
+    let mut chars = snippet.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+                if chars.next_if_eq(&':').is_some() {
+                    while let Some(c) = chars.next_if(|&c| c != '}') {
+                        text.push(c);
+                    }
+                }
+                chars.next();
+            }
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+            }
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+// Hover contents are markup, a string, an object with a language, or a list
+// of the last two.
+fn parse_hover(result: &Value) -> Vec<String> {
+    let markup = |value: &Value| -> String {
+        value
+            .as_str()
+            .or_else(|| value.get("value").and_then(Value::as_str))
+            .unwrap_or_default()
+            .to_string()
+    };
+    let text = match result.get("contents") {
+        Some(Value::Array(items)) => items.iter().map(markup).collect::<Vec<_>>().join("\n\n"),
+        Some(contents) => markup(contents),
+        None => String::new(),
+    };
+    let lines: Vec<String> = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .map(|line| line.trim_end().to_string())
+        .collect();
+    let first = lines.iter().position(|line| !line.is_empty());
+    let last = lines.iter().rposition(|line| !line.is_empty());
+    match (first, last) {
+        (Some(first), Some(last)) => lines.get(first..=last).unwrap_or_default().to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+// The path of a `file://` URI, relative to the current directory when it
+// is under it.
+fn file_name(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut at = 0_usize;
+    while let Some(&byte) = encoded.get(at) {
+        let escaped = encoded
+            .get(at.saturating_add(1)..at.saturating_add(3))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(escaped) if byte == b'%' => {
+                bytes.push(escaped);
+                at = at.saturating_add(3);
+            }
+            _ => {
+                bytes.push(byte);
+                at = at.saturating_add(1);
+            }
+        }
+    }
+    let path = PathBuf::from(String::from_utf8_lossy(&bytes).into_owned());
+    let relative = env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf));
+    Some(relative.unwrap_or(path).to_string_lossy().into_owned())
+}
+
+fn text(document: &Document) -> String {
+    (0..document.len())
+        .filter_map(|y| document.row(y))
+        .map(|row| format!("{}\n", row.as_str()))
+        .collect()
+}
+
+// The `file://` URI of an absolute path, with anything that isn't plain
+// escaped.
+fn uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(char::from(byte));
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+// The name servers know a language by, from its file type name.
+fn language_id(file_type: &str) -> String {
+    match file_type {
+        "C++" => String::from("cpp"),
+        "Shell" => String::from("shellscript"),
+        name => name.to_lowercase(),
+    }
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Information => "info",
+            Self::Hint => "hint",
+        }
+    }
+}
@@ -9,21 +9,135 @@
     clippy::else_if_without_else
 )]
 
+mod abbreviations;
+mod autocmd;
+mod backend;
+mod cells;
+mod cli;
+mod completion;
+mod config;
+mod diff;
+mod diff_view;
+mod digraph;
 mod document;
 mod editor;
+mod event;
+mod explorer;
+mod file_encoding;
+mod file_format;
 mod filetype;
+mod filetype_settings;
+mod finder;
+mod git;
+mod grep;
+mod headless;
+mod help;
+mod hex;
 mod highlighting;
+mod history;
+mod json;
+mod jump_list;
+mod keymap;
+mod line_range;
+mod loader;
+mod lsp;
+mod make;
+mod markdown;
+mod motion;
+mod overlay;
+mod quickfix;
+mod register;
+mod rope;
 mod row;
+mod script;
+mod search;
+mod session;
+mod settings;
+mod shell;
+mod spell;
+mod status_line;
+mod substitute;
+mod suggestions;
+mod surround;
+mod tags;
+mod term_window;
 mod terminal;
+mod text_object;
+mod theme;
+mod undo;
+mod user_commands;
+pub use abbreviations::Abbreviations;
+pub use autocmd::Autocommands;
+use cli::Invocation;
+pub use completion::Completion;
 pub use document::Document;
 use editor::Editor;
 pub use editor::Position;
 pub use editor::SearchDirection;
+pub use file_encoding::FileEncoding;
+pub use file_format::FileFormat;
 pub use filetype::FileType;
 pub use filetype::HighlightingOptions;
+pub use filetype::IndentOptions;
+pub use filetype_settings::FileTypeSettings;
+pub use finder::Finder;
+pub use history::History;
+pub use jump_list::JumpList;
+pub use keymap::Keymap;
+pub use line_range::LineRange;
+pub use motion::Motion;
+pub use register::Register;
+pub use register::Registers;
+pub use rope::Rope;
 pub use row::Row;
+pub use script::Scripts;
+pub use settings::Settings;
+pub use status_line::StatusLine;
+use std::env;
+use std::process;
+pub use substitute::Substitute;
+pub use suggestions::Suggestion;
+pub use suggestions::Suggestions;
+pub use tags::Tags;
 pub use terminal::Terminal;
+pub use text_object::TextObject;
+pub use theme::Theme;
+pub use user_commands::UserCommands;
 
 fn main() {
-    Editor::default().run();
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Invocation::Edit(options)) => options,
+        Ok(Invocation::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Invocation::Version) => {
+            println!("ggedit {}", editor::VERSION);
+            return;
+        }
+        Err(message) => {
+            eprint!("ggedit: {message}\n{}", cli::USAGE);
+            process::exit(2);
+        }
+    };
+    let script = match options.script.as_deref().map(cli::read_script).transpose() {
+        Ok(script) => script,
+        Err(message) => {
+            eprintln!("ggedit: {message}");
+            process::exit(2);
+        }
+    };
+    let mut editor = Editor::new(&options, script);
+    let Err(error) = editor.run() else {
+        return;
+    };
+    let recovered = editor.recover();
+    // Dropping the editor gives the terminal back its normal mode before
+    // anything is printed.
+    drop(editor);
+    eprintln!("ggedit: {error}");
+    for line in recovered {
+        eprintln!("{line}");
+    }
+    process::exit(1);
 }
@@ -0,0 +1,68 @@
+use crate::event::Event;
+use crate::quickfix::Entry;
+use crate::shell;
+use regex::Regex;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+// A build command that has finished, or couldn't be run.
+pub struct Build {
+    pub command: String,
+    pub result: Result<(bool, String), String>,
+}
+
+// Runs `command` away from the editor, which hears back once it is done.
+pub fn start(command: String, sender: Sender<Event>) {
+    thread::spawn(move || {
+        let _ = sender.send(Event::Make(run(command)));
+    });
+}
+
+pub fn run(command: String) -> Build {
+    let result = shell::capture(&command);
+    Build { command, result }
+}
+
+// The errors and warnings in the `output` of a build, like gcc's
+// `file:line:col: message`, or cargo's `--> file:line:col` under the
+// `error: message` it belongs to. Only files that exist count, so that
+// things like times aren't taken for places.
+pub fn parse(output: &str) -> Vec<Entry> {
+    let Ok(place) = Regex::new(
+        r"^\s*(?:--> )?(?P<file>[^:\s][^:]*):(?P<line>\d+)(?::(?P<column>\d+))?(?::\s*(?P<text>.*))?$",
+    ) else {
+        return Vec::new();
+    };
+    let mut entries = Vec::new();
+    let mut message = "";
+    for line in output.lines() {
+        if line.starts_with("error") || line.starts_with("warning") {
+            message = line;
+        }
+        let Some(captures) = place.captures(line) else {
+            continue;
+        };
+        let file = captures.name("file").map_or("", |file| file.as_str());
+        if !Path::new(file).is_file() {
+            continue;
+        }
+        let number = |name: &str| {
+            captures
+                .name(name)
+                .and_then(|number| number.as_str().parse::<usize>().ok())
+        };
+        let text = captures
+            .name("text")
+            .map(|text| text.as_str())
+            .filter(|text| !text.is_empty())
+            .unwrap_or(message);
+        entries.push(Entry {
+            file: file.to_string(),
+            line: number("line").unwrap_or(1),
+            column: number("column").unwrap_or(1),
+            text: text.to_string(),
+        });
+    }
+    entries
+}
@@ -0,0 +1,282 @@
+use crate::highlighting::Type;
+use crate::theme::Style;
+use crate::Theme;
+use termion::color;
+use termion::style;
+use unicode_width::UnicodeWidthChar;
+
+// What a line of a Markdown document is, as far as `:preview` cares.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Line {
+    // A ``` or ~~~ line opening or closing a code block.
+    Fence,
+    Code,
+    Text,
+}
+
+// What each of `lines` is. Code blocks run from one fence to the next made
+// of the same character.
+pub fn classify<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<Line> {
+    let mut fence: Option<char> = None;
+    lines
+        .into_iter()
+        .map(|line| {
+            let opening = ['`', '~']
+                .into_iter()
+                .find(|c| line.trim_start().starts_with(&c.to_string().repeat(3)));
+            match (fence, opening) {
+                (Some(open), Some(close)) if open == close => {
+                    fence = None;
+                    Line::Fence
+                }
+                (Some(_), _) => Line::Code,
+                (None, Some(open)) => {
+                    fence = Some(open);
+                    Line::Fence
+                }
+                (None, None) => Line::Text,
+            }
+        })
+        .collect()
+}
+
+// `line` drawn the way it reads rather than the way it is written, in at
+// most `width` columns: headings without their `#`s, emphasis without its
+// markers, bullets for list items and rules across the width.
+pub fn render(line: &str, kind: Line, width: usize, tabstop: usize, theme: &Theme) -> String {
+    let plain = Style::default();
+    let code = theme.highlight(&Type::String);
+    let faint = theme.highlight(&Type::Comment);
+    let mut spans: Vec<(String, Style)> = Vec::new();
+    match kind {
+        Line::Fence => {
+            let language = line.trim().trim_start_matches(['`', '~']).trim();
+            let label = if language.is_empty() {
+                String::new()
+            } else {
+                format!("── {language} ")
+            };
+            let rule = "─".repeat(width.saturating_sub(label.chars().count()));
+            spans.push((format!("{label}{rule}"), faint));
+        }
+        Line::Code => spans.push((line.to_string(), code)),
+        Line::Text => {
+            let indent = line.len().saturating_sub(line.trim_start().len());
+            let (leading, rest) = line.split_at(indent);
+            if is_rule(rest) {
+                spans.push(("─".repeat(width), faint));
+            } else if let Some((level, heading)) = heading(rest) {
+                let style = Style {
+                    bold: true,
+                    underline: level == 1,
+                    ..theme.highlight(&Type::PrimaryKeywords)
+                };
+                inline(heading, style, code, theme, &mut spans);
+            } else if let Some(quoted) = rest.strip_prefix('>') {
+                spans.push((format!("{leading}│ "), faint));
+                inline(
+                    quoted.strip_prefix(' ').unwrap_or(quoted),
+                    faint,
+                    code,
+                    theme,
+                    &mut spans,
+                );
+            } else if let Some((marker, item)) = list_item(rest) {
+                spans.push((leading.to_string(), plain));
+                spans.push((marker, theme.highlight(&Type::Number)));
+                inline(item, plain, code, theme, &mut spans);
+            } else {
+                spans.push((leading.to_string(), plain));
+                inline(rest, plain, code, theme, &mut spans);
+            }
+        }
+    }
+    fit(&spans, width, tabstop)
+}
+
+// Three or more `-`, `*` or `_`, with nothing else but spaces.
+fn is_rule(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| *c != ' ').collect();
+    marks.len() >= 3
+        && marks.first().is_some_and(|first| {
+            matches!(first, '-' | '*' | '_') && marks.iter().all(|c| c == first)
+        })
+}
+
+// The level and text of an ATX heading like `## Usage ##`.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let text = line.get(level..)?;
+    if !text.is_empty() && !text.starts_with(' ') {
+        return None;
+    }
+    Some((level, text.trim().trim_end_matches('#').trim_end()))
+}
+
+// What a list item starts with once drawn, and its text: `-`, `*` and `+`
+// become bullets, task boxes become check boxes and numbers stay.
+fn list_item(line: &str) -> Option<(String, &str)> {
+    if let Some(item) = ["- ", "* ", "+ "]
+        .into_iter()
+        .find_map(|marker| line.strip_prefix(marker))
+    {
+        for (task, check) in [("[ ] ", "☐ "), ("[x] ", "☑ "), ("[X] ", "☑ ")] {
+            if let Some(item) = item.strip_prefix(task) {
+                return Some((check.to_string(), item));
+            }
+        }
+        return Some((String::from("• "), item));
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = line.get(digits..)?;
+    let item = rest
+        .strip_prefix(". ")
+        .or_else(|| rest.strip_prefix(") "))?;
+    if digits == 0 {
+        return None;
+    }
+    Some((line.get(..digits.saturating_add(2))?.to_string(), item))
+}
+
+// Adds `text` to `spans` in `base`, with `**bold**`, `*italic*`, `` `code` ``
+// and `[links](url)` styled and their markers left out. Underscores only
+// count at the edges of words, so names like `snake_case` stay whole.
+fn inline(text: &str, base: Style, code: Style, theme: &Theme, spans: &mut Vec<(String, Style)>) {
+    let chars: Vec<char> = text.chars().collect();
+    let (mut bold, mut italic) = (false, false);
+    let mut index = 0;
+    let style = |bold: bool, italic: bool| Style {
+        bold: base.bold || bold,
+        italic: base.italic || italic,
+        ..base
+    };
+    let closes = |from: usize, marker: &[char]| {
+        (from..chars.len()).any(|at| chars.get(at..at.saturating_add(marker.len())) == Some(marker))
+    };
+    while let Some(&c) = chars.get(index) {
+        let next = chars.get(index.saturating_add(1)).copied();
+        let before = index.checked_sub(1).and_then(|at| chars.get(at)).copied();
+        let in_word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+        match c {
+            '\\' if next.is_some_and(|next| next.is_ascii_punctuation()) => {
+                spans.push((next.unwrap_or(c).to_string(), style(bold, italic)));
+                index = index.saturating_add(2);
+                continue;
+            }
+            '`' => {
+                let end =
+                    (index.saturating_add(1)..chars.len()).find(|at| chars.get(*at) == Some(&'`'));
+                if let Some(end) = end {
+                    let text: String = chars
+                        .get(index.saturating_add(1)..end)
+                        .unwrap_or_default()
+                        .iter()
+                        .collect();
+                    spans.push((text, code));
+                    index = end.saturating_add(1);
+                    continue;
+                }
+            }
+            '*' | '_' if next == Some(c) => {
+                let after = chars.get(index.saturating_add(2)).copied();
+                let edge = c == '*'
+                    || if bold {
+                        !in_word(after)
+                    } else {
+                        !in_word(before)
+                    };
+                if edge && (bold || closes(index.saturating_add(2), &[c, c])) {
+                    bold = !bold;
+                    index = index.saturating_add(2);
+                    continue;
+                }
+            }
+            '*' | '_' => {
+                let edge = c == '*'
+                    || if italic {
+                        !in_word(next)
+                    } else {
+                        !in_word(before)
+                    };
+                if edge && (italic || closes(index.saturating_add(1), &[c])) {
+                    italic = !italic;
+                    index = index.saturating_add(1);
+                    continue;
+                }
+            }
+            '[' => {
+                if let Some(link) = link(&chars, index) {
+                    let text: String = chars
+                        .get(index.saturating_add(1)..link.0)
+                        .unwrap_or_default()
+                        .iter()
+                        .collect();
+                    let style = Style {
+                        fg: theme.highlight(&Type::SecondaryKeywords).fg,
+                        underline: true,
+                        ..style(bold, italic)
+                    };
+                    spans.push((text, style));
+                    index = link.1;
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        spans.push((c.to_string(), style(bold, italic)));
+        index = index.saturating_add(1);
+    }
+}
+
+// Where the text of the link starting at `start` ends and where the link
+// itself does, for `[text](url)`.
+fn link(chars: &[char], start: usize) -> Option<(usize, usize)> {
+    let close = (start..chars.len()).find(|at| chars.get(*at) == Some(&']'))?;
+    if chars.get(close.saturating_add(1)) != Some(&'(') {
+        return None;
+    }
+    let end = (close..chars.len()).find(|at| chars.get(*at) == Some(&')'))?;
+    Some((close, end.saturating_add(1)))
+}
+
+// The spans drawn one after the other, cut off at `width` columns, with
+// tabs expanded to the next multiple of `tabstop`.
+fn fit(spans: &[(String, Style)], width: usize, tabstop: usize) -> String {
+    let mut result = String::new();
+    let mut current: Option<Style> = None;
+    let mut column = 0_usize;
+    'spans: for (text, style) in spans {
+        for c in text.chars() {
+            let cells = if c == '\t' {
+                tabstop.max(1).saturating_sub(column % tabstop.max(1))
+            } else {
+                c.width().unwrap_or(0)
+            };
+            if column.saturating_add(cells) > width {
+                break 'spans;
+            }
+            if current != Some(*style) {
+                current = Some(*style);
+                result.push_str(&style.escape());
+            }
+            if c == '\t' {
+                result.push_str(&" ".repeat(cells));
+            } else {
+                result.push(c);
+            }
+            column = column.saturating_add(cells);
+        }
+    }
+    result.push_str(&format!(
+        "{}{}{}{}{}",
+        color::Bg(color::Reset),
+        color::Fg(color::Reset),
+        style::NoUnderline,
+        style::NoFaint,
+        style::NoItalic,
+    ));
+    result
+}
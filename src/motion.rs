@@ -0,0 +1,468 @@
+use crate::Document;
+use crate::Position;
+use crate::Row;
+use crate::SearchDirection;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    // `^`, the first character of the line that isn't whitespace.
+    FirstNonBlank,
+    // `0` with `smarthome`: the first non-blank, or the start of the line
+    // when already there.
+    SmartLineStart,
+    LineEnd,
+    WordForward,
+    WordEnd,
+    WordBackward,
+    // `}` and `{`, to the blank line after or before a paragraph.
+    ParagraphForward,
+    ParagraphBackward,
+    // `)` and `(`, to the start of the next or current sentence.
+    SentenceForward,
+    SentenceBackward,
+    LastLine,
+    Line(usize),
+    Find(CharFind),
+    MatchingBracket,
+    Mark(char),
+    MarkLine(char),
+}
+
+// An `f`, `F`, `t` or `T` search for a character within the current row.
+#[derive(Copy, Clone, PartialEq)]
+pub struct CharFind {
+    pub c: char,
+    pub direction: SearchDirection,
+    pub till: bool,
+    // Repeating `t` with `;` must not get stuck right before the last match.
+    pub repeat: bool,
+}
+
+#[derive(PartialEq)]
+pub enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
+impl CharClass {
+    pub fn of(c: char) -> Self {
+        if c.is_whitespace() {
+            Self::Space
+        } else if c.is_alphanumeric() || c == '_' {
+            Self::Word
+        } else {
+            Self::Punctuation
+        }
+    }
+}
+
+impl Motion {
+    // Linewise motions make operators act on whole rows.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Self::Up | Self::Down | Self::LastLine | Self::Line(_) | Self::MarkLine(_)
+        )
+    }
+
+    // Jumps are remembered in the jump list when used on their own.
+    pub fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Self::LastLine
+                | Self::Line(_)
+                | Self::Mark(_)
+                | Self::MarkLine(_)
+                | Self::MatchingBracket
+                | Self::ParagraphForward
+                | Self::ParagraphBackward
+                | Self::SentenceForward
+                | Self::SentenceBackward
+        )
+    }
+
+    // Inclusive motions make operators include the character under the target.
+    pub fn is_inclusive(self) -> bool {
+        match self {
+            Self::WordEnd | Self::MatchingBracket => true,
+            Self::Find(find) => find.direction == SearchDirection::Forward,
+            _ => false,
+        }
+    }
+
+    pub fn target(self, document: &Document, at: &Position, count: usize) -> Position {
+        let mut chars = RowChars::new(document);
+        let mut position = at.clone();
+        for step in 0..count.max(1) {
+            let motion = match self {
+                Self::Find(find) if step > 0 => Self::Find(CharFind {
+                    repeat: true,
+                    ..find
+                }),
+                _ => self,
+            };
+            let next = motion.step(&mut chars, &position);
+            if next.x == position.x && next.y == position.y {
+                break;
+            }
+            position = next;
+        }
+        position
+    }
+
+    fn step(self, chars: &mut RowChars, at: &Position) -> Position {
+        let document = chars.document;
+        let Position { x, y } = *at;
+        let last_row = document.len().saturating_sub(1);
+        match self {
+            Self::Left => Position {
+                x: x.saturating_sub(1),
+                y,
+            },
+            Self::Right => Position {
+                x: x.saturating_add(1).min(row_len(document, y)),
+                y,
+            },
+            Self::Up => Position {
+                x,
+                y: y.saturating_sub(1),
+            },
+            Self::Down => Position {
+                x,
+                y: y.saturating_add(1).min(last_row),
+            },
+            Self::LineStart => Position { x: 0, y },
+            Self::FirstNonBlank => Position {
+                x: first_non_blank(document, y),
+                y,
+            },
+            Self::SmartLineStart => Position {
+                x: match first_non_blank(document, y) {
+                    first if first == x => 0,
+                    first => first,
+                },
+                y,
+            },
+            Self::LineEnd => Position {
+                x: row_len(document, y),
+                y,
+            },
+            Self::LastLine => Position {
+                x: first_non_blank(document, last_row),
+                y: last_row,
+            },
+            Self::Line(y) => Position {
+                x: first_non_blank(document, y.min(last_row)),
+                y: y.min(last_row),
+            },
+            Self::WordForward => word_forward(chars, at),
+            Self::WordEnd => word_end(chars, at),
+            Self::WordBackward => word_backward(chars, at),
+            Self::ParagraphForward => (y.saturating_add(1)..document.len())
+                .find(|&y| is_blank(document, y) && !is_blank(document, y.saturating_sub(1)))
+                .map_or_else(
+                    || Position {
+                        x: row_len(document, last_row),
+                        y: last_row,
+                    },
+                    |y| Position { x: 0, y },
+                ),
+            Self::ParagraphBackward => (0..y)
+                .rev()
+                .find(|&y| is_blank(document, y) && !is_blank(document, y.saturating_add(1)))
+                .map_or_else(Position::default, |y| Position { x: 0, y }),
+            Self::SentenceForward => {
+                let mut position = at.clone();
+                while let Some(next) = next(document, &position) {
+                    position = next;
+                    if is_sentence_start(chars, &position) {
+                        break;
+                    }
+                }
+                position
+            }
+            Self::SentenceBackward => {
+                let mut position = at.clone();
+                while let Some(previous) = previous(document, &position) {
+                    position = previous;
+                    if is_sentence_start(chars, &position) {
+                        break;
+                    }
+                }
+                position
+            }
+            Self::Find(find) => document
+                .row(y)
+                .and_then(|row| find.target(row, x))
+                .map_or_else(|| at.clone(), |x| Position { x, y }),
+            // Like `%`, use the first bracket at or after the cursor
+            Self::MatchingBracket => document
+                .row(y)
+                .and_then(|row| {
+                    row.chars()
+                        .iter()
+                        .skip(x)
+                        .position(|c| "()[]{}".contains(*c))
+                })
+                .and_then(|offset| {
+                    document.matching_bracket(&Position {
+                        x: x.saturating_add(offset),
+                        y,
+                    })
+                })
+                .unwrap_or_else(|| at.clone()),
+            Self::Mark(name) => document.mark(name).cloned().unwrap_or_else(|| at.clone()),
+            Self::MarkLine(name) => document.mark(name).map_or_else(
+                || at.clone(),
+                |mark| Position {
+                    x: first_non_blank(document, mark.y),
+                    y: mark.y,
+                },
+            ),
+        }
+    }
+}
+
+impl CharFind {
+    pub fn reversed(self) -> Self {
+        Self {
+            direction: match self.direction {
+                SearchDirection::Forward => SearchDirection::Backward,
+                SearchDirection::Backward => SearchDirection::Forward,
+            },
+            ..self
+        }
+    }
+
+    fn target(self, row: &Row, x: usize) -> Option<usize> {
+        let forward = self.direction == SearchDirection::Forward;
+        let from = match (self.till && self.repeat, forward) {
+            (true, true) => x.saturating_add(1),
+            (true, false) => x.saturating_sub(1),
+            (false, _) => x,
+        };
+        let found = row.find_char(self.c, from, self.direction)?;
+        match (self.till, forward) {
+            (true, true) => Some(found.saturating_sub(1)),
+            (true, false) => Some(found.saturating_add(1)),
+            (false, _) => Some(found),
+        }
+    }
+}
+
+pub fn row_len(document: &Document, y: usize) -> usize {
+    document.row(y).map_or(0, Row::len)
+}
+
+pub fn first_non_blank(document: &Document, y: usize) -> usize {
+    document.row(y).map_or(0, |row| {
+        row.chars()
+            .iter()
+            .position(|c| !c.is_whitespace())
+            .unwrap_or_else(|| row.len())
+    })
+}
+
+// Treats the end of every row as a newline so word motions can cross rows.
+pub fn char_at(document: &Document, at: &Position) -> char {
+    document
+        .row(at.y)
+        .and_then(|row| row.char_at(at.x))
+        .unwrap_or('\n')
+}
+
+// The characters of one row at a time, for motions that look at them one
+// after the other. A row is only collected again once they move off it.
+struct RowChars<'a> {
+    document: &'a Document,
+    y: Option<usize>,
+    chars: Vec<char>,
+}
+
+impl<'a> RowChars<'a> {
+    fn new(document: &'a Document) -> Self {
+        Self {
+            document,
+            y: None,
+            chars: Vec::new(),
+        }
+    }
+
+    // Like `char_at`.
+    fn at(&mut self, at: &Position) -> char {
+        if self.y != Some(at.y) {
+            self.y = Some(at.y);
+            self.chars = self.document.row(at.y).map(Row::chars).unwrap_or_default();
+        }
+        self.chars.get(at.x).copied().unwrap_or('\n')
+    }
+}
+
+pub fn is_empty_line(document: &Document, at: &Position) -> bool {
+    at.x == 0 && row_len(document, at.y) == 0
+}
+
+pub fn next(document: &Document, at: &Position) -> Option<Position> {
+    if at.x < row_len(document, at.y) {
+        Some(Position {
+            x: at.x.saturating_add(1),
+            y: at.y,
+        })
+    } else if at.y.saturating_add(1) < document.len() {
+        Some(Position {
+            x: 0,
+            y: at.y.saturating_add(1),
+        })
+    } else {
+        None
+    }
+}
+
+pub fn previous(document: &Document, at: &Position) -> Option<Position> {
+    if at.x > 0 {
+        Some(Position {
+            x: at.x.saturating_sub(1),
+            y: at.y,
+        })
+    } else if at.y > 0 {
+        let y = at.y.saturating_sub(1);
+        Some(Position {
+            x: row_len(document, y),
+            y,
+        })
+    } else {
+        None
+    }
+}
+
+// Lines of nothing but whitespace separate paragraphs, as for `ip`.
+fn is_blank(document: &Document, y: usize) -> bool {
+    document
+        .row(y)
+        .is_none_or(|row| row.as_str().trim().is_empty())
+}
+
+// Sentences start after a `.`, `!` or `?`, maybe followed by closing
+// brackets or quotes, and then whitespace, and at the start of a paragraph.
+// The blank line before a paragraph counts as a sentence of its own.
+fn is_sentence_start(chars: &mut RowChars, at: &Position) -> bool {
+    let document = chars.document;
+    if is_empty_line(document, at) {
+        return at.y == 0 || !is_blank(document, at.y.saturating_sub(1));
+    }
+    if chars.at(at).is_whitespace() {
+        return false;
+    }
+    let mut position = at.clone();
+    let mut spaced = false;
+    loop {
+        let Some(previous) = previous(document, &position) else {
+            return true;
+        };
+        position = previous;
+        if is_blank(document, position.y) {
+            return true;
+        }
+        if !chars.at(&position).is_whitespace() {
+            break;
+        }
+        spaced = true;
+    }
+    while ")]\"'".contains(chars.at(&position)) {
+        match previous(document, &position) {
+            Some(previous) if previous.y == position.y => position = previous,
+            _ => return false,
+        }
+    }
+    spaced && ".!?".contains(chars.at(&position))
+}
+
+fn word_forward(chars: &mut RowChars, at: &Position) -> Position {
+    let document = chars.document;
+    let class = CharClass::of(chars.at(at));
+    let mut position = at.clone();
+    let mut crossed_space = false;
+    while let Some(next) = next(document, &position) {
+        position = next;
+        if is_empty_line(document, &position) {
+            return position;
+        }
+        let current = CharClass::of(chars.at(&position));
+        if current == CharClass::Space {
+            crossed_space = true;
+        } else if crossed_space || current != class {
+            return position;
+        }
+    }
+    position
+}
+
+fn word_end(chars: &mut RowChars, at: &Position) -> Position {
+    let document = chars.document;
+    let Some(mut position) = next(document, at) else {
+        return at.clone();
+    };
+    while CharClass::of(chars.at(&position)) == CharClass::Space {
+        match next(document, &position) {
+            Some(next) => position = next,
+            None => return position,
+        }
+    }
+    let class = CharClass::of(chars.at(&position));
+    while let Some(next) = next(document, &position) {
+        if CharClass::of(chars.at(&next)) != class {
+            break;
+        }
+        position = next;
+    }
+    position
+}
+
+fn word_backward(chars: &mut RowChars, at: &Position) -> Position {
+    let document = chars.document;
+    let Some(mut position) = previous(document, at) else {
+        return at.clone();
+    };
+    while CharClass::of(chars.at(&position)) == CharClass::Space {
+        if is_empty_line(document, &position) {
+            return position;
+        }
+        match previous(document, &position) {
+            Some(previous) => position = previous,
+            None => return position,
+        }
+    }
+    let class = CharClass::of(chars.at(&position));
+    while let Some(previous) = previous(document, &position) {
+        if CharClass::of(chars.at(&previous)) != class {
+            break;
+        }
+        position = previous;
+    }
+    position
+}
+
+// `cw` changes up to the end of the word rather than the start of the next
+// one, unless the cursor sits on whitespace.
+pub fn change_word_end(document: &Document, at: &Position, count: usize) -> Option<Position> {
+    if CharClass::of(char_at(document, at)) == CharClass::Space {
+        return None;
+    }
+    let mut position = Motion::WordForward.target(document, at, count);
+    while let Some(previous) = previous(document, &position) {
+        if (previous.y, previous.x) < (at.y, at.x) {
+            break;
+        }
+        position = previous;
+        if CharClass::of(char_at(document, &position)) != CharClass::Space {
+            break;
+        }
+    }
+    Some(position)
+}
@@ -0,0 +1,96 @@
+use termion::color;
+use unicode_width::UnicodeWidthChar;
+
+// Lays `layer` over the rows of a frame with its top left corner at row
+// `top`, column `left`. The rows keep what shows on either side of it.
+pub fn draw(lines: &mut [String], layer: &[String], top: usize, left: usize) {
+    for (index, part) in layer.iter().enumerate() {
+        if let Some(line) = lines.get_mut(top.saturating_add(index)) {
+            *line = splice(line, part, left);
+        }
+    }
+}
+
+// The columns a line takes on screen, leaving out its escape codes.
+pub fn width(line: &str) -> usize {
+    pieces(line).map(|(_, width)| width).sum()
+}
+
+// The start of a line that fits in `width` columns, cut between characters.
+pub fn truncate(line: &str, width: usize) -> &str {
+    let mut column = 0_usize;
+    let mut end = 0_usize;
+    for (piece, piece_width) in pieces(line) {
+        column = column.saturating_add(piece_width);
+        if column > width {
+            break;
+        }
+        end = end.saturating_add(piece.len());
+    }
+    line.get(..end).unwrap_or(line)
+}
+
+// The escape codes and characters of a line, with how many columns each
+// takes.
+pub fn pieces(line: &str) -> impl Iterator<Item = (&str, usize)> {
+    let mut rest = line;
+    std::iter::from_fn(move || {
+        let c = rest.chars().next()?;
+        let len = if c == '\x1b' && rest.get(1..2) == Some("[") {
+            // A CSI sequence runs up to its final byte, in `@` to `~`.
+            rest.bytes()
+                .skip(2)
+                .position(|byte| (b'@'..=b'~').contains(&byte))
+                .map_or(rest.len(), |end| end.saturating_add(3))
+        } else {
+            c.len_utf8()
+        };
+        let (piece, after) = rest.split_at(len);
+        rest = after;
+        let width = if c == '\x1b' {
+            0
+        } else {
+            c.width().unwrap_or(0)
+        };
+        Some((piece, width))
+    })
+}
+
+// Replaces the columns of `line` under `part` by it. The colors in effect
+// where `part` ends are set again after it, and wide characters cut in two
+// become spaces.
+fn splice(line: &str, part: &str, left: usize) -> String {
+    let right = left.saturating_add(width(part));
+    let reset = format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset));
+    let mut before = String::new();
+    let mut covered_codes = String::new();
+    let mut after = String::new();
+    let mut column = 0_usize;
+    for (piece, piece_width) in pieces(line) {
+        let end = column.saturating_add(piece_width);
+        if piece_width == 0 {
+            if column < left {
+                before.push_str(piece);
+            } else if column >= right {
+                after.push_str(piece);
+            } else if piece.starts_with('\x1b') {
+                covered_codes.push_str(piece);
+            }
+        } else if end <= left {
+            before.push_str(piece);
+        } else if column >= right {
+            after.push_str(piece);
+        } else {
+            if column < left {
+                before.push_str(&" ".repeat(left.saturating_sub(column)));
+            }
+            if end > right {
+                after.push_str(&" ".repeat(end.saturating_sub(right)));
+            }
+        }
+        column = end;
+    }
+    // Lines too short to reach the layer are padded out to it.
+    before.push_str(&" ".repeat(left.saturating_sub(column)));
+    format!("{before}{reset}{part}{reset}{covered_codes}{after}")
+}
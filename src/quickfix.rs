@@ -0,0 +1,63 @@
+// A place in a file to go to, like a line that `:grep` found, with
+// one-based line and column numbers.
+pub struct Entry {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+// The results of the last `:grep`, gone through with `:cnext` and `:cprev`.
+#[derive(Default)]
+pub struct Quickfix {
+    pub title: String,
+    entries: Vec<Entry>,
+    current: usize,
+}
+
+impl Quickfix {
+    pub fn new(title: String, entries: Vec<Entry>) -> Self {
+        Self {
+            title,
+            entries,
+            current: 0,
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&Entry> {
+        self.entries.get(self.current)
+    }
+
+    // Moves `count` entries forward, or back when `forward` is false,
+    // stopping at either end. Returns the new entry, or `None` when already
+    // at the end.
+    pub fn step(&mut self, forward: bool, count: usize) -> Option<&Entry> {
+        let last = self.entries.len().saturating_sub(1);
+        let next = if forward {
+            self.current.saturating_add(count).min(last)
+        } else {
+            self.current.saturating_sub(count)
+        };
+        if next == self.current {
+            return None;
+        }
+        self.current = next;
+        self.current()
+    }
+}
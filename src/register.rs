@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+#[derive(Default, Clone)]
+pub struct Register {
+    pub lines: Vec<String>,
+    pub linewise: bool,
+}
+
+#[derive(Default)]
+pub struct Registers {
+    unnamed: Register,
+    named: HashMap<char, Register>,
+}
+
+impl Register {
+    pub fn linewise(lines: Vec<String>) -> Self {
+        Self {
+            lines,
+            linewise: true,
+        }
+    }
+
+    pub fn charwise(text: &str) -> Self {
+        Self {
+            lines: text.split('\n').map(String::from).collect(),
+            linewise: false,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+}
+
+impl Registers {
+    pub fn is_valid(name: char) -> bool {
+        name == '"' || name.is_ascii_alphabetic()
+    }
+
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        match name {
+            None | Some('"') => Some(&self.unnamed),
+            Some(name) => self.named.get(&name.to_ascii_lowercase()),
+        }
+    }
+
+    // Writing to an uppercase register appends to its lowercase counterpart,
+    // and every write also updates the unnamed register.
+    pub fn set(&mut self, name: Option<char>, register: Register) {
+        let register = match name {
+            Some(name) if name.is_ascii_uppercase() => {
+                let name = name.to_ascii_lowercase();
+                let entry = self.named.entry(name).or_default();
+                let mut lines = register.lines.into_iter();
+                if !entry.linewise && !register.linewise {
+                    if let Some(last) = entry.lines.last_mut() {
+                        last.push_str(&lines.next().unwrap_or_default());
+                    }
+                }
+                entry.lines.extend(lines);
+                entry.linewise |= register.linewise;
+                entry.clone()
+            }
+            Some(name) if name.is_ascii_lowercase() => {
+                self.named.insert(name, register.clone());
+                register
+            }
+            _ => register,
+        };
+        self.unnamed = register;
+    }
+
+    pub fn list(&self) -> Vec<(char, &Register)> {
+        let mut registers = vec![('"', &self.unnamed)];
+        let mut named: Vec<(char, &Register)> = self
+            .named
+            .iter()
+            .map(|(name, register)| (*name, register))
+            .collect();
+        named.sort_by_key(|(name, _)| *name);
+        registers.extend(named);
+        registers
+    }
+}
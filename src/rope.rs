@@ -0,0 +1,217 @@
+use crate::Row;
+use std::ops::{Index, IndexMut};
+
+// Rows are kept in chunks of bounded size, so inserting or removing a row
+// only shifts the rows of one chunk instead of the whole document, and
+// loading a large file never needs one huge contiguous allocation.
+const MAX_CHUNK_LEN: usize = 1024;
+
+#[derive(Default)]
+pub struct Rope {
+    chunks: Vec<Vec<Row>>,
+    // The row each chunk starts at, so finding a row is a binary search
+    // rather than a walk over the chunks.
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl FromIterator<Row> for Rope {
+    fn from_iter<I: IntoIterator<Item = Row>>(rows: I) -> Self {
+        let mut rope = Self::default();
+        for row in rows {
+            rope.push(row);
+        }
+        rope
+    }
+}
+
+impl Rope {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, y: usize) -> Option<&Row> {
+        let (chunk, index) = self.locate(y)?;
+        self.chunks.get(chunk)?.get(index)
+    }
+
+    pub fn get_mut(&mut self, y: usize) -> Option<&mut Row> {
+        let (chunk, index) = self.locate(y)?;
+        self.chunks.get_mut(chunk)?.get_mut(index)
+    }
+
+    pub fn push(&mut self, row: Row) {
+        match self.chunks.last_mut() {
+            Some(chunk) if chunk.len() < MAX_CHUNK_LEN => chunk.push(row),
+            _ => {
+                let mut chunk = Vec::with_capacity(MAX_CHUNK_LEN);
+                chunk.push(row);
+                self.chunks.push(chunk);
+                self.starts.push(self.len);
+            }
+        }
+        self.len = self.len.saturating_add(1);
+    }
+
+    // Inserting at `len` appends.
+    pub fn insert(&mut self, y: usize, row: Row) {
+        if y >= self.len {
+            self.push(row);
+            return;
+        }
+        let Some((chunk, index)) = self.locate(y) else {
+            return;
+        };
+        let Some(rows) = self.chunks.get_mut(chunk) else {
+            return;
+        };
+        rows.insert(index, row);
+        if rows.len() > MAX_CHUNK_LEN {
+            let half = rows.split_off(rows.len() / 2);
+            let start = self.starts.get(chunk).copied().unwrap_or(0);
+            self.starts
+                .insert(chunk.saturating_add(1), start.saturating_add(rows.len()));
+            self.chunks.insert(chunk.saturating_add(1), half);
+            self.shift_starts(chunk.saturating_add(2), |start| start.saturating_add(1));
+        } else {
+            self.shift_starts(chunk.saturating_add(1), |start| start.saturating_add(1));
+        }
+        self.len = self.len.saturating_add(1);
+    }
+
+    pub fn remove(&mut self, y: usize) -> Option<Row> {
+        let (chunk, index) = self.locate(y)?;
+        let rows = self.chunks.get_mut(chunk)?;
+        let row = rows.remove(index);
+        if rows.is_empty() {
+            self.chunks.remove(chunk);
+            self.starts.remove(chunk);
+            self.shift_starts(chunk, |start| start.saturating_sub(1));
+        } else {
+            self.shift_starts(chunk.saturating_add(1), |start| start.saturating_sub(1));
+        }
+        self.len = self.len.saturating_sub(1);
+        Some(row)
+    }
+
+    // Replaces up to `count` rows starting at `y` with `rows`. The chunks
+    // the replaced rows were in are split where they start and end, swapped
+    // for chunks of the new rows all at once, and small chunks left at the
+    // seams are joined with their neighbours.
+    pub fn splice(&mut self, y: usize, count: usize, rows: impl IntoIterator<Item = Row>) {
+        let y = y.min(self.len);
+        let end = y.saturating_add(count).min(self.len);
+        let first = self.split_at(y);
+        let last = self.split_at(end);
+        let mut added = Vec::new();
+        let mut rows = rows.into_iter().peekable();
+        while rows.peek().is_some() {
+            added.push(rows.by_ref().take(MAX_CHUNK_LEN).collect::<Vec<Row>>());
+        }
+        let added_len = added.len();
+        self.chunks.splice(first..last, added);
+        self.join_small(
+            first.saturating_sub(1),
+            first.saturating_add(added_len).saturating_add(1),
+        );
+        self.reindex();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Row> {
+        self.chunks.iter().flatten()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Row> {
+        self.chunks.iter_mut().flatten()
+    }
+
+    // The chunk holding row `y` and the row's index within it.
+    fn locate(&self, y: usize) -> Option<(usize, usize)> {
+        if y >= self.len {
+            return None;
+        }
+        let chunk = self
+            .starts
+            .partition_point(|start| *start <= y)
+            .checked_sub(1)?;
+        let start = self.starts.get(chunk)?;
+        Some((chunk, y.saturating_sub(*start)))
+    }
+
+    // Makes row `y` the first of a chunk and returns that chunk, or the
+    // number of chunks for the end of the rope.
+    fn split_at(&mut self, y: usize) -> usize {
+        let Some((chunk, index)) = self.locate(y) else {
+            return self.chunks.len();
+        };
+        if index == 0 {
+            return chunk;
+        }
+        let Some(rows) = self.chunks.get_mut(chunk) else {
+            return self.chunks.len();
+        };
+        let tail = rows.split_off(index);
+        self.chunks.insert(chunk.saturating_add(1), tail);
+        self.starts.insert(chunk.saturating_add(1), y);
+        chunk.saturating_add(1)
+    }
+
+    // Joins neighbouring chunks among `from..to` that fit in one together.
+    fn join_small(&mut self, from: usize, to: usize) {
+        let mut chunk = from;
+        let mut to = to.min(self.chunks.len());
+        while chunk.saturating_add(1) < to {
+            let next = chunk.saturating_add(1);
+            let fits = match (self.chunks.get(chunk), self.chunks.get(next)) {
+                (Some(rows), Some(next_rows)) => {
+                    rows.len().saturating_add(next_rows.len()) <= MAX_CHUNK_LEN
+                }
+                _ => false,
+            };
+            if fits {
+                let next_rows = self.chunks.remove(next);
+                if let Some(rows) = self.chunks.get_mut(chunk) {
+                    rows.extend(next_rows);
+                }
+                to = to.saturating_sub(1);
+            } else {
+                chunk = next;
+            }
+        }
+    }
+
+    // Works out where every chunk starts again after chunks were replaced.
+    fn reindex(&mut self) {
+        self.starts.clear();
+        let mut start = 0_usize;
+        for rows in &self.chunks {
+            self.starts.push(start);
+            start = start.saturating_add(rows.len());
+        }
+        self.len = start;
+    }
+
+    fn shift_starts(&mut self, from: usize, shift: impl Fn(usize) -> usize) {
+        for start in self.starts.iter_mut().skip(from) {
+            *start = shift(*start);
+        }
+    }
+}
+
+impl Index<usize> for Rope {
+    type Output = Row;
+
+    fn index(&self, y: usize) -> &Row {
+        self.get(y).expect("row index out of bounds")
+    }
+}
+
+impl IndexMut<usize> for Rope {
+    fn index_mut(&mut self, y: usize) -> &mut Row {
+        self.get_mut(y).expect("row index out of bounds")
+    }
+}
@@ -1,12 +1,55 @@
-use crate::{highlighting, HighlightingOptions, SearchDirection};
+use crate::highlighting::{self, Context};
+use crate::theme::Style;
+use crate::{HighlightingOptions, SearchDirection, Settings, Theme};
+use regex::Regex;
 use std::cmp;
-use termion::color;
+use std::iter;
+use termion::color::{self, Rgb};
+use termion::style;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// Words that stand out when they're part of a comment.
+const TODO_MARKERS: [&str; 4] = ["TODO", "FIXME", "XXX", "NOTE"];
+
+// Backgrounds drawn behind a row where its highlighting leaves the
+// terminal's: `line` across the whole width, for the cursor line, and
+// `column` at the display columns of `columns`, for `colorcolumn`.
+#[derive(Default)]
+pub struct Backdrop<'a> {
+    pub line: Option<Rgb>,
+    pub column: Option<Rgb>,
+    pub columns: &'a [usize],
+}
+
+impl Backdrop<'_> {
+    // `style` over the backdrop of the cells from `start` to `end`.
+    fn behind(&self, style: Style, start: usize, end: usize) -> Style {
+        if style.bg.is_some() {
+            return style;
+        }
+        let bg = if self
+            .columns
+            .iter()
+            .any(|guide| (start..end).contains(guide))
+        {
+            self.column.or(self.line)
+        } else {
+            self.line
+        };
+        Style { bg, ..style }
+    }
+}
 
 #[derive(Default)]
 pub struct Row {
     string: String,
     highlighting: Vec<highlighting::Type>,
+    // The highlighting generation `highlighting` was computed for, or None
+    // once the row has changed since.
+    highlighted: Option<u64>,
+    start_context: Context,
+    end_context: Context,
     len: usize,
 }
 
@@ -15,75 +58,187 @@ impl From<&str> for Row {
         Self {
             string: String::from(slice),
             highlighting: Vec::new(),
+            highlighted: None,
+            start_context: Context::Normal,
+            end_context: Context::Normal,
             len: slice.graphemes(true).count(),
         }
     }
 }
 
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
+    // `start` and `end` are display columns, so tabs expand to the next
+    // multiple of `tabstop`. `overlay` temporarily overrides the highlighting
+    // of single graphemes, e.g. to show the bracket matching the one under the
+    // cursor, and `backdrop` colors what has no background of its own. With
+    // `list`, tabs, trailing spaces and the end of the line show up as `»···`,
+    // `·` and `¬`.
+    pub fn render(
+        &self,
+        start: usize,
+        end: usize,
+        settings: &Settings,
+        overlay: &[(usize, highlighting::Type)],
+        backdrop: &Backdrop,
+        theme: &Theme,
+    ) -> String {
         let mut result = String::new();
-        let mut current_highlighting = &highlighting::Type::None;
-        #[allow(clippy::integer_arithmetic)]
-        for (index, grapheme) in self.string[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-        {
+        let mut current_style = Style::default();
+        let mut set_style = |result: &mut String, style: Style| {
+            if style != current_style {
+                current_style = style;
+                result.push_str(&style.escape());
+            }
+        };
+        let tabstop = settings.tabstop;
+        let trailing = self.string.trim_end().graphemes(true).count();
+        let invisible = |style: Style| Style {
+            fg: theme.invisible.fg,
+            ..style
+        };
+        let mut column = 0_usize;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if column >= end {
+                break;
+            }
+            let width = grapheme_width(grapheme, column, tabstop);
+            let tab_start = column;
+            let column_end = column.saturating_add(width);
+            let visible_start = cmp::max(column, start);
+            let visible_end = cmp::min(column_end, end);
+            column = column_end;
+            if visible_start >= visible_end {
+                continue;
+            }
             if let Some(c) = grapheme.chars().next() {
-                let highlighting_type = self
+                let highlighted = self
                     .highlighting
                     .get(index)
                     .unwrap_or(&highlighting::Type::None);
+                let style = match overlay
+                    .iter()
+                    .find(|(overlay_index, _)| *overlay_index == index)
+                {
+                    Some((_, highlighting::Type::WordMatch)) => Style {
+                        bg: theme.highlight(&highlighting::Type::WordMatch).bg,
+                        ..theme.highlight(highlighted)
+                    },
+                    Some((_, overlay_type)) => theme.highlight(overlay_type),
+                    None => theme.highlight(highlighted),
+                };
 
-                if highlighting_type != current_highlighting {
-                    current_highlighting = highlighting_type;
-                    let start_highlight: String = highlighting_type.to_string();
-                    result.push_str(&start_highlight[..]);
-                }
-
-                if c == '\t' {
-                    result.push_str(" ");
+                // Tabs, and wide characters cut off by the edge of the
+                // screen, are drawn as spaces, one cell at a time so a
+                // color column can fall in between.
+                if c == '\t' && settings.list {
+                    for cell in visible_start..visible_end {
+                        let style = backdrop.behind(style, cell, cell.saturating_add(1));
+                        set_style(&mut result, invisible(style));
+                        result.push(if cell == tab_start { '»' } else { '·' });
+                    }
+                } else if c == ' ' && settings.list && index >= trailing {
+                    let style = backdrop.behind(style, visible_start, visible_end);
+                    set_style(&mut result, invisible(style));
+                    result.push('·');
+                } else if c == '\t' || visible_end.saturating_sub(visible_start) < width {
+                    for cell in visible_start..visible_end {
+                        set_style(
+                            &mut result,
+                            backdrop.behind(style, cell, cell.saturating_add(1)),
+                        );
+                        result.push(' ');
+                    }
                 } else {
-                    result.push(c);
+                    set_style(
+                        &mut result,
+                        backdrop.behind(style, visible_start, visible_end),
+                    );
+                    result.push_str(grapheme);
                 }
             }
         }
+        if settings.list && (start..end).contains(&column) {
+            let style = backdrop.behind(Style::default(), column, column.saturating_add(1));
+            set_style(&mut result, invisible(style));
+            result.push('¬');
+            column = column.saturating_add(1);
+        }
+        // The backdrop goes on past the end of the text.
+        let last_column = if backdrop.line.is_some() {
+            end
+        } else {
+            backdrop
+                .columns
+                .iter()
+                .filter(|&&guide| guide < end)
+                .max()
+                .map_or(0, |guide| guide.saturating_add(1))
+        };
+        for cell in cmp::max(column, start)..last_column {
+            set_style(
+                &mut result,
+                backdrop.behind(Style::default(), cell, cell.saturating_add(1)),
+            );
+            result.push(' ');
+        }
         let end_highlight: String = format!(
-            "{}{}",
+            "{}{}{}{}{}",
             color::Bg(color::Reset).to_string(),
             color::Fg(color::Reset).to_string(),
+            style::NoUnderline,
+            style::NoFaint,
+            style::NoItalic,
         );
         result.push_str(&end_highlight[..]);
         result
     }
+
+    // The display column at which the grapheme at `at` starts.
+    pub fn column(&self, at: usize, tabstop: usize) -> usize {
+        self.string[..]
+            .graphemes(true)
+            .take(at)
+            .fold(0, |column, grapheme| {
+                column.saturating_add(grapheme_width(grapheme, column, tabstop))
+            })
+    }
+
+    // The grapheme drawn at display column `column`, or the length of the row
+    // when the column is past its end.
+    pub fn index_at(&self, column: usize, tabstop: usize) -> usize {
+        let mut start = 0_usize;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            start = start.saturating_add(grapheme_width(grapheme, start, tabstop));
+            if start > column {
+                return index;
+            }
+        }
+        self.len
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
     pub fn is_empty(&self) -> bool {
         self.len == 0
     }
+    // A combining character joins the grapheme before it, so the length is
+    // recounted rather than assumed to grow by one.
     pub fn insert(&mut self, at: usize, c: char) {
         if at >= self.len() {
             self.string.push(c);
-            self.len += 1;
-            return;
-        }
-        let mut result: String = String::new();
-        let mut length = 0;
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            length += 1;
-            if index == at {
-                length += 1;
-                result.push(c);
+        } else {
+            let mut result: String = String::new();
+            for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+                if index == at {
+                    result.push(c);
+                }
+                result.push_str(grapheme);
             }
-            result.push_str(grapheme);
+            self.string = result;
         }
-        self.len = length;
-        self.string = result;
+        self.len = self.string[..].graphemes(true).count();
+        self.highlighted = None;
     }
     pub fn delete(&mut self, at: usize) {
         if at >= self.len() {
@@ -98,49 +253,113 @@ impl Row {
             }
         }
         self.len = length;
+        self.highlighted = None;
         self.string = result;
     }
 
+    // Overwrites the grapheme at `at`, or appends when past the end, and
+    // returns whatever was overwritten.
+    pub fn replace(&mut self, at: usize, grapheme: &str) -> Option<String> {
+        let mut replaced = None;
+        let mut result: String = String::new();
+        for (index, current) in self.string[..].graphemes(true).enumerate() {
+            if index == at {
+                replaced = Some(current.to_string());
+                result.push_str(grapheme);
+            } else {
+                result.push_str(current);
+            }
+        }
+        if replaced.is_none() {
+            result.push_str(grapheme);
+        }
+        self.len = result[..].graphemes(true).count();
+        self.highlighted = None;
+        self.string = result;
+        replaced
+    }
+
     pub fn append(&mut self, new: &Self) {
         self.string = format!("{}{}", self.string, new.string);
-        self.len += new.len;
+        self.len = self.string[..].graphemes(true).count();
+        self.highlighted = None;
+    }
+
+    pub fn find(&self, pattern: &Regex, at: usize, direction: SearchDirection) -> Option<usize> {
+        self.find_match(pattern, at, direction)
+            .map(|(start, _)| start)
     }
 
-    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
-        if at > self.len || query.is_empty() {
+    // The graphemes spanned by the nearest match starting at or after `at`,
+    // or before it when searching backward. A match starting inside a
+    // grapheme, e.g. on a combining accent, doesn't count.
+    pub fn find_match(
+        &self,
+        pattern: &Regex,
+        at: usize,
+        direction: SearchDirection,
+    ) -> Option<(usize, usize)> {
+        if at > self.len {
             return None;
         }
-        let start = if direction == SearchDirection::Forward {
-            at
-        } else {
-            0
-        };
-        let end = if direction == SearchDirection::Forward {
-            self.len
-        } else {
-            at
-        };
-        #[allow(clippy::integer_arithmetic)]
-        let substring: String = self.string[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
+        let boundaries: Vec<usize> = self.string[..]
+            .grapheme_indices(true)
+            .map(|(byte_index, _)| byte_index)
+            .chain(iter::once(self.string.len()))
             .collect();
-        let matching_byte_index = if direction == SearchDirection::Forward {
-            substring.find(query)
-        } else {
-            substring.rfind(query)
+        let from = match direction {
+            SearchDirection::Forward => boundaries.get(at).copied()?,
+            SearchDirection::Backward => 0,
         };
-        if let Some(mathing_byte_index) = matching_byte_index {
-            for (grapheme_index, (byte_index, _)) in
-                substring[..].grapheme_indices(true).enumerate()
-            {
-                if byte_index == mathing_byte_index {
-                    return Some(grapheme_index + start);
+        let mut found = None;
+        let mut byte_index = from;
+        // Matches may overlap, so each search starts just after the last
+        // match's start rather than at its end.
+        while let Some(search_match) = pattern.find_at(&self.string, byte_index) {
+            if let Ok(start) = boundaries.binary_search(&search_match.start()) {
+                if direction == SearchDirection::Backward && start >= at {
+                    break;
+                }
+                let end = boundaries.partition_point(|boundary| *boundary < search_match.end());
+                found = Some((start, end));
+                if direction == SearchDirection::Forward {
+                    break;
                 }
             }
+            let next = self.string.get(search_match.start()..)?.chars().next();
+            let Some(next) = next else {
+                break;
+            };
+            byte_index = search_match.start().saturating_add(next.len_utf8());
         }
-        None
+        found
+    }
+
+    // The row as it would read with matches of `pattern` replaced, and the
+    // graphemes the replacements take up, to preview a `:s`. Line breaks in
+    // the replacement are shown as `⏎`. `None` if nothing matches.
+    pub fn substituted(
+        &self,
+        pattern: &Regex,
+        replacement: &str,
+        global: bool,
+    ) -> Option<(Self, Vec<usize>)> {
+        let limit = if global { usize::MAX } else { 1 };
+        let mut text = String::new();
+        let mut replaced = Vec::new();
+        let mut last = None;
+        for captures in pattern.captures_iter(&self.string).take(limit) {
+            let whole = captures.get(0)?;
+            text.push_str(self.string.get(last.unwrap_or(0)..whole.start())?);
+            let start = text[..].graphemes(true).count();
+            let mut expanded = String::new();
+            captures.expand(replacement, &mut expanded);
+            text.push_str(&expanded.replace('\n', "⏎"));
+            replaced.extend(start..text[..].graphemes(true).count());
+            last = Some(whole.end());
+        }
+        text.push_str(self.string.get(last?..)?);
+        Some((Self::from(text.as_str()), replaced))
     }
 
     pub fn split(&mut self, at: usize) -> Self {
@@ -160,30 +379,36 @@ impl Row {
 
         self.string = row;
         self.len = length;
+        self.highlighted = None;
         Self {
             string: splitted_row,
             highlighting: Vec::new(),
+            highlighted: None,
+            start_context: Context::Normal,
+            end_context: Context::Normal,
             len: splitted_length,
         }
     }
 
-    fn highlight_match(&mut self, word: Option<&str>) {
-        if let Some(word) = word {
-            if word.is_empty() {
-                return;
-            }
-            let mut index = 0;
-            while let Some(search_match) = self.find(word, index, SearchDirection::Forward) {
-                if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count())
-                {
-                    #[allow(clippy::indexing_slicing)]
-                    for i in search_match..next_index {
-                        self.highlighting[i] = highlighting::Type::SearchMatch;
-                    }
-                    index = next_index;
-                } else {
-                    break;
-                }
+    // Every non-overlapping match of `pattern`, as grapheme ranges.
+    pub fn matches(&self, pattern: &Regex) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        let mut index = 0;
+        while let Some((start, end)) = self.find_match(pattern, index, SearchDirection::Forward) {
+            matches.push((start, end));
+            // An empty match covers nothing but still has to move on.
+            index = end.max(start.saturating_add(1));
+        }
+        matches
+    }
+
+    fn highlight_match(&mut self, pattern: Option<&Regex>) {
+        let Some(pattern) = pattern else {
+            return;
+        };
+        for (start, end) in self.matches(pattern) {
+            for highlight in self.highlighting.iter_mut().take(end).skip(start) {
+                *highlight = highlighting::Type::SearchMatch;
             }
         }
     }
@@ -191,7 +416,7 @@ impl Row {
     fn highlight_char(
         &mut self,
         index: &mut usize,
-        opts: HighlightingOptions,
+        opts: &HighlightingOptions,
         c: char,
         chars: &[char],
     ) -> bool {
@@ -219,89 +444,376 @@ impl Row {
     fn highlight_comment(
         &mut self,
         index: &mut usize,
-        opts: HighlightingOptions,
-        c: char,
+        opts: &HighlightingOptions,
         chars: &[char],
     ) -> bool {
-        if opts.comments() && c == '/' && *index < chars.len() {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                if *next_char == '/' {
-                    for _ in *index..chars.len() {
-                        self.highlighting.push(highlighting::Type::Comment);
-                        *index += 1;
-                    }
-                    return true;
-                }
-            };
+        let Some(delimiter) = opts.line_comment() else {
+            return false;
+        };
+        if starts_with_at(chars, *index, delimiter) {
+            for _ in *index..chars.len() {
+                self.highlighting.push(highlighting::Type::Comment);
+                *index += 1;
+            }
+            return true;
         }
         false
     }
 
+    // Marks the TODO, FIXME, XXX and NOTE words that are part of a comment.
+    fn highlight_todos(&mut self, chars: &[char]) {
+        let is_word = |index: usize| {
+            chars
+                .get(index)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+        };
+        let mut index = 0;
+        while index < chars.len() {
+            let marker = TODO_MARKERS.iter().find(|marker| {
+                starts_with_at(chars, index, marker)
+                    && !(index > 0 && is_word(index.saturating_sub(1)))
+                    && !is_word(index.saturating_add(marker.len()))
+            });
+            let Some(marker) = marker else {
+                index = index.saturating_add(1);
+                continue;
+            };
+            let end = index.saturating_add(marker.len());
+            let span = self.highlighting.get_mut(index..end).unwrap_or_default();
+            if span
+                .iter()
+                .all(|highlighted| *highlighted == highlighting::Type::Comment)
+            {
+                span.fill(highlighting::Type::Todo);
+            }
+            index = end;
+        }
+    }
+
     fn highlight_string(
         &mut self,
         index: &mut usize,
-        opts: HighlightingOptions,
+        opts: &HighlightingOptions,
         c: char,
         chars: &[char],
-    ) -> bool {
-        if opts.strings() && c == '"' {
-            loop {
-                self.highlighting.push(highlighting::Type::String);
-                *index += 1;
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char == '"' {
-                        break;
+    ) -> Option<Context> {
+        if opts.string_quotes().contains(&c) {
+            self.highlighting.push(highlighting::Type::String);
+            *index += 1;
+            let context = self.highlight_context(index, Context::String(c), opts, chars);
+            // Strings left open only continue on the next row in languages
+            // that allow it.
+            return Some(if opts.multiline_quotes().contains(&c) {
+                context
+            } else {
+                Context::Normal
+            });
+        }
+        None
+    }
+
+    fn highlight_block_comment(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> Option<Context> {
+        let (start, _) = opts.block_comment()?;
+        if !starts_with_at(chars, *index, start) {
+            return None;
+        }
+        for _ in start.chars() {
+            self.highlighting.push(highlighting::Type::Comment);
+            *index += 1;
+        }
+        Some(self.highlight_context(index, Context::BlockComment(1), opts, chars))
+    }
+
+    // Rust raw strings: `r"..."`, `r#"..."#` and so on.
+    fn highlight_raw_string(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> Option<Context> {
+        if !opts.raw_strings() || c != 'r' {
+            return None;
+        }
+        if let Some(previous) = index
+            .checked_sub(1)
+            .and_then(|previous| chars.get(previous))
+        {
+            if previous.is_alphanumeric() || *previous == '_' {
+                return None;
+            }
+        }
+        let hashes = chars
+            .iter()
+            .skip(index.saturating_add(1))
+            .take_while(|c| **c == '#')
+            .count();
+        if chars.get(index.saturating_add(hashes).saturating_add(1)) != Some(&'"') {
+            return None;
+        }
+        for _ in 0..hashes.saturating_add(2) {
+            self.highlighting.push(highlighting::Type::String);
+            *index += 1;
+        }
+        Some(self.highlight_context(index, Context::RawString(hashes), opts, chars))
+    }
+
+    // Highlights the rest of a construct that is open at `index`, returning
+    // the context still open at the end of the row, if it doesn't close.
+    fn highlight_context(
+        &mut self,
+        index: &mut usize,
+        context: Context,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> Context {
+        let mut context = context;
+        while let Some(c) = chars.get(*index) {
+            let next = chars.get(index.saturating_add(1));
+            let (highlighting_type, consumed, next_context) = match context {
+                Context::Normal => return Context::Normal,
+                Context::BlockComment(depth) => {
+                    let (start, end) = opts.block_comment().unwrap_or_default();
+                    if starts_with_at(chars, *index, end) {
+                        let next_context = if depth <= 1 {
+                            Context::Normal
+                        } else {
+                            Context::BlockComment(depth.saturating_sub(1))
+                        };
+                        (
+                            highlighting::Type::Comment,
+                            end.chars().count(),
+                            next_context,
+                        )
+                    } else if opts.nested_comments() && starts_with_at(chars, *index, start) {
+                        (
+                            highlighting::Type::Comment,
+                            start.chars().count(),
+                            Context::BlockComment(depth.saturating_add(1)),
+                        )
+                    } else {
+                        (highlighting::Type::Comment, 1, context)
                     }
-                } else {
-                    break;
+                }
+                Context::String(quote) => match c {
+                    '\\' if next.is_some() => (
+                        highlighting::Type::Escape,
+                        escape_length(chars, *index),
+                        context,
+                    ),
+                    _ if *c == quote => (highlighting::Type::String, 1, Context::Normal),
+                    _ => (highlighting::Type::String, 1, context),
+                },
+                Context::RawString(hashes) => {
+                    let closes = *c == '"'
+                        && chars
+                            .iter()
+                            .skip(index.saturating_add(1))
+                            .take(hashes)
+                            .filter(|c| **c == '#')
+                            .count()
+                            == hashes;
+                    if closes {
+                        (
+                            highlighting::Type::String,
+                            hashes.saturating_add(1),
+                            Context::Normal,
+                        )
+                    } else {
+                        (highlighting::Type::String, 1, context)
+                    }
+                }
+            };
+            for _ in 0..consumed {
+                if *index < chars.len() {
+                    self.highlighting.push(highlighting_type.clone());
+                    *index += 1;
                 }
             }
-            self.highlighting.push(highlighting::Type::String);
+            context = next_context;
+            if context == Context::Normal {
+                return context;
+            }
+        }
+        context
+    }
+
+    fn highlight_str(
+        &mut self,
+        index: &mut usize,
+        substring: &str,
+        chars: &[char],
+        hl_type: highlighting::Type,
+    ) -> bool {
+        if substring.is_empty() {
+            return false;
+        }
+        for (substring_index, c) in substring.chars().enumerate() {
+            if chars.get(index.saturating_add(substring_index)) != Some(&c) {
+                return false;
+            }
+        }
+        for _ in 0..substring.chars().count() {
+            self.highlighting.push(hl_type.clone());
             *index += 1;
-            return true;
+        }
+        true
+    }
+
+    // Keywords only match whole words, so `fn` isn't found in `my_fn`.
+    fn highlight_keywords(
+        &mut self,
+        index: &mut usize,
+        chars: &[char],
+        keywords: &[String],
+        hl_type: highlighting::Type,
+    ) -> bool {
+        if let Some(previous) = index
+            .checked_sub(1)
+            .and_then(|previous| chars.get(previous))
+        {
+            if !is_separator(*previous) {
+                return false;
+            }
+        }
+        for word in keywords {
+            let after = index.saturating_add(word.chars().count());
+            if chars.get(after).is_some_and(|next| !is_separator(*next)) {
+                continue;
+            }
+            if self.highlight_str(index, word, chars, hl_type.clone()) {
+                return true;
+            }
         }
         false
     }
 
+    // Numbers are taken whole: `0x`, `0b` and `0o` prefixes, a fraction,
+    // an exponent, `_` separators and suffixes like `u8` or `f64`. Digits
+    // that are part of a word, like the 2 in `foo2bar`, aren't numbers.
     fn highlight_number(
         &mut self,
         index: &mut usize,
-        opts: HighlightingOptions,
+        opts: &HighlightingOptions,
         c: char,
         chars: &[char],
     ) -> bool {
-        if opts.numbers() && c.is_ascii_digit() {
-            if *index > 0 {
-                #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
-                let prev_char = chars[*index - 1];
-                if !prev_char.is_ascii_punctuation() && !prev_char.is_ascii_whitespace() {
-                    return false;
-                }
+        if !opts.numbers() || !c.is_ascii_digit() {
+            return false;
+        }
+        if let Some(previous) = index
+            .checked_sub(1)
+            .and_then(|previous| chars.get(previous))
+        {
+            if !is_separator(*previous) {
+                return false;
             }
-            loop {
-                self.highlighting.push(highlighting::Type::Number);
-                *index += 1;
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char != '.' && !next_char.is_ascii_digit() {
-                        break;
-                    }
-                } else {
-                    break;
+        }
+        let at = |offset: usize| chars.get(index.saturating_add(offset)).copied();
+        let radix = match (c, at(1)) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('b' | 'B')) => 2,
+            ('0', Some('o' | 'O')) => 8,
+            _ => 10,
+        };
+        let mut end = *index;
+        let digits = |end: &mut usize, radix: u32| {
+            while chars
+                .get(*end)
+                .is_some_and(|c| c.is_digit(radix) || *c == '_')
+            {
+                *end = end.saturating_add(1);
+            }
+        };
+        if radix == 10 {
+            digits(&mut end, 10);
+            let digit_at = |at: usize| chars.get(at).is_some_and(char::is_ascii_digit);
+            if chars.get(end) == Some(&'.') && digit_at(end.saturating_add(1)) {
+                end = end.saturating_add(1);
+                digits(&mut end, 10);
+            }
+            if matches!(chars.get(end), Some('e' | 'E')) {
+                let sign = usize::from(matches!(chars.get(end.saturating_add(1)), Some('+' | '-')));
+                if digit_at(end.saturating_add(1).saturating_add(sign)) {
+                    end = end.saturating_add(1).saturating_add(sign);
+                    digits(&mut end, 10);
                 }
             }
-            return true;
+        } else {
+            end = end.saturating_add(2);
+            digits(&mut end, radix);
         }
-        false
+        while chars.get(end).is_some_and(|c| !is_separator(*c)) {
+            end = end.saturating_add(1);
+        }
+        while *index < end {
+            self.highlighting.push(highlighting::Type::Number);
+            *index += 1;
+        }
+        true
+    }
+
+    pub fn is_highlighted(&self, generation: u64) -> bool {
+        self.highlighted == Some(generation)
+    }
+
+    pub fn invalidate_highlighting(&mut self) {
+        self.highlighted = None;
     }
 
-    pub fn highlight(&mut self, opts: HighlightingOptions, word: Option<&str>) {
+    // Whether the row must be highlighted again to be correct after a row
+    // ending in `context`.
+    pub fn needs_highlight(&self, context: Context) -> bool {
+        self.highlighted.is_none() || self.start_context != context
+    }
+
+    // The construct, like a block comment, still open at the end of the row.
+    pub fn end_context(&self) -> Context {
+        self.end_context
+    }
+
+    pub fn highlight(
+        &mut self,
+        opts: &HighlightingOptions,
+        pattern: Option<&Regex>,
+        generation: u64,
+        context: Context,
+    ) {
+        self.highlighted = Some(generation);
+        self.start_context = context;
         self.highlighting = Vec::new();
-        let chars: Vec<char> = self.string.chars().collect();
+        // One entry per grapheme so highlighting lines up with rendering
+        let chars = self.chars();
         let mut index = 0;
+        let mut context = self.highlight_context(&mut index, context, opts, &chars);
         while let Some(c) = chars.get(index) {
+            if let Some(opened) = self
+                .highlight_block_comment(&mut index, opts, &chars)
+                .or_else(|| self.highlight_raw_string(&mut index, opts, *c, &chars))
+                .or_else(|| self.highlight_string(&mut index, opts, *c, &chars))
+            {
+                context = opened;
+                continue;
+            }
             if self.highlight_char(&mut index, opts, *c, &chars)
-                || self.highlight_comment(&mut index, opts, *c, &chars)
-                || self.highlight_string(&mut index, opts, *c, &chars)
+                || self.highlight_comment(&mut index, opts, &chars)
+                || self.highlight_keywords(
+                    &mut index,
+                    &chars,
+                    opts.primary_keywords(),
+                    highlighting::Type::PrimaryKeywords,
+                )
+                || self.highlight_keywords(
+                    &mut index,
+                    &chars,
+                    opts.secondary_keywords(),
+                    highlighting::Type::SecondaryKeywords,
+                )
                 || self.highlight_number(&mut index, opts, *c, &chars)
             {
                 continue;
@@ -309,10 +821,122 @@ impl Row {
             self.highlighting.push(highlighting::Type::None);
             index += 1;
         }
-        self.highlight_match(word);
+        self.end_context = context;
+        self.highlight_todos(&chars);
+        self.highlight_match(pattern);
     }
 
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
+    // How grapheme `index` was last highlighted.
+    pub fn highlighting_at(&self, index: usize) -> &highlighting::Type {
+        self.highlighting
+            .get(index)
+            .unwrap_or(&highlighting::Type::None)
+    }
+
+    // The leading character of every grapheme, so indices line up with `len`.
+    pub fn chars(&self) -> Vec<char> {
+        self.string[..]
+            .graphemes(true)
+            .filter_map(|grapheme| grapheme.chars().next())
+            .collect()
+    }
+
+    // The leading character of grapheme `index`, without collecting the
+    // rest. Rows of nothing but ASCII are indexed straight into.
+    pub fn char_at(&self, index: usize) -> Option<char> {
+        if self.len == self.string.len() {
+            return self
+                .string
+                .as_bytes()
+                .get(index)
+                .map(|byte| char::from(*byte));
+        }
+        self.string[..]
+            .graphemes(true)
+            .nth(index)
+            .and_then(|grapheme| grapheme.chars().next())
+    }
+
+    pub fn substring(&self, start: usize, end: usize) -> String {
+        self.string[..]
+            .graphemes(true)
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .collect()
+    }
+
+    // The index of the nearest `c` strictly after (or before) `at`.
+    pub fn find_char(&self, c: char, at: usize, direction: SearchDirection) -> Option<usize> {
+        let chars = self.chars();
+        match direction {
+            SearchDirection::Forward => chars
+                .iter()
+                .enumerate()
+                .skip(at.saturating_add(1))
+                .find(|(_, candidate)| **candidate == c)
+                .map(|(index, _)| index),
+            SearchDirection::Backward => chars
+                .iter()
+                .enumerate()
+                .take(at)
+                .rev()
+                .find(|(_, candidate)| **candidate == c)
+                .map(|(index, _)| index),
+        }
+    }
+}
+
+// The number of columns a grapheme takes when it starts at `column`, which
+// is two for most CJK characters.
+fn grapheme_width(grapheme: &str, column: usize, tabstop: usize) -> usize {
+    if grapheme == "\t" && tabstop > 0 {
+        tabstop.saturating_sub(column.checked_rem(tabstop).unwrap_or(0))
+    } else {
+        grapheme.width()
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    !c.is_alphanumeric() && c != '_'
+}
+
+// How many characters the escape sequence starting with the `\\` at
+// `index` takes: `\\x7f`, `\\u{1F600}`, `\\u00e9`, `\\U0001F600` and octal
+// `\\012` run on past the letter, the others are just `\\` and one character.
+fn escape_length(chars: &[char], index: usize) -> usize {
+    let after = |offset: usize| chars.get(index.saturating_add(offset)).copied();
+    let digits = |from: usize, limit: usize, radix: u32| {
+        (from..from.saturating_add(limit))
+            .take_while(|offset| after(*offset).is_some_and(|c| c.is_digit(radix)))
+            .count()
+    };
+    let extra = match after(1) {
+        Some('u') if after(2) == Some('{') => {
+            let digits = digits(3, 6, 16);
+            let closed = after(digits.saturating_add(3)) == Some('}');
+            digits.saturating_add(1).saturating_add(usize::from(closed))
+        }
+        Some('x') => digits(2, 2, 16),
+        Some('u') => digits(2, 4, 16),
+        Some('U') => digits(2, 8, 16),
+        Some('0'..='7') => digits(2, 2, 8),
+        _ => 0,
+    };
+    extra.saturating_add(2)
+}
+
+fn starts_with_at(chars: &[char], index: usize, delimiter: &str) -> bool {
+    !delimiter.is_empty()
+        && delimiter
+            .chars()
+            .enumerate()
+            .all(|(offset, c)| chars.get(index.saturating_add(offset)) == Some(&c))
 }
@@ -0,0 +1,1076 @@
+use crate::config;
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// How many statements and calls one run of a script may take, so a loop
+// that never ends stops with an error instead of hanging the editor.
+const STEP_LIMIT: usize = 1_000_000;
+
+// A value in a script. Lists are shared, so a function can add to a list it
+// is given.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Rc<RefCell<Vec<Value>>>),
+}
+
+impl Value {
+    pub fn list(values: Vec<Value>) -> Self {
+        Self::List(Rc::new(RefCell::new(values)))
+    }
+
+    fn is_true(&self) -> bool {
+        !matches!(self, Self::Nil | Self::Bool(false))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Bool(_) => "boolean",
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+        }
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::List(a), Self::List(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Self {
+        Self::String(string.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Self {
+        Self::String(string)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(number: usize) -> Self {
+        Self::Number(number as f64)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Nil => write!(f, "nil"),
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => {
+                write!(f, "{}", *number as i64)
+            }
+            Self::Number(number) => write!(f, "{number}"),
+            Self::String(string) => write!(f, "{string}"),
+            Self::List(values) => write_list(f, values, &mut Vec::new()),
+        }
+    }
+}
+
+// Writes `values` with its strings quoted. A list can hold itself, so the
+// lists already being written are kept in `outer`, and one of them showing
+// up again is written as `[...]`.
+fn write_list(
+    f: &mut fmt::Formatter,
+    values: &Rc<RefCell<Vec<Value>>>,
+    outer: &mut Vec<*const RefCell<Vec<Value>>>,
+) -> fmt::Result {
+    if outer.contains(&Rc::as_ptr(values)) {
+        return write!(f, "[...]");
+    }
+    outer.push(Rc::as_ptr(values));
+    write!(f, "[")?;
+    for (index, value) in values.borrow().iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        match value {
+            Value::String(string) => write!(f, "{string:?}")?,
+            Value::List(values) => write_list(f, values, outer)?,
+            value => write!(f, "{value}")?,
+        }
+    }
+    outer.pop();
+    write!(f, "]")
+}
+
+// What scripts can do to the editor: the functions it offers, by name.
+pub trait Host {
+    // Calls the host function `name`, or returns `None` when there is none.
+    fn call(&mut self, name: &str, arguments: &[Value]) -> Option<Result<Value, String>>;
+}
+
+// The functions and global variables scripts have defined so far. Functions
+// whose name starts with a capital letter can also be run as commands, like
+// `:Title`.
+#[derive(Clone, Default)]
+pub struct Scripts {
+    functions: HashMap<String, Rc<Function>>,
+    globals: HashMap<String, Value>,
+}
+
+struct Function {
+    parameters: Vec<String>,
+    body: Rc<Vec<Statement>>,
+}
+
+impl Scripts {
+    // Runs `source`, defining its functions for later.
+    pub fn run(&mut self, source: &str, host: &mut dyn Host) -> Result<(), String> {
+        let statements = Parser::new(source)?.block(&[])?;
+        let mut run = Run {
+            scripts: self,
+            host,
+            frames: vec![HashMap::new()],
+            steps: 0,
+        };
+        run.block(&statements)?;
+        Ok(())
+    }
+
+    pub fn call(
+        &mut self,
+        name: &str,
+        arguments: Vec<Value>,
+        host: &mut dyn Host,
+    ) -> Result<Value, String> {
+        let mut run = Run {
+            scripts: self,
+            host,
+            frames: Vec::new(),
+            steps: 0,
+        };
+        run.call(name, arguments, 0)
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        is_command(name) && self.functions.contains_key(name)
+    }
+}
+
+fn is_command(name: &str) -> bool {
+    name.chars().next().is_some_and(char::is_uppercase)
+}
+
+// The scripts in the `scripts` directory of the config directory, in the
+// order of their names, with their contents.
+pub fn sources() -> Vec<(String, Result<String, String>)> {
+    let Some(dir) = config::dir().map(|dir| dir.join("scripts")) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "gg"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            let contents = fs::read_to_string(&path).map_err(|error| error.to_string());
+            (name, contents)
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Name(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &[
+    "==", "~=", "!=", "<=", ">=", "..", "(", ")", "[", "]", ",", "=", "<", ">", "+", "-", "*", "/",
+    "%",
+];
+
+const KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in", "local",
+    "nil", "not", "or", "return", "then", "true", "while",
+];
+
+// Splits `source` into tokens, each with the line it is on.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1_usize;
+    let mut at = 0_usize;
+    while let Some(&c) = chars.get(at) {
+        if c == '\n' {
+            line = line.saturating_add(1);
+            at = at.saturating_add(1);
+        } else if c.is_whitespace() {
+            at = at.saturating_add(1);
+        } else if c == '-' && chars.get(at.saturating_add(1)) == Some(&'-') {
+            while chars.get(at).is_some_and(|&c| c != '\n') {
+                at = at.saturating_add(1);
+            }
+        } else if c.is_ascii_digit() {
+            let start = at;
+            while chars
+                .get(at)
+                .is_some_and(|&c| c.is_ascii_digit() || c == '.')
+            {
+                // `1..2` is a number and `..`, not `1.` and `.2`.
+                if chars.get(at) == Some(&'.') && chars.get(at.saturating_add(1)) == Some(&'.') {
+                    break;
+                }
+                at = at.saturating_add(1);
+            }
+            let text: String = chars.get(start..at).unwrap_or_default().iter().collect();
+            let number = text
+                .parse()
+                .map_err(|_| format!("line {line}: invalid number {text}"))?;
+            tokens.push((Token::Number(number), line));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = at;
+            while chars
+                .get(at)
+                .is_some_and(|&c| c.is_alphanumeric() || c == '_')
+            {
+                at = at.saturating_add(1);
+            }
+            let name: String = chars.get(start..at).unwrap_or_default().iter().collect();
+            tokens.push((Token::Name(name), line));
+        } else if c == '"' || c == '\'' {
+            let mut string = String::new();
+            at = at.saturating_add(1);
+            loop {
+                match chars.get(at) {
+                    None | Some('\n') => return Err(format!("line {line}: unfinished string")),
+                    Some(&quote) if quote == c => break,
+                    Some('\\') => {
+                        at = at.saturating_add(1);
+                        string.push(match chars.get(at) {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some(&escaped) => escaped,
+                            None => return Err(format!("line {line}: unfinished string")),
+                        });
+                    }
+                    Some(&c) => string.push(c),
+                }
+                at = at.saturating_add(1);
+            }
+            at = at.saturating_add(1);
+            tokens.push((Token::String(string), line));
+        } else {
+            let rest: String = chars.get(at..).unwrap_or_default().iter().take(2).collect();
+            let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) else {
+                return Err(format!("line {line}: unexpected character {c}"));
+            };
+            at = at.saturating_add(symbol.len());
+            tokens.push((Token::Symbol(symbol), line));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expression {
+    Literal(Value),
+    Variable(String),
+    List(Vec<Expression>),
+    Index(Box<Expression>, Box<Expression>),
+    Call(String, Vec<Expression>),
+    Unary(&'static str, Box<Expression>),
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug)]
+enum Kind {
+    Local(String, Expression),
+    Assign(String, Expression),
+    AssignIndex(Expression, Expression, Expression),
+    If(Vec<(Expression, Vec<Statement>)>, Vec<Statement>),
+    While(Expression, Vec<Statement>),
+    // `for i = first, last do`, counting up to and including `last`.
+    Count(String, Expression, Expression, Vec<Statement>),
+    // `for item in list do`.
+    Each(String, Expression, Vec<Statement>),
+    Function(String, Vec<String>, Rc<Vec<Statement>>),
+    Return(Option<Expression>),
+    Break,
+    Expression(Expression),
+}
+
+#[derive(Debug)]
+struct Statement {
+    kind: Kind,
+    line: usize,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    at: usize,
+}
+
+// Operators by how tightly they bind, loosest first.
+const PRECEDENCE: &[&[&str]] = &[
+    &["or"],
+    &["and"],
+    &["==", "~=", "!=", "<", "<=", ">", ">="],
+    &[".."],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, String> {
+        Ok(Self {
+            tokens: tokenize(source)?,
+            at: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.at)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.at = self.at.saturating_add(1);
+        token
+    }
+
+    fn error(&self, what: &str) -> String {
+        match self.peek() {
+            Some(token) => format!(
+                "line {}: expected {what}, found {}",
+                self.line(),
+                show(token)
+            ),
+            None => format!("line {}: expected {what} before the end", self.line()),
+        }
+    }
+
+    // Whether the next token is the keyword or symbol `word`.
+    fn is(&self, word: &str) -> bool {
+        match self.peek() {
+            Some(Token::Name(name)) => name == word,
+            Some(Token::Symbol(symbol)) => *symbol == word,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        let found = self.is(word);
+        if found {
+            self.at = self.at.saturating_add(1);
+        }
+        found
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        if self.eat(word) {
+            Ok(())
+        } else {
+            Err(self.error(word))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Name(name)) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.at = self.at.saturating_add(1);
+                Ok(name)
+            }
+            _ => Err(self.error("a name")),
+        }
+    }
+
+    // Statements up to one of the keywords in `ends`, which is left for the
+    // caller, or up to the end of the script when there are none.
+    fn block(&mut self, ends: &[&str]) -> Result<Vec<Statement>, String> {
+        let mut statements = Vec::new();
+        loop {
+            if ends.iter().any(|end| self.is(end)) {
+                return Ok(statements);
+            }
+            if self.peek().is_none() {
+                return if ends.is_empty() {
+                    Ok(statements)
+                } else {
+                    Err(self.error(ends.join(" or ").as_str()))
+                };
+            }
+            statements.push(self.statement()?);
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, String> {
+        let line = self.line();
+        let kind = if self.eat("local") {
+            let name = self.name()?;
+            let value = if self.eat("=") {
+                self.expression()?
+            } else {
+                Expression::Literal(Value::Nil)
+            };
+            Kind::Local(name, value)
+        } else if self.eat("if") {
+            let mut branches = Vec::new();
+            let mut otherwise = Vec::new();
+            loop {
+                let condition = self.expression()?;
+                self.expect("then")?;
+                branches.push((condition, self.block(&["elseif", "else", "end"])?));
+                if self.eat("elseif") {
+                    continue;
+                }
+                if self.eat("else") {
+                    otherwise = self.block(&["end"])?;
+                }
+                self.expect("end")?;
+                break;
+            }
+            Kind::If(branches, otherwise)
+        } else if self.eat("while") {
+            let condition = self.expression()?;
+            self.expect("do")?;
+            let body = self.block(&["end"])?;
+            self.expect("end")?;
+            Kind::While(condition, body)
+        } else if self.eat("for") {
+            let name = self.name()?;
+            if self.eat("=") {
+                let first = self.expression()?;
+                self.expect(",")?;
+                let last = self.expression()?;
+                self.expect("do")?;
+                let body = self.block(&["end"])?;
+                self.expect("end")?;
+                Kind::Count(name, first, last, body)
+            } else {
+                self.expect("in")?;
+                let list = self.expression()?;
+                self.expect("do")?;
+                let body = self.block(&["end"])?;
+                self.expect("end")?;
+                Kind::Each(name, list, body)
+            }
+        } else if self.eat("function") {
+            let name = self.name()?;
+            self.expect("(")?;
+            let mut parameters = Vec::new();
+            if !self.eat(")") {
+                loop {
+                    parameters.push(self.name()?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            let body = self.block(&["end"])?;
+            self.expect("end")?;
+            Kind::Function(name, parameters, Rc::new(body))
+        } else if self.eat("return") {
+            let value = if self.is("end")
+                || self.is("else")
+                || self.is("elseif")
+                || self.peek().is_none()
+            {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+            Kind::Return(value)
+        } else if self.eat("break") {
+            Kind::Break
+        } else {
+            let target = self.expression()?;
+            if self.eat("=") {
+                let value = self.expression()?;
+                match target {
+                    Expression::Variable(name) => Kind::Assign(name, value),
+                    Expression::Index(list, index) => Kind::AssignIndex(*list, *index, value),
+                    _ => return Err(format!("line {line}: cannot assign to that")),
+                }
+            } else if matches!(target, Expression::Call(..)) {
+                Kind::Expression(target)
+            } else {
+                return Err(format!("line {line}: expected a statement"));
+            }
+        };
+        Ok(Statement { kind, line })
+    }
+
+    fn expression(&mut self) -> Result<Expression, String> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, String> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level.saturating_add(1))?;
+        while let Some(operator) = operators.iter().find(|operator| self.is(operator)) {
+            self.at = self.at.saturating_add(1);
+            // Joining strings goes right to left, like in Lua.
+            let right = if *operator == ".." {
+                self.binary(level)?
+            } else {
+                self.binary(level.saturating_add(1))?
+            };
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if self.eat("not") {
+            return Ok(Expression::Unary("not", Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expression::Unary("-", Box::new(self.unary()?)));
+        }
+        let mut expression = self.primary()?;
+        while self.eat("[") {
+            let index = self.expression()?;
+            self.expect("]")?;
+            expression = Expression::Index(Box::new(expression), Box::new(index));
+        }
+        Ok(expression)
+    }
+
+    fn primary(&mut self) -> Result<Expression, String> {
+        match self.peek().cloned() {
+            Some(Token::Number(number)) => {
+                self.next();
+                Ok(Expression::Literal(Value::Number(number)))
+            }
+            Some(Token::String(string)) => {
+                self.next();
+                Ok(Expression::Literal(Value::String(string)))
+            }
+            Some(Token::Name(name)) if name == "nil" => {
+                self.next();
+                Ok(Expression::Literal(Value::Nil))
+            }
+            Some(Token::Name(name)) if name == "true" || name == "false" => {
+                self.next();
+                Ok(Expression::Literal(Value::Bool(name == "true")))
+            }
+            Some(Token::Symbol("(")) => {
+                self.next();
+                let expression = self.expression()?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Some(Token::Symbol("[")) => {
+                self.next();
+                Ok(Expression::List(self.arguments("]")?))
+            }
+            _ => {
+                let name = self.name()?;
+                if self.eat("(") {
+                    Ok(Expression::Call(name, self.arguments(")")?))
+                } else {
+                    Ok(Expression::Variable(name))
+                }
+            }
+        }
+    }
+
+    // Expressions separated by commas, up to `end`.
+    fn arguments(&mut self, end: &str) -> Result<Vec<Expression>, String> {
+        let mut arguments = Vec::new();
+        if self.eat(end) {
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.expression()?);
+            if self.eat(end) {
+                return Ok(arguments);
+            }
+            self.expect(",")?;
+        }
+    }
+}
+
+fn show(token: &Token) -> String {
+    match token {
+        Token::Number(number) => Value::Number(*number).to_string(),
+        Token::String(string) => format!("{string:?}"),
+        Token::Name(name) => name.clone(),
+        Token::Symbol(symbol) => (*symbol).to_string(),
+    }
+}
+
+// How a block of statements finished.
+enum Flow {
+    Next,
+    Break,
+    Return(Value),
+}
+
+// One run of a script or call of a function, with the local variables of
+// each function being run.
+struct Run<'a> {
+    scripts: &'a mut Scripts,
+    host: &'a mut dyn Host,
+    frames: Vec<HashMap<String, Value>>,
+    steps: usize,
+}
+
+impl Run<'_> {
+    fn step(&mut self, line: usize) -> Result<(), String> {
+        self.steps = self.steps.saturating_add(1);
+        if self.steps > STEP_LIMIT {
+            return Err(format!("line {line}: script ran too long"));
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, statements: &[Statement]) -> Result<Flow, String> {
+        for statement in statements {
+            match self.statement(statement)? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<Flow, String> {
+        let line = statement.line;
+        self.step(line)?;
+        match &statement.kind {
+            Kind::Local(name, value) => {
+                let value = self.evaluate(value, line)?;
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.insert(name.clone(), value);
+                }
+            }
+            Kind::Assign(name, value) => {
+                let value = self.evaluate(value, line)?;
+                self.assign(name, value);
+            }
+            Kind::AssignIndex(list, index, value) => {
+                let list = self.evaluate(list, line)?;
+                let index = self.evaluate(index, line)?;
+                let value = self.evaluate(value, line)?;
+                let Value::List(values) = list else {
+                    return Err(format!("line {line}: cannot index a {}", list.type_name()));
+                };
+                let mut values = values.borrow_mut();
+                let len = values.len();
+                match list_index(&index, len.saturating_add(1)) {
+                    Some(at) if at == len => values.push(value),
+                    Some(at) => {
+                        if let Some(slot) = values.get_mut(at) {
+                            *slot = value;
+                        }
+                    }
+                    None => return Err(format!("line {line}: index {index} out of range")),
+                }
+            }
+            Kind::If(branches, otherwise) => {
+                for (condition, body) in branches {
+                    if self.evaluate(condition, line)?.is_true() {
+                        return self.block(body);
+                    }
+                }
+                return self.block(otherwise);
+            }
+            Kind::While(condition, body) => {
+                while self.evaluate(condition, line)?.is_true() {
+                    match self.block(body)? {
+                        Flow::Next => (),
+                        Flow::Break => break,
+                        flow => return Ok(flow),
+                    }
+                    self.step(line)?;
+                }
+            }
+            Kind::Count(name, first, last, body) => {
+                let first = self.number(first, line)?;
+                let last = self.number(last, line)?;
+                let mut counter = first;
+                while counter <= last {
+                    self.set_local(name, Value::Number(counter));
+                    match self.block(body)? {
+                        Flow::Next => (),
+                        Flow::Break => break,
+                        flow => return Ok(flow),
+                    }
+                    self.step(line)?;
+                    counter += 1.0;
+                }
+            }
+            Kind::Each(name, list, body) => {
+                let list = self.evaluate(list, line)?;
+                let Value::List(values) = list else {
+                    return Err(format!(
+                        "line {line}: cannot go through a {}",
+                        list.type_name()
+                    ));
+                };
+                // Going through a copy lets the body change the list.
+                let values = values.borrow().clone();
+                for value in values {
+                    self.set_local(name, value);
+                    match self.block(body)? {
+                        Flow::Next => (),
+                        Flow::Break => break,
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            Kind::Function(name, parameters, body) => {
+                self.scripts.functions.insert(
+                    name.clone(),
+                    Rc::new(Function {
+                        parameters: parameters.clone(),
+                        body: Rc::clone(body),
+                    }),
+                );
+            }
+            Kind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.evaluate(value, line)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Kind::Break => return Ok(Flow::Break),
+            Kind::Expression(expression) => {
+                self.evaluate(expression, line)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn set_local(&mut self, name: &str, value: Value) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.insert(name.to_string(), value);
+        }
+    }
+
+    // Assigns to the local variable `name` if there is one, or else the
+    // global one.
+    fn assign(&mut self, name: &str, value: Value) {
+        match self.frames.last_mut().and_then(|frame| frame.get_mut(name)) {
+            Some(slot) => *slot = value,
+            None => {
+                self.scripts.globals.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    fn number(&mut self, expression: &Expression, line: usize) -> Result<f64, String> {
+        match self.evaluate(expression, line)? {
+            Value::Number(number) => Ok(number),
+            value => Err(format!(
+                "line {line}: expected a number, got {}",
+                value.type_name()
+            )),
+        }
+    }
+
+    fn evaluate(&mut self, expression: &Expression, line: usize) -> Result<Value, String> {
+        match expression {
+            Expression::Literal(value) => Ok(value.clone()),
+            Expression::Variable(name) => self
+                .frames
+                .last()
+                .and_then(|frame| frame.get(name))
+                .or_else(|| self.scripts.globals.get(name))
+                .cloned()
+                .ok_or_else(|| format!("line {line}: unknown variable {name}")),
+            Expression::List(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| self.evaluate(value, line))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::list(values))
+            }
+            Expression::Index(list, index) => {
+                let list = self.evaluate(list, line)?;
+                let index = self.evaluate(index, line)?;
+                match &list {
+                    Value::List(values) => {
+                        let values = values.borrow();
+                        Ok(list_index(&index, values.len())
+                            .and_then(|at| values.get(at))
+                            .cloned()
+                            .unwrap_or(Value::Nil))
+                    }
+                    Value::String(string) => Ok(list_index(&index, string.chars().count())
+                        .and_then(|at| string.chars().nth(at))
+                        .map_or(Value::Nil, |c| Value::String(c.to_string()))),
+                    _ => Err(format!("line {line}: cannot index a {}", list.type_name())),
+                }
+            }
+            Expression::Call(name, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument, line))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, arguments, line)
+            }
+            Expression::Unary(operator, operand) => {
+                let operand = self.evaluate(operand, line)?;
+                match (*operator, operand) {
+                    ("not", operand) => Ok(Value::Bool(!operand.is_true())),
+                    (_, Value::Number(number)) => Ok(Value::Number(-number)),
+                    (_, operand) => Err(format!(
+                        "line {line}: cannot negate a {}",
+                        operand.type_name()
+                    )),
+                }
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = self.evaluate(left, line)?;
+                match *operator {
+                    "and" if !left.is_true() => return Ok(left),
+                    "or" if left.is_true() => return Ok(left),
+                    "and" | "or" => return self.evaluate(right, line),
+                    _ => (),
+                }
+                let right = self.evaluate(right, line)?;
+                binary(operator, &left, &right).map_err(|error| format!("line {line}: {error}"))
+            }
+        }
+    }
+
+    // Calls the script function `name`, or else the built-in or host
+    // function of that name.
+    fn call(&mut self, name: &str, arguments: Vec<Value>, line: usize) -> Result<Value, String> {
+        self.step(line)?;
+        if let Some(function) = self.scripts.functions.get(name).cloned() {
+            if self.frames.len() > 200 {
+                return Err(format!("line {line}: too many nested calls"));
+            }
+            let mut frame = HashMap::new();
+            let mut arguments = arguments.into_iter();
+            for parameter in &function.parameters {
+                frame.insert(parameter.clone(), arguments.next().unwrap_or(Value::Nil));
+            }
+            self.frames.push(frame);
+            let flow = self.block(&function.body);
+            self.frames.pop();
+            return match flow? {
+                Flow::Return(value) => Ok(value),
+                _ => Ok(Value::Nil),
+            };
+        }
+        if let Some(result) = builtin(name, &arguments) {
+            return result.map_err(|error| format!("line {line}: {name}: {error}"));
+        }
+        match self.host.call(name, &arguments) {
+            Some(result) => result.map_err(|error| format!("line {line}: {name}: {error}")),
+            None => Err(format!("line {line}: unknown function {name}")),
+        }
+    }
+}
+
+// The zero-based position of the one-based `index` in a list of `len`
+// values, if it is one.
+fn list_index(index: &Value, len: usize) -> Option<usize> {
+    let Value::Number(number) = index else {
+        return None;
+    };
+    if number.fract() != 0.0 || *number < 1.0 || *number > len as f64 {
+        return None;
+    }
+    (*number as usize).checked_sub(1)
+}
+
+fn binary(operator: &str, left: &Value, right: &Value) -> Result<Value, String> {
+    match (operator, left, right) {
+        ("==", left, right) => Ok(Value::Bool(left.equals(right))),
+        ("~=" | "!=", left, right) => Ok(Value::Bool(!left.equals(right))),
+        ("..", left, right) => Ok(Value::String(format!("{left}{right}"))),
+        (_, Value::Number(left), Value::Number(right)) => Ok(match operator {
+            "+" => Value::Number(left + right),
+            "-" => Value::Number(left - right),
+            "*" => Value::Number(left * right),
+            "/" => Value::Number(left / right),
+            "%" => Value::Number(left.rem_euclid(*right)),
+            "<" => Value::Bool(left < right),
+            "<=" => Value::Bool(left <= right),
+            ">" => Value::Bool(left > right),
+            _ => Value::Bool(left >= right),
+        }),
+        ("<" | "<=" | ">" | ">=", Value::String(left), Value::String(right)) => {
+            Ok(Value::Bool(match operator {
+                "<" => left < right,
+                "<=" => left <= right,
+                ">" => left > right,
+                _ => left >= right,
+            }))
+        }
+        _ => Err(format!(
+            "cannot use {operator} on a {} and a {}",
+            left.type_name(),
+            right.type_name()
+        )),
+    }
+}
+
+pub fn string_argument(arguments: &[Value], index: usize) -> Result<String, String> {
+    match arguments.get(index) {
+        Some(Value::String(string)) => Ok(string.clone()),
+        Some(Value::Number(number)) => Ok(Value::Number(*number).to_string()),
+        Some(value) => Err(format!("expected a string, got {}", value.type_name())),
+        None => Err(String::from("expected a string")),
+    }
+}
+
+pub fn number_argument(arguments: &[Value], index: usize) -> Result<f64, String> {
+    match arguments.get(index) {
+        Some(Value::Number(number)) => Ok(*number),
+        Some(value) => Err(format!("expected a number, got {}", value.type_name())),
+        None => Err(String::from("expected a number")),
+    }
+}
+
+fn list_argument(arguments: &[Value], index: usize) -> Result<Rc<RefCell<Vec<Value>>>, String> {
+    match arguments.get(index) {
+        Some(Value::List(values)) => Ok(Rc::clone(values)),
+        Some(value) => Err(format!("expected a list, got {}", value.type_name())),
+        None => Err(String::from("expected a list")),
+    }
+}
+
+// The functions every script has, or `None` when `name` isn't one.
+fn builtin(name: &str, arguments: &[Value]) -> Option<Result<Value, String>> {
+    let string = |index| string_argument(arguments, index);
+    let result = match name {
+        "len" => match arguments.first() {
+            Some(Value::List(values)) => Ok(Value::from(values.borrow().len())),
+            _ => string(0).map(|string| Value::from(string.chars().count())),
+        },
+        "upper" => string(0).map(|string| Value::from(string.to_uppercase())),
+        "lower" => string(0).map(|string| Value::from(string.to_lowercase())),
+        "trim" => string(0).map(|string| Value::from(string.trim())),
+        // The characters from `first` to `last`, counting from one, with
+        // negative positions counting from the end.
+        "sub" => string(0).and_then(|text| {
+            let len = text.chars().count() as f64;
+            let position = |index: usize, default: f64| {
+                let number = arguments
+                    .get(index)
+                    .map_or(Ok(default), |_| number_argument(arguments, index))?;
+                Ok::<f64, String>(if number < 0.0 {
+                    len + number + 1.0
+                } else {
+                    number
+                })
+            };
+            let first = position(1, 1.0)?.max(1.0) as usize;
+            let last = position(2, len)?.min(len) as usize;
+            Ok(Value::from(
+                text.chars()
+                    .skip(first.saturating_sub(1))
+                    .take(last.saturating_add(1).saturating_sub(first))
+                    .collect::<String>(),
+            ))
+        }),
+        // Where `needle` first appears, counting characters from one.
+        "find" => string(0).and_then(|text| {
+            let needle = string(1)?;
+            Ok(text.find(&needle).map_or(Value::Nil, |at| {
+                Value::from(
+                    text.get(..at)
+                        .unwrap_or_default()
+                        .chars()
+                        .count()
+                        .saturating_add(1),
+                )
+            }))
+        }),
+        "replace" => {
+            string(0).and_then(|text| Ok(Value::from(text.replace(&string(1)?, &string(2)?))))
+        }
+        "matches" => string(0).and_then(|text| {
+            let pattern = Regex::new(&string(1)?).map_err(|error| error.to_string())?;
+            Ok(Value::Bool(pattern.is_match(&text)))
+        }),
+        "substitute" => string(0).and_then(|text| {
+            let pattern = Regex::new(&string(1)?).map_err(|error| error.to_string())?;
+            Ok(Value::from(
+                pattern.replace_all(&text, string(2)?.as_str()).into_owned(),
+            ))
+        }),
+        "split" => string(0).and_then(|text| {
+            let separator = string(1)?;
+            let parts: Vec<Value> = if separator.is_empty() {
+                text.chars().map(|c| Value::from(c.to_string())).collect()
+            } else {
+                text.split(separator.as_str()).map(Value::from).collect()
+            };
+            Ok(Value::list(parts))
+        }),
+        "join" => list_argument(arguments, 0).and_then(|values| {
+            let separator = arguments.get(1).map_or(Ok(String::new()), |_| string(1))?;
+            Ok(Value::from(
+                values
+                    .borrow()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(&separator),
+            ))
+        }),
+        "push" => list_argument(arguments, 0).map(|values| {
+            values
+                .borrow_mut()
+                .push(arguments.get(1).cloned().unwrap_or(Value::Nil));
+            Value::Nil
+        }),
+        "pop" => list_argument(arguments, 0)
+            .map(|values| values.borrow_mut().pop().unwrap_or(Value::Nil)),
+        "tostring" => Ok(Value::from(
+            arguments
+                .first()
+                .map_or_else(String::new, ToString::to_string),
+        )),
+        "tonumber" => Ok(match arguments.first() {
+            Some(Value::Number(number)) => Value::Number(*number),
+            Some(Value::String(string)) => string.trim().parse().map_or(Value::Nil, Value::Number),
+            _ => Value::Nil,
+        }),
+        "type" => Ok(Value::from(
+            arguments.first().map_or("nil", Value::type_name),
+        )),
+        _ => return None,
+    };
+    Some(result)
+}
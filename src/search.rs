@@ -0,0 +1,20 @@
+use regex::{Regex, RegexBuilder};
+
+// Compiles what was typed after `/`, escaping it first when searching for it
+// literally. Errors are cut down to the line that says what is wrong, so they
+// fit in the status bar.
+pub fn compile(pattern: &str, literal: bool, ignore_case: bool) -> Result<Regex, String> {
+    let pattern = if literal {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|error| {
+            let message = error.to_string();
+            let reason = message.lines().last().unwrap_or_default();
+            format!("Invalid pattern: {}", reason.trim_start_matches("error: "))
+        })
+}
@@ -0,0 +1,124 @@
+use crate::config;
+use crate::Position;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MAX_FILES: usize = 200;
+
+// The file `:mksession` writes when not given one.
+pub const DEFAULT_FILE: &str = "Session.ggedit";
+
+// A recently edited file and where the cursor and view were in it.
+struct Entry {
+    path: PathBuf,
+    cursor: Position,
+    offset: Position,
+}
+
+// Where the cursor was in recently edited files, kept in `positions` in the
+// config directory so reopening a file carries on where it was left. Each
+// line holds the cursor row and column, the view's row and column, and then
+// the file's path.
+#[derive(Default)]
+pub struct Positions {
+    entries: Vec<Entry>,
+}
+
+impl Positions {
+    pub fn load() -> Self {
+        let entries = path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| contents.lines().filter_map(parse_entry).collect())
+            .unwrap_or_default();
+        Self { entries }
+    }
+
+    pub fn get(&self, file_name: &str) -> Option<(Position, Position)> {
+        let path = canonical(file_name);
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .map(|entry| (entry.cursor.clone(), entry.offset.clone()))
+    }
+
+    // Remembers a position, moving the file to the end so the oldest ones
+    // are forgotten first.
+    pub fn set(&mut self, file_name: &str, cursor: &Position, offset: &Position) {
+        let path = canonical(file_name);
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.push(Entry {
+            path,
+            cursor: cursor.clone(),
+            offset: offset.clone(),
+        });
+        let excess = self.entries.len().saturating_sub(MAX_FILES);
+        self.entries.drain(..excess);
+    }
+
+    pub fn save(&self) -> Result<(), io::Error> {
+        let Some(path) = path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{} {} {} {} {}\n",
+                    entry.cursor.y,
+                    entry.cursor.x,
+                    entry.offset.y,
+                    entry.offset.x,
+                    entry.path.display()
+                )
+            })
+            .collect();
+        fs::write(path, contents)
+    }
+}
+
+// Writes the Ex commands that open `files` again and return to the one at
+// index `current`, for `:source` to run.
+pub fn write(path: &Path, files: &[&str], current: usize) -> Result<(), io::Error> {
+    let mut contents = String::from("\" ggedit session\n");
+    for file in files {
+        contents.push_str(&format!("e {}\n", canonical(file).display()));
+    }
+    // Editing an open file again switches back to it.
+    if let Some(file) = files.get(current) {
+        contents.push_str(&format!("e {}\n", canonical(file).display()));
+    }
+    fs::write(path, contents)
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut fields = line.splitn(5, ' ');
+    let mut number = || fields.next()?.parse::<usize>().ok();
+    let cursor = Position {
+        y: number()?,
+        x: number()?,
+    };
+    let offset = Position {
+        y: number()?,
+        x: number()?,
+    };
+    let path = PathBuf::from(fields.next()?);
+    Some(Entry {
+        path,
+        cursor,
+        offset,
+    })
+}
+
+// Files are told apart by their absolute path, however they were opened.
+fn canonical(file_name: &str) -> PathBuf {
+    fs::canonicalize(file_name).unwrap_or_else(|_| PathBuf::from(file_name))
+}
+
+fn path() -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join("positions"))
+}
@@ -0,0 +1,246 @@
+pub struct Settings {
+    pub shiftwidth: usize,
+    pub tabstop: usize,
+    pub expandtab: bool,
+    pub number: bool,
+    pub relativenumber: bool,
+    // Whether searches are regular expressions rather than literal text.
+    pub regex: bool,
+    // Whether saving first copies the old file to `file~`, kept in
+    // `backupdir` when that is set.
+    pub backup: bool,
+    pub backupdir: String,
+    // Whether misspelled words are underlined, going by the dictionary for
+    // `spelllang`.
+    pub spell: bool,
+    pub spelllang: String,
+    // Whether saving strips whitespace from the ends of lines.
+    pub trimtrailingwhitespace: bool,
+    // How many lines above and below the cursor, and columns either side of
+    // it, are kept in sight when scrolling.
+    pub scrolloff: usize,
+    pub sidescrolloff: usize,
+    // Whether the line under the cursor has a background of its own.
+    pub cursorline: bool,
+    // The columns to mark as guides, like `80,120`.
+    pub colorcolumn: String,
+    // Whether tabs, trailing spaces and line ends are drawn.
+    pub list: bool,
+    // Whether `0` and Home go to the first non-blank before the start of the
+    // line.
+    pub smarthome: bool,
+    // Whether the undo history is saved with the file and picked up again
+    // when it is next opened.
+    pub undofile: bool,
+    // The command `:make` runs, with its arguments after it.
+    pub makeprg: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            shiftwidth: 4,
+            tabstop: 4,
+            expandtab: true,
+            number: false,
+            relativenumber: false,
+            regex: true,
+            backup: false,
+            backupdir: String::new(),
+            spell: false,
+            spelllang: String::from("en_US"),
+            trimtrailingwhitespace: false,
+            scrolloff: 0,
+            sidescrolloff: 0,
+            cursorline: false,
+            colorcolumn: String::new(),
+            list: false,
+            smarthome: false,
+            undofile: false,
+            makeprg: String::from("make"),
+        }
+    }
+}
+
+// Every option `:set` knows, for completion.
+pub const NAMES: &[&str] = &[
+    "backup",
+    "backupdir",
+    "colorcolumn",
+    "cursorline",
+    "expandtab",
+    "list",
+    "makeprg",
+    "number",
+    "regex",
+    "relativenumber",
+    "scrolloff",
+    "shiftwidth",
+    "sidescrolloff",
+    "smarthome",
+    "spell",
+    "spelllang",
+    "tabstop",
+    "trimtrailingwhitespace",
+    "undofile",
+];
+
+impl Settings {
+    // Applies a single `:set` argument: `name`, `noname`, `name=value` or
+    // `name?`, which returns the current value instead of changing it.
+    pub fn set(&mut self, argument: &str) -> Result<Option<String>, String> {
+        if let Some(name) = argument.strip_suffix('?') {
+            return self.get(name).map(Some);
+        }
+        if let Some((name, value)) = argument.split_once('=') {
+            if let Some(text) = self.string_mut(name) {
+                *text = value.to_string();
+                return Ok(None);
+            }
+            let Some(number) = self.number_mut(name) else {
+                return Err(format!("Unknown option: {name}"));
+            };
+            // Only widths have to be more than zero.
+            let may_be_zero = matches!(canonical_name(name), "scrolloff" | "sidescrolloff");
+            match value.parse::<usize>() {
+                Ok(parsed) if parsed > 0 || may_be_zero => *number = parsed,
+                _ => return Err(format!("Invalid value for {name}: {value}")),
+            }
+        } else if let Some(flag) = self.flag_mut(argument) {
+            *flag = true;
+        } else if let Some(flag) = argument
+            .strip_prefix("no")
+            .and_then(|name| self.flag_mut(name))
+        {
+            *flag = false;
+        } else {
+            return Err(format!("Unknown option: {argument}"));
+        }
+        Ok(None)
+    }
+
+    pub fn get(&mut self, name: &str) -> Result<String, String> {
+        let full_name = canonical_name(name);
+        if let Some(number) = self.number_mut(name) {
+            return Ok(format!("{full_name}={number}"));
+        }
+        if let Some(text) = self.string_mut(name) {
+            return Ok(format!("{full_name}={text}"));
+        }
+        match self.flag_mut(name) {
+            Some(true) => Ok(full_name.to_string()),
+            Some(false) => Ok(format!("no{full_name}")),
+            None => Err(format!("Unknown option: {name}")),
+        }
+    }
+
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match canonical_name(name) {
+            "expandtab" => Some(&mut self.expandtab),
+            "number" => Some(&mut self.number),
+            "relativenumber" => Some(&mut self.relativenumber),
+            "regex" => Some(&mut self.regex),
+            "backup" => Some(&mut self.backup),
+            "spell" => Some(&mut self.spell),
+            "trimtrailingwhitespace" => Some(&mut self.trimtrailingwhitespace),
+            "cursorline" => Some(&mut self.cursorline),
+            "list" => Some(&mut self.list),
+            "smarthome" => Some(&mut self.smarthome),
+            "undofile" => Some(&mut self.undofile),
+            _ => None,
+        }
+    }
+
+    fn number_mut(&mut self, name: &str) -> Option<&mut usize> {
+        match canonical_name(name) {
+            "shiftwidth" => Some(&mut self.shiftwidth),
+            "tabstop" => Some(&mut self.tabstop),
+            "scrolloff" => Some(&mut self.scrolloff),
+            "sidescrolloff" => Some(&mut self.sidescrolloff),
+            _ => None,
+        }
+    }
+
+    fn string_mut(&mut self, name: &str) -> Option<&mut String> {
+        match canonical_name(name) {
+            "backupdir" => Some(&mut self.backupdir),
+            "spelllang" => Some(&mut self.spelllang),
+            "colorcolumn" => Some(&mut self.colorcolumn),
+            "makeprg" => Some(&mut self.makeprg),
+            _ => None,
+        }
+    }
+
+    // The zero-based display columns of `colorcolumn`, skipping what isn't
+    // a column number.
+    pub fn color_columns(&self) -> Vec<usize> {
+        self.colorcolumn
+            .split(',')
+            .filter_map(|column| column.trim().parse::<usize>().ok()?.checked_sub(1))
+            .collect()
+    }
+
+    // The leading whitespace that spans `width` columns.
+    pub fn indentation(&self, width: usize) -> String {
+        if self.expandtab || self.tabstop == 0 {
+            " ".repeat(width)
+        } else {
+            let tabs = width.checked_div(self.tabstop).unwrap_or(0);
+            let spaces = width.checked_rem(self.tabstop).unwrap_or(width);
+            format!("{}{}", "\t".repeat(tabs), " ".repeat(spaces))
+        }
+    }
+
+    // The number of columns taken by leading whitespace.
+    pub fn indent_width(&self, leading: &str) -> usize {
+        leading.chars().fold(0, |width, c| {
+            if c == '\t' && self.tabstop > 0 {
+                let rem = width.checked_rem(self.tabstop).unwrap_or(0);
+                width.saturating_add(self.tabstop.saturating_sub(rem))
+            } else {
+                width.saturating_add(1)
+            }
+        })
+    }
+}
+
+// The arguments of `:set` from its whitespace separated `words`, where a
+// word ending in `\` goes on with a space and the next one, like
+// `makeprg=cargo\ build`.
+pub fn join_escaped(words: &[&str]) -> Vec<String> {
+    let mut arguments: Vec<String> = Vec::new();
+    let mut continued = false;
+    for word in words {
+        match arguments.last_mut().filter(|_| continued) {
+            Some(last) => last.push_str(word),
+            None => arguments.push((*word).to_string()),
+        }
+        continued = arguments.last().is_some_and(|last| last.ends_with('\\'));
+        if let Some(last) = arguments.last_mut().filter(|_| continued) {
+            last.pop();
+            last.push(' ');
+        }
+    }
+    arguments
+}
+
+// Expands the short option names vim users are used to.
+fn canonical_name(name: &str) -> &str {
+    match name {
+        "bdir" => "backupdir",
+        "bk" => "backup",
+        "cc" => "colorcolumn",
+        "cul" => "cursorline",
+        "et" => "expandtab",
+        "mp" => "makeprg",
+        "nu" => "number",
+        "rnu" => "relativenumber",
+        "so" => "scrolloff",
+        "siso" => "sidescrolloff",
+        "spl" => "spelllang",
+        "sw" => "shiftwidth",
+        "ts" => "tabstop",
+        "udf" => "undofile",
+        _ => name,
+    }
+}
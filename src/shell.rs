@@ -0,0 +1,84 @@
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+// The user's `$SHELL`, or `sh` without one.
+pub fn program() -> String {
+    env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| String::from("sh"))
+}
+
+// `cmd` run by the user's shell, so pipes and globs work like they do at a
+// prompt.
+fn command(cmd: &str) -> Command {
+    let mut command = Command::new(program());
+    command.arg("-c").arg(cmd);
+    command
+}
+
+// `text` as a single word for the shell, whatever it contains.
+pub fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+// Runs `cmd` writing straight to the terminal. Input stays with the editor,
+// whose reader thread would otherwise race the command for keys.
+pub fn run(cmd: &str, input: Option<&str>) -> Result<ExitStatus, io::Error> {
+    let mut child = command(cmd)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .spawn()?;
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        stdin.write_all(input.as_bytes())?;
+    }
+    child.wait()
+}
+
+// Runs `cmd` with `input` on its stdin and returns what it printed, or an
+// error with what it complained about when it fails.
+pub fn output(cmd: &str, input: Option<&str>) -> Result<String, String> {
+    let mut child = command(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("Cannot run {cmd}: {error}"))?;
+    // The input is written from another thread so a command that prints as
+    // it reads can't fill its output pipe while we are still writing.
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        let input = input.to_string();
+        // A command that doesn't read all of its input closes the pipe early,
+        // which is not an error.
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|error| format!("Cannot run {cmd}: {error}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or_default();
+        return Err(match output.status.code() {
+            Some(code) => format!("shell returned {code}: {reason}"),
+            None => format!("shell command was interrupted: {reason}"),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Runs `cmd` to the end and returns whether it succeeded, with what it
+// printed on stdout followed by what it printed on stderr.
+pub fn capture(cmd: &str) -> Result<(bool, String), String> {
+    let output = command(cmd)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| format!("Cannot run {cmd}: {error}"))?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok((output.status.success(), text))
+}
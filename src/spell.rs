@@ -0,0 +1,386 @@
+use crate::config;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+// How many suggestions `z=` offers, each picked with its digit.
+const SUGGESTIONS: usize = 9;
+
+// The words of a language, to tell which ones are misspelled.
+pub struct Dictionary {
+    language: String,
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    // Reads the hunspell dictionary for `language`, like `en_US`, from
+    // `spell/` in the config directory or where distributions install them,
+    // falling back to the system word list.
+    pub fn load(language: &str) -> Result<Self, String> {
+        let mut dirs: Vec<PathBuf> = config::dir()
+            .map(|dir| dir.join("spell"))
+            .into_iter()
+            .collect();
+        dirs.extend(
+            [
+                "/usr/share/hunspell",
+                "/usr/share/myspell",
+                "/usr/share/myspell/dicts",
+            ]
+            .map(PathBuf::from),
+        );
+        let read = |path: PathBuf| {
+            fs::read(path)
+                .ok()
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        };
+        for dir in dirs {
+            if let Some(dic) = read(dir.join(format!("{language}.dic"))) {
+                let aff = read(dir.join(format!("{language}.aff"))).unwrap_or_default();
+                return Ok(Self::hunspell(language, &dic, &aff));
+            }
+        }
+        let words = read(PathBuf::from("/usr/share/dict/words"))
+            .ok_or_else(|| format!("No dictionary for {language}"))?;
+        Ok(Self {
+            language: language.to_string(),
+            words: words.lines().map(String::from).collect(),
+        })
+    }
+
+    // A dictionary from the `.dic` word list of hunspell, with each word
+    // expanded into the forms its flags allow by the affix rules of `.aff`.
+    pub fn hunspell(language: &str, dic: &str, aff: &str) -> Self {
+        let affixes = Affixes::parse(aff);
+        let mut words = HashSet::new();
+        // The first line is the number of words.
+        for line in dic.lines().skip(1) {
+            let Some(entry) = line.split_whitespace().next() else {
+                continue;
+            };
+            let (word, flags) = entry.split_once('/').unwrap_or((entry, ""));
+            affixes.expand(word, &affixes.flags(flags), &mut words);
+        }
+        Self {
+            language: language.to_string(),
+            words,
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    // Words are accepted as they are in the dictionary, or capitalized or in
+    // capitals when it has them in lowercase.
+    pub fn check(&self, word: &str) -> bool {
+        if self.words.contains(word) {
+            return true;
+        }
+        let lowercase = word.to_lowercase();
+        let mut letters = word.chars();
+        let capitalized = letters.next().is_some_and(char::is_uppercase)
+            && letters.as_str().to_lowercase() == letters.as_str();
+        if capitalized && self.words.contains(&lowercase) {
+            return true;
+        }
+        word.to_uppercase() == word
+            && (self.words.contains(&lowercase) || self.words.contains(&capitalize(&lowercase)))
+    }
+
+    // The spans of the words in `chars` that aren't in the dictionary. Words
+    // with digits, underscores or capitals inside are names from code, which
+    // are left alone.
+    pub fn misspelled(&self, chars: &[char]) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let is_word = |x: usize| {
+                chars
+                    .get(x)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+            };
+            if !is_word(start) {
+                start = start.saturating_add(1);
+                continue;
+            }
+            let mut end = start;
+            // An apostrophe between letters is part of the word, as in
+            // "don't".
+            while is_word(end)
+                || (chars.get(end) == Some(&'\'') && end > start && is_word(end.saturating_add(1)))
+            {
+                end = end.saturating_add(1);
+            }
+            let word: String = chars.get(start..end).unwrap_or_default().iter().collect();
+            let is_name = word.contains(|c: char| c.is_numeric() || c == '_')
+                || (word.to_uppercase() != word && word.chars().skip(1).any(char::is_uppercase));
+            if !is_name && !self.check(&word) {
+                spans.push((start, end));
+            }
+            start = end;
+        }
+        spans
+    }
+
+    // The words closest to `word`, at most two typos away, in the same case.
+    pub fn suggest(&self, word: &str) -> Vec<String> {
+        let lowercase: Vec<char> = word.to_lowercase().chars().collect();
+        let mut candidates: Vec<(usize, &String)> = self
+            .words
+            .iter()
+            .filter(|candidate| candidate.chars().count().abs_diff(lowercase.len()) <= 2)
+            .filter_map(|candidate| {
+                let chars: Vec<char> = candidate.to_lowercase().chars().collect();
+                let distance = distance(&lowercase, &chars);
+                (distance <= 2).then_some((distance, candidate))
+            })
+            .collect();
+        candidates.sort();
+        let capitalized = word.chars().next().is_some_and(char::is_uppercase);
+        let mut suggestions: Vec<String> = Vec::new();
+        for (_, candidate) in candidates {
+            let suggestion = if capitalized {
+                capitalize(candidate)
+            } else {
+                candidate.clone()
+            };
+            if suggestion != word && !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+            if suggestions.len() == SUGGESTIONS {
+                break;
+            }
+        }
+        suggestions
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+// The number of characters to insert, delete, replace or swap with the next
+// one to turn `a` into `b`.
+fn distance(a: &[char], b: &[char]) -> usize {
+    let width = b.len().saturating_add(1);
+    let mut rows = vec![vec![0_usize; width]; a.len().saturating_add(1)];
+    for (i, row) in rows.iter_mut().enumerate() {
+        if let Some(first) = row.first_mut() {
+            *first = i;
+        }
+    }
+    if let Some(first) = rows.first_mut() {
+        for (j, cell) in first.iter_mut().enumerate() {
+            *cell = j;
+        }
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let at = |rows: &Vec<Vec<usize>>, i: usize, j: usize| {
+                rows.get(i).and_then(|row| row.get(j)).copied().unwrap_or(0)
+            };
+            let cost = usize::from(a.get(i.saturating_sub(1)) != b.get(j.saturating_sub(1)));
+            let mut best = at(&rows, i.saturating_sub(1), j)
+                .saturating_add(1)
+                .min(at(&rows, i, j.saturating_sub(1)).saturating_add(1))
+                .min(at(&rows, i.saturating_sub(1), j.saturating_sub(1)).saturating_add(cost));
+            let swapped = i > 1
+                && j > 1
+                && a.get(i.saturating_sub(1)) == b.get(j.saturating_sub(2))
+                && a.get(i.saturating_sub(2)) == b.get(j.saturating_sub(1));
+            if swapped {
+                best =
+                    best.min(at(&rows, i.saturating_sub(2), j.saturating_sub(2)).saturating_add(1));
+            }
+            if let Some(cell) = rows.get_mut(i).and_then(|row| row.get_mut(j)) {
+                *cell = best;
+            }
+        }
+    }
+    rows.last().and_then(|row| row.last()).copied().unwrap_or(0)
+}
+
+// One character of an affix condition.
+enum Class {
+    Any,
+    OneOf(Vec<char>),
+    NoneOf(Vec<char>),
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::OneOf(chars) => chars.contains(&c),
+            Self::NoneOf(chars) => !chars.contains(&c),
+        }
+    }
+}
+
+// A prefix or suffix rule: `strip` is taken off the word and `add` put in
+// its place, when the start or end of the word matches `condition`.
+struct Affix {
+    prefix: bool,
+    // Whether prefixes and suffixes can be combined.
+    cross: bool,
+    strip: String,
+    add: String,
+    condition: Vec<Class>,
+}
+
+impl Affix {
+    fn apply(&self, word: &str) -> Option<String> {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() < self.condition.len() {
+            return None;
+        }
+        let tested = if self.prefix {
+            chars.get(..self.condition.len())
+        } else {
+            chars.get(chars.len().saturating_sub(self.condition.len())..)
+        }?;
+        if !self
+            .condition
+            .iter()
+            .zip(tested)
+            .all(|(class, c)| class.matches(*c))
+        {
+            return None;
+        }
+        if self.prefix {
+            let rest = word.strip_prefix(self.strip.as_str())?;
+            Some(format!("{}{rest}", self.add))
+        } else {
+            let rest = word.strip_suffix(self.strip.as_str())?;
+            Some(format!("{rest}{}", self.add))
+        }
+    }
+}
+
+// How flags are written in a `.dic` file.
+#[derive(Clone, Copy)]
+enum FlagFormat {
+    Char,
+    // Two characters each, with `FLAG long`.
+    Long,
+    // Numbers separated by commas, with `FLAG num`.
+    Numeric,
+}
+
+// The affix rules of an `.aff` file, by flag.
+struct Affixes {
+    format: FlagFormat,
+    rules: HashMap<String, Vec<Affix>>,
+}
+
+impl Affixes {
+    fn parse(aff: &str) -> Self {
+        let mut affixes = Self {
+            format: FlagFormat::Char,
+            rules: HashMap::new(),
+        };
+        let mut cross = HashMap::new();
+        for line in aff.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FLAG", "long", ..] => affixes.format = FlagFormat::Long,
+                ["FLAG", "num", ..] => affixes.format = FlagFormat::Numeric,
+                // The header of a rule group, saying whether it combines.
+                [kind @ ("PFX" | "SFX"), flag, combines, count, ..]
+                    if count.parse::<usize>().is_ok() && matches!(*combines, "Y" | "N") =>
+                {
+                    cross.insert((*kind, flag.to_string()), *combines == "Y");
+                }
+                [kind @ ("PFX" | "SFX"), flag, strip, add, condition, ..] => {
+                    let zero = |text: &str| {
+                        if text == "0" {
+                            String::new()
+                        } else {
+                            text.to_string()
+                        }
+                    };
+                    // Continuation flags after the affix aren't supported.
+                    let add = add.split('/').next().unwrap_or_default();
+                    affixes
+                        .rules
+                        .entry(flag.to_string())
+                        .or_default()
+                        .push(Affix {
+                            prefix: *kind == "PFX",
+                            cross: cross
+                                .get(&(*kind, flag.to_string()))
+                                .copied()
+                                .unwrap_or(false),
+                            strip: zero(strip),
+                            add: zero(add),
+                            condition: parse_condition(condition),
+                        });
+                }
+                _ => (),
+            }
+        }
+        affixes
+    }
+
+    fn flags(&self, flags: &str) -> Vec<String> {
+        match self.format {
+            FlagFormat::Char => flags.chars().map(String::from).collect(),
+            FlagFormat::Long => {
+                let chars: Vec<char> = flags.chars().collect();
+                chars.chunks(2).map(|pair| pair.iter().collect()).collect()
+            }
+            FlagFormat::Numeric => flags.split(',').map(String::from).collect(),
+        }
+    }
+
+    // Adds `word` and every form its affixes make of it to `words`.
+    fn expand(&self, word: &str, flags: &[String], words: &mut HashSet<String>) {
+        let rules: Vec<&Affix> = flags
+            .iter()
+            .filter_map(|flag| self.rules.get(flag))
+            .flatten()
+            .collect();
+        let suffixed: Vec<(String, bool)> = rules
+            .iter()
+            .filter(|rule| !rule.prefix)
+            .filter_map(|rule| Some((rule.apply(word)?, rule.cross)))
+            .collect();
+        for rule in rules.iter().filter(|rule| rule.prefix) {
+            words.extend(rule.apply(word));
+            if rule.cross {
+                for (form, _) in suffixed.iter().filter(|(_, cross)| *cross) {
+                    words.extend(rule.apply(form));
+                }
+            }
+        }
+        words.extend(suffixed.into_iter().map(|(form, _)| form));
+        words.insert(word.to_string());
+    }
+}
+
+// A condition like `[^aeiou]y`, or `.` for any word.
+fn parse_condition(condition: &str) -> Vec<Class> {
+    if condition == "." {
+        return Vec::new();
+    }
+    let mut classes = Vec::new();
+    let mut chars = condition.chars();
+    while let Some(c) = chars.next() {
+        classes.push(match c {
+            '.' => Class::Any,
+            '[' => {
+                let set: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                match set.strip_prefix('^') {
+                    Some(set) => Class::NoneOf(set.chars().collect()),
+                    None => Class::OneOf(set.chars().collect()),
+                }
+            }
+            c => Class::OneOf(vec![c]),
+        });
+    }
+    classes
+}
@@ -0,0 +1,140 @@
+use crate::config;
+
+// Something the status bar can show.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Segment {
+    // The keys of a command that isn't finished yet, like `"a2d`.
+    Pending,
+    // The mode, like `[ Normal ]`.
+    Mode,
+    // The file name, cut down to 20 characters.
+    File,
+    // `[+]` when there are unsaved changes.
+    Modified,
+    // `[RO]` for read-only buffers.
+    ReadOnly,
+    // The git branch, like `[main*]`.
+    Branch,
+    // The language server's problems, like `[E2 W1]`.
+    Diagnostics,
+    // Which of the matches of the highlighted search the cursor is on, like
+    // `[4/23]`.
+    Search,
+    // Which of the files given on the command line this is, like `(2 of 3)`.
+    Arguments,
+    // How far a large file has been read, like `[loading 40%]`.
+    Loading,
+    FileType,
+    Encoding,
+    FileFormat,
+    // The cursor line out of the number of lines, like `3/120`.
+    Position,
+    Line,
+    // The display column of the cursor, like `col 7`.
+    Column,
+    // How much of the file is above the screen, like `42%`, or `Top`, `Bot`
+    // or `All` when the screen shows its start, end or all of it.
+    Percent,
+}
+
+impl Segment {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pending" => Some(Self::Pending),
+            "mode" => Some(Self::Mode),
+            "file" => Some(Self::File),
+            "modified" => Some(Self::Modified),
+            "readonly" => Some(Self::ReadOnly),
+            "branch" => Some(Self::Branch),
+            "diagnostics" => Some(Self::Diagnostics),
+            "search" => Some(Self::Search),
+            "arguments" => Some(Self::Arguments),
+            "loading" => Some(Self::Loading),
+            "filetype" => Some(Self::FileType),
+            "encoding" => Some(Self::Encoding),
+            "fileformat" => Some(Self::FileFormat),
+            "position" => Some(Self::Position),
+            "line" => Some(Self::Line),
+            "column" => Some(Self::Column),
+            "percent" => Some(Self::Percent),
+            _ => None,
+        }
+    }
+}
+
+// What the status bar shows on its left and right, read from the
+// `[statusline]` table of `config.toml`:
+//
+//     [statusline]
+//     left = ["file", "modified", "branch"]
+//     right = ["pending", "mode", "filetype", "percent", "line", "column"]
+//
+// Segments with nothing to show are left out, and the rest are separated by
+// spaces.
+#[derive(PartialEq, Debug)]
+pub struct StatusLine {
+    pub left: Vec<Segment>,
+    pub right: Vec<Segment>,
+}
+
+impl Default for StatusLine {
+    fn default() -> Self {
+        Self {
+            left: vec![
+                Segment::File,
+                Segment::Modified,
+                Segment::ReadOnly,
+                Segment::Branch,
+                Segment::Diagnostics,
+                Segment::Arguments,
+                Segment::Loading,
+            ],
+            right: vec![
+                Segment::Search,
+                Segment::Pending,
+                Segment::Mode,
+                Segment::Encoding,
+                Segment::FileFormat,
+                Segment::Position,
+                Segment::Column,
+                Segment::Percent,
+            ],
+        }
+    }
+}
+
+impl StatusLine {
+    pub fn load() -> Result<Self, String> {
+        Self::from_section(config::section("statusline")?)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        Self::from_section(config::section_in(contents, "statusline")?)
+    }
+
+    fn from_section(sides: Option<toml::Table>) -> Result<Self, String> {
+        let mut status_line = Self::default();
+        let Some(sides) = sides else {
+            return Ok(status_line);
+        };
+        for (side, segments) in &sides {
+            let segments = segments
+                .as_array()
+                .ok_or_else(|| format!("statusline.{side} must be a list of segments"))?
+                .iter()
+                .map(|segment| {
+                    segment
+                        .as_str()
+                        .and_then(Segment::from_name)
+                        .ok_or_else(|| format!("Unknown status line segment: {segment}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            match side.as_str() {
+                "left" => status_line.left = segments,
+                "right" => status_line.right = segments,
+                _ => return Err(format!("Unknown status line side: {side}")),
+            }
+        }
+        Ok(status_line)
+    }
+}
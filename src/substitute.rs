@@ -0,0 +1,105 @@
+// A parsed `:s/pattern/replacement/flags` command.
+pub struct Substitute {
+    pub pattern: String,
+    // In the regex crate's syntax, ready for `Regex::replace`.
+    pub replacement: String,
+    // `g`: replace every match on a line, not just the first.
+    pub global: bool,
+    // `c`: ask before each replacement.
+    pub confirm: bool,
+    // `i`: ignore case.
+    pub ignore_case: bool,
+}
+
+impl Substitute {
+    // Parses a command like `s/a/b/g` or `substitute/a/b/`, without its
+    // range. Any punctuation can delimit the parts and `\` escapes it, so
+    // `:s#/usr#/opt#` and `:s/\/usr/\/opt/` both work. Returns `None` when
+    // this isn't a substitution at all, like `:set`.
+    pub fn parse(command: &str) -> Option<Result<Self, String>> {
+        let text = command
+            .strip_prefix("substitute")
+            .or_else(|| command.strip_prefix('s'))?;
+        let delimiter = text.chars().next()?;
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() || delimiter == '\\' {
+            return None;
+        }
+        let parts = split(
+            text.get(delimiter.len_utf8()..).unwrap_or_default(),
+            delimiter,
+        );
+        let mut parts = parts.into_iter();
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let flags = parts.next().unwrap_or_default();
+        if pattern.is_empty() {
+            return Some(Err(String::from("Empty pattern")));
+        }
+        let mut substitute = Self {
+            pattern,
+            replacement: replacement_syntax(&replacement),
+            global: false,
+            confirm: false,
+            ignore_case: false,
+        };
+        for flag in flags.trim_end().chars() {
+            match flag {
+                'g' => substitute.global = true,
+                'c' => substitute.confirm = true,
+                'i' => substitute.ignore_case = true,
+                'I' => substitute.ignore_case = false,
+                _ => return Some(Err(format!("Invalid flag: {flag}"))),
+            }
+        }
+        Some(Ok(substitute))
+    }
+}
+
+// Splits on unescaped delimiters, dropping the `\` that escapes one.
+fn split(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let Some(part) = parts.last_mut() else {
+            break;
+        };
+        if c == '\\' {
+            match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push(c);
+                    part.push(next);
+                }
+                None => part.push(c),
+            }
+        } else if c == delimiter {
+            parts.push(String::new());
+        } else {
+            part.push(c);
+        }
+    }
+    parts
+}
+
+// Turns vim's replacement syntax into the regex crate's: `&` and `\0` are the
+// whole match, `\1`–`\9` are groups and `\r` (or `\n`) breaks the line.
+fn replacement_syntax(replacement: &str) -> String {
+    let mut converted = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => converted.push_str("${0}"),
+            '$' => converted.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => converted.push_str(&format!("${{{digit}}}")),
+                Some('r' | 'n') => converted.push('\n'),
+                Some('t') => converted.push('\t'),
+                Some('$') => converted.push_str("$$"),
+                Some(other) => converted.push(other),
+                None => converted.push('\\'),
+            },
+            _ => converted.push(c),
+        }
+    }
+    converted
+}
@@ -0,0 +1,95 @@
+use crate::Position;
+
+// Something the word being typed could become.
+#[derive(Clone)]
+pub struct Suggestion {
+    pub label: String,
+    // What replaces the word when it is picked, which is the label unless a
+    // language server says otherwise.
+    pub text: String,
+    // A short note shown after the label, like a type.
+    pub detail: String,
+}
+
+impl From<String> for Suggestion {
+    fn from(word: String) -> Self {
+        Self {
+            label: word.clone(),
+            text: word,
+            detail: String::new(),
+        }
+    }
+}
+
+// The completion menu of Insert mode: what the word starting at `start` could
+// be, narrowed down to what still matches as more of it is typed.
+pub struct Suggestions {
+    pub start: Position,
+    all: Vec<Suggestion>,
+    // Indexes into `all` of the suggestions matching what is typed.
+    matching: Vec<usize>,
+    selected: usize,
+}
+
+impl Suggestions {
+    // `None` when nothing matches `typed`.
+    pub fn new(start: Position, all: Vec<Suggestion>, typed: &str) -> Option<Self> {
+        let mut suggestions = Self {
+            start,
+            all,
+            matching: Vec::new(),
+            selected: 0,
+        };
+        suggestions.filter(typed).then_some(suggestions)
+    }
+
+    // Keeps the suggestions starting with `typed`, ignoring case, and
+    // returns whether there are any.
+    pub fn filter(&mut self, typed: &str) -> bool {
+        let typed = typed.to_lowercase();
+        self.matching = self
+            .all
+            .iter()
+            .enumerate()
+            .filter(|(_, suggestion)| {
+                let label = suggestion.label.to_lowercase();
+                label.starts_with(&typed) && label != typed
+            })
+            .map(|(index, _)| index)
+            .collect();
+        self.selected = 0;
+        !self.matching.is_empty()
+    }
+
+    // Moves the selection by `delta`, wrapping around at either end.
+    pub fn select(&mut self, delta: isize) {
+        let len = self.matching.len() as isize;
+        if len > 0 {
+            self.selected = (self.selected as isize)
+                .saturating_add(delta)
+                .rem_euclid(len) as usize;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Suggestion> {
+        self.all.get(*self.matching.get(self.selected)?)
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn matching(&self) -> impl Iterator<Item = &Suggestion> {
+        self.matching
+            .iter()
+            .filter_map(|&index| self.all.get(index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.matching.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matching.is_empty()
+    }
+}
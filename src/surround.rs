@@ -0,0 +1,90 @@
+use crate::motion;
+use crate::Document;
+use crate::Position;
+use crate::TextObject;
+
+// The text `ys` and `cs` put on either side for the character typed after
+// them. An opening bracket pads the inside with spaces and a closing one
+// doesn't, like `b` and `B`, while other punctuation goes on both sides.
+pub fn pair(c: char) -> Option<(String, String)> {
+    let (open, close, padded) = match c {
+        '(' => ('(', ')', true),
+        ')' | 'b' => ('(', ')', false),
+        '[' => ('[', ']', true),
+        ']' => ('[', ']', false),
+        '{' => ('{', '}', true),
+        '}' | 'B' => ('{', '}', false),
+        '<' | '>' => ('<', '>', false),
+        c if c.is_ascii_punctuation() => (c, c, false),
+        _ => return None,
+    };
+    Some(if padded {
+        (format!("{open} "), format!(" {close}"))
+    } else {
+        (open.to_string(), close.to_string())
+    })
+}
+
+// Where the delimiters around something are, as spans ending exclusively.
+pub struct Delimiters {
+    pub open: (Position, Position),
+    pub close: (Position, Position),
+}
+
+// The delimiters `ds` and `cs` act on for `c` around `at`: brackets as found
+// by their text object, and quotes or other punctuation paired up on the
+// line. For an opening bracket, the spaces just inside go with them.
+pub fn find(document: &Document, at: &Position, c: char) -> Option<Delimiters> {
+    let object = match TextObject::from_char(c) {
+        Some(TextObject::Word | TextObject::Paragraph) => return None,
+        Some(object) => object,
+        None if c.is_ascii_punctuation() => TextObject::Quote(c),
+        None => return None,
+    };
+    // Around a bracket is exactly the brackets and what they hold, but inside
+    // quotes is what leaves out exactly the quotes.
+    let (open, close) = if let TextObject::Bracket(..) = object {
+        let selection = object.selection(document, at, true)?;
+        let close = Position {
+            x: selection.end.x.saturating_sub(1),
+            y: selection.end.y,
+        };
+        (selection.start, close)
+    } else {
+        let selection = object.selection(document, at, false)?;
+        let open = Position {
+            x: selection.start.x.saturating_sub(1),
+            y: selection.start.y,
+        };
+        (open, selection.end)
+    };
+    let mut inner_start = Position {
+        x: open.x.saturating_add(1),
+        y: open.y,
+    };
+    let mut inner_end = close.clone();
+    if matches!(c, '(' | '[' | '{') {
+        let is_space = |at: &Position| motion::char_at(document, at) == ' ';
+        while inner_start.y == inner_end.y && inner_start.x < inner_end.x && is_space(&inner_start)
+        {
+            inner_start.x = inner_start.x.saturating_add(1);
+        }
+        while inner_start.y == inner_end.y
+            && inner_end.x > inner_start.x
+            && is_space(&Position {
+                x: inner_end.x.saturating_sub(1),
+                y: inner_end.y,
+            })
+        {
+            inner_end.x = inner_end.x.saturating_sub(1);
+        }
+    }
+    let close_end = Position {
+        x: close.x.saturating_add(1),
+        y: close.y,
+    };
+    Some(Delimiters {
+        open: (open, inner_start),
+        close: (inner_end, close_end),
+    })
+}
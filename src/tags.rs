@@ -0,0 +1,157 @@
+use crate::completion::Source;
+use crate::{Document, Position};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Where a tag is in its file: a line number, or a line to look for, as
+// ctags writes `/^fn main() {$/`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Address {
+    Line(usize),
+    Pattern {
+        text: String,
+        at_start: bool,
+        at_end: bool,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Tag {
+    pub name: String,
+    // Relative to the working directory when it is below it.
+    pub file: String,
+    pub address: Address,
+}
+
+// The tags of a `tags` file as ctags writes them, one per line:
+//
+//     main	src/main.rs	/^fn main() {$/;"	f
+//
+// Lines starting with `!_TAG_` describe the file itself and are skipped.
+#[derive(Default)]
+pub struct Tags {
+    tags: Vec<Tag>,
+}
+
+impl Tags {
+    // The `tags` file in the working directory or the closest directory
+    // above it, like vim's `./tags;`.
+    pub fn load() -> Result<Self, String> {
+        let current = env::current_dir().map_err(|error| error.to_string())?;
+        let path = current
+            .ancestors()
+            .map(|dir| dir.join("tags"))
+            .find(|path| path.is_file())
+            .ok_or_else(|| String::from("No tags file"))?;
+        let contents = fs::read_to_string(&path)
+            .map_err(|error| format!("Error reading {}: {error}", path.display()))?;
+        let dir = path.parent().unwrap_or(&current);
+        Ok(Self::parse(&contents, dir, &current))
+    }
+
+    // Tags read from `contents`, with their files relative to `dir`.
+    pub fn parse(contents: &str, dir: &Path, current: &Path) -> Self {
+        let tags = contents
+            .lines()
+            .filter(|line| !line.starts_with("!_TAG_"))
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let name = fields.next()?;
+                let file = dir.join(fields.next()?);
+                let file = file.strip_prefix(current).unwrap_or(&file);
+                let address = parse_address(fields.next()?)?;
+                Some(Tag {
+                    name: name.to_string(),
+                    file: file.to_string_lossy().into_owned(),
+                    address,
+                })
+            })
+            .collect();
+        Self { tags }
+    }
+
+    pub fn find(&self, name: &str) -> Vec<&Tag> {
+        self.tags.iter().filter(|tag| tag.name == name).collect()
+    }
+}
+
+impl Source for Tags {
+    fn complete(&self, word: &str) -> Vec<String> {
+        self.tags
+            .iter()
+            .map(|tag| tag.name.as_str())
+            .filter(|name| name.starts_with(word))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+}
+
+// A line number, or a search pattern, followed by an optional `;"` and the
+// extra fields.
+fn parse_address(field: &str) -> Option<Address> {
+    let address = field
+        .split_once(";\"")
+        .map_or(field, |(address, _)| address);
+    if let Ok(line) = address.trim().parse::<usize>() {
+        return Some(Address::Line(line));
+    }
+    let delimiter = address.chars().next().filter(|c| matches!(c, '/' | '?'))?;
+    let pattern = address.get(1..)?.strip_suffix(delimiter)?;
+    let (at_start, pattern) = match pattern.strip_prefix('^') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let (at_end, pattern) = match pattern.strip_suffix('$') {
+        Some(rest) if !rest.ends_with('\\') => (true, rest),
+        _ => (false, pattern),
+    };
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            text.extend(chars.next());
+        } else {
+            text.push(c);
+        }
+    }
+    Some(Address::Pattern {
+        text,
+        at_start,
+        at_end,
+    })
+}
+
+impl Tag {
+    // Where the tag is in `document`, on its name when the line has it.
+    pub fn position(&self, document: &Document) -> Option<Position> {
+        let y = match &self.address {
+            Address::Line(line) => line.saturating_sub(1).min(document.len().saturating_sub(1)),
+            Address::Pattern {
+                text,
+                at_start,
+                at_end,
+            } => (0..document.len()).find(|y| {
+                document.row(*y).is_some_and(|row| {
+                    let line = row.as_str();
+                    match (at_start, at_end) {
+                        (true, true) => line == text,
+                        (true, false) => line.starts_with(text.as_str()),
+                        (false, true) => line.ends_with(text.as_str()),
+                        (false, false) => line.contains(text.as_str()),
+                    }
+                })
+            })?,
+        };
+        let row = document.row(y)?;
+        let x = Regex::new(&regex::escape(&self.name))
+            .ok()
+            .and_then(|name| row.matches(&name).first().map(|(x, _)| *x))
+            .unwrap_or(0);
+        Some(Position { x, y })
+    }
+}
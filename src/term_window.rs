@@ -0,0 +1,377 @@
+use crate::event::Event;
+use crate::shell;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+use std::ptr;
+use std::sync::mpsc::Sender;
+use std::thread;
+use termion::event::Key;
+
+// A shell running in a pseudo-terminal, shown in a window under the text.
+// What it prints arrives as `Event::TerminalOutput` and is kept in `screen`.
+pub struct TermWindow {
+    pub title: String,
+    pub screen: Screen,
+    master: File,
+    child: Child,
+}
+
+impl TermWindow {
+    // Starts the user's shell on a terminal of `width` by `height`.
+    pub fn open(width: usize, height: usize, sender: Sender<Event>) -> Result<Self, String> {
+        let error = |error: io::Error| format!("Cannot open a terminal: {error}");
+        let (mut master, mut slave): (RawFd, RawFd) = (0, 0);
+        let size = window_size(width, height);
+        // SAFETY: the pointers are to locals that outlive the call, and a
+        // null name and termios are allowed.
+        let opened =
+            unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), &size) };
+        if opened != 0 {
+            return Err(error(io::Error::last_os_error()));
+        }
+        // SAFETY: openpty returned two descriptors that nothing else owns.
+        let (master, slave) = unsafe {
+            libc::fcntl(master, libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl(slave, libc::F_SETFD, libc::FD_CLOEXEC);
+            (File::from_raw_fd(master), File::from_raw_fd(slave))
+        };
+        let title = shell::program();
+        let mut command = Command::new(&title);
+        // Only the simplest control sequences are understood, so programs
+        // are asked not to send more.
+        command
+            .env("TERM", "dumb")
+            .stdin(slave.try_clone().map_err(error)?)
+            .stdout(slave.try_clone().map_err(error)?)
+            .stderr(slave);
+        // SAFETY: only async-signal-safe calls are made between fork and
+        // exec. The shell gets a session of its own, with the pseudo-terminal
+        // as its controlling terminal so Ctrl-C and job control work.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn().map_err(error)?;
+        // Our copies of the other end go with the command, so reading stops
+        // once the shell and whatever it started are gone.
+        drop(command);
+        let mut reader = master.try_clone().map_err(error)?;
+        thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            loop {
+                let event = match reader.read(&mut buffer) {
+                    Ok(0) | Err(_) => Event::TerminalClosed,
+                    Ok(read) => {
+                        Event::TerminalOutput(buffer.get(..read).unwrap_or_default().to_vec())
+                    }
+                };
+                let closed = matches!(event, Event::TerminalClosed);
+                if sender.send(event).is_err() || closed {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            title,
+            screen: Screen::new(width, height),
+            master,
+            child,
+        })
+    }
+
+    // Sends `key` to the shell as a terminal would.
+    pub fn send(&mut self, key: Key) {
+        let _ = self.master.write_all(&key_bytes(key));
+    }
+
+    // Tells the shell the window is now `width` by `height`.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if self.screen.size() == (width, height) {
+            return;
+        }
+        self.screen.resize(width, height);
+        let size = window_size(width, height);
+        // SAFETY: `size` is a valid winsize for the duration of the call.
+        unsafe {
+            libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &size);
+        }
+    }
+}
+
+impl Drop for TermWindow {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn window_size(width: usize, height: usize) -> libc::winsize {
+    libc::winsize {
+        ws_row: u16::try_from(height).unwrap_or(u16::MAX),
+        ws_col: u16::try_from(width).unwrap_or(u16::MAX),
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+// The bytes a terminal sends for `key`.
+pub fn key_bytes(key: Key) -> Vec<u8> {
+    match key {
+        Key::Char('\n') => vec![b'\r'],
+        Key::Char(c) => c.to_string().into_bytes(),
+        Key::Alt(c) => format!("\x1b{c}").into_bytes(),
+        Key::Ctrl(c) if c.is_ascii() => vec![(c as u8) & 0x1f],
+        Key::Backspace => vec![0x7f],
+        Key::Up => b"\x1b[A".to_vec(),
+        Key::Down => b"\x1b[B".to_vec(),
+        Key::Right => b"\x1b[C".to_vec(),
+        Key::Left => b"\x1b[D".to_vec(),
+        Key::Home => b"\x1b[H".to_vec(),
+        Key::End => b"\x1b[F".to_vec(),
+        Key::Delete => b"\x1b[3~".to_vec(),
+        Key::PageUp => b"\x1b[5~".to_vec(),
+        Key::PageDown => b"\x1b[6~".to_vec(),
+        Key::BackTab => b"\x1b[Z".to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+enum Parser {
+    Text,
+    // After an Esc, waiting to see what sequence it starts.
+    Escape,
+    // A control sequence, `Esc [` followed by its parameters.
+    Control(String),
+    // An operating system command like a window title, which runs until a
+    // bell or an Esc.
+    Command,
+}
+
+// What the shell has drawn: a grid of characters and a cursor. Lines that
+// scroll off the top are gone.
+pub struct Screen {
+    rows: Vec<Vec<char>>,
+    width: usize,
+    x: usize,
+    y: usize,
+    parser: Parser,
+    // The start of a character split between two reads.
+    partial: Vec<u8>,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            rows: vec![vec![' '; width]; height],
+            width,
+            x: 0,
+            y: 0,
+            parser: Parser::Text,
+            partial: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.rows.len())
+    }
+
+    // Where the cursor is, as a column and a row.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.x.min(self.width.saturating_sub(1)), self.y)
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.rows.iter().map(|row| row.iter().collect()).collect()
+    }
+
+    // Keeps the bottom of what is shown when there are fewer rows, so the
+    // prompt stays in view.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        for row in &mut self.rows {
+            row.resize(width, ' ');
+        }
+        let excess = self.rows.len().saturating_sub(height);
+        let unused = self.rows.len().saturating_sub(self.y.saturating_add(1));
+        let removed = excess.saturating_sub(unused);
+        self.rows.drain(..removed);
+        self.rows.truncate(height);
+        self.rows.resize(height, vec![' '; width]);
+        self.width = width;
+        self.y = self.y.saturating_sub(removed).min(height.saturating_sub(1));
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut bytes = [self.partial.as_slice(), bytes].concat();
+        self.partial.clear();
+        loop {
+            match std::str::from_utf8(&bytes) {
+                Ok(text) => {
+                    text.chars().for_each(|c| self.put(c));
+                    return;
+                }
+                Err(error) => {
+                    let (valid, rest) = bytes.split_at(error.valid_up_to());
+                    String::from_utf8_lossy(valid)
+                        .chars()
+                        .for_each(|c| self.put(c));
+                    match error.error_len() {
+                        Some(invalid) => {
+                            self.put(char::REPLACEMENT_CHARACTER);
+                            bytes = rest.get(invalid..).unwrap_or_default().to_vec();
+                        }
+                        None => {
+                            self.partial = rest.to_vec();
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        match &mut self.parser {
+            Parser::Text => match c {
+                '\x1b' => self.parser = Parser::Escape,
+                '\r' => self.x = 0,
+                '\n' => self.line_feed(),
+                '\x08' => self.x = self.cursor().0.saturating_sub(1),
+                '\t' => {
+                    self.x = (self.x / 8)
+                        .saturating_add(1)
+                        .saturating_mul(8)
+                        .min(self.width.saturating_sub(1));
+                }
+                c if c.is_control() => (),
+                c => {
+                    if self.x >= self.width {
+                        self.x = 0;
+                        self.line_feed();
+                    }
+                    if let Some(cell) = self
+                        .rows
+                        .get_mut(self.y)
+                        .and_then(|row| row.get_mut(self.x))
+                    {
+                        *cell = c;
+                    }
+                    self.x = self.x.saturating_add(1);
+                }
+            },
+            Parser::Escape => {
+                self.parser = match c {
+                    '[' => Parser::Control(String::new()),
+                    ']' => Parser::Command,
+                    _ => Parser::Text,
+                };
+            }
+            Parser::Control(parameters) => {
+                if ('\x40'..='\x7e').contains(&c) {
+                    let parameters = std::mem::take(parameters);
+                    self.parser = Parser::Text;
+                    self.control(&parameters, c);
+                } else {
+                    parameters.push(c);
+                }
+            }
+            Parser::Command => match c {
+                '\x07' => self.parser = Parser::Text,
+                '\x1b' => self.parser = Parser::Escape,
+                _ => (),
+            },
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.y.saturating_add(1) < self.rows.len() {
+            self.y = self.y.saturating_add(1);
+        } else if !self.rows.is_empty() {
+            self.rows.remove(0);
+            self.rows.push(vec![' '; self.width]);
+        }
+    }
+
+    // The control sequence ending in `action`. Private ones, which start
+    // with `?`, only change modes nothing here has.
+    fn control(&mut self, parameters: &str, action: char) {
+        if parameters.starts_with('?') {
+            return;
+        }
+        let numbers: Vec<usize> = parameters
+            .split(';')
+            .map(|number| number.parse().unwrap_or(0))
+            .collect();
+        let number = |index: usize| numbers.get(index).copied().unwrap_or(0);
+        // Moves count at least one.
+        let count = number(0).max(1);
+        let bottom = self.rows.len().saturating_sub(1);
+        let (x, y) = self.cursor();
+        match action {
+            'A' => self.y = y.saturating_sub(count),
+            'B' => self.y = y.saturating_add(count).min(bottom),
+            'C' => self.x = x.saturating_add(count).min(self.width.saturating_sub(1)),
+            'D' => self.x = x.saturating_sub(count),
+            'G' => self.x = count.saturating_sub(1).min(self.width.saturating_sub(1)),
+            'H' | 'f' => {
+                self.y = number(0).max(1).saturating_sub(1).min(bottom);
+                self.x = number(1)
+                    .max(1)
+                    .saturating_sub(1)
+                    .min(self.width.saturating_sub(1));
+            }
+            'J' => {
+                let (from, to) = match number(0) {
+                    0 => {
+                        self.clear(y, x, self.width);
+                        (y.saturating_add(1), self.rows.len())
+                    }
+                    1 => {
+                        self.clear(y, 0, x.saturating_add(1));
+                        (0, y)
+                    }
+                    _ => (0, self.rows.len()),
+                };
+                for row in from..to {
+                    self.clear(row, 0, self.width);
+                }
+            }
+            'K' => match number(0) {
+                0 => self.clear(y, x, self.width),
+                1 => self.clear(y, 0, x.saturating_add(1)),
+                _ => self.clear(y, 0, self.width),
+            },
+            'P' => {
+                if let Some(row) = self.rows.get_mut(y) {
+                    let end = x.saturating_add(count).min(row.len());
+                    row.drain(x.min(end)..end);
+                    row.resize(self.width, ' ');
+                }
+            }
+            '@' => {
+                if let Some(row) = self.rows.get_mut(y) {
+                    for _ in 0..count {
+                        row.insert(x.min(row.len()), ' ');
+                    }
+                    row.truncate(self.width);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Blanks the columns `start..end` of `row`.
+    fn clear(&mut self, row: usize, start: usize, end: usize) {
+        if let Some(row) = self.rows.get_mut(row) {
+            for cell in row.iter_mut().take(end).skip(start) {
+                *cell = ' ';
+            }
+        }
+    }
+}
@@ -1,46 +1,234 @@
+use crate::backend::{Backend, CursorStyle, Size};
+use crate::cells;
+use crate::event::Event;
+use crate::theme::Style;
 use crate::Position;
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::default;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
 use termion::color;
 use termion::cursor;
-use termion::event::Key;
-use termion::input::TermRead;
+use termion::event::{self, Key};
+use termion::input::{MouseTerminal, TermRead};
 use termion::raw::{IntoRawMode, RawTerminal};
 
-pub struct Size {
-    pub height: u16,
-    pub width: u16,
+// How often `Event::Tick` is sent.
+const TICK: Duration = Duration::from_secs(1);
+
+// What `MouseTerminal` sends to turn mouse reporting off and back on.
+const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+
+// Set by Ctrl-Z or a SIGTSTP from elsewhere, and acted on by the event loop
+// since a signal handler can do little more than this.
+static SUSPEND_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_terminal_stop(_signal: libc::c_int) {
+    SUSPEND_REQUESTED.store(true, Ordering::SeqCst);
 }
 
-pub struct Terminal {
-    size: Size,
-    _stdout: RawTerminal<std::io::Stdout>,
+fn catch_terminal_stop() {
+    // SAFETY: the handler only stores to an atomic, which is safe to do
+    // from a signal handler.
+    unsafe {
+        libc::signal(
+            libc::SIGTSTP,
+            on_terminal_stop as *const () as libc::sighandler_t,
+        );
+    }
 }
 
-pub enum CursorStyle {
-    Bar,
-    Block,
-    Underline,
+pub struct Terminal {
+    size: Size,
+    stdout: MouseTerminal<RawTerminal<std::io::Stdout>>,
+    sender: Sender<Event>,
+    events: Receiver<Event>,
+    // Events that arrived while waiting specifically for a key.
+    deferred: RefCell<VecDeque<Event>>,
+    // The lines on screen, so a frame only rewrites those that changed.
+    drawn: Vec<String>,
 }
 
 impl default::Default for Terminal {
     fn default() -> Terminal {
         let size = termion::terminal_size().unwrap();
+        catch_terminal_stop();
+        let (sender, events) = mpsc::channel();
+        let input = sender.clone();
+        let timer = sender.clone();
+        thread::spawn(move || loop {
+            thread::sleep(TICK);
+            if timer.send(Event::Tick).is_err() {
+                break;
+            }
+        });
+        // Keys come from the terminal itself when stdin is a pipe holding
+        // the text to edit.
+        let keys: Box<dyn Read + Send> = if termion::is_tty(&io::stdin()) {
+            Box::new(io::stdin())
+        } else {
+            match termion::get_tty() {
+                Ok(tty) => Box::new(tty),
+                Err(_) => Box::new(io::stdin()),
+            }
+        };
+        thread::spawn(move || {
+            for event in keys.events() {
+                let event = match event {
+                    Ok(event::Event::Key(key)) => Event::Key(Ok(key)),
+                    Ok(event::Event::Mouse(mouse)) => Event::Mouse(mouse),
+                    Ok(event::Event::Unsupported(_)) => continue,
+                    Err(error) => Event::Key(Err(error)),
+                };
+                if input.send(event).is_err() {
+                    break;
+                }
+            }
+        });
         Terminal {
             size: Size {
                 width: size.0,
                 height: size.1.saturating_sub(2),
             },
-            _stdout: io::stdout().into_raw_mode().unwrap(),
+            stdout: MouseTerminal::from(io::stdout().into_raw_mode().unwrap()),
+            sender,
+            events,
+            deferred: RefCell::new(VecDeque::new()),
+            drawn: Vec::new(),
         }
     }
 }
 
-impl Terminal {
-    pub fn size(&self) -> &Size {
+impl Backend for Terminal {
+    fn size(&self) -> &Size {
         &self.size
     }
 
+    // Only the cells of rows that don't already show the same thing are
+    // written, or whole rows where they can't be told apart. Nothing is
+    // cleared beforehand, so the screen never flickers blank between frames.
+    fn draw(
+        &mut self,
+        lines: Vec<String>,
+        cursor: &Position,
+        style: CursorStyle,
+    ) -> Result<(), std::io::Error> {
+        Self::hide_cursor();
+        for (y, line) in lines.iter().enumerate() {
+            let drawn = self.drawn.get(y);
+            if drawn == Some(line) {
+                continue;
+            }
+            let y = u16::try_from(y.saturating_add(1)).unwrap_or(u16::MAX);
+            match (
+                drawn.and_then(|drawn| cells::parse(drawn)),
+                cells::parse(line),
+            ) {
+                (Some(old), Some(new)) => print!("{}", cells::changes(&old, &new, y)),
+                _ => print!(
+                    "{}{line}{}",
+                    termion::cursor::Goto(1, y),
+                    termion::clear::UntilNewline
+                ),
+            }
+        }
+        self.drawn = lines;
+        Self::cursor_position(cursor);
+        Self::change_cursor_style(style);
+        Self::show_cursor();
+        Self::flush()
+    }
+
+    fn clear(&mut self) -> Result<(), std::io::Error> {
+        Self::cursor_position(&Position::default());
+        Self::clear_screen();
+        Self::show_cursor();
+        self.invalidate();
+        Self::flush()
+    }
+
+    fn invalidate(&mut self) {
+        self.drawn.clear();
+    }
+
+    fn next_event(&self) -> Result<Event, std::io::Error> {
+        if let Some(event) = self.deferred.borrow_mut().pop_front() {
+            return Ok(event);
+        }
+        self.events.recv().map_err(|_| input_closed())
+    }
+
+    fn next_event_timeout(&self, timeout: Duration) -> Result<Option<Event>, std::io::Error> {
+        if let Some(event) = self.deferred.borrow_mut().pop_front() {
+            return Ok(Some(event));
+        }
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(input_closed()),
+        }
+    }
+
+    fn read_key(&self) -> Result<Key, std::io::Error> {
+        loop {
+            match self.events.recv().map_err(|_| input_closed())? {
+                Event::Key(key) => return key,
+                event => self.deferred.borrow_mut().push_back(event),
+            }
+        }
+    }
+
+    fn event_sender(&self) -> Sender<Event> {
+        self.sender.clone()
+    }
+
+    fn suspend_raw_mode(&self) -> Result<(), std::io::Error> {
+        self.stdout.suspend_raw_mode()
+    }
+
+    fn activate_raw_mode(&self) -> Result<(), std::io::Error> {
+        self.stdout.activate_raw_mode()
+    }
+
+    // The terminal goes back to normal until the shell resumes the editor
+    // with `fg`.
+    fn suspend(&mut self) -> Result<(), std::io::Error> {
+        print!(
+            "{MOUSE_OFF}{}{}{}",
+            termion::clear::All,
+            cursor::Goto(1, 1),
+            cursor::Show
+        );
+        Self::flush()?;
+        self.suspend_raw_mode()?;
+        // SAFETY: the default action of SIGTSTP stops the process, which
+        // carries on from here once it gets SIGCONT.
+        unsafe {
+            libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+            libc::raise(libc::SIGTSTP);
+        }
+        catch_terminal_stop();
+        self.activate_raw_mode()?;
+        print!("{MOUSE_ON}");
+        self.invalidate();
+        Ok(())
+    }
+}
+
+impl Terminal {
+    pub fn request_suspend() {
+        SUSPEND_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn take_suspend_request() -> bool {
+        SUSPEND_REQUESTED.swap(false, Ordering::SeqCst)
+    }
+
     pub fn clear_screen() {
         print!("{}", termion::clear::All);
     }
@@ -65,14 +253,6 @@ impl Terminal {
         io::stdout().flush()
     }
 
-    pub fn read_key() -> Result<Key, std::io::Error> {
-        loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
-            }
-        }
-    }
-
     pub fn hide_cursor() {
         print!("{}", termion::cursor::Hide);
     }
@@ -93,6 +273,10 @@ impl Terminal {
         print!("{}", color::Fg(color));
     }
 
+    pub fn set_style(style: &Style) {
+        print!("{}", style.escape());
+    }
+
     pub fn reset_fg_color() {
         print!("{}", color::Fg(color::Reset));
     }
@@ -105,3 +289,7 @@ impl Terminal {
         }
     }
 }
+
+pub fn input_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Input closed")
+}
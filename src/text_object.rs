@@ -0,0 +1,219 @@
+use crate::motion::{self, CharClass};
+use crate::Document;
+use crate::Position;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TextObject {
+    Word,
+    Quote(char),
+    Bracket(char, char),
+    Paragraph,
+}
+
+// The span a text object covers; `end` is exclusive unless `linewise` is set,
+// in which case only the rows of `start` and `end` matter.
+pub struct Selection {
+    pub start: Position,
+    pub end: Position,
+    pub linewise: bool,
+}
+
+impl TextObject {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'w' => Some(Self::Word),
+            '"' | '\'' | '`' => Some(Self::Quote(c)),
+            '(' | ')' | 'b' => Some(Self::Bracket('(', ')')),
+            '[' | ']' => Some(Self::Bracket('[', ']')),
+            '{' | '}' | 'B' => Some(Self::Bracket('{', '}')),
+            '<' | '>' => Some(Self::Bracket('<', '>')),
+            'p' => Some(Self::Paragraph),
+            _ => None,
+        }
+    }
+
+    pub fn selection(self, document: &Document, at: &Position, around: bool) -> Option<Selection> {
+        match self {
+            Self::Word => word(document, at, around),
+            Self::Quote(quote) => quoted(document, at, quote, around),
+            Self::Bracket(open, close) => bracketed(document, at, open, close, around),
+            Self::Paragraph => paragraph(document, at, around),
+        }
+    }
+}
+
+fn word(document: &Document, at: &Position, around: bool) -> Option<Selection> {
+    let chars = document.row(at.y)?.chars();
+    if chars.is_empty() {
+        return None;
+    }
+    let class_at = |x: usize| chars.get(x).map(|c| CharClass::of(*c));
+    let x = at.x.min(chars.len().saturating_sub(1));
+    let class = class_at(x);
+    let mut start = x;
+    while start > 0 && class_at(start.saturating_sub(1)) == class {
+        start = start.saturating_sub(1);
+    }
+    let mut end = x.saturating_add(1);
+    while end < chars.len() && class_at(end) == class {
+        end = end.saturating_add(1);
+    }
+    if around {
+        // Prefer swallowing trailing whitespace, falling back to leading.
+        let trailing_start = end;
+        while end < chars.len() && class_at(end) == Some(CharClass::Space) {
+            end = end.saturating_add(1);
+        }
+        if end == trailing_start {
+            while start > 0 && class_at(start.saturating_sub(1)) == Some(CharClass::Space) {
+                start = start.saturating_sub(1);
+            }
+        }
+    }
+    Some(Selection {
+        start: Position { x: start, y: at.y },
+        end: Position { x: end, y: at.y },
+        linewise: false,
+    })
+}
+
+fn quoted(document: &Document, at: &Position, quote: char, around: bool) -> Option<Selection> {
+    let chars = document.row(at.y)?.chars();
+    let mut quotes = Vec::new();
+    for (index, c) in chars.iter().enumerate() {
+        let escaped = index > 0 && chars.get(index.saturating_sub(1)) == Some(&'\\');
+        if *c == quote && !escaped {
+            quotes.push(index);
+        }
+    }
+    // Pair quotes up from the start of the line and pick the pair around the
+    // cursor, or the first one after it.
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(_, close)| *close >= at.x)?;
+    let (mut start, mut end) = if around {
+        (open, close.saturating_add(1))
+    } else {
+        (open.saturating_add(1), close)
+    };
+    if around {
+        let trailing_start = end;
+        while chars.get(end).is_some_and(|c| c.is_whitespace()) {
+            end = end.saturating_add(1);
+        }
+        if end == trailing_start {
+            while start > 0
+                && chars
+                    .get(start.saturating_sub(1))
+                    .is_some_and(|c| c.is_whitespace())
+            {
+                start = start.saturating_sub(1);
+            }
+        }
+    }
+    Some(Selection {
+        start: Position { x: start, y: at.y },
+        end: Position { x: end, y: at.y },
+        linewise: false,
+    })
+}
+
+fn bracketed(
+    document: &Document,
+    at: &Position,
+    open: char,
+    close: char,
+    around: bool,
+) -> Option<Selection> {
+    let opening = if motion::char_at(document, at) == open {
+        at.clone()
+    } else {
+        let mut depth = 0_usize;
+        let mut position = at.clone();
+        loop {
+            let c = motion::char_at(document, &position);
+            if c == close && (position.x != at.x || position.y != at.y) {
+                depth = depth.saturating_add(1);
+            } else if c == open {
+                if depth == 0 {
+                    break position;
+                }
+                depth = depth.saturating_sub(1);
+            }
+            position = motion::previous(document, &position)?;
+        }
+    };
+
+    let mut depth = 0_usize;
+    let mut closing = opening.clone();
+    loop {
+        closing = motion::next(document, &closing)?;
+        let c = motion::char_at(document, &closing);
+        if c == open {
+            depth = depth.saturating_add(1);
+        } else if c == close {
+            if depth == 0 {
+                break;
+            }
+            depth = depth.saturating_sub(1);
+        }
+    }
+
+    if around {
+        return Some(Selection {
+            start: opening,
+            end: Position {
+                x: closing.x.saturating_add(1),
+                y: closing.y,
+            },
+            linewise: false,
+        });
+    }
+    let start = motion::next(document, &opening)?;
+    Some(Selection {
+        start,
+        end: closing,
+        linewise: false,
+    })
+}
+
+fn paragraph(document: &Document, at: &Position, around: bool) -> Option<Selection> {
+    if at.y >= document.len() {
+        return None;
+    }
+    let is_blank = |y: usize| {
+        document
+            .row(y)
+            .is_none_or(|row| row.as_str().trim().is_empty())
+    };
+    let blank = is_blank(at.y);
+    let last_row = document.len().saturating_sub(1);
+    let mut first = at.y;
+    while first > 0 && is_blank(first.saturating_sub(1)) == blank {
+        first = first.saturating_sub(1);
+    }
+    let mut last = at.y;
+    while last < last_row && is_blank(last.saturating_add(1)) == blank {
+        last = last.saturating_add(1);
+    }
+    if around {
+        // Include the following run of lines of the other kind, or the
+        // preceding one when the paragraph ends the document.
+        if last < last_row {
+            last = last.saturating_add(1);
+            while last < last_row && is_blank(last.saturating_add(1)) != blank {
+                last = last.saturating_add(1);
+            }
+        } else {
+            while first > 0 && is_blank(first.saturating_sub(1)) != blank {
+                first = first.saturating_sub(1);
+            }
+        }
+    }
+    Some(Selection {
+        start: Position { x: 0, y: first },
+        end: Position { x: 0, y: last },
+        linewise: true,
+    })
+}
@@ -0,0 +1,359 @@
+use crate::config;
+use crate::highlighting;
+use std::fs;
+use termion::color::{self, Bg, Fg, Rgb};
+use termion::style;
+
+// Colors to draw something with; None leaves the terminal's default.
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+    pub underline: bool,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+pub struct Theme {
+    name: String,
+    pub status_bar: Style,
+    pub empty_line: Style,
+    pub line_number: Style,
+    number: Style,
+    string: Style,
+    escape: Style,
+    character: Style,
+    comment: Style,
+    primary_keywords: Style,
+    secondary_keywords: Style,
+    search_match: Style,
+    matching_bracket: Style,
+    selection: Style,
+    pub git_added: Style,
+    pub git_modified: Style,
+    pub git_removed: Style,
+    pub diff_added: Style,
+    pub diff_changed: Style,
+    pub diff_removed: Style,
+    pub cursor_line: Style,
+    pub color_column: Style,
+    pub invisible: Style,
+    error: Style,
+    warning: Style,
+    misspelled: Style,
+    trailing_whitespace: Style,
+    word_match: Style,
+    todo: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Style {
+    fn fg(fg: Rgb) -> Self {
+        Self {
+            fg: Some(fg),
+            bg: None,
+            underline: false,
+            bold: false,
+            italic: false,
+        }
+    }
+
+    fn bg(bg: Rgb) -> Self {
+        Self {
+            fg: None,
+            bg: Some(bg),
+            underline: false,
+            bold: false,
+            italic: false,
+        }
+    }
+
+    fn new(fg: Rgb, bg: Rgb) -> Self {
+        Self {
+            fg: Some(fg),
+            bg: Some(bg),
+            underline: false,
+            bold: false,
+            italic: false,
+        }
+    }
+
+    fn underlined(fg: Rgb) -> Self {
+        Self {
+            fg: Some(fg),
+            bg: None,
+            underline: true,
+            bold: false,
+            italic: false,
+        }
+    }
+
+    fn bold(fg: Rgb) -> Self {
+        Self {
+            fg: Some(fg),
+            bg: None,
+            underline: false,
+            bold: true,
+            italic: false,
+        }
+    }
+
+    // The escape codes switching to this style, resetting unset colors.
+    pub fn escape(&self) -> String {
+        format!(
+            "{}{}{}{}{}",
+            self.bg
+                .map_or_else(|| Bg(color::Reset).to_string(), |bg| Bg(bg).to_string()),
+            self.fg
+                .map_or_else(|| Fg(color::Reset).to_string(), |fg| Fg(fg).to_string()),
+            if self.underline {
+                style::Underline.to_string()
+            } else {
+                style::NoUnderline.to_string()
+            },
+            // `NoBold` is taken for a double underline by many terminals,
+            // while going back to normal intensity undoes bold everywhere.
+            if self.bold {
+                style::Bold.to_string()
+            } else {
+                style::NoFaint.to_string()
+            },
+            if self.italic {
+                style::Italic.to_string()
+            } else {
+                style::NoItalic.to_string()
+            },
+        )
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: String::from("dark"),
+            status_bar: Style::new(Rgb(23, 23, 23), Rgb(255, 255, 255)),
+            empty_line: Style::fg(Rgb(204, 102, 255)),
+            line_number: Style::fg(Rgb(118, 118, 118)),
+            number: Style::fg(Rgb(232, 165, 165)),
+            string: Style::fg(Rgb(211, 54, 130)),
+            escape: Style::fg(Rgb(95, 215, 255)),
+            character: Style::fg(Rgb(255, 234, 96)),
+            comment: Style::fg(Rgb(124, 124, 124)),
+            primary_keywords: Style::fg(Rgb(181, 137, 0)),
+            secondary_keywords: Style::fg(Rgb(42, 161, 152)),
+            search_match: Style::new(Rgb(0, 0, 0), Rgb(255, 255, 0)),
+            matching_bracket: Style::bg(Rgb(0, 95, 135)),
+            selection: Style::bg(Rgb(68, 68, 68)),
+            git_added: Style::fg(Rgb(95, 175, 95)),
+            git_modified: Style::fg(Rgb(215, 175, 95)),
+            git_removed: Style::fg(Rgb(215, 95, 95)),
+            diff_added: Style::bg(Rgb(30, 60, 30)),
+            diff_changed: Style::bg(Rgb(40, 40, 80)),
+            diff_removed: Style::fg(Rgb(135, 60, 60)),
+            cursor_line: Style::bg(Rgb(38, 38, 38)),
+            color_column: Style::bg(Rgb(58, 38, 38)),
+            invisible: Style::fg(Rgb(88, 88, 88)),
+            error: Style::underlined(Rgb(255, 95, 95)),
+            warning: Style::underlined(Rgb(255, 175, 0)),
+            misspelled: Style::underlined(Rgb(95, 175, 255)),
+            trailing_whitespace: Style::bg(Rgb(175, 95, 0)),
+            word_match: Style::bg(Rgb(58, 58, 78)),
+            todo: Style::bold(Rgb(255, 175, 0)),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: String::from("light"),
+            status_bar: Style::new(Rgb(255, 255, 255), Rgb(60, 60, 60)),
+            empty_line: Style::fg(Rgb(135, 95, 215)),
+            line_number: Style::fg(Rgb(150, 150, 150)),
+            number: Style::fg(Rgb(9, 134, 88)),
+            string: Style::fg(Rgb(163, 21, 21)),
+            escape: Style::fg(Rgb(0, 95, 215)),
+            character: Style::fg(Rgb(175, 95, 0)),
+            comment: Style::fg(Rgb(0, 128, 0)),
+            primary_keywords: Style::fg(Rgb(0, 0, 255)),
+            secondary_keywords: Style::fg(Rgb(38, 127, 153)),
+            search_match: Style::new(Rgb(0, 0, 0), Rgb(255, 215, 95)),
+            matching_bracket: Style::bg(Rgb(175, 215, 255)),
+            selection: Style::bg(Rgb(200, 200, 200)),
+            git_added: Style::fg(Rgb(0, 135, 0)),
+            git_modified: Style::fg(Rgb(175, 135, 0)),
+            git_removed: Style::fg(Rgb(175, 0, 0)),
+            diff_added: Style::bg(Rgb(215, 245, 215)),
+            diff_changed: Style::bg(Rgb(220, 220, 250)),
+            diff_removed: Style::fg(Rgb(215, 135, 135)),
+            cursor_line: Style::bg(Rgb(238, 238, 238)),
+            color_column: Style::bg(Rgb(255, 228, 228)),
+            invisible: Style::fg(Rgb(188, 188, 188)),
+            error: Style::underlined(Rgb(215, 0, 0)),
+            warning: Style::underlined(Rgb(175, 95, 0)),
+            misspelled: Style::underlined(Rgb(0, 95, 215)),
+            trailing_whitespace: Style::bg(Rgb(255, 215, 135)),
+            word_match: Style::bg(Rgb(225, 228, 240)),
+            todo: Style::bold(Rgb(175, 0, 175)),
+        }
+    }
+
+    // Built-in themes are found by name, others are read from
+    // `themes/<name>.toml` in the config directory.
+    pub fn load(name: &str) -> Result<Self, String> {
+        match name {
+            "dark" => Ok(Self::dark()),
+            "light" => Ok(Self::light()),
+            _ => {
+                let path = config::dir()
+                    .ok_or_else(|| String::from("No config directory"))?
+                    .join("themes")
+                    .join(format!("{name}.toml"));
+                let contents = fs::read_to_string(&path)
+                    .map_err(|_| format!("Cannot find color scheme '{name}'"))?;
+                Self::parse(name, &contents)
+            }
+        }
+    }
+
+    // The theme in `theme.toml` in the config directory, if there is one.
+    pub fn load_default() -> Result<Self, String> {
+        let Some(path) = config::dir().map(|dir| dir.join("theme.toml")) else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse("theme", &contents),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    // A theme file holds `name = { fg = "#rrggbb", bg = "#rrggbb" }` entries,
+    // which may also set `underline`, `bold` or `italic` to true, and may
+    // start from another one with `base = "light"`.
+    pub fn parse(name: &str, contents: &str) -> Result<Self, String> {
+        let table: toml::Table = contents.parse().map_err(|error: toml::de::Error| {
+            format!("Invalid theme '{name}': {}", error.message())
+        })?;
+        let mut theme = match table.get("base").and_then(toml::Value::as_str) {
+            Some(base) => Self::load(base)?,
+            None => Self::default(),
+        };
+        theme.name = name.to_string();
+        for (key, value) in &table {
+            if key == "base" {
+                continue;
+            }
+            let Some(style) = theme.style_mut(key) else {
+                return Err(format!("Unknown theme entry: {key}"));
+            };
+            let color = |field: &str| -> Result<Option<Rgb>, String> {
+                value
+                    .get(field)
+                    .map(|color| {
+                        color
+                            .as_str()
+                            .and_then(parse_color)
+                            .ok_or_else(|| format!("Invalid color for {key}.{field}"))
+                    })
+                    .transpose()
+            };
+            if let Some(fg) = color("fg")? {
+                style.fg = Some(fg);
+            }
+            if let Some(bg) = color("bg")? {
+                style.bg = Some(bg);
+            }
+            if let Some(underline) = value.get("underline") {
+                style.underline = underline
+                    .as_bool()
+                    .ok_or_else(|| format!("Invalid underline for {key}"))?;
+            }
+            if let Some(bold) = value.get("bold") {
+                style.bold = bold
+                    .as_bool()
+                    .ok_or_else(|| format!("Invalid bold for {key}"))?;
+            }
+            if let Some(italic) = value.get("italic") {
+                style.italic = italic
+                    .as_bool()
+                    .ok_or_else(|| format!("Invalid italic for {key}"))?;
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn highlight(&self, highlighting_type: &highlighting::Type) -> Style {
+        match highlighting_type {
+            highlighting::Type::None => Style::default(),
+            highlighting::Type::Number => self.number,
+            highlighting::Type::SearchMatch => self.search_match,
+            highlighting::Type::String => self.string,
+            highlighting::Type::Escape => self.escape,
+            highlighting::Type::Character => self.character,
+            highlighting::Type::Comment => self.comment,
+            highlighting::Type::PrimaryKeywords => self.primary_keywords,
+            highlighting::Type::SecondaryKeywords => self.secondary_keywords,
+            highlighting::Type::MatchingBracket => self.matching_bracket,
+            highlighting::Type::Selection => self.selection,
+            highlighting::Type::Error => self.error,
+            highlighting::Type::Warning => self.warning,
+            highlighting::Type::Misspelled => self.misspelled,
+            highlighting::Type::TrailingWhitespace => self.trailing_whitespace,
+            highlighting::Type::WordMatch => self.word_match,
+            highlighting::Type::Todo => self.todo,
+        }
+    }
+
+    fn style_mut(&mut self, key: &str) -> Option<&mut Style> {
+        match key {
+            "status_bar" => Some(&mut self.status_bar),
+            "empty_line" => Some(&mut self.empty_line),
+            "line_number" => Some(&mut self.line_number),
+            "number" => Some(&mut self.number),
+            "string" => Some(&mut self.string),
+            "escape" => Some(&mut self.escape),
+            "character" => Some(&mut self.character),
+            "comment" => Some(&mut self.comment),
+            "primary_keywords" => Some(&mut self.primary_keywords),
+            "secondary_keywords" => Some(&mut self.secondary_keywords),
+            "search_match" => Some(&mut self.search_match),
+            "matching_bracket" => Some(&mut self.matching_bracket),
+            "selection" => Some(&mut self.selection),
+            "git_added" => Some(&mut self.git_added),
+            "git_modified" => Some(&mut self.git_modified),
+            "git_removed" => Some(&mut self.git_removed),
+            "diff_added" => Some(&mut self.diff_added),
+            "diff_changed" => Some(&mut self.diff_changed),
+            "diff_removed" => Some(&mut self.diff_removed),
+            "cursor_line" => Some(&mut self.cursor_line),
+            "color_column" => Some(&mut self.color_column),
+            "invisible" => Some(&mut self.invisible),
+            "error" => Some(&mut self.error),
+            "warning" => Some(&mut self.warning),
+            "misspelled" => Some(&mut self.misspelled),
+            "trailing_whitespace" => Some(&mut self.trailing_whitespace),
+            "word_match" => Some(&mut self.word_match),
+            "todo" => Some(&mut self.todo),
+            _ => None,
+        }
+    }
+}
+
+fn parse_color(hex: &str) -> Option<Rgb> {
+    let hex = hex.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |start: usize| {
+        hex.get(start..start.saturating_add(2))
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+    };
+    Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
@@ -0,0 +1,350 @@
+use crate::config;
+use crate::json::Value;
+use crate::Position;
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// A reversible edit: the rows starting at `y` went from `before` to `after`.
+pub struct Edit {
+    pub y: usize,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+    pub cursor: Position,
+}
+
+// A state the text has been in, reached from its parent by making `edits`.
+struct State {
+    parent: usize,
+    edits: Vec<Edit>,
+    time: SystemTime,
+    // The child redo goes to: the one last made or undone.
+    redo: Option<usize>,
+}
+
+// Every state the text has been in, as a tree rooted in the text as it was
+// opened, so changing the text after undoing starts a new branch rather than
+// losing the undone changes. States are numbered in the order they were
+// made, which is what `:earlier` and `:later` go by.
+pub struct UndoTree {
+    states: Vec<State>,
+    current: usize,
+    pending: Vec<Edit>,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self {
+            states: vec![State {
+                parent: 0,
+                edits: Vec::new(),
+                time: UNIX_EPOCH,
+                redo: None,
+            }],
+            current: 0,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl UndoTree {
+    pub fn record(&mut self, edit: Edit) {
+        self.pending.push(edit);
+    }
+
+    // Makes every edit recorded since the last commit a new state, a child
+    // of the current one.
+    pub fn commit(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let state = self.states.len();
+        self.states.push(State {
+            parent: self.current,
+            edits: mem::take(&mut self.pending),
+            time: SystemTime::now(),
+            redo: None,
+        });
+        if let Some(parent) = self.states.get_mut(self.current) {
+            parent.redo = Some(state);
+        }
+        self.current = state;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.len() <= 1 && self.pending.is_empty()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        if self.current == 0 {
+            return None;
+        }
+        self.states.get(self.current).map(|state| state.parent)
+    }
+
+    pub fn redo_child(&self) -> Option<usize> {
+        self.states.get(self.current)?.redo
+    }
+
+    // The state made `count` states before the current one.
+    pub fn earlier(&self, count: usize) -> usize {
+        self.current.saturating_sub(count)
+    }
+
+    pub fn later(&self, count: usize) -> usize {
+        self.current
+            .saturating_add(count)
+            .min(self.states.len().saturating_sub(1))
+    }
+
+    // The last state made at least `duration` before the current one.
+    pub fn earlier_by(&self, duration: Duration) -> usize {
+        let Some(cutoff) = self
+            .states
+            .get(self.current)
+            .and_then(|state| state.time.checked_sub(duration))
+        else {
+            return 0;
+        };
+        self.last_before(cutoff).min(self.current)
+    }
+
+    // The last state made at most `duration` after the current one.
+    pub fn later_by(&self, duration: Duration) -> usize {
+        let Some(cutoff) = self
+            .states
+            .get(self.current)
+            .and_then(|state| state.time.checked_add(duration))
+        else {
+            return self.states.len().saturating_sub(1);
+        };
+        self.last_before(cutoff).max(self.current)
+    }
+
+    fn last_before(&self, cutoff: SystemTime) -> usize {
+        self.states
+            .iter()
+            .rposition(|state| state.time <= cutoff)
+            .unwrap_or(0)
+    }
+
+    // Makes `target` the current state, returning the way there: each state
+    // whose edits are undone or redone, with whether they are undone, in the
+    // order to do so. Undoing goes up to where the two branches meet and
+    // redoing comes back down the other.
+    pub fn go(&mut self, target: usize) -> Vec<(usize, bool)> {
+        if target >= self.states.len() {
+            return Vec::new();
+        }
+        let ancestors = self.ancestors(self.current);
+        let mut down = Vec::new();
+        let mut meeting = target;
+        while !ancestors.contains(&meeting) {
+            down.push(meeting);
+            meeting = self.states.get(meeting).map_or(0, |state| state.parent);
+        }
+        let mut steps: Vec<(usize, bool)> = ancestors
+            .iter()
+            .take_while(|&&state| state != meeting)
+            .map(|&state| (state, true))
+            .collect();
+        steps.extend(down.iter().rev().map(|&state| (state, false)));
+        // Redo should come back the way this went.
+        for &(state, _) in &steps {
+            let parent = self.states.get(state).map_or(0, |state| state.parent);
+            if let Some(parent) = self.states.get_mut(parent) {
+                parent.redo = Some(state);
+            }
+        }
+        self.current = target;
+        steps
+    }
+
+    // `state` and the states above it, up to the root.
+    fn ancestors(&self, mut state: usize) -> Vec<usize> {
+        let mut ancestors = vec![state];
+        while state > 0 {
+            state = self.states.get(state).map_or(0, |state| state.parent);
+            ancestors.push(state);
+        }
+        ancestors
+    }
+
+    pub fn edits(&self, state: usize) -> &[Edit] {
+        self.states.get(state).map_or(&[], |state| &state.edits)
+    }
+
+    // The tree as JSON, for the text whose hash is `hash`.
+    pub fn to_json(&self, hash: u64) -> Value {
+        let states = self
+            .states
+            .iter()
+            .map(|state| {
+                let time = state
+                    .time
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs());
+                Value::object([
+                    ("parent", state.parent.into()),
+                    ("time", time.into()),
+                    ("redo", state.redo.map_or(Value::Null, Value::from)),
+                    (
+                        "edits",
+                        Value::Array(state.edits.iter().map(edit_to_json).collect()),
+                    ),
+                ])
+            })
+            .collect();
+        Value::object([
+            ("hash", format!("{hash:016x}").into()),
+            ("current", self.current.into()),
+            ("states", Value::Array(states)),
+        ])
+    }
+
+    // The tree saved by `to_json`, if it was saved for the text whose hash is
+    // `hash`.
+    pub fn from_json(value: &Value, hash: u64) -> Option<Self> {
+        if value.get("hash")?.as_str()? != format!("{hash:016x}") {
+            return None;
+        }
+        let states = value
+            .get("states")?
+            .as_array()?
+            .iter()
+            .map(|state| {
+                Some(State {
+                    parent: index(state.get("parent")?)?,
+                    edits: state
+                        .get("edits")?
+                        .as_array()?
+                        .iter()
+                        .map(edit_from_json)
+                        .collect::<Option<_>>()?,
+                    time: UNIX_EPOCH
+                        .checked_add(Duration::from_secs(state.get("time")?.as_u64()?))?,
+                    redo: match state.get("redo")? {
+                        Value::Null => None,
+                        redo => Some(index(redo)?),
+                    },
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let current = index(value.get("current")?)?;
+        let valid = |state: usize| state < states.len();
+        if !valid(current)
+            || !states
+                .iter()
+                .enumerate()
+                .all(|(at, state)| (at == 0 || state.parent < at) && state.redo.is_none_or(valid))
+        {
+            return None;
+        }
+        Some(Self {
+            states,
+            current,
+            pending: Vec::new(),
+        })
+    }
+}
+
+fn edit_to_json(edit: &Edit) -> Value {
+    let lines =
+        |lines: &[String]| Value::Array(lines.iter().map(|line| line.as_str().into()).collect());
+    Value::object([
+        ("y", edit.y.into()),
+        (
+            "cursor",
+            Value::Array(vec![edit.cursor.x.into(), edit.cursor.y.into()]),
+        ),
+        ("before", lines(&edit.before)),
+        ("after", lines(&edit.after)),
+    ])
+}
+
+fn edit_from_json(value: &Value) -> Option<Edit> {
+    let lines = |key: &str| {
+        value
+            .get(key)?
+            .as_array()?
+            .iter()
+            .map(|line| line.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+    };
+    let cursor = value.get("cursor")?.as_array()?;
+    Some(Edit {
+        y: index(value.get("y")?)?,
+        before: lines("before")?,
+        after: lines("after")?,
+        cursor: Position {
+            x: index(cursor.first()?)?,
+            y: index(cursor.get(1)?)?,
+        },
+    })
+}
+
+fn index(value: &Value) -> Option<usize> {
+    usize::try_from(value.as_u64()?).ok()
+}
+
+// An FNV-1a hash of `lines`, to tell whether an undo file was saved for the
+// text as it is now.
+pub fn hash<'a>(lines: impl Iterator<Item = &'a str>) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (index, line) in lines.enumerate() {
+        let separator: &[u8] = if index > 0 { b"\n" } else { b"" };
+        for byte in separator.iter().chain(line.as_bytes()) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+// Where the undo history of `file_name` is kept: under the config directory,
+// named after the file's absolute path with `%` for `/`.
+pub fn path(file_name: &str) -> Option<PathBuf> {
+    let absolute = fs::canonicalize(file_name).unwrap_or_else(|_| PathBuf::from(file_name));
+    let name = absolute.to_string_lossy().replace('/', "%");
+    config::dir().map(|dir| dir.join("undo").join(name))
+}
+
+// `:earlier` and `:later` take a number of changes, or a time like `10s`,
+// `5m`, `1h` or `2d`.
+pub enum Distance {
+    Changes(usize),
+    Time(Duration),
+}
+
+impl Distance {
+    pub fn parse(argument: Option<&str>) -> Option<Self> {
+        let Some(argument) = argument else {
+            return Some(Self::Changes(1));
+        };
+        let (number, unit) = match argument.char_indices().last()? {
+            (at, unit) if unit.is_ascii_alphabetic() => (argument.get(..at)?, Some(unit)),
+            _ => (argument, None),
+        };
+        let number: u64 = number.parse().ok()?;
+        let seconds = match unit {
+            None => return Some(Self::Changes(usize::try_from(number).ok()?)),
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some('d') => 24 * 60 * 60,
+            Some(_) => return None,
+        };
+        Some(Self::Time(Duration::from_secs(
+            number.saturating_mul(seconds),
+        )))
+    }
+}
@@ -0,0 +1,99 @@
+use crate::config;
+use std::collections::BTreeMap;
+use std::mem;
+
+// Commands the user has named, read from the `[commands]` table of
+// `config.toml` or added with `:command`:
+//
+//     [commands]
+//     Wq = "w | q"
+//     Grep = "grep <args> | copen"
+//
+// Like in vim their names start with a capital letter, so they can't clash
+// with the built-in commands. `<args>` stands for whatever follows the name,
+// and `|` separates the commands to run, with `\|` for a `|` itself.
+#[derive(Default)]
+pub struct UserCommands {
+    commands: BTreeMap<String, String>,
+}
+
+impl UserCommands {
+    pub fn load() -> Result<Self, String> {
+        let mut commands = Self::default();
+        let Some(entries) = config::section("commands")? else {
+            return Ok(commands);
+        };
+        for (name, replacement) in &entries {
+            let replacement = replacement
+                .as_str()
+                .ok_or_else(|| format!("Command {name} must be a string"))?;
+            commands.add(name, replacement, true)?;
+        }
+        Ok(commands)
+    }
+
+    // Adds the command `name`, which only replaces one of that name when
+    // `replace` is set, like `:command!`.
+    pub fn add(&mut self, name: &str, replacement: &str, replace: bool) -> Result<(), String> {
+        if !is_name(name) {
+            return Err(format!("Invalid command name: {name}"));
+        }
+        if !replace && self.commands.contains_key(name) {
+            return Err(format!(
+                "Command already exists, add ! to replace it: {name}"
+            ));
+        }
+        self.commands
+            .insert(name.to_string(), replacement.to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.commands.remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.commands.get(name).map(String::as_str)
+    }
+
+    // Every command and what it runs, in alphabetical order.
+    pub fn list(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.commands
+            .iter()
+            .map(|(name, replacement)| (name.as_str(), replacement.as_str()))
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_uppercase())
+        && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// The commands `replacement` runs when given `arguments`.
+pub fn expand(replacement: &str, arguments: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut command = String::new();
+    let mut rest = replacement;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("<args>") {
+            command.push_str(arguments);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("\\|") {
+            command.push('|');
+            rest = after;
+        } else {
+            if c == '|' {
+                commands.push(mem::take(&mut command));
+            } else {
+                command.push(c);
+            }
+            rest = rest.get(c.len_utf8()..).unwrap_or_default();
+        }
+    }
+    commands.push(command);
+    commands
+        .into_iter()
+        .map(|command| command.trim().to_string())
+        .filter(|command| !command.is_empty())
+        .collect()
+}